@@ -0,0 +1,194 @@
+//! Generates the sources the benchmarks run on, the same ones for the same seed, so that they
+//! don't have to be checked in.
+
+/// A generated source, named after what it's representative of.
+pub struct Input {
+    pub name: &'static str,
+    pub source: String,
+}
+
+/// Returns every input, each about `size` bytes long.
+pub fn all(seed: u64, size: usize) -> Vec<Input> {
+    vec![
+        Input {
+            name: "data-table",
+            source: data_table(seed, size),
+        },
+        Input {
+            name: "nested-expressions",
+            source: nested_expressions(seed, size),
+        },
+        Input {
+            name: "oo-module",
+            source: oo_module(seed, size),
+        },
+        Input {
+            name: "comment-heavy",
+            source: comment_heavy(seed, size),
+        },
+    ]
+}
+
+/// A xorshift generator, good enough to vary the inputs and the same on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves 0.
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+// none starts with an `e`, the lexer takes `.e` and `-e` for the start of a number.
+const WORDS: &[&str] = &[
+    "account", "balance", "buffer", "config", "count", "index", "item", "length", "name", "node",
+    "offset", "owner", "parent", "price", "queue", "result", "state", "total", "value", "weight",
+];
+
+/// A large table literal of records, like the data files generated by other tools.
+fn data_table(seed: u64, size: usize) -> String {
+    let mut rng = Rng::new(seed);
+    let mut records = Vec::new();
+    let mut len = 0;
+
+    while len < size {
+        let id = records.len() + 1;
+        let record = format!(
+            "  {{ id = {id}, name = \"{}{id}\", price = {}.{:02}, stock = {}, active = {}, tags = {{ \"{}\", \"{}\" }} }}",
+            rng.pick(WORDS),
+            rng.below(1000),
+            rng.below(100),
+            rng.below(500),
+            rng.below(2) == 1,
+            rng.pick(WORDS),
+            rng.pick(WORDS),
+        );
+        len += record.len() + 2;
+        records.push(record);
+    }
+
+    // without a separator after the last record, which the parser doesn't take yet.
+    format!("return {{\n{}\n}}\n", records.join(",\n"))
+}
+
+/// Writes an expression of `depth` nested operations.
+fn expression(rng: &mut Rng, depth: usize) -> String {
+    if depth == 0 {
+        return match rng.below(3) {
+            0 => rng.below(100).to_string(),
+            1 => rng.pick(WORDS).to_string(),
+            _ => format!("{}.{}", rng.pick(WORDS), rng.pick(WORDS)),
+        };
+    }
+
+    let operator = rng.pick(&["+", "-", "*", "/", "..", "<", "==", "and", "or"]);
+    let (left, right) = (expression(rng, depth - 1), expression(rng, 0));
+    match rng.below(3) {
+        0 => format!("({left} {operator} {right})"),
+        1 => format!("{right} {operator} ({left})"),
+        _ => format!("not ({left}) {operator} -{right}"),
+    }
+}
+
+/// Statements whose expressions are nested deep, well under the parser's limit.
+fn nested_expressions(seed: u64, size: usize) -> String {
+    let mut rng = Rng::new(seed);
+    let mut out = String::new();
+    let mut n = 0;
+
+    while out.len() < size {
+        n += 1;
+        let depth = 10 + rng.below(30) as usize;
+        out.push_str(&format!("local e{n} = {}\n", expression(&mut rng, depth)));
+    }
+    out
+}
+
+/// Classes with methods full of field accesses and calls, where most tokens are names.
+fn oo_module(seed: u64, size: usize) -> String {
+    let mut rng = Rng::new(seed);
+    let mut out = String::from("local M = {}\n\n");
+    let mut n = 0;
+
+    while out.len() < size {
+        n += 1;
+        let class = format!("Class{n}");
+        let (field, other) = (rng.pick(WORDS), rng.pick(WORDS));
+        out.push_str(&format!(
+            "local {class} = {{}}\n\
+             {class}.__index = {class}\n\
+             M.{class} = {class}\n\n\
+             function {class}.new({field}, {other})\n\
+             \x20 local self = setmetatable({{}}, {class})\n\
+             \x20 self.{field} = {field}\n\
+             \x20 self.{other} = {other} or {{}}\n\
+             \x20 return self\n\
+             end\n\n\
+             function {class}:update(delta)\n\
+             \x20 self.{field} = self.{field} + delta\n\
+             \x20 if self.{field} > self.{other}.limit then\n\
+             \x20   self:reset(self.{other}.start)\n\
+             \x20 end\n\
+             \x20 return self.{field}\n\
+             end\n\n\
+             function {class}:reset(value)\n\
+             \x20 local i = 0\n\
+             \x20 while i < value do\n\
+             \x20   i = i + 1\n\
+             \x20   self.{other}[i] = tostring(i) .. self.{field}\n\
+             \x20 end\n\
+             end\n\n"
+        ));
+    }
+
+    out.push_str("return M\n");
+    out
+}
+
+/// Short functions under long documentation comments and between line comments.
+fn comment_heavy(seed: u64, size: usize) -> String {
+    let mut rng = Rng::new(seed);
+    let mut out = String::new();
+    let mut n = 0;
+
+    while out.len() < size {
+        n += 1;
+        out.push_str("--[[\n");
+        for _ in 0..3 + rng.below(5) {
+            let words = (0..8).map(|_| rng.pick(WORDS)).collect::<Vec<_>>();
+            out.push_str(&format!("  {}.\n", words.join(" ")));
+        }
+        out.push_str("]]\n");
+        out.push_str(&format!(
+            "local function f{n}(x)\n\
+             \x20 -- {} {} {}.\n\
+             \x20 return x + {} -- {}.\n\
+             end\n\
+             -- {} {}.\n\n",
+            rng.pick(WORDS),
+            rng.pick(WORDS),
+            rng.pick(WORDS),
+            rng.below(10),
+            rng.pick(WORDS),
+            rng.pick(WORDS),
+            rng.pick(WORDS),
+        ));
+    }
+    out
+}
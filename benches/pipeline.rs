@@ -0,0 +1,131 @@
+//! Times the stages of the compiler over generated sources, run with `cargo bench`.
+//!
+//! `cargo bench -- <filter>` only runs the benchmarks whose name contains the filter, e.g.
+//! `lex` or `oo-module`. `BENCH_SIZE` sets the size of every input in bytes, 1 MB by default,
+//! and `BENCH_SEED` the seed they're generated from.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lua_compiler::lexer::{Lexer, SpannedToken};
+use lua_compiler::parser::Parser;
+use lua_compiler::Compiler;
+
+mod corpus;
+
+// how long every benchmark runs for, after its warm up.
+const MEASURE: Duration = Duration::from_secs(2);
+const WARM_UP: Duration = Duration::from_millis(300);
+
+/// A stage of the pipeline, timed on its own over each input.
+struct Stage {
+    name: &'static str,
+    run: fn(&Prepared) -> usize,
+}
+
+/// An input along with what the stages after the lexer start from.
+struct Prepared {
+    source: String,
+    tokens: Vec<SpannedToken>,
+}
+
+// a stage that lands gets a line here, its input prepared in `prepare` if it needs one.
+const STAGES: &[Stage] = &[
+    Stage {
+        name: "lex",
+        run: |input| Lexer::new(&input.source).tokenize().map_or(0, |t| t.len()),
+    },
+    Stage {
+        name: "parse",
+        run: |input| {
+            let mut parser = Parser::new(input.tokens.clone());
+            parser.parse().is_some() as usize
+        },
+    },
+    Stage {
+        name: "check",
+        run: |input| {
+            Compiler::default()
+                .compile("bench.lua", &input.source)
+                .diagnostics
+                .len()
+        },
+    },
+];
+
+fn prepare(input: &corpus::Input) -> Prepared {
+    let tokens = Lexer::new(&input.source)
+        .tokenize()
+        .unwrap_or_else(|| panic!("the {} input doesn't lex", input.name));
+    // a source that doesn't parse would only time how fast the parser gives up.
+    let result = Compiler::default().compile(input.name, &input.source);
+    assert!(
+        result.ast.is_some(),
+        "the {} input doesn't parse: {:?}",
+        input.name,
+        result.diagnostics.first().map(|d| &d.message)
+    );
+
+    Prepared {
+        source: input.source.clone(),
+        tokens,
+    }
+}
+
+/// Runs `stage` over `input` for `MEASURE`, returning the median time of a run.
+fn measure(stage: &Stage, input: &Prepared) -> (Duration, usize) {
+    let started = Instant::now();
+    while started.elapsed() < WARM_UP {
+        black_box((stage.run)(black_box(input)));
+    }
+
+    let mut times = Vec::new();
+    let started = Instant::now();
+    while started.elapsed() < MEASURE || times.len() < 5 {
+        let run = Instant::now();
+        black_box((stage.run)(black_box(input)));
+        times.push(run.elapsed());
+    }
+
+    times.sort();
+    (times[times.len() / 2], times.len())
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn main() {
+    // cargo passes `--bench`, anything else is a filter.
+    let filter = std::env::args().skip(1).find(|a| !a.starts_with("--"));
+    let size = env_or("BENCH_SIZE", 1 << 20);
+    let seed = env_or("BENCH_SEED", 1);
+
+    let inputs = corpus::all(seed, size)
+        .iter()
+        .map(|input| (input.name, prepare(input)))
+        .collect::<Vec<_>>();
+
+    for stage in STAGES {
+        for (name, input) in &inputs {
+            let benchmark = format!("{}/{name}", stage.name);
+            if filter
+                .as_ref()
+                .is_some_and(|f| !benchmark.contains(f.as_str()))
+            {
+                continue;
+            }
+
+            let (median, runs) = measure(stage, input);
+            let megabytes = input.source.len() as f64 / 1e6;
+            println!(
+                "{benchmark:<28} {:>10.2?} {:>9.1} MB/s  ({runs} runs)",
+                median,
+                megabytes / median.as_secs_f64()
+            );
+        }
+    }
+}
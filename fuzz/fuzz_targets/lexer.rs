@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lua_compiler::diagnostic::{DiagnosticSink, RenderingSink, SourceContext};
+use lua_compiler::lexer::Lexer;
+use lua_compiler::term_color::OutputCaps;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let mut lexer = Lexer::new(source);
+    lexer.tokenize();
+
+    // the spans are only sliced with once the diagnostics are rendered.
+    let ctx = SourceContext::new("fuzz.lua", source);
+    let caps = OutputCaps {
+        color: false,
+        unicode: true,
+    };
+    let mut sink = RenderingSink::new(&ctx, Default::default(), caps);
+    for d in lexer.diagnostics() {
+        sink.emit(d.clone());
+    }
+    sink.finish();
+});
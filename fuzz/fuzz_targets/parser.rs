@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lua_compiler::lexer::Lexer;
+use lua_compiler::parser::Parser;
+
+// every token has a spelling, so going through the lexer still reaches any token sequence
+// while keeping the inputs readable.
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Some(tokens) = Lexer::new(source).tokenize() else {
+        return;
+    };
+
+    Parser::new(tokens).parse();
+});
@@ -0,0 +1,113 @@
+"""Python bindings to the lua-compiler checker and parser, built on its C interface.
+
+Build the shared library with `cargo build --release --features ffi`. It's looked for in the
+`LUA_COMPILER_LIB` environment variable, next to this file, then in the `target/release` and
+`target/debug` directories of the repository.
+
+    >>> import lua_compiler
+    >>> lua_compiler.check("local = 1")[0]["code"]
+    'E100'
+    >>> lua_compiler.parse("local x = 1")["kind"]
+    'Chunk'
+
+ctypes lets go of the GIL while the library runs, so other threads keep going while a large
+source is checked.
+"""
+
+import ctypes
+import json
+import os
+import sys
+
+__all__ = ["ParseError", "check", "parse", "version"]
+
+
+class ParseError(Exception):
+    """Raised by `parse` when the source has errors, with the diagnostics that explain them."""
+
+    def __init__(self, diagnostics):
+        super().__init__(diagnostics[0]["message"] if diagnostics else "the source didn't parse")
+        self.diagnostics = diagnostics
+
+
+def _library_name():
+    if sys.platform == "win32":
+        return "lua_compiler.dll"
+    if sys.platform == "darwin":
+        return "liblua_compiler.dylib"
+    return "liblua_compiler.so"
+
+
+def _load():
+    here = os.path.dirname(os.path.abspath(__file__))
+    name = _library_name()
+    candidates = [
+        os.environ.get("LUA_COMPILER_LIB"),
+        os.path.join(here, name),
+        os.path.join(here, "..", "target", "release", name),
+        os.path.join(here, "..", "target", "debug", name),
+    ]
+
+    for path in candidates:
+        if path and os.path.isfile(path):
+            library = ctypes.CDLL(path)
+            break
+    else:
+        raise ImportError(
+            f"could not find {name}, build it with `cargo build --release --features ffi` "
+            "or point LUA_COMPILER_LIB at it"
+        )
+
+    # the returned strings are kept as raw pointers, so they can be released.
+    for function in (library.luac_check, library.luac_parse):
+        function.argtypes = [ctypes.c_char_p, ctypes.c_size_t]
+        function.restype = ctypes.c_void_p
+    library.luac_free_string.argtypes = [ctypes.c_void_p]
+    library.luac_free_string.restype = None
+    library.luac_version.argtypes = []
+    library.luac_version.restype = ctypes.c_char_p
+    return library
+
+
+_library = _load()
+
+
+def _call(function, source):
+    if isinstance(source, str):
+        source = source.encode("utf-8")
+
+    pointer = function(source, len(source))
+    try:
+        result = json.loads(ctypes.string_at(pointer).decode("utf-8"))
+    finally:
+        _library.luac_free_string(pointer)
+
+    if isinstance(result, dict) and "error" in result:
+        raise ValueError(result["error"])
+    return result
+
+
+def check(source):
+    """Checks the source, returning its diagnostics as a list of dicts.
+
+    The dicts are the objects of `--diagnostics-format json`. `source` can be a str or UTF-8
+    bytes.
+    """
+    return _call(_library.luac_check, source)
+
+
+def parse(source):
+    """Parses the source, returning its syntax tree as nested dicts and lists.
+
+    Every node is a dict with the `kind` of node, the literal `value` it holds or None, and the
+    list of its `children`. Raises `ParseError` if the source has errors.
+    """
+    result = _call(_library.luac_parse, source)
+    if result["ast"] is None:
+        raise ParseError(result["diagnostics"])
+    return result["ast"]
+
+
+def version():
+    """Returns the version of the library."""
+    return _library.luac_version().decode("utf-8")
@@ -0,0 +1,513 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::resolve::{resolve, BindingKind};
+
+/// A function whose parameters are known where it's called, by the binding it's a local of
+/// and, for one defined on a local table like `function M.new()`, the name of its field.
+type Key = (usize, Option<String>);
+
+/// A function defined in the source with a fixed number of parameters.
+struct Definition {
+    // the name it's defined with, e.g. `clamp` or `M:update`.
+    name: String,
+    // how many arguments it takes when called with a `.`, `self` included.
+    parameters: usize,
+    // where its name is.
+    span: Span,
+}
+
+/// A call of a function that could be a known one, e.g. `clamp(x, 0, 1)` or `M:update(dt)`.
+struct Call {
+    key: Key,
+    // how many arguments it passes, `self` included for a call with `:`.
+    arguments: usize,
+    // whether the last argument is a call or `...`, which can pass any number of values.
+    open_ended: bool,
+    is_method_call: bool,
+    span: Span,
+}
+
+/// Returns the index of the bracket that closes the one at `open`.
+fn closing(tokens: &[SpannedToken], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        match t.token {
+            Token::LEFT_PAREN | Token::LEFT_BRACKET | Token::LEFT_BRACE => depth += 1,
+            Token::RIGHT_PAREN | Token::RIGHT_BRACKET | Token::RIGHT_BRACE => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits what's between the brackets at `open` and `close` at the commas outside of nested
+/// brackets, returning the index ranges of the parts, none if there's nothing between them.
+fn split_list(tokens: &[SpannedToken], open: usize, close: usize) -> Vec<(usize, usize)> {
+    let mut parts = Vec::new();
+    if close == open + 1 {
+        return parts;
+    }
+
+    let mut depth = 0;
+    let mut start = open + 1;
+    for (i, t) in tokens.iter().enumerate().take(close).skip(open + 1) {
+        match t.token {
+            Token::LEFT_PAREN | Token::LEFT_BRACKET | Token::LEFT_BRACE => depth += 1,
+            Token::RIGHT_PAREN | Token::RIGHT_BRACKET | Token::RIGHT_BRACE => depth -= 1,
+            Token::COMMA if depth == 0 => {
+                parts.push((start, i));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push((start, close));
+    parts
+}
+
+/// Returns how many parameters the list at the `(` at `open` declares, or None if it takes
+/// any number through `...`.
+fn parameters(tokens: &[SpannedToken], open: usize) -> Option<usize> {
+    let close = closing(tokens, open)?;
+    let parameters = split_list(tokens, open, close);
+    let variadic = parameters
+        .iter()
+        .any(|&(start, _)| tokens[start].token == Token::DOTS);
+    (!variadic).then_some(parameters.len())
+}
+
+/// Returns how many arguments the call at `at` passes, whether its last one can stand for any
+/// number of values, and the index of its last token.
+fn arguments(tokens: &[SpannedToken], at: usize) -> Option<(usize, bool, usize)> {
+    match tokens.get(at)?.token {
+        Token::STRING(_) => Some((1, false, at)),
+        Token::LEFT_BRACE => Some((1, false, closing(tokens, at)?)),
+        Token::LEFT_PAREN => {
+            let close = closing(tokens, at)?;
+            let arguments = split_list(tokens, at, close);
+            let open_ended = arguments.last().is_some_and(|&(start, end)| {
+                // a call in parentheses is cut down to one value.
+                tokens[start].token == Token::DOTS
+                    || (tokens[end - 1].token == Token::RIGHT_PAREN
+                        && (tokens[start].token != Token::LEFT_PAREN
+                            || closing(tokens, start) != Some(end - 1)))
+            });
+            Some((arguments.len(), open_ended, close))
+        }
+        _ => None,
+    }
+}
+
+/// Returns whether the name at `i` is assigned to, on its own or in a list like `a, f = ...`.
+fn is_assigned(tokens: &[SpannedToken], i: usize) -> bool {
+    let mut next = i + 1;
+    loop {
+        match tokens.get(next).map(|t| &t.token) {
+            Some(Token::ASSIGN) => return true,
+            Some(Token::COMMA | Token::DOT) => {
+                if !matches!(tokens.get(next + 1).map(|t| &t.token), Some(Token::NAME(_))) {
+                    return false;
+                }
+                next += 2;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// Records a definition, or that the function is defined more than once or with `...`.
+fn define(
+    definitions: &mut HashMap<Key, Definition>,
+    reassigned: &mut HashSet<Key>,
+    key: Key,
+    definition: Option<Definition>,
+) {
+    match definition {
+        Some(d) if !definitions.contains_key(&key) => {
+            definitions.insert(key, d);
+        }
+        _ => {
+            reassigned.insert(key);
+        }
+    }
+}
+
+/// Finds the functions defined with a fixed number of parameters, the calls that may be of
+/// them, and the ones that are assigned to anywhere.
+fn find(tokens: &[SpannedToken]) -> (HashMap<Key, Definition>, Vec<Call>, HashSet<Key>) {
+    let bindings = resolve(tokens);
+    // the locals by where their name is, declarations and references alike.
+    let mut locals = HashMap::new();
+    // where the locals are declared, which assigns them their first value.
+    let mut declarations = HashSet::new();
+    for (b, binding) in bindings.iter().enumerate() {
+        if binding.kind == BindingKind::Local {
+            for span in binding.occurrences() {
+                locals.insert(span.start, b);
+            }
+            declarations.extend(binding.declaration.map(|d| d.start));
+        }
+    }
+    let local_at = |i: usize| match tokens.get(i) {
+        Some(t) if matches!(t.token, Token::NAME(_)) => locals.get(&t.span.start).copied(),
+        _ => None,
+    };
+    let name_at = |i: usize| match tokens.get(i).map(|t| &t.token) {
+        Some(Token::NAME(name)) => Some(name.clone()),
+        _ => None,
+    };
+
+    let mut definitions = HashMap::new();
+    let mut calls = Vec::new();
+    // the functions that are assigned to, or defined more than once.
+    let mut reassigned = HashSet::new();
+
+    for (i, t) in tokens.iter().enumerate() {
+        let Some(b) = local_at(i) else {
+            continue;
+        };
+        let token = |n: usize| tokens.get(n).map(|t| &t.token);
+        let before = i.checked_sub(1).and_then(token);
+
+        // `local function f(...)` and `local f = function(...)`.
+        let function_at = match (before, token(i + 1)) {
+            (Some(Token::FUNCTION), _) if i >= 2 && tokens[i - 2].token == Token::LOCAL => {
+                Some(i + 1)
+            }
+            (Some(Token::LOCAL), Some(Token::ASSIGN)) if token(i + 2) == Some(&Token::FUNCTION) => {
+                Some(i + 3)
+            }
+            _ => None,
+        };
+        if let Some(open) = function_at.filter(|&open| token(open) == Some(&Token::LEFT_PAREN)) {
+            define(
+                &mut definitions,
+                &mut reassigned,
+                (b, None),
+                parameters(tokens, open).map(|parameters| Definition {
+                    name: name_at(i).unwrap_or_default(),
+                    parameters,
+                    span: t.span,
+                }),
+            );
+            continue;
+        }
+
+        // `M.f`, `M:f` and `f`, as a definition, an assignment or a call.
+        let (key, is_method, callee_end) = match (token(i + 1), name_at(i + 2)) {
+            (Some(Token::DOT), Some(field)) => ((b, Some(field)), false, i + 2),
+            (Some(Token::COLON), Some(field)) => ((b, Some(field)), true, i + 2),
+            _ => ((b, None), false, i),
+        };
+
+        if before == Some(&Token::FUNCTION) {
+            if key.1.is_some() && token(callee_end + 1) == Some(&Token::LEFT_PAREN) {
+                let name = tokens[i..=callee_end]
+                    .iter()
+                    .map(|t| t.token.spelling())
+                    .collect::<String>();
+                let definition = parameters(tokens, callee_end + 1).map(|n| Definition {
+                    name,
+                    parameters: n + is_method as usize,
+                    span: Span {
+                        end: tokens[callee_end].span.end,
+                        ..t.span
+                    },
+                });
+                define(&mut definitions, &mut reassigned, key, definition);
+            }
+            continue;
+        }
+
+        if key.1.is_none() && declarations.contains(&t.span.start) {
+            continue;
+        }
+        if is_assigned(tokens, callee_end) {
+            reassigned.insert(key);
+            continue;
+        }
+        // `M[k] = ...` or `M = ...` could replace any of the table's functions.
+        if key.1.is_none() && token(i + 1) == Some(&Token::LEFT_BRACKET) {
+            let assigns_index =
+                closing(tokens, i + 1).is_some_and(|close| is_assigned(tokens, close));
+            if assigns_index {
+                reassigned.insert((b, None));
+            }
+        }
+
+        if let Some((arguments, open_ended, last)) = arguments(tokens, callee_end + 1) {
+            calls.push(Call {
+                key,
+                arguments: arguments + is_method as usize,
+                open_ended,
+                is_method_call: is_method,
+                span: Span {
+                    end: tokens[last].span.end,
+                    ..t.span
+                },
+            });
+        }
+    }
+
+    (definitions, calls, reassigned)
+}
+
+/// Returns `n` followed by `argument` or `arguments`.
+fn arguments_count(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_string(),
+        n => format!("{n} arguments"),
+    }
+}
+
+/// Compares the arguments of the calls of local functions, and of functions defined on local
+/// tables, with the parameters of their definition.
+///
+/// Passing more than a function takes is a W207 warning, fewer a W208 one, which is only
+/// reported when `too_few` is set since trailing nils are often left out on purpose. Calls
+/// through anything else than the function's name are left alone, as are functions taking
+/// `...`, calls whose last argument can stand for any number of values and functions that are
+/// assigned to anywhere. A definition with `:` counts its `self`.
+///
+/// ```
+/// use lua_compiler::arity;
+/// use lua_compiler::lexer::Lexer;
+///
+/// let warnings = |source| arity::check(&Lexer::new(source).tokenize().unwrap(), true);
+///
+/// let source = "local function clamp(x, lo, hi) return x end\nclamp(1, 2, 3, 4, 5)";
+/// assert_eq!(
+///     warnings(source)[0].message,
+///     "function 'clamp' defined at line 1 takes 3 arguments but 5 were given"
+/// );
+///
+/// let source = "local M = {}\nfunction M:move(dx) end\nM:move(1)\nM.move(M, 1)";
+/// assert!(warnings(source).is_empty());
+///
+/// // a call of `:` passes `self`, which the definition counts.
+/// let source = "local M = {}\nfunction M:move(dx) end\nM:move(1, 2)";
+/// assert_eq!(
+///     warnings(source)[0].message,
+///     "function 'M:move' defined at line 2 takes 1 argument but 2 were given"
+/// );
+///
+/// // fewer is W208, unless the last argument can stand for more than one value.
+/// let source = "local function f(a, b) end\nf(1)\nf(g())\nf(...)";
+/// let found = warnings(source);
+/// assert_eq!((found.len(), found[0].code), (1, "W208"));
+///
+/// let exempt = [
+///     // it could be anything once it's assigned to.
+///     "local function f(a) end\nf = print\nf(1, 2)",
+///     "local M = {}\nfunction M.f(a) end\nM[k] = print\nM.f(1, 2)",
+///     // it takes any number.
+///     "local function f(a, ...) end\nf(1, 2, 3)",
+///     // it's only called through another expression.
+///     "local function f(a) end\nlocal t = {}\nt[1] = f\nt[1](1, 2)",
+/// ];
+/// for source in exempt {
+///     assert!(warnings(source).is_empty(), "{source}");
+/// }
+/// ```
+pub fn check(tokens: &[SpannedToken], too_few: bool) -> Vec<Diagnostic> {
+    let (definitions, calls, reassigned) = find(tokens);
+    let mut diagnostics = Vec::new();
+
+    for call in calls {
+        if reassigned.contains(&call.key) || reassigned.contains(&(call.key.0, None)) {
+            continue;
+        }
+        let Some(definition) = definitions.get(&call.key) else {
+            continue;
+        };
+
+        // a call with `:` passes `self` without writing it, so it's left out of both counts.
+        let hidden = call.is_method_call as usize;
+        let (expected, given) = (definition.parameters, call.arguments);
+        let code = match given.cmp(&expected) {
+            std::cmp::Ordering::Greater => "W207",
+            std::cmp::Ordering::Less if too_few && !call.open_ended => "W208",
+            _ => continue,
+        };
+
+        let message = format!(
+            "function '{}' defined at line {} takes {} but {} {} given",
+            definition.name,
+            definition.span.line,
+            arguments_count(expected.saturating_sub(hidden)),
+            given - hidden,
+            if given - hidden == 1 { "was" } else { "were" }
+        );
+        let note = match code {
+            "W207" => "the extra ones are evaluated, then dropped.",
+            _ => "the missing ones are nil.",
+        };
+        diagnostics.push(
+            Diagnostic::warning(code, message, call.span)
+                .with_note(note.to_string(), None)
+                .with_label(definition.span, "defined here".to_string()),
+        );
+    }
+
+    diagnostics.sort_by_key(|d| d.span.start);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // the code and the message of each warning, fewer arguments included.
+    fn warnings(source: &str) -> Vec<(&'static str, String)> {
+        check(&Lexer::new(source).tokenize().unwrap(), true)
+            .into_iter()
+            .map(|d| (d.code, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn too_many_arguments_is_a_warning_at_the_call() {
+        let source = "local x = 1\n\nlocal function clamp(x, lo, hi)\n  return x\nend\n\
+                      print(clamp(x, 0, 1, 2, 3))\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let found = check(&tokens, false);
+        assert_eq!(found.len(), 1);
+        let d = &found[0];
+        assert_eq!(d.code, "W207");
+        assert_eq!(
+            d.message,
+            "function 'clamp' defined at line 3 takes 3 arguments but 5 were given"
+        );
+        assert_eq!((d.span.line, d.span.column), (6, 7));
+        assert_eq!(d.labels[0].span.line, 3);
+        assert_eq!(
+            d.notes[0].message,
+            "the extra ones are evaluated, then dropped."
+        );
+
+        // the other ways of defining one, and a single argument.
+        assert_eq!(
+            warnings("local f = function() end\nf(1)"),
+            [(
+                "W207",
+                "function 'f' defined at line 1 takes 0 arguments but 1 was given".to_string()
+            )]
+        );
+        assert_eq!(
+            warnings("local M = {}\nfunction M.new(a) end\nM.new(1, 2)"),
+            [(
+                "W207",
+                "function 'M.new' defined at line 2 takes 1 argument but 2 were given".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn fewer_arguments_are_only_reported_when_asked_for() {
+        let source = "local function f(a, b, c) end\nf(1)";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        assert!(check(&tokens, false).is_empty());
+        assert_eq!(
+            warnings(source),
+            [(
+                "W208",
+                "function 'f' defined at line 1 takes 3 arguments but 1 was given".to_string()
+            )]
+        );
+        assert_eq!(
+            check(&tokens, true)[0].notes[0].message,
+            "the missing ones are nil."
+        );
+    }
+
+    #[test]
+    fn a_method_counts_self() {
+        let define = "local M = {}\nfunction M:move(dx, dy) end\n";
+        for call in ["M:move(1, 2)", "M.move(M, 1, 2)", "M:move(1)", "M.move(M)"] {
+            let found = warnings(&format!("{define}{call}"));
+            assert!(found.iter().all(|(code, _)| *code == "W208"), "{call}");
+        }
+        assert_eq!(
+            warnings(&format!("{define}M.move(M, 1, 2, 3)")),
+            [(
+                "W207",
+                "function 'M:move' defined at line 2 takes 3 arguments but 4 were given"
+                    .to_string()
+            )]
+        );
+        assert_eq!(
+            warnings(&format!("{define}M:move(1, 2, 3)"))[0].1,
+            "function 'M:move' defined at line 2 takes 2 arguments but 3 were given"
+        );
+    }
+
+    #[test]
+    fn calls_through_other_expressions_are_exempt() {
+        for source in [
+            "local function f(a) end\nlocal t = {f = f}\nt.f(1, 2)",
+            "local function f(a) end\nlocal g = f\ng(1, 2)",
+            "local function f(a) end\n(f)(1, 2)",
+            "local M = {}\nfunction M.f(a) end\nlocal k = 'f'\nM[k](1, 2)",
+            // a global of the same name isn't the local function.
+            "function f(a) end\nf(1, 2)",
+        ] {
+            assert!(warnings(source).is_empty(), "{source}");
+        }
+    }
+
+    #[test]
+    fn varargs_in_the_arguments_or_the_parameters_are_exempt() {
+        for source in [
+            "local function f(a, b) end\nf(...)",
+            "local function f(a, b) end\nf(1, g())",
+            "local function f(a, b) end\nf(1, (...))",
+            "local function f(...) end\nf(1, 2, 3)",
+            "local M = {}\nfunction M:f(a, ...) end\nM:f(1, 2, 3)",
+        ] {
+            assert!(
+                warnings(source).iter().all(|(code, _)| *code != "W208"),
+                "{source}"
+            );
+            assert!(
+                warnings(source).iter().all(|(code, _)| *code != "W207"),
+                "{source}"
+            );
+        }
+        // an open-ended argument that isn't the last counts as one.
+        assert_eq!(warnings("local function f(a) end\nf(g(), 2)")[0].0, "W207");
+    }
+
+    #[test]
+    fn reassigned_functions_are_exempt() {
+        for source in [
+            "local function f(a) end\nf = print\nf(1, 2)",
+            "local function f(a) end\nlocal x\nx, f = 1, print\nf(1, 2)",
+            "local function f(a) end\nf(1, 2)\nf = print",
+            "local M = {}\nfunction M.f(a) end\nfunction M.f(a, b) end\nM.f(1, 2, 3)",
+            "local M = {}\nfunction M.f(a) end\nM.f = print\nM.f(1, 2)",
+            "local M = {}\nfunction M.f(a) end\nM = other\nM.f(1, 2)",
+            "local M = {}\nfunction M.f(a) end\nM[k] = print\nM.f(1, 2)",
+        ] {
+            assert!(warnings(source).is_empty(), "{source}");
+        }
+
+        // a local of the same name is another function, with a definition of its own.
+        assert_eq!(
+            warnings("local function f(a) end\nlocal function f(a, b) end\nf(1, 2, 3)")[0].1,
+            "function 'f' defined at line 2 takes 2 arguments but 3 were given"
+        );
+        // another field of the table being assigned leaves this one alone.
+        assert_eq!(
+            warnings("local M = {}\nfunction M.f(a) end\nM.g = print\nM.f(1, 2)")[0].0,
+            "W207"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Writes an artifact to `path`, refusing to clobber the input file.
+///
+/// The contents are written to a temporary file next to the destination which is then renamed
+/// over it, so an interrupted run never leaves a truncated artifact behind.
+pub fn write(path: &Path, input: &Path, contents: &str) -> Result<(), String> {
+    // both paths have to exist to be canonicalized, which is fine since a missing output can't
+    // be the input.
+    if let (Ok(a), Ok(b)) = (fs::canonicalize(path), fs::canonicalize(input)) {
+        if a == b {
+            return Err(format!(
+                "refusing to overwrite the input file '{}'",
+                input.display()
+            ));
+        }
+    }
+
+    replace(path, contents)
+}
+
+/// Writes `contents` to `path` through a temporary file, the same way `write` does but
+/// without checking what the file is.
+pub fn replace(path: &Path, contents: &str) -> Result<(), String> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    fs::create_dir_all(parent)
+        .map_err(|e| format!("could not create '{}': {e}", parent.display()))?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("'{}' is not a file path", path.display()))?;
+
+    // the temporary file lives in the same directory so the rename can't cross file systems.
+    let temp = parent.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = fs::File::create(&temp)
+        .and_then(|mut f| {
+            f.write_all(contents.as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp, path));
+
+    result.map_err(|e| {
+        // don't leave the half written file lying around.
+        let _ = fs::remove_file(&temp);
+        format!("could not write '{}': {e}", path.display())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // a directory of its own for every test, the tests run at the same time.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "lua-compiler-artifact-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // the names of the files in the directory, sorted.
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn the_input_isnt_overwritten() {
+        let dir = temp_dir("input");
+        let input = dir.join("main.lua");
+        fs::write(&input, "local x = 1\n").unwrap();
+
+        // however the path to it is spelled.
+        for path in [input.clone(), dir.join(".").join("main.lua")] {
+            let error = write(&path, &input, "artifact").unwrap_err();
+            assert!(
+                error.starts_with("refusing to overwrite the input file"),
+                "{error}"
+            );
+        }
+        assert_eq!(fs::read_to_string(&input).unwrap(), "local x = 1\n");
+        assert_eq!(files(&dir), ["main.lua"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_artifact_replaces_the_file_through_a_temporary_one() {
+        let dir = temp_dir("replace");
+        let input = dir.join("main.lua");
+        let output = dir.join("out/main.ast");
+        fs::write(&input, "local x = 1\n").unwrap();
+
+        // the directories it's in are created.
+        write(&output, &input, "first").unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "first");
+
+        write(&output, &input, "second").unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "second");
+        // nothing is left of the temporary file once it's renamed.
+        assert_eq!(files(&dir.join("out")), ["main.ast"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_rename_leaves_the_destination_and_no_temporary_file() {
+        let dir = temp_dir("failed");
+        // a directory can't be renamed over with a file.
+        let output = dir.join("out");
+        fs::create_dir_all(output.join("inside")).unwrap();
+
+        let error = replace(&output, "artifact").unwrap_err();
+        assert!(error.starts_with("could not write"), "{error}");
+        assert_eq!(files(&dir), ["out"]);
+        assert_eq!(files(&output), ["inside"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
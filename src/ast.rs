@@ -0,0 +1,647 @@
+use crate::json;
+use crate::lexer::Token;
+use crate::parser::ASTNode;
+
+/// What part of the language a node is, which decides how it's drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    Statement,
+    Expression,
+    Literal,
+}
+
+impl ASTNode {
+    /// Returns the name of the node's variant, without its fields.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ASTNode::Chunk(..) => "Chunk",
+            ASTNode::Block(..) => "Block",
+            ASTNode::Statement(..) => "Statement",
+            ASTNode::Expression(..) => "Expression",
+            ASTNode::FunctionCall(..) => "FunctionCall",
+            ASTNode::LValueAssign { .. } => "LValueAssign",
+            ASTNode::Do(..) => "Do",
+            ASTNode::While { .. } => "While",
+            ASTNode::Repeat { .. } => "Repeat",
+            ASTNode::If { .. } => "If",
+            ASTNode::ForNumeric { .. } => "ForNumeric",
+            ASTNode::ForGeneric { .. } => "ForGeneric",
+            ASTNode::Function { .. } => "Function",
+            ASTNode::FunctionStatement { .. } => "FunctionStatement",
+            ASTNode::LocalFunction { .. } => "LocalFunction",
+            ASTNode::LocalVariable { .. } => "LocalVariable",
+            ASTNode::Return(..) => "Return",
+            ASTNode::FunctionName { .. } => "FunctionName",
+            ASTNode::VariableList { .. } => "VariableList",
+            ASTNode::Variable(..) => "Variable",
+            ASTNode::PrefixExpression(..) => "PrefixExpression",
+            ASTNode::PrefixExpressionBracketsExpression { .. } => {
+                "PrefixExpressionBracketsExpression"
+            }
+            ASTNode::PrefixExpressionDotName { .. } => "PrefixExpressionDotName",
+            ASTNode::PrefixExpressionArgs { .. } => "PrefixExpressionArgs",
+            ASTNode::PrefixExpressionNameArgs { .. } => "PrefixExpressionNameArgs",
+            ASTNode::NameList { .. } => "NameList",
+            ASTNode::BinaryOp { .. } => "BinaryOp",
+            ASTNode::UnaryOp { .. } => "UnaryOp",
+            ASTNode::ExpressionList { .. } => "ExpressionList",
+            ASTNode::ArgsParamList(..) => "ArgsParamList",
+            ASTNode::FunctionBody { .. } => "FunctionBody",
+            ASTNode::ParameterListA { .. } => "ParameterListA",
+            ASTNode::ParameterListB(..) => "ParameterListB",
+            ASTNode::TableConstructor(..) => "TableConstructor",
+            ASTNode::FieldList { .. } => "FieldList",
+            ASTNode::Field(..) => "Field",
+            ASTNode::FieldA { .. } => "FieldA",
+            ASTNode::FieldB { .. } => "FieldB",
+            ASTNode::Fieldsep(..) => "Fieldsep",
+            ASTNode::Args(..) => "Args",
+            ASTNode::LastStatement(..) => "LastStatement",
+            ASTNode::Name(..) => "Name",
+            ASTNode::Token(..) => "Token",
+        }
+    }
+
+    /// Returns whether the node is a statement, an expression or a literal.
+    pub fn category(&self) -> Category {
+        match self {
+            ASTNode::Chunk(..)
+            | ASTNode::Block(..)
+            | ASTNode::Statement(..)
+            | ASTNode::LValueAssign { .. }
+            | ASTNode::Do(..)
+            | ASTNode::While { .. }
+            | ASTNode::Repeat { .. }
+            | ASTNode::If { .. }
+            | ASTNode::ForNumeric { .. }
+            | ASTNode::ForGeneric { .. }
+            | ASTNode::FunctionStatement { .. }
+            | ASTNode::LocalFunction { .. }
+            | ASTNode::LocalVariable { .. }
+            | ASTNode::Return(..)
+            | ASTNode::LastStatement(..) => Category::Statement,
+            ASTNode::Name(..) | ASTNode::Token(..) => Category::Literal,
+            _ => Category::Expression,
+        }
+    }
+
+    /// Returns the literal value a leaf holds, like a name, a number or an operator.
+    pub fn value(&self) -> Option<String> {
+        match self {
+            ASTNode::Name(name) => Some(name.clone()),
+            ASTNode::Token(Token::NAME(s) | Token::STRING(s)) => Some(format!("{s:?}")),
+            ASTNode::Token(Token::NUMBER(n)) => Some(n.to_string()),
+            ASTNode::Token(token) => Some(token.kind().to_string()),
+            ASTNode::ParameterListA { variadic: true, .. } => Some("...".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Returns the nodes directly below this one, in source order.
+    pub fn children(&self) -> Vec<&ASTNode> {
+        let mut children = Vec::new();
+
+        match self {
+            ASTNode::Chunk(statements, last) => {
+                children.extend(statements);
+                children.extend(last.as_deref());
+            }
+            ASTNode::Block(node)
+            | ASTNode::Statement(node)
+            | ASTNode::Expression(node)
+            | ASTNode::FunctionCall(node)
+            | ASTNode::Do(node)
+            | ASTNode::Variable(node)
+            | ASTNode::PrefixExpression(node)
+            | ASTNode::ParameterListB(node)
+            | ASTNode::Field(node)
+            | ASTNode::Fieldsep(node)
+            | ASTNode::Args(node)
+            | ASTNode::LastStatement(node)
+            | ASTNode::Function {
+                function_body: node,
+            } => children.push(&**node),
+            ASTNode::Return(node)
+            | ASTNode::ArgsParamList(node)
+            | ASTNode::TableConstructor(node) => children.extend(node.as_deref()),
+            ASTNode::LValueAssign {
+                var_list: a,
+                expression_list: b,
+            }
+            | ASTNode::While {
+                expression: a,
+                do_block: b,
+            }
+            | ASTNode::Repeat {
+                block: a,
+                expression: b,
+            }
+            | ASTNode::FunctionStatement {
+                func_name: a,
+                function_body: b,
+            }
+            | ASTNode::LocalFunction {
+                name: a,
+                function_body: b,
+            }
+            | ASTNode::PrefixExpressionBracketsExpression {
+                prefix_expression: a,
+                expression: b,
+            }
+            | ASTNode::PrefixExpressionDotName {
+                prefix_expression: a,
+                name: b,
+            }
+            | ASTNode::PrefixExpressionArgs {
+                prefix_expression: a,
+                arguments: b,
+            }
+            | ASTNode::UnaryOp {
+                unary_operator: a,
+                right: b,
+            }
+            | ASTNode::FieldA {
+                expression_a: a,
+                expression_b: b,
+            }
+            | ASTNode::FieldB {
+                name: a,
+                expression: b,
+            } => children.extend([&**a, &**b]),
+            ASTNode::If {
+                expression,
+                block,
+                elseif,
+                then_else,
+            } => {
+                children.extend([&**expression, &**block]);
+                for (condition, block) in elseif {
+                    children.extend([condition, block]);
+                }
+                children.extend(then_else.as_deref());
+            }
+            ASTNode::ForNumeric {
+                name,
+                from_expression,
+                to_expression,
+                step_expression,
+                do_block,
+            } => {
+                children.extend([&**name, &**from_expression, &**to_expression]);
+                children.extend(step_expression.as_deref());
+                children.push(do_block);
+            }
+            ASTNode::ForGeneric {
+                name_list,
+                expression_list_1,
+                do_block,
+            } => children.extend([&**name_list, &**expression_list_1, &**do_block]),
+            ASTNode::LocalVariable {
+                name_list,
+                expression_list,
+            } => {
+                children.push(name_list);
+                children.extend(expression_list.as_deref());
+            }
+            ASTNode::FunctionName {
+                name,
+                members,
+                colon,
+            } => {
+                children.push(name);
+                children.extend(members);
+                children.extend(colon.as_deref());
+            }
+            ASTNode::VariableList {
+                variable: head,
+                tail_list: tail,
+            }
+            | ASTNode::NameList {
+                name: head,
+                tail_list: tail,
+            } => {
+                children.push(head);
+                children.extend(tail);
+            }
+            ASTNode::PrefixExpressionNameArgs {
+                prefix_expression,
+                name,
+                arguments,
+            } => children.extend([&**prefix_expression, &**name, &**arguments]),
+            ASTNode::BinaryOp {
+                left,
+                binary_operator,
+                right,
+            } => children.extend([&**left, &**binary_operator, &**right]),
+            ASTNode::ExpressionList {
+                head_list,
+                expression,
+            } => {
+                children.extend(head_list);
+                children.push(expression);
+            }
+            ASTNode::FunctionBody {
+                parameter_list,
+                block,
+            } => {
+                children.extend(parameter_list.as_deref());
+                children.push(block);
+            }
+            ASTNode::ParameterListA { name_list, .. } => children.push(name_list),
+            ASTNode::FieldList {
+                field,
+                separated_fields,
+                separator,
+            } => {
+                children.push(field);
+                for (separator, field) in separated_fields {
+                    children.extend([separator, field]);
+                }
+                children.extend(separator.as_deref());
+            }
+            ASTNode::Name(..) | ASTNode::Token(..) => {}
+        }
+
+        children
+    }
+}
+
+/// Writes the syntax tree as nested JSON objects of `kind`, `value` and `children`.
+///
+/// `kind` is the name of the node's variant, `value` the literal it holds or null, and
+/// `children` the nodes below it in source order.
+pub fn to_json(node: &ASTNode) -> String {
+    let value = match node.value() {
+        Some(value) => json::string(&value),
+        None => "null".to_string(),
+    };
+    let children = node
+        .children()
+        .into_iter()
+        .map(to_json)
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!(
+        "{{\"kind\":{},\"value\":{value},\"children\":[{children}]}}",
+        json::string(node.name())
+    )
+}
+
+/// Writes the syntax tree one node per line, indented by its depth, with the literal a node
+/// holds after its name.
+///
+/// ```
+/// use lua_compiler::ast::to_tree;
+/// use lua_compiler::lexer::Lexer;
+/// use lua_compiler::parser::Parser;
+///
+/// let tokens = Lexer::new("x").tokenize().unwrap();
+/// let tree = Parser::new(tokens).parse_expression().unwrap();
+/// assert!(to_tree(&tree).ends_with("Name x\n"));
+/// ```
+pub fn to_tree(node: &ASTNode) -> String {
+    fn write(out: &mut String, node: &ASTNode, depth: usize) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(node.name());
+        if let Some(value) = node.value() {
+            out.push(' ');
+            out.push_str(&value);
+        }
+        out.push('\n');
+        for child in node.children() {
+            write(out, child, depth + 1);
+        }
+    }
+
+    let mut out = String::new();
+    write(&mut out, node, 0);
+    out
+}
+
+/// Hashes what the syntax tree means: its shape, the names and the literal values in it.
+///
+/// Spans, comments and formatting aren't part of the tree, so two sources that only differ in
+/// those hash the same, and so do numbers written differently, like `16` and `0x10`. The hash is
+/// FNV-1a rather than the standard library's hasher, and is stable for a given version of the
+/// crate; another version may hash the same tree differently.
+pub fn semantic_hash(node: &ASTNode) -> u64 {
+    fn feed(hash: &mut u64, bytes: &[u8]) {
+        for &b in bytes {
+            *hash ^= b as u64;
+            *hash = hash.wrapping_mul(0x100000001b3);
+        }
+        // keeps `ab` + `c` apart from `a` + `bc`.
+        *hash ^= 0xff;
+        *hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    fn visit(hash: &mut u64, node: &ASTNode) {
+        let children = node.children();
+        feed(hash, node.name().as_bytes());
+        feed(hash, node.value().unwrap_or_default().as_bytes());
+        // the count keeps a node's children apart from its siblings.
+        // as 8 bytes whatever the size of `usize`, so 32-bit targets get the same hash.
+        feed(hash, &(children.len() as u64).to_le_bytes());
+        for child in children {
+            visit(hash, child);
+        }
+    }
+
+    let mut hash = 0xcbf29ce484222325;
+    visit(&mut hash, node);
+    hash
+}
+
+/// Writes an expression back as source with every operation in parentheses, which shows how
+/// the parser grouped it, e.g. `a + b * c` is `(a + (b * c))`.
+pub fn to_parenthesized(node: &ASTNode) -> String {
+    match node {
+        ASTNode::BinaryOp {
+            left,
+            binary_operator,
+            right,
+        } => format!(
+            "({} {} {})",
+            to_parenthesized(left),
+            to_parenthesized(binary_operator),
+            to_parenthesized(right)
+        ),
+        ASTNode::UnaryOp {
+            unary_operator,
+            right,
+        } => {
+            let operator = to_parenthesized(unary_operator);
+            // `not` is a word, it needs a space before its operand.
+            let space = if operator == "not" { " " } else { "" };
+            format!("({operator}{space}{})", to_parenthesized(right))
+        }
+        ASTNode::PrefixExpressionDotName {
+            prefix_expression,
+            name,
+        } => format!(
+            "{}.{}",
+            to_parenthesized(prefix_expression),
+            to_parenthesized(name)
+        ),
+        ASTNode::PrefixExpressionBracketsExpression {
+            prefix_expression,
+            expression,
+        } => format!(
+            "{}[{}]",
+            to_parenthesized(prefix_expression),
+            to_parenthesized(expression)
+        ),
+        ASTNode::PrefixExpressionArgs {
+            prefix_expression,
+            arguments,
+        } => format!(
+            "{}{}",
+            to_parenthesized(prefix_expression),
+            to_parenthesized(arguments)
+        ),
+        ASTNode::PrefixExpressionNameArgs {
+            prefix_expression,
+            name,
+            arguments,
+        } => format!(
+            "{}:{}{}",
+            to_parenthesized(prefix_expression),
+            to_parenthesized(name),
+            to_parenthesized(arguments)
+        ),
+        // a call with a table or a string is written with parentheses too.
+        ASTNode::Args(arguments) => match &**arguments {
+            ASTNode::ArgsParamList(..) => to_parenthesized(arguments),
+            other => format!("({})", to_parenthesized(other)),
+        },
+        ASTNode::ArgsParamList(list) => {
+            format!(
+                "({})",
+                list.as_deref().map(to_parenthesized).unwrap_or_default()
+            )
+        }
+        ASTNode::ExpressionList { .. } | ASTNode::FieldList { .. } => node
+            .children()
+            .into_iter()
+            .filter(|c| !matches!(c, ASTNode::Fieldsep(..)))
+            .map(to_parenthesized)
+            .collect::<Vec<_>>()
+            .join(", "),
+        ASTNode::TableConstructor(fields) => {
+            format!(
+                "{{{}}}",
+                fields.as_deref().map(to_parenthesized).unwrap_or_default()
+            )
+        }
+        ASTNode::FieldA {
+            expression_a,
+            expression_b,
+        } => format!(
+            "[{}] = {}",
+            to_parenthesized(expression_a),
+            to_parenthesized(expression_b)
+        ),
+        ASTNode::FieldB { name, expression } => {
+            format!(
+                "{} = {}",
+                to_parenthesized(name),
+                to_parenthesized(expression)
+            )
+        }
+        // the statements of a function have no place in an expression, only that it's there.
+        ASTNode::Function { .. } => "function ... end".to_string(),
+        ASTNode::Name(name) => name.clone(),
+        ASTNode::Token(token) => token.spelling().into_owned(),
+        // the rest of the nodes have no operators of their own to group.
+        _ => node
+            .children()
+            .into_iter()
+            .map(to_parenthesized)
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Renders the syntax tree as a Graphviz digraph, with an edge from every node to each child.
+///
+/// Statements are boxes, expressions ellipses and literals notes.
+pub fn to_dot(ast: &ASTNode) -> String {
+    to_dot_with_max_depth(ast, None)
+}
+
+/// Renders the syntax tree like `to_dot`, but replaces what's below `max_depth` by a single
+/// `...` node so a large tree stays readable.
+pub fn to_dot_with_max_depth(ast: &ASTNode, max_depth: Option<usize>) -> String {
+    let mut out = String::from("digraph ast {\n  node [fontname=\"monospace\", style=filled];\n");
+    let mut next_id = 0;
+    write_node(ast, 0, max_depth, &mut next_id, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// Writes the node and everything below it, returning the id it was given.
+fn write_node(
+    node: &ASTNode,
+    depth: usize,
+    max_depth: Option<usize>,
+    next_id: &mut usize,
+    out: &mut String,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+
+    let label = match node.value() {
+        Some(value) => format!("{}\n{value}", node.name()),
+        None => node.name().to_string(),
+    };
+    let (shape, color) = match node.category() {
+        Category::Statement => ("box", "lightblue"),
+        Category::Expression => ("ellipse", "lightyellow"),
+        Category::Literal => ("note", "lightgrey"),
+    };
+    // a json string is also a valid dot string, with the same escapes.
+    out.push_str(&format!(
+        "  n{id} [label={}, shape={shape}, fillcolor={color}];\n",
+        json::string(&label)
+    ));
+
+    let children = node.children();
+    if children.is_empty() {
+        return id;
+    }
+
+    if max_depth.is_some_and(|max| depth >= max) {
+        let elided = *next_id;
+        *next_id += 1;
+        out.push_str(&format!(
+            "  n{elided} [label=\"...\", shape=plaintext, style=\"\"];\n  n{id} -> n{elided};\n"
+        ));
+        return id;
+    }
+
+    for child in children {
+        let child = write_node(child, depth + 1, max_depth, next_id, out);
+        out.push_str(&format!("  n{id} -> n{child};\n"));
+    }
+
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ASTNode {
+        crate::Compiler::default()
+            .compile("main.lua", source)
+            .ast
+            .unwrap()
+    }
+
+    fn count(node: &ASTNode) -> usize {
+        1 + node.children().into_iter().map(count).sum::<usize>()
+    }
+
+    // the lines declaring a node, and the lines of the edges.
+    fn nodes_and_edges(dot: &str) -> (Vec<&str>, Vec<&str>) {
+        let lines = dot.lines().filter(|l| {
+            l.strip_prefix("  n")
+                .is_some_and(|id| id.starts_with(|c: char| c.is_ascii_digit()))
+        });
+        lines.partition(|l| !l.contains("->"))
+    }
+
+    #[test]
+    fn every_node_of_the_tree_is_in_the_graph() {
+        let ast = parse("local x = 1 + \"a\"\nprint(x)\n");
+        let dot = to_dot(&ast);
+        assert!(dot.starts_with("digraph ast {\n"), "{dot}");
+        assert!(dot.ends_with("}\n"), "{dot}");
+
+        let (nodes, edges) = nodes_and_edges(&dot);
+        assert_eq!(nodes.len(), count(&ast));
+        // a tree has an edge into every node but the root.
+        assert_eq!(edges.len(), nodes.len() - 1);
+
+        assert_eq!(
+            nodes[0],
+            "  n0 [label=\"Chunk\", shape=box, fillcolor=lightblue];"
+        );
+        for expected in [
+            "[label=\"LocalVariable\", shape=box, fillcolor=lightblue];",
+            "[label=\"BinaryOp\", shape=ellipse, fillcolor=lightyellow];",
+            "[label=\"Name\\nx\", shape=note, fillcolor=lightgrey];",
+            // the quotes of the string are escaped in the label.
+            "[label=\"Token\\n\\\"a\\\"\", shape=note, fillcolor=lightgrey];",
+            "[label=\"Name\\nprint\", shape=note, fillcolor=lightgrey];",
+        ] {
+            assert!(nodes.iter().any(|n| n.ends_with(expected)), "{expected}");
+        }
+    }
+
+    #[test]
+    fn subtrees_below_the_max_depth_are_an_ellipsis() {
+        let ast = parse("local x = 1\nprint(x)\nreturn x\n");
+        let dot = to_dot_with_max_depth(&ast, Some(1));
+
+        let (nodes, edges) = nodes_and_edges(&dot);
+        // the chunk, its three statements and an ellipsis under each.
+        assert_eq!(nodes.len(), 7, "{dot}");
+        assert_eq!(edges.len(), 6, "{dot}");
+        assert_eq!(
+            nodes
+                .iter()
+                .filter(|n| n.ends_with("[label=\"...\", shape=plaintext, style=\"\"];"))
+                .count(),
+            3
+        );
+
+        // a depth the tree doesn't reach leaves it whole.
+        assert_eq!(to_dot_with_max_depth(&ast, Some(100)), to_dot(&ast));
+    }
+
+    #[test]
+    fn formatting_comments_and_number_spelling_dont_change_the_hash() {
+        let hash = semantic_hash(&parse("local t = {16, \"a\"}\nif t then f(t) end\n"));
+        for same in [
+            "local t={16,\"a\"} if t then f(t) end",
+            "-- a table\nlocal t = {\n  0x10, -- sixteen\n  'a'\n}\nif t then\n  f(t)\nend\n",
+            "local t = {16.0, [[a]]}\n--[[ a\nlong comment ]]\nif t then f(t) end\n",
+        ] {
+            assert_eq!(semantic_hash(&parse(same)), hash, "{same:?}");
+        }
+    }
+
+    #[test]
+    fn names_values_and_structure_change_the_hash() {
+        let hash = semantic_hash(&parse("local t = a.b(c)\n"));
+        for different in [
+            "local u = a.b(c)\n",
+            "local t = a.b(d)\n",
+            "local t = a:b(c)\n",
+            "local t = a.b(c, nil)\n",
+            "local t = (a.b)(c)\n",
+            "t = a.b(c)\n",
+            "local t = a.b(c) return\n",
+        ] {
+            assert_ne!(semantic_hash(&parse(different)), hash, "{different:?}");
+        }
+    }
+
+    #[test]
+    fn the_hash_is_the_same_on_every_platform() {
+        // pinned, a change here changes every hash users have stored.
+        assert_eq!(
+            format!("{:016x}", semantic_hash(&parse("return 1\n"))),
+            "f6dc3543bf8ae0f4"
+        );
+        assert_eq!(
+            format!(
+                "{:016x}",
+                semantic_hash(&parse(include_str!("../program.lua")))
+            ),
+            "73264d4c9e9c3f38"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Span, SpannedToken, Token};
+
+/// A bracket or keyword that the source has to close, or the token that closes one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delimiter {
+    pub spelling: &'static str,
+    pub span: Span,
+}
+
+/// What's wrong with how the delimiters of a source nest.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unbalanced {
+    // the source ends with these still open, the innermost last.
+    Unclosed(Vec<Delimiter>),
+    // a closer that doesn't go with the innermost opener, or that has nothing to close.
+    Mismatched {
+        opener: Option<Delimiter>,
+        closer: Delimiter,
+    },
+}
+
+/// Returns what a token opens, with the closers that go with it.
+fn opens(token: &Token) -> Option<(&'static str, &'static [&'static str])> {
+    Some(match token {
+        Token::LEFT_PAREN => ("(", &[")"]),
+        Token::LEFT_BRACKET => ("[", &["]"]),
+        Token::LEFT_BRACE => ("{", &["}"]),
+        Token::FUNCTION => ("function", &["end"]),
+        Token::DO => ("do", &["end"]),
+        Token::IF => ("if", &["end"]),
+        Token::WHILE => ("while", &["end"]),
+        Token::FOR => ("for", &["end"]),
+        Token::REPEAT => ("repeat", &["until"]),
+        _ => return None,
+    })
+}
+
+fn closes(token: &Token) -> Option<&'static str> {
+    Some(match token {
+        Token::RIGHT_PAREN => ")",
+        Token::RIGHT_BRACKET => "]",
+        Token::RIGHT_BRACE => "}",
+        Token::END => "end",
+        Token::UNTIL => "until",
+        _ => return None,
+    })
+}
+
+/// Matches the brackets of the tokens and the keywords that open and close blocks, returning
+/// the first thing that doesn't match, or None if everything does.
+///
+/// This only looks at the delimiters, not at what's between them, so it can say which block
+/// is left open when the parser only finds out at the end of the source.
+///
+/// ```
+/// use lua_compiler::balance::{self, Unbalanced};
+/// use lua_compiler::lexer::Lexer;
+///
+/// let tokens = |source| Lexer::new(source).tokenize().unwrap();
+/// assert_eq!(balance::check(&tokens("while x do f(t[1], {2}) end")), None);
+///
+/// let source = "function f()\n  if x then\n    for i = 1, 2 do\n  end\nend";
+/// let Some(Unbalanced::Unclosed(open)) = balance::check(&tokens(source)) else {
+///     panic!("the function isn't closed");
+/// };
+/// assert_eq!((open[0].spelling, open[0].span.line), ("function", 1));
+///
+/// let Some(Unbalanced::Mismatched { opener, closer }) = balance::check(&tokens("x = {(1}")) else {
+///     panic!("the parenthesis isn't closed");
+/// };
+/// assert_eq!((opener.unwrap().spelling, closer.spelling), ("(", "}"));
+/// ```
+pub fn check(tokens: &[SpannedToken]) -> Option<Unbalanced> {
+    // the openers with their closers, and whether a `while` or `for` is still before its `do`.
+    let mut open: Vec<(Delimiter, &'static [&'static str], bool)> = Vec::new();
+
+    for t in tokens {
+        if let Some(closer) = closes(&t.token) {
+            let closer = Delimiter {
+                spelling: closer,
+                span: t.span,
+            };
+            match open.pop() {
+                Some((_, closers, _)) if closers.contains(&closer.spelling) => {}
+                opener => {
+                    return Some(Unbalanced::Mismatched {
+                        opener: opener.map(|(opener, _, _)| opener),
+                        closer,
+                    })
+                }
+            }
+            continue;
+        }
+
+        // the `do` of a loop is part of it, the `end` closes both.
+        if t.token == Token::DO {
+            if let Some((_, _, before_do)) = open.last_mut().filter(|(_, _, b)| *b) {
+                *before_do = false;
+                continue;
+            }
+        }
+
+        if let Some((spelling, closers)) = opens(&t.token) {
+            let is_loop = matches!(t.token, Token::WHILE | Token::FOR);
+            let opener = Delimiter {
+                spelling,
+                span: t.span,
+            };
+            open.push((opener, closers, is_loop));
+        }
+    }
+
+    match open.is_empty() {
+        true => None,
+        false => Some(Unbalanced::Unclosed(
+            open.into_iter().map(|(opener, _, _)| opener).collect(),
+        )),
+    }
+}
+
+impl Unbalanced {
+    /// Describes the problem as a syntax error, at the innermost opener left open or at the
+    /// closer that doesn't match.
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self {
+            Unbalanced::Unclosed(open) => {
+                let innermost = &open[open.len() - 1];
+                let message = match open.len() {
+                    1 => format!(
+                        "unclosed '{}' starting at line {}.",
+                        innermost.spelling, innermost.span.line
+                    ),
+                    n => format!(
+                        "{n} unclosed blocks, the innermost is the '{}' starting at line {}.",
+                        innermost.spelling, innermost.span.line
+                    ),
+                };
+
+                let mut d = Diagnostic::error("E100", message, innermost.span);
+                for outer in open[..open.len() - 1].iter().rev() {
+                    d = d.with_label(outer.span, format!("`{}` opened here", outer.spelling));
+                }
+                d
+            }
+            Unbalanced::Mismatched { opener, closer } => match opener {
+                Some(opener) => Diagnostic::error(
+                    "E100",
+                    format!(
+                        "'{}' doesn't close the '{}' starting at line {}.",
+                        closer.spelling, opener.spelling, opener.span.line
+                    ),
+                    closer.span,
+                )
+                .with_label(opener.span, format!("`{}` opened here", opener.spelling)),
+                None => Diagnostic::error(
+                    "E100",
+                    format!("'{}' with nothing to close.", closer.spelling),
+                    closer.span,
+                ),
+            },
+        }
+    }
+}
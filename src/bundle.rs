@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+
+use lua_compiler::diagnostic::{
+    Diagnostic, DiagnosticSink, RenderingSink, Severity, SourceContext,
+};
+use lua_compiler::term_color::*;
+use lua_compiler::{codes, format_error, require, strings, suppress};
+
+use crate::{cli, emit_artifact, source, walk, ExitCode, Output};
+
+// stands in for the module table while the module runs, like the value reference Lua leaves
+// in `package.loaded`, so that a module requiring itself again fails the same way.
+const SHIM: &str = "\
+local __modules = {}
+local __loading = {}
+local function __require(name)
+  local loaded = package.loaded
+  local value = loaded[name]
+  if value == __loading then
+    error(\"loop or previous error loading module '\" .. tostring(name) .. \"'\", 2)
+  end
+  if value then
+    return value
+  end
+  local load = __modules[name]
+  if not load then
+    return require(name)
+  end
+  loaded[name] = __loading
+  local result = load(name)
+  if result ~= nil then
+    loaded[name] = result
+  end
+  if loaded[name] == __loading then
+    loaded[name] = true
+  end
+  return loaded[name]
+end
+local require = __require
+";
+
+/// A module of the bundle, by the name it's required by.
+struct Module {
+    name: String,
+    code: String,
+}
+
+/// Writes the file at `path` and every module it requires, directly or not, as one source.
+///
+/// Each module becomes a function that a `require` of its name runs the first time, through
+/// `package.loaded` like the real one, so modules still run in the order they're required and
+/// one that puts its table in `package.loaded` early can be required back while it runs.
+/// Requires of computed names, and of modules that aren't found, go to the real `require`.
+pub fn emit(options: &cli::Options, path: &Path, out: &mut Output) -> ExitCode {
+    // modules are looked up from the entry file's directory, like `check` does.
+    let base = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let name = |p: &Path| walk::relative_name(p, &base);
+
+    let mut modules: Vec<Module> = Vec::new();
+    let mut entry = None;
+    // the files to read, with the name they're required by, None for the entry file.
+    let mut queue: Vec<(PathBuf, Option<String>)> = vec![(path.to_path_buf(), None)];
+    let mut result = ExitCode::Success;
+
+    // read in the order they're first required, so that the bundle reads top down.
+    let mut next = 0;
+    while next < queue.len() {
+        let (file, required_as) = queue[next].clone();
+        next += 1;
+
+        let code = match source::read(&file) {
+            Ok(code) => code,
+            Err(e) => {
+                out.stderr.push_str(&format_error!("{e}.\n"));
+                result = result.max(ExitCode::UsageError);
+                continue;
+            }
+        };
+
+        let from = name(&file);
+        let (tokens, mut diagnostics) = lua_compiler::Compiler::from(options).tokenize(&code);
+        let failed = tokens.is_none();
+        let tokens = tokens.unwrap_or_default();
+
+        for r in require::find_requires(&tokens) {
+            if queue.iter().any(|(_, n)| n.as_ref() == Some(&r.name)) {
+                continue;
+            }
+            match require::resolve(&r.name, &base) {
+                Some(to) => queue.push((to, Some(r.name))),
+                None => {
+                    let searched = require::candidates(&r.name, &base)
+                        .iter()
+                        .map(|p| name(p))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    diagnostics.emit(
+                        Diagnostic::warning(
+                            "W200",
+                            format!("module '{}' not found", r.name),
+                            r.span,
+                        )
+                        .with_note(
+                            format!("looked for {searched}, it's left to `require`"),
+                            None,
+                        ),
+                    );
+                }
+            }
+        }
+
+        suppress::apply(&mut diagnostics, &code, &tokens);
+        codes::apply_levels(&mut diagnostics, &options.warnings);
+        let ctx = SourceContext::new(&from, &code);
+        let mut sink = RenderingSink::new(&ctx, options.diagnostics_format, output_caps());
+        sink.show_suppressed = options.show_suppressed;
+        for d in diagnostics {
+            sink.emit(d);
+        }
+        let (rendered, diagnostics) = sink.finish();
+        out.push_diagnostics(options.diagnostics_format, &rendered);
+        // a module with errors would only fail once the bundle runs.
+        if failed || diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            result = result.max(ExitCode::CompileError);
+        }
+
+        match required_as {
+            Some(name) => modules.push(Module { name, code }),
+            None => entry = Some(code),
+        }
+    }
+
+    let Some(entry) = entry else {
+        return result;
+    };
+    if result != ExitCode::Success {
+        return result;
+    }
+
+    let mut bundle = String::from(SHIM);
+    for module in &modules {
+        bundle.push_str(&format!(
+            "\n__modules[{}] = function(...)\n{}end\n",
+            strings::escape(&module.name, '"'),
+            body(&module.code)
+        ));
+    }
+    bundle.push_str(&format!(
+        "\nreturn (function(...)\n{}end)(...)\n",
+        body(&entry)
+    ));
+
+    emit_artifact(options.output.as_deref(), path, &bundle, out)
+}
+
+/// Returns the code of a file as the body of a function, ending with a line break so that a
+/// last line comment doesn't swallow the `end`.
+fn body(code: &str) -> String {
+    match code.ends_with('\n') {
+        true => code.to_string(),
+        false => format!("{code}\n"),
+    }
+}
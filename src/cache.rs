@@ -0,0 +1,354 @@
+use std::path::{Path, PathBuf};
+
+use lua_compiler::codes;
+use lua_compiler::diagnostic::{Diagnostic, Label, Note, Severity};
+use lua_compiler::json::{self, Value};
+use lua_compiler::lexer::Span;
+use lua_compiler::log_debug;
+use lua_compiler::require::Require;
+
+use crate::cli;
+
+// the entries of another version of the compiler may not mean the same, so they're ignored.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// where the cache goes when `--cache` is given without `--cache-dir`.
+const DEFAULT_DIR: &str = "target/lua-compiler";
+
+/// What checking a source found, everything that doesn't depend on other files.
+pub struct Entry {
+    pub diagnostics: Vec<Diagnostic>,
+    pub requires: Vec<Require>,
+}
+
+/// Returns the directory the cache is kept in, or None if it's not wanted.
+pub fn dir(options: &cli::Options) -> Option<PathBuf> {
+    match (&options.cache_dir, options.cache) {
+        (Some(dir), _) => Some(dir.clone()),
+        (None, true) => Some(PathBuf::from(DEFAULT_DIR)),
+        (None, false) => None,
+    }
+}
+
+/// Hashes the source along with the options that change what checking it finds.
+///
+/// This is FNV-1a rather than the standard library's hasher, whose output may change between
+/// Rust releases.
+pub fn key(source: &str, options: &cli::Options) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for &b in bytes {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        // keeps `ab` + `c` apart from `a` + `bc`.
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(0x100000001b3);
+    };
+
+    feed(VERSION.as_bytes());
+    feed(format!("{:?}", options.warnings).as_bytes());
+    feed(format!("{:?}", options.naming_style).as_bytes());
+    feed(source.as_bytes());
+    hash
+}
+
+fn path(dir: &Path, key: u64) -> PathBuf {
+    dir.join(format!("{key:016x}.json"))
+}
+
+/// Looks the entry up, treating anything that can't be read back, or that another version
+/// wrote, as missing.
+pub fn load(dir: &Path, key: u64) -> Option<Entry> {
+    let text = std::fs::read_to_string(path(dir, key)).ok()?;
+    let value = json::parse(&text)?;
+
+    if value.get("version")?.as_str()? != VERSION {
+        return None;
+    }
+
+    let diagnostics = value
+        .get("diagnostics")?
+        .as_array()?
+        .iter()
+        .map(diagnostic_from_json)
+        .collect::<Option<Vec<_>>>()?;
+
+    let requires = value
+        .get("requires")?
+        .as_array()?
+        .iter()
+        .map(|r| {
+            Some(Require {
+                name: r.get("name")?.as_str()?.to_string(),
+                span: span_from_json(r.get("span")?)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Entry {
+        diagnostics,
+        requires,
+    })
+}
+
+/// Writes the entry, a cache that can't be written to only makes the next run slower.
+pub fn store(dir: &Path, key: u64, entry: &Entry) {
+    let requires = entry
+        .requires
+        .iter()
+        .map(|r| {
+            Value::Object(vec![
+                ("name".to_string(), Value::String(r.name.clone())),
+                ("span".to_string(), span_to_json(r.span)),
+            ])
+        })
+        .collect();
+
+    let value = Value::Object(vec![
+        ("version".to_string(), Value::String(VERSION.to_string())),
+        (
+            "diagnostics".to_string(),
+            Value::Array(entry.diagnostics.iter().map(diagnostic_to_json).collect()),
+        ),
+        ("requires".to_string(), Value::Array(requires)),
+    ]);
+
+    // written next to the entry and moved over it, so a run that's cut short can't leave
+    // half an entry behind.
+    let path = path(dir, key);
+    let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+    let written = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&temporary, value.to_json()))
+        .and_then(|_| std::fs::rename(&temporary, &path));
+
+    if let Err(e) = written {
+        log_debug!("could not write to the cache at '{}': {e}.", dir.display());
+        let _ = std::fs::remove_file(&temporary);
+    }
+}
+
+fn span_to_json(span: Span) -> Value {
+    let number = |n: usize| Value::Number(n as f64);
+    Value::Array(vec![
+        number(span.line),
+        number(span.column),
+        number(span.start),
+        number(span.end),
+    ])
+}
+
+fn span_from_json(value: &Value) -> Option<Span> {
+    let numbers = value
+        .as_array()?
+        .iter()
+        .map(|n| match n {
+            Value::Number(n) if *n >= 0.0 => Some(*n as usize),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    match numbers[..] {
+        [line, column, start, end] => Some(Span {
+            line,
+            column,
+            start,
+            end,
+        }),
+        _ => None,
+    }
+}
+
+fn diagnostic_to_json(d: &Diagnostic) -> Value {
+    let labels = d
+        .labels
+        .iter()
+        .map(|l| {
+            Value::Object(vec![
+                ("span".to_string(), span_to_json(l.span)),
+                ("message".to_string(), Value::String(l.message.clone())),
+            ])
+        })
+        .collect();
+
+    let notes = d
+        .notes
+        .iter()
+        .map(|n| {
+            Value::Object(vec![
+                ("message".to_string(), Value::String(n.message.clone())),
+                ("span".to_string(), n.span.map_or(Value::Null, span_to_json)),
+            ])
+        })
+        .collect();
+
+    Value::Object(vec![
+        (
+            "severity".to_string(),
+            Value::String(d.severity.as_str().to_string()),
+        ),
+        ("code".to_string(), Value::String(d.code.to_string())),
+        ("message".to_string(), Value::String(d.message.clone())),
+        ("span".to_string(), span_to_json(d.span)),
+        ("labels".to_string(), Value::Array(labels)),
+        ("notes".to_string(), Value::Array(notes)),
+        (
+            "suggestion".to_string(),
+            d.suggestion.clone().map_or(Value::Null, Value::String),
+        ),
+    ])
+}
+
+fn diagnostic_from_json(value: &Value) -> Option<Diagnostic> {
+    let severity = match value.get("severity")?.as_str()? {
+        "error" => Severity::Error,
+        "warning" => Severity::Warning,
+        _ => return None,
+    };
+
+    // diagnostics hold their code as a static string, which the table of codes has.
+    let code = codes::lookup(value.get("code")?.as_str()?)?.code;
+
+    let labels = value
+        .get("labels")?
+        .as_array()?
+        .iter()
+        .map(|l| {
+            Some(Label {
+                span: span_from_json(l.get("span")?)?,
+                message: l.get("message")?.as_str()?.to_string(),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let notes = value
+        .get("notes")?
+        .as_array()?
+        .iter()
+        .map(|n| {
+            let span = match n.get("span")? {
+                Value::Null => None,
+                span => Some(span_from_json(span)?),
+            };
+            Some(Note {
+                message: n.get("message")?.as_str()?.to_string(),
+                span,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let suggestion = match value.get("suggestion")? {
+        Value::Null => None,
+        s => Some(s.as_str()?.to_string()),
+    };
+
+    Some(Diagnostic {
+        severity,
+        code,
+        message: value.get("message")?.as_str()?.to_string(),
+        span: span_from_json(value.get("span")?)?,
+        labels,
+        notes,
+        suggestion,
+        // what the cache holds is from before the suppression comments are applied.
+        suppressed: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own for every test, the tests run at the same time.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lua-compiler-cache-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn span(line: usize, start: usize) -> Span {
+        Span {
+            line,
+            column: 3,
+            start,
+            end: start + 4,
+        }
+    }
+
+    fn entry() -> Entry {
+        let mut warning =
+            Diagnostic::warning("W200", "module 'a' not found".to_string(), span(1, 0))
+                .with_label(span(2, 10), "here \"too\"".to_string())
+                .with_note("looked for a.lua".to_string(), None)
+                .with_note("and here".to_string(), Some(span(3, 20)));
+        warning.suggestion = Some("write it 'b'".to_string());
+
+        Entry {
+            diagnostics: vec![
+                warning,
+                Diagnostic::error("E100", "expected 'end'".to_string(), span(4, 30)),
+            ],
+            requires: vec![Require {
+                name: "a".to_string(),
+                span: span(1, 0),
+            }],
+        }
+    }
+
+    #[test]
+    fn an_entry_reads_back_the_same() {
+        let dir = temp_dir("round-trip");
+        let written = entry();
+        store(&dir, 1, &written);
+
+        let read = load(&dir, 1).unwrap();
+        assert_eq!(read.diagnostics, written.diagnostics);
+        assert_eq!(read.requires, written.requires);
+        assert!(load(&dir, 2).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_corrupt_or_foreign_entry_is_missing() {
+        let dir = temp_dir("corrupt");
+        store(&dir, 1, &entry());
+        let path = path(&dir, 1);
+        let text = std::fs::read_to_string(&path).unwrap();
+
+        let cases = [
+            text[..text.len() / 2].to_string(),
+            text.replace(VERSION, "0.0.0-other"),
+            text.replace("W200", "W999"),
+            String::new(),
+        ];
+        for case in cases {
+            std::fs::write(&path, &case).unwrap();
+            assert!(load(&dir, 1).is_none(), "{case}");
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_key_changes_with_the_source_and_the_options_that_matter() {
+        let options = cli::Options::default();
+        let base = key("local x = 1", &options);
+        assert_eq!(key("local x = 1", &options), base);
+        assert_ne!(key("local x = 2", &options), base);
+
+        let warnings = cli::Options {
+            warnings: vec![("W200", codes::Level::Allow)],
+            ..cli::Options::default()
+        };
+        assert_ne!(key("local x = 1", &warnings), base);
+
+        // how the diagnostics are shown doesn't change what's found.
+        let jobs = cli::Options {
+            jobs: Some(4),
+            ..cli::Options::default()
+        };
+        assert_eq!(key("local x = 1", &jobs), base);
+    }
+}
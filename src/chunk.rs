@@ -0,0 +1,179 @@
+// how long a chunk id can be in the reference, LUA_IDSIZE, counting its terminating NUL.
+const ID_SIZE: usize = 60;
+
+/// What a chunk is called, the way reference Lua names the sources it loads.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ChunkName {
+    // a file, `@path` to Lua.
+    File(String),
+    // a name shown as it is, `=name` to Lua, like the `=stdin` of a source read from stdin.
+    Literal(String),
+    // a chunk loaded from a string, which is named after its own code.
+    Source(String),
+}
+
+impl ChunkName {
+    /// The name of a source read from stdin.
+    pub fn stdin() -> Self {
+        ChunkName::Literal("stdin".to_string())
+    }
+
+    /// Reads a name the way `load` takes it, `@` starts a file and `=` a literal name, anything
+    /// else is the code of the chunk.
+    pub fn parse(name: &str) -> Self {
+        if let Some(path) = name.strip_prefix('@') {
+            ChunkName::File(path.to_string())
+        } else if let Some(literal) = name.strip_prefix('=') {
+            ChunkName::Literal(literal.to_string())
+        } else {
+            ChunkName::Source(name.to_string())
+        }
+    }
+
+    /// Returns the name with its prefix, what `debug.getinfo` calls the source.
+    pub fn source(&self) -> String {
+        match self {
+            ChunkName::File(path) => format!("@{path}"),
+            ChunkName::Literal(name) => format!("={name}"),
+            ChunkName::Source(code) => code.clone(),
+        }
+    }
+
+    /// Returns the name the way reference Lua prefixes errors and tracebacks with it.
+    ///
+    /// It's cut to fit in 60 bytes, a file keeps the end of its path after `...`, a literal
+    /// keeps its start, and a source shows its first line as `[string "..."]`.
+    pub fn id(&self) -> String {
+        // the prefix counts towards the length in the reference.
+        let room = ID_SIZE - 1;
+        match self {
+            ChunkName::Literal(name) if name.len() <= room => name.clone(),
+            ChunkName::Literal(name) => name[..floor_boundary(name, room)].to_string(),
+            ChunkName::File(path) if path.len() <= room => path.clone(),
+            ChunkName::File(path) => {
+                let keep = room - "...".len();
+                format!("...{}", &path[ceil_boundary(path, path.len() - keep)..])
+            }
+            ChunkName::Source(code) => {
+                // what's left once `[string "`, `..."]` and the NUL are in.
+                let room = ID_SIZE - r#"[string ""]"#.len() - "...".len() - 1;
+                match code.find('\n') {
+                    None if code.len() < room => format!(r#"[string "{code}"]"#),
+                    line_end => {
+                        let line = &code[..line_end.unwrap_or(code.len())];
+                        let line = &line[..floor_boundary(line, room.min(line.len()))];
+                        format!(r#"[string "{line}..."]"#)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Shows what the diagnostics call the chunk. Unlike [`ChunkName::id`], a file keeps its whole
+/// path so an editor can still open it.
+impl std::fmt::Display for ChunkName {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChunkName::File(path) => write!(f, "{path}"),
+            ChunkName::Literal(name) => write!(f, "{name}"),
+            ChunkName::Source(_) => write!(f, "{}", self.id()),
+        }
+    }
+}
+
+impl From<&std::path::Path> for ChunkName {
+    fn from(path: &std::path::Path) -> Self {
+        ChunkName::File(path.display().to_string())
+    }
+}
+
+// the reference cuts on bytes, these move the cut off the middle of a character.
+fn floor_boundary(text: &str, mut at: usize) -> usize {
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    at
+}
+
+fn ceil_boundary(text: &str, mut at: usize) -> usize {
+    while !text.is_char_boundary(at) {
+        at += 1;
+    }
+    at
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_read_back_with_their_prefix() {
+        for name in ["@src/main.lua", "=stdin", "return 1"] {
+            assert_eq!(ChunkName::parse(name).source(), name);
+        }
+        assert_eq!(ChunkName::stdin(), ChunkName::parse("=stdin"));
+        assert_eq!(
+            ChunkName::from(std::path::Path::new("lib/a.lua")),
+            ChunkName::File("lib/a.lua".to_string())
+        );
+    }
+
+    #[test]
+    fn short_names_are_shown_as_they_are() {
+        assert_eq!(ChunkName::parse("@script.lua").id(), "script.lua");
+        assert_eq!(ChunkName::stdin().id(), "stdin");
+        assert_eq!(ChunkName::parse("x = 1").id(), r#"[string "x = 1"]"#);
+        // what the diagnostics show keeps the whole path.
+        assert_eq!(ChunkName::stdin().to_string(), "stdin");
+        assert_eq!(ChunkName::parse("x = 1").to_string(), r#"[string "x = 1"]"#);
+    }
+
+    #[test]
+    fn long_names_are_cut_like_the_reference_cuts_them() {
+        // a path of 59 bytes fits, one more keeps the last 56 after `...`.
+        let fits = format!("{}.lua", "d/".repeat(27) + "a");
+        assert_eq!(fits.len(), 59);
+        assert_eq!(ChunkName::File(fits.clone()).id(), fits);
+        let long = format!("x{fits}");
+        let id = ChunkName::File(long.clone()).id();
+        assert_eq!(id, format!("...{}", &long[long.len() - 56..]));
+        assert_eq!(id.len(), 59);
+        assert_eq!(ChunkName::File(long.clone()).to_string(), long);
+
+        // a literal keeps its first 59 bytes.
+        let literal = "n".repeat(70);
+        assert_eq!(ChunkName::Literal(literal).id(), "n".repeat(59));
+
+        // a source keeps at most 44 bytes of its first line.
+        let code = "c".repeat(44);
+        assert_eq!(
+            ChunkName::Source(code.clone()).id(),
+            format!(r#"[string "{code}"]"#)
+        );
+        let code = "c".repeat(45);
+        assert_eq!(
+            ChunkName::Source(code.clone()).id(),
+            format!(r#"[string "{code}..."]"#)
+        );
+        assert_eq!(
+            ChunkName::Source("c".repeat(80)).id(),
+            format!(r#"[string "{}..."]"#, "c".repeat(45))
+        );
+        assert_eq!(
+            ChunkName::Source("local x = 1\nreturn x".to_string()).id(),
+            r#"[string "local x = 1..."]"#
+        );
+    }
+
+    #[test]
+    fn cuts_stay_off_the_middle_of_a_character() {
+        let path = "é".repeat(40);
+        let id = ChunkName::File(path).id();
+        assert!(id.starts_with("...é"));
+        assert_eq!(id.len(), 3 + 56);
+
+        let literal = "é".repeat(40);
+        assert_eq!(ChunkName::Literal(literal).id(), "é".repeat(29));
+    }
+}
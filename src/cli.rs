@@ -0,0 +1,800 @@
+use std::path::PathBuf;
+
+use lua_compiler::codes::Level;
+use lua_compiler::diagnostic::DiagnosticsFormat;
+use lua_compiler::naming::NamingStyle;
+use lua_compiler::term_color::ColorChoice;
+
+/// The format used by the token dump and the dependency graph, which is DOT as text.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+/// The artifact a compilation produces.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Emit {
+    // no --emit, the source is only compiled unless -o asks for the syntax tree.
+    #[default]
+    Unspecified,
+    // the syntax tree, in its debug form.
+    Ast,
+    // the source as a page with its tokens highlighted.
+    Html,
+    // a tags file of the functions and top level variables, for editors to jump to.
+    Tags,
+    // the syntax tree as a Graphviz digraph.
+    Dot,
+    // the graph of which module requires which, across every input.
+    Deps,
+    // a hash of the syntax tree, the same for sources that only differ in formatting.
+    Hash,
+    // every string literal with where it is, for translating or auditing them.
+    Strings,
+    // counts of the lines and tokens of every input.
+    Loc,
+    // the ranges of lines an editor can fold, as JSON.
+    Folds,
+    // the file and every module it requires as one source.
+    Bundle,
+}
+
+/// Everything the command line asked for, consumed by the pipeline in `main.rs`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Options {
+    // `-` means the source is read from stdin.
+    pub input: Option<PathBuf>,
+    // only report diagnostics, without producing an artifact.
+    pub check: bool,
+    // run a language server on stdin and stdout instead of compiling.
+    pub lsp: bool,
+    // compare the statements of `input` with those of `new_input` instead of compiling.
+    pub diff: bool,
+    pub new_input: Option<PathBuf>,
+    // compare the output of every file of `input` with its `.expected` file instead of compiling.
+    pub test: bool,
+    // rewrite the `.expected` files with what the files print now.
+    pub bless: bool,
+    // rename the variable at the 1-based line and column of `input` to `new_name`.
+    pub rename: bool,
+    pub rename_at: Option<(usize, usize)>,
+    pub new_name: Option<String>,
+    // print what would change as a patch instead of changing the file.
+    pub dry_run: bool,
+    // skip checking the sources that haven't changed since a previous check, see `cache.rs`.
+    pub cache: bool,
+    pub cache_dir: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    // the name diagnostics use for a source read from stdin, it is never opened.
+    pub stdin_filename: Option<PathBuf>,
+    // globs selecting the files to compile when the input is a directory, `*.lua` if empty.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // the files of a directory that are run on their own, the rest should be required by one.
+    pub entries: Vec<PathBuf>,
+    // how many files are compiled at once, the number of cores if not given.
+    pub jobs: Option<usize>,
+    pub dump_tokens: bool,
+    pub format: Format,
+    pub emit: Emit,
+    // a stylesheet the html page links to instead of embedding its own.
+    pub html_css: Option<String>,
+    // write the tags in the format of Emacs instead of ctags.
+    pub etags: bool,
+    // leave the names of required modules and the string keys of tables out of --emit=strings.
+    pub skip_require_strings: bool,
+    pub skip_key_strings: bool,
+    // how deep the dot graph goes before the rest of a subtree is left out.
+    pub dot_max_depth: Option<usize>,
+    pub diagnostics_format: DiagnosticsFormat,
+    pub color: ColorChoice,
+    pub quiet: bool,
+    // how many times -v was given.
+    pub verbose: u8,
+    pub no_banner: bool,
+    pub watch: bool,
+    pub warnings_as_errors: bool,
+    // a problem reported more often than this is shown once, with the rest as notes.
+    pub group_repeated: Option<usize>,
+    // print the diagnostics a suppression comment silenced, marked as such.
+    pub show_suppressed: bool,
+    // the `-W` flags in the order they were given, later ones win.
+    pub warnings: Vec<(&'static str, Level)>,
+    // the style declared names should follow, they aren't checked if None.
+    pub naming_style: Option<NamingStyle>,
+    // the code `--explain` was asked about.
+    pub explain: Option<String>,
+    // the expression `--explain-parse` shows the grouping of.
+    pub explain_parse: Option<String>,
+    // the expression `--explain-ast` shows the syntax tree and the grouping of.
+    pub explain_ast: Option<String>,
+    // the stage `--debug-panic` makes the compiler panic in, to try out the crash report.
+    pub debug_panic: Option<&'static str>,
+    pub help: bool,
+    pub version: bool,
+}
+
+/// A flag the compiler understands, used both for parsing and for the help text.
+struct Flag {
+    long: &'static str,
+    short: Option<char>,
+    // the name of the value shown in the help text, flags without one are plain switches.
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+// flags for working on the compiler itself, left out of the help text.
+const HIDDEN_FLAGS: &[Flag] = &[Flag {
+    long: "debug-panic",
+    short: None,
+    value: Some("lex|parse|check"),
+    help: "panic in a stage of the compiler, to see how a crash is reported",
+}];
+
+const FLAGS: &[Flag] = &[
+    Flag {
+        long: "check",
+        short: None,
+        value: None,
+        help: "only check the source for errors, same as the `check` command",
+    },
+    Flag {
+        long: "output",
+        short: Some('o'),
+        value: Some("path"),
+        help: "write the artifact to <path> instead of stdout",
+    },
+    Flag {
+        long: "stdin-filename",
+        short: None,
+        value: Some("path"),
+        help: "report diagnostics for stdin as coming from <path>, without reading it",
+    },
+    Flag {
+        long: "include",
+        short: None,
+        value: Some("glob"),
+        help: "compile the files matching <glob> in a directory, *.lua by default",
+    },
+    Flag {
+        long: "exclude",
+        short: None,
+        value: Some("glob"),
+        help: "skip the files and directories matching <glob> in a directory",
+    },
+    Flag {
+        long: "entry",
+        short: None,
+        value: Some("file"),
+        help: "a file of a directory that's run on its own, can be repeated",
+    },
+    Flag {
+        long: "jobs",
+        short: Some('j'),
+        value: Some("n"),
+        help: "compile up to <n> files of a directory at once, one per core by default",
+    },
+    Flag {
+        long: "dump-tokens",
+        short: None,
+        value: None,
+        help: "print the token stream as `line:col KIND \"lexeme\"` and stop",
+    },
+    Flag {
+        long: "format",
+        short: None,
+        value: Some("text|json"),
+        help: "the format of the token dump, --emit=deps, --emit=strings and --emit=loc",
+    },
+    Flag {
+        long: "emit",
+        short: None,
+        value: Some("ast|html|tags|dot|deps|hash|strings|loc|folds|bundle"),
+        help: "the artifact to produce, the syntax tree if only -o is given",
+    },
+    Flag {
+        long: "html-css",
+        short: None,
+        value: Some("href"),
+        help: "link the stylesheet at <href> from --emit=html instead of embedding one",
+    },
+    Flag {
+        long: "etags",
+        short: None,
+        value: None,
+        help: "write --emit=tags in the Emacs format instead of the ctags one",
+    },
+    Flag {
+        long: "skip-strings",
+        short: None,
+        value: Some("require|keys"),
+        help: "leave module names or table keys out of --emit=strings, can be repeated",
+    },
+    Flag {
+        long: "bless",
+        short: None,
+        value: None,
+        help: "make `test` rewrite the .expected files instead of comparing with them",
+    },
+    Flag {
+        long: "dry-run",
+        short: None,
+        value: None,
+        help: "make `rename` print a patch instead of rewriting the file",
+    },
+    Flag {
+        long: "cache",
+        short: None,
+        value: None,
+        help: "with check, skip the files that haven't changed since the last check",
+    },
+    Flag {
+        long: "cache-dir",
+        short: None,
+        value: Some("dir"),
+        help: "keep the --cache in <dir> instead of target/lua-compiler, implies --cache",
+    },
+    Flag {
+        long: "dot-max-depth",
+        short: None,
+        value: Some("n"),
+        help: "leave out what's deeper than <n> levels in --emit=dot",
+    },
+    Flag {
+        long: "diagnostics-format",
+        short: None,
+        value: Some("human|json|github|short"),
+        help: "how diagnostics are printed, see the json schema below",
+    },
+    Flag {
+        long: "color",
+        short: None,
+        value: Some("auto|always|never"),
+        help: "when to use colored output, auto respects NO_COLOR and TERM=dumb",
+    },
+    Flag {
+        long: "watch",
+        short: Some('w'),
+        value: None,
+        help: "recompile whenever the file changes",
+    },
+    Flag {
+        long: "warnings-as-errors",
+        short: None,
+        value: None,
+        help: "treat warnings as errors in the summary and exit code",
+    },
+    Flag {
+        long: "verbose",
+        short: Some('v'),
+        value: None,
+        help: "print what each stage does to stderr, -vv for more detail",
+    },
+    Flag {
+        long: "group-repeated",
+        short: None,
+        value: Some("n"),
+        help: "show a diagnostic repeated more than <n> times once, with the rest as notes",
+    },
+    Flag {
+        long: "show-suppressed",
+        short: None,
+        value: None,
+        help: "print the diagnostics silenced by a --luacompiler: comment, marked as such",
+    },
+    Flag {
+        long: "warning",
+        short: Some('W'),
+        value: Some("[no-|error=]name"),
+        help: "enable, disable (no-) or deny (error=) a warning by its name or code",
+    },
+    Flag {
+        long: "naming-style",
+        short: None,
+        value: Some("snake_case|camelCase|off"),
+        help: "warn about declared names that don't follow the style, off by default",
+    },
+    Flag {
+        long: "explain",
+        short: None,
+        value: Some("code"),
+        help: "describe a diagnostic in more detail, with examples",
+    },
+    Flag {
+        long: "explain-parse",
+        short: None,
+        value: Some("expr"),
+        help: "print an expression with parentheses around every operation, and its value if it's constant",
+    },
+    Flag {
+        long: "explain-ast",
+        short: None,
+        value: Some("expr"),
+        help: "print the syntax tree of an expression, then what --explain-parse prints",
+    },
+    Flag {
+        long: "quiet",
+        short: Some('q'),
+        value: None,
+        help: "only print diagnostics",
+    },
+    Flag {
+        long: "no-banner",
+        short: None,
+        value: None,
+        help: "leave the banner out of --version",
+    },
+    Flag {
+        long: "version",
+        short: Some('V'),
+        value: None,
+        help: "print the version and exit",
+    },
+    Flag {
+        long: "help",
+        short: Some('h'),
+        value: None,
+        help: "print this help and exit",
+    },
+];
+
+/// Builds the `--help` text from the flag table.
+pub fn help() -> String {
+    let mut out = String::from(
+        "usage: lua-compiler [options] <file>\n       \
+         lua-compiler check [options] <file>\n       \
+         lua-compiler lsp [options]\n       \
+         lua-compiler diff [options] <old> <new>\n       \
+         lua-compiler test [options] <dir>\n       \
+         lua-compiler rename [options] <file> <line>:<column> <new-name>\n\n\
+         <file> can be `-` to read the source from stdin, or a directory to compile every\n\
+         file in it. `lsp` runs a language server over stdin and stdout. `diff` lists the\n\
+         statements that differ between two files, ignoring formatting and comments.\n\
+         --emit=deps writes one graph of the requires of the file or of the whole directory,\n\
+         cycles fail with exit code 1 and missing modules are warned about. `test` compares\n\
+         what every file of <dir> prints, the syntax tree or with --dump-tokens the tokens,\n\
+         then the diagnostics, with the .expected file next to it. --emit=hash prints a\n\
+         hash of the syntax tree that ignores comments and formatting. --emit=strings lists\n\
+         every string literal, as TSV or with --format=json as JSON. --emit=loc counts the\n\
+         blank, comment and code lines and the tokens of the file or of every file in the\n\
+         directory. `rename` renames the variable at the position and every use of it,\n\
+         leaving alone other variables of the same name. A directory given --entry also\n\
+         gets warnings about the files nothing requires and the fields of modules nothing\n\
+         reads. --emit=bundle writes the file and every module it requires as one source,\n\
+         where `require` runs the bundled modules.\n\n\
+         settings are also read from the nearest luacompiler.toml above the input, which\n\
+         can set include, exclude, jobs, warnings-as-errors and a [warnings] table of\n\
+         name = \"allow\" | \"warn\" | \"error\". flags override it.\n\n\
+         flags go before the files, on either side of the command. `--` ends them, so that a\n\
+         file can start with a dash: `lua-compiler check -- -file.lua`.\n\n\
+         options:\n",
+    );
+
+    for flag in FLAGS {
+        let mut name = match flag.short {
+            Some(c) => format!("-{c}, --{}", flag.long),
+            None => format!("    --{}", flag.long),
+        };
+
+        if let Some(value) = flag.value {
+            name.push_str(&format!(" <{value}>"));
+        }
+
+        out.push_str(&format!("  {name:<32} {}\n", flag.help));
+    }
+
+    out.push_str(
+        "\njson diagnostics (--diagnostics-format=json), one object per line on stdout:\n  \
+         {\"file\": string, \"severity\": \"error\" | \"warning\", \"code\": string,\n   \
+         \"message\": string, \"start\": {\"line\": number, \"column\": number},\n   \
+         \"end\": {\"line\": number, \"column\": number},\n   \
+         \"related\": [{\"message\": string, \"start\": position, \"end\": position}],\n   \
+         \"notes\": [{\"message\": string, \"start\": position | null, \"end\": position | null}],\n   \
+         \"suggestion\": string | null}\n  \
+         lines and columns start at 1, the end is exclusive.\n",
+    );
+
+    out.push_str(
+        "\nexit codes:\n  \
+         0  success\n  \
+         1  the source has errors, the files differ for diff or a test failed\n  \
+         2  usage error, e.g. a bad flag or a missing file\n  \
+         3  internal compiler error\n",
+    );
+
+    out
+}
+
+/// Parses the command line arguments (without the program name) into `Options`.
+///
+/// The flags come first, then the command if any, then the files. Everything from the first
+/// file on is positional, a flag there is a usage error, and `--` forces the boundary so that
+/// a file can start with a dash.
+///
+/// The error is a message describing the usage error.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    // whether the command can still be given, whether a file was, and whether `--` was.
+    let (mut command_allowed, mut after_files, mut after_dashes) = (true, false, false);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" && !after_dashes {
+            after_dashes = true;
+            continue;
+        }
+        let is_flag = !after_dashes && arg.starts_with('-') && arg != "-";
+        if is_flag && after_files {
+            let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+            return Err(format!(
+                "'{name}' comes after the file, flags have to come before it"
+            ));
+        }
+
+        // the command comes before the files, after any flags.
+        if !is_flag && !after_dashes && std::mem::take(&mut command_allowed) {
+            let command = match arg.as_str() {
+                "check" => Some(&mut options.check),
+                "lsp" => Some(&mut options.lsp),
+                "diff" => Some(&mut options.diff),
+                "test" => Some(&mut options.test),
+                "rename" => Some(&mut options.rename),
+                _ => None,
+            };
+            if let Some(command) = command {
+                *command = true;
+                continue;
+            }
+        }
+
+        // anything that isn't a flag is the input file.
+        if !is_flag {
+            (command_allowed, after_files) = (false, true);
+            // the diff compares two files.
+            if options.diff && options.input.is_some() && options.new_input.is_none() {
+                options.new_input = Some(arg.into());
+                continue;
+            }
+            // the rename takes the file, where the variable is and its new name.
+            if options.rename && options.input.is_some() && options.rename_at.is_none() {
+                let position = arg
+                    .split_once(':')
+                    .and_then(|(line, column)| Some((line.parse().ok()?, column.parse().ok()?)))
+                    .filter(|&(line, column)| line > 0 && column > 0);
+                options.rename_at = Some(position.ok_or_else(|| {
+                    format!("invalid position '{arg}', expected <line>:<column>")
+                })?);
+                continue;
+            }
+            if options.rename && options.rename_at.is_some() && options.new_name.is_none() {
+                options.new_name = Some(arg);
+                continue;
+            }
+            if options.input.is_some() {
+                return Err(format!(
+                    "unexpected argument '{arg}', only one file can be given"
+                ));
+            }
+            options.input = Some(arg.into());
+            continue;
+        }
+
+        // `-vv` is the only short flag that can be stacked.
+        if arg.len() > 2 && arg[1..].chars().all(|c| c == 'v') {
+            let count = u8::try_from(arg.len() - 1).unwrap_or(u8::MAX);
+            options.verbose = options.verbose.saturating_add(count);
+            continue;
+        }
+
+        // split `--name=value` into its two halves.
+        let (name, mut value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+
+        let flag = FLAGS
+            .iter()
+            .chain(HIDDEN_FLAGS)
+            .find(|f| match name.strip_prefix("--") {
+                Some(long) => f.long == long,
+                None => name.len() == 2 && f.short == name.chars().nth(1),
+            })
+            .ok_or_else(|| format!("unknown flag '{name}'"))?;
+
+        if flag.value.is_some() && value.is_none() {
+            value = Some(
+                args.next()
+                    .ok_or_else(|| format!("'{name}' expects a value"))?,
+            );
+        } else if flag.value.is_none() && value.is_some() {
+            return Err(format!("'{name}' doesn't take a value"));
+        }
+
+        let value = value.unwrap_or_default();
+
+        match flag.long {
+            "check" => options.check = true,
+            "output" => options.output = Some(value.into()),
+            "stdin-filename" => options.stdin_filename = Some(value.into()),
+            "include" => options.include.push(value),
+            "exclude" => options.exclude.push(value),
+            "entry" => options.entries.push(PathBuf::from(value)),
+            "jobs" => {
+                let jobs = value.parse().ok().filter(|&n| n > 0);
+                options.jobs = Some(jobs.ok_or_else(|| {
+                    format!("invalid number of jobs '{value}', expected a positive number")
+                })?)
+            }
+            "dump-tokens" => options.dump_tokens = true,
+            "format" => {
+                options.format = match value.as_str() {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    _ => return Err(format!("invalid format '{value}', expected text or json")),
+                }
+            }
+            "emit" => {
+                options.emit = match value.as_str() {
+                    "ast" => Emit::Ast,
+                    "html" => Emit::Html,
+                    "tags" => Emit::Tags,
+                    "dot" => Emit::Dot,
+                    "deps" => Emit::Deps,
+                    "hash" => Emit::Hash,
+                    "strings" => Emit::Strings,
+                    "loc" => Emit::Loc,
+                    "folds" => Emit::Folds,
+                    "bundle" => Emit::Bundle,
+                    _ => {
+                        return Err(format!(
+                        "invalid artifact '{value}', expected ast, html, tags, dot, deps, hash, strings, loc, folds or bundle"
+                    ))
+                    }
+                }
+            }
+            "html-css" => options.html_css = Some(value),
+            "etags" => options.etags = true,
+            "skip-strings" => match value.as_str() {
+                "require" => options.skip_require_strings = true,
+                "keys" => options.skip_key_strings = true,
+                _ => {
+                    return Err(format!(
+                        "invalid kind of string '{value}', expected require or keys"
+                    ))
+                }
+            },
+            "bless" => options.bless = true,
+            "dry-run" => options.dry_run = true,
+            "cache" => options.cache = true,
+            "cache-dir" => options.cache_dir = Some(value.into()),
+            "dot-max-depth" => {
+                options.dot_max_depth = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid depth '{value}', expected a number"))?,
+                )
+            }
+            "diagnostics-format" => {
+                options.diagnostics_format = match value.as_str() {
+                    "human" => DiagnosticsFormat::Human,
+                    "json" => DiagnosticsFormat::Json,
+                    "github" => DiagnosticsFormat::Github,
+                    "short" => DiagnosticsFormat::Short,
+                    _ => {
+                        return Err(format!(
+                            "invalid diagnostics format '{value}', expected human, json, github or short"
+                        ))
+                    }
+                }
+            }
+            "color" => {
+                options.color = ColorChoice::parse(&value).ok_or_else(|| {
+                    format!("invalid color choice '{value}', expected auto, always or never")
+                })?
+            }
+            "quiet" => options.quiet = true,
+            "verbose" => options.verbose = options.verbose.saturating_add(1),
+            "no-banner" => options.no_banner = true,
+            "watch" => options.watch = true,
+            "warnings-as-errors" => options.warnings_as_errors = true,
+            "group-repeated" => {
+                options.group_repeated = Some(value.parse().map_err(|_| {
+                    format!("invalid number of repeats '{value}', expected a number")
+                })?)
+            }
+            "show-suppressed" => options.show_suppressed = true,
+            "warning" => options.warnings.push(Level::parse_flag(&value)?),
+            "naming-style" => {
+                options.naming_style = match value.as_str() {
+                    "snake_case" => Some(NamingStyle::SnakeCase),
+                    "camelCase" => Some(NamingStyle::CamelCase),
+                    "off" => None,
+                    _ => {
+                        return Err(format!(
+                            "invalid naming style '{value}', expected snake_case, camelCase or off"
+                        ))
+                    }
+                }
+            }
+            "explain" => options.explain = Some(value),
+            "explain-parse" => options.explain_parse = Some(value),
+            "explain-ast" => options.explain_ast = Some(value),
+            "debug-panic" => {
+                options.debug_panic = match value.as_str() {
+                    "lex" => Some("lex"),
+                    "parse" => Some("parse"),
+                    "check" => Some("check"),
+                    _ => {
+                        return Err(format!(
+                            "invalid stage '{value}', expected lex, parse or check"
+                        ))
+                    }
+                }
+            }
+            "version" => options.version = true,
+            "help" => options.help = true,
+            _ => unreachable!("every flag in the table is handled"),
+        }
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Options, String> {
+        parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn verbosity_adds_up() {
+        assert_eq!(parse_args(&["-v"]).unwrap().verbose, 1);
+        assert_eq!(parse_args(&["-vv", "--verbose"]).unwrap().verbose, 3);
+
+        // more than a u8 holds stops at the highest level instead of wrapping around.
+        let many = format!("-{}", "v".repeat(300));
+        assert_eq!(parse_args(&[&many]).unwrap().verbose, u8::MAX);
+        let exactly = format!("-{}", "v".repeat(256));
+        assert_eq!(parse_args(&[&exactly, "-v"]).unwrap().verbose, u8::MAX);
+    }
+
+    #[test]
+    fn command_lines_give_their_options() {
+        assert_eq!(
+            parse_args(&["main.lua"]).unwrap(),
+            Options {
+                input: Some("main.lua".into()),
+                ..Options::default()
+            }
+        );
+        assert_eq!(
+            parse_args(&[
+                "check",
+                "-q",
+                "--color=never",
+                "-W",
+                "error=unused-suppression",
+                "src"
+            ])
+            .unwrap(),
+            Options {
+                input: Some("src".into()),
+                check: true,
+                quiet: true,
+                color: ColorChoice::Never,
+                warnings: vec![("W001", Level::Deny)],
+                ..Options::default()
+            }
+        );
+        assert_eq!(
+            parse_args(&[
+                "--emit",
+                "html",
+                "-o",
+                "out.html",
+                "--diagnostics-format=json",
+                "-"
+            ])
+            .unwrap(),
+            Options {
+                input: Some("-".into()),
+                emit: Emit::Html,
+                output: Some("out.html".into()),
+                diagnostics_format: DiagnosticsFormat::Json,
+                ..Options::default()
+            }
+        );
+        assert_eq!(
+            parse_args(&[
+                "-j",
+                "4",
+                "--include",
+                "*.lua",
+                "--exclude=vendor/*",
+                "--",
+                "-dir"
+            ])
+            .unwrap(),
+            Options {
+                input: Some("-dir".into()),
+                jobs: Some(4),
+                include: vec!["*.lua".to_string()],
+                exclude: vec!["vendor/*".to_string()],
+                ..Options::default()
+            }
+        );
+        assert_eq!(
+            parse_args(&["rename", "main.lua", "3:7", "total"]).unwrap(),
+            Options {
+                input: Some("main.lua".into()),
+                rename: true,
+                rename_at: Some((3, 7)),
+                new_name: Some("total".to_string()),
+                ..Options::default()
+            }
+        );
+        assert!(parse_args(&["--help"]).unwrap().help);
+        assert!(parse_args(&["-V"]).unwrap().version);
+    }
+
+    #[test]
+    fn usage_errors() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--frobnicate"], "unknown flag '--frobnicate'"),
+            (&["-x"], "unknown flag '-x'"),
+            (&["--output"], "'--output' expects a value"),
+            (&["--check=yes"], "'--check' doesn't take a value"),
+            (
+                &["--jobs=0"],
+                "invalid number of jobs '0', expected a positive number",
+            ),
+            (
+                &["--color=blue"],
+                "invalid color choice 'blue', expected auto, always or never",
+            ),
+            (
+                &["a.lua", "b.lua"],
+                "unexpected argument 'b.lua', only one file can be given",
+            ),
+            (
+                &["a.lua", "-q"],
+                "'-q' comes after the file, flags have to come before it",
+            ),
+            (
+                &["rename", "a.lua", "3", "x"],
+                "invalid position '3', expected <line>:<column>",
+            ),
+            (
+                &["--naming-style=kebab-case"],
+                "invalid naming style 'kebab-case', expected snake_case, camelCase or off",
+            ),
+        ];
+        for (args, message) in cases {
+            assert_eq!(parse_args(args), Err(message.to_string()), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn the_naming_style_can_be_chosen_or_turned_off() {
+        let style = |args: &[&str]| parse_args(args).unwrap().naming_style;
+        assert_eq!(style(&[]), None);
+        assert_eq!(
+            style(&["--naming-style=snake_case"]),
+            Some(NamingStyle::SnakeCase)
+        );
+        assert_eq!(
+            style(&["--naming-style", "camelCase"]),
+            Some(NamingStyle::CamelCase)
+        );
+        // the last one wins, so `off` undoes what a config or an earlier flag chose.
+        assert_eq!(
+            style(&["--naming-style=camelCase", "--naming-style=off"]),
+            None
+        );
+    }
+}
@@ -0,0 +1,500 @@
+use crate::diagnostic::{Diagnostic, Severity};
+
+/// What is known about a kind of diagnostic, shared by the stages that report it and the CLI.
+pub struct Code {
+    pub code: &'static str,
+    // the name used with `-W`, e.g. `-W no-module-not-found`.
+    pub name: &'static str,
+    pub severity: Severity,
+    // the longer description printed by `--explain`.
+    pub explanation: &'static str,
+}
+
+/// Every diagnostic the compiler can report.
+pub const CODES: &[Code] = &[
+    Code {
+        code: "E001",
+        name: "unclosed-string",
+        severity: Severity::Error,
+        explanation: "\
+A quoted string doesn't end on the line it starts on.
+
+    local greeting = \"hello
+
+Close the string with the same quote it was opened with. A backslash at the
+end of the line carries the string over to the next, and `\\z` skips the line
+breaks and spaces after it. A string that has to span lines can also use the
+long bracket form:
+
+    local greeting = [[hello
+    world]]
+
+A long string runs until the closing bracket with as many `=` as its opening
+one, `[==[` until `]==]`, and one that never gets there is reported at its
+opening bracket.",
+    },
+    Code {
+        code: "E002",
+        name: "malformed-number",
+        severity: Severity::Error,
+        explanation: "\
+A number literal isn't valid, e.g. an exponent without digits, a second
+fraction or letters right after it, or an empty hexadecimal number.
+
+    local a = 1e+
+    local b = 1.2.3
+    local c = 0x
+
+A number is digits with an optional fraction after a `.`, then an optional
+exponent after an `e` with an optional sign, e.g. `1.5e+3`. Everything that
+follows it up to a character that can't be part of a name is reported with it.
+Write out the missing digits, or put a space or an operator between the number
+and what follows.",
+    },
+    Code {
+        code: "E003",
+        name: "undefined-token",
+        severity: Severity::Error,
+        explanation: "\
+A character that can't start any token in Lua.
+
+    local x = 1 @ 2
+
+Remove the character, or put it inside a string or a comment.",
+    },
+    Code {
+        code: "E004",
+        name: "invalid-escape",
+        severity: Severity::Error,
+        explanation: "\
+A backslash in a quoted string isn't followed by an escape Lua knows, or a
+decimal escape is over 255.
+
+    local path = \"C:\\quiet\"
+    local byte = \"\\300\"
+
+The escapes are \\a \\b \\f \\n \\r \\t \\v \\\\ \\\" \\', a backslash before a line
+break, \\z, \\xXX, \\u{XXX} and \\ddd up to \\255. Double the backslash to keep it,
+e.g. `\"C:\\\\quiet\"`.",
+    },
+    Code {
+        code: "E005",
+        name: "unclosed-comment",
+        severity: Severity::Error,
+        explanation: "\
+A long comment isn't closed before the end of the source.
+
+    --[==[
+    everything from here on is part of the comment ]]
+
+A long comment runs until the closing bracket with as many `=` as its opening
+one, `--[==[` until `]==]`, so one with `]]` in it can use a level of its own.
+The error points at the comment's opening bracket.",
+    },
+    Code {
+        code: "W001",
+        name: "unused-suppression",
+        severity: Severity::Warning,
+        explanation: "\
+A suppression comment doesn't silence anything, or can't be understood.
+
+    local json = require \"json\" --luacompiler: disable-line module-not-found
+
+`disable-line` silences the warnings of the line the comment is on and
+`disable-next-line` those of the line after it, every warning or only the
+comma separated codes or names that follow. A comment that no longer matches a
+warning is left over from code that changed, remove it.",
+    },
+    Code {
+        code: "E100",
+        name: "syntax-error",
+        severity: Severity::Error,
+        explanation: "\
+The tokens don't form a valid Lua program, the message says what was expected
+at the underlined position.
+
+    local x = = 1
+
+Often something is missing right before the underlined token, like an `end`,
+a closing parenthesis or an operand.",
+    },
+    Code {
+        code: "E101",
+        name: "reserved-word",
+        severity: Severity::Error,
+        explanation: "\
+A reserved word is used where only a name can be: a local, a parameter, a
+function name, or a field or method name after `.` or `:`.
+
+    local end = 1
+    print(t.function)
+
+Rename it, or index with a string to get at a field named like a keyword:
+
+    print(t[\"function\"])
+
+The reserved words are and, break, do, else, elseif, end, false, for,
+function, if, in, local, nil, not, or, repeat, return, then, true, until and
+while.",
+    },
+    Code {
+        code: "E201",
+        name: "cyclic-require",
+        severity: Severity::Error,
+        explanation: "\
+Modules require each other in a cycle, so one of them would be loaded while it
+is still being loaded and see an incomplete module.
+
+    -- a.lua
+    local b = require \"b\"
+    -- b.lua
+    local a = require \"a\"
+
+Move what both modules need into a third module, or require one of them lazily
+inside the function that uses it.",
+    },
+    Code {
+        code: "E202",
+        name: "unreadable-module",
+        severity: Severity::Error,
+        explanation: "\
+A required module was found on the search path but couldn't be read, e.g.
+because of its permissions or because it isn't valid UTF-8.",
+    },
+    Code {
+        code: "E300",
+        name: "invalid-config",
+        severity: Severity::Error,
+        explanation: "\
+The luacompiler.toml file has a syntax error, an unknown key or a value of the
+wrong kind.
+
+    jobs = \"four\"
+
+The settings it understands are `include` and `exclude` (arrays of globs),
+`jobs` (a positive integer), `warnings-as-errors` (a boolean) and a
+`[warnings]` table, see `--explain` of a warning for its name.",
+    },
+    Code {
+        code: "W200",
+        name: "module-not-found",
+        severity: Severity::Warning,
+        explanation: "\
+A module required with a constant name isn't on the search path next to the
+file being compiled, `?.lua` and `?/init.lua`.
+
+    local socket = require \"socket\"
+
+This is only a warning since the module could still be found at runtime, e.g.
+as a C module or through LUA_PATH. Silence it with `-W no-module-not-found`.",
+    },
+    Code {
+        code: "W201",
+        name: "unused-module",
+        severity: Severity::Warning,
+        explanation: "\
+A file of the project isn't required by any other file and isn't one of the
+entry points given with `--entry`, so nothing loads it.
+
+    lua-compiler src --entry main.lua
+
+Remove the file if it's dead, or pass it with `--entry` if it's run on its own,
+like a script or a test. This is only checked for a directory, when at least
+one `--entry` is given.",
+    },
+    Code {
+        code: "W202",
+        name: "unused-export",
+        severity: Severity::Warning,
+        explanation: "\
+A field of the table a module returns isn't read by any module that requires
+it.
+
+    -- utils.lua
+    local M = {}
+    function M.unused() end
+    return M
+
+Only fields read as `m.name` or `m:name()` through the result of `require`
+count as used. A module whose result is used any other way, like being passed
+to a function, has every field counted as used.",
+    },
+    Code {
+        code: "W203",
+        name: "naming",
+        severity: Severity::Warning,
+        explanation: "\
+A local, parameter, loop variable or function name doesn't follow the style
+chosen with `--naming-style`, snake_case or camelCase.
+
+    lua-compiler check --naming-style=snake_case main.lua
+    local itemCount = 0  -- rename it to 'item_count'
+
+Constants in all caps like `MAX_SIZE`, single letters and names starting with
+`_` are left alone, as are globals, which often come from another API. Each
+segment of a function name like `M.newItem` is checked, apart from the first
+of a global one. Nothing is checked unless a style is chosen.",
+    },
+    Code {
+        code: "W204",
+        name: "number-style",
+        severity: Severity::Warning,
+        explanation: "\
+A number literal is spelled differently from the first one of the source
+spelled either way, in the case of its hex digits or in the leading zero of a
+fraction.
+
+    local mask = 0xff
+    local high = 0xFF00  -- write it '0xff00'
+    local half = 0.5
+    local third = .33    -- write it '0.33'
+
+The suggested spelling always reads back as the same number. This is off
+unless turned on with `-W number-style`.",
+    },
+    Code {
+        code: "W205",
+        name: "whitespace",
+        severity: Severity::Warning,
+        explanation: "\
+Lines end with spaces or tabs, or the lines of a block aren't all indented the
+same way as its first indented line, some with tabs and some with spaces.
+
+    trailing whitespace on lines 12, 40-43
+    indentation mixes tabs and spaces on line 7
+
+Each is reported once for the whole source, and once for every block, with the
+lines it's found on. The lines within a long string are left alone, their
+whitespace is part of it. This is off unless turned on with `-W whitespace`.",
+    },
+    Code {
+        code: "W206",
+        name: "concat-in-loop",
+        severity: Severity::Warning,
+        explanation: "\
+A string is built up with `..` in a loop, which copies all of it on every
+iteration and takes time quadratic in its length.
+
+    local s = \"\"
+    for i = 1, #items do
+      s = s .. items[i]
+    end
+
+Collect the pieces in a table and join them once the loop is over:
+
+    local parts = {}
+    for i = 1, #items do
+      parts[#parts + 1] = items[i]
+    end
+    local s = table.concat(parts)
+
+A variable declared in the loop starts over on every iteration and is left
+alone, as is one built up in a function the loop only defines. Silence it
+with `-W no-concat-in-loop`.",
+    },
+    Code {
+        code: "W207",
+        name: "too-many-arguments",
+        severity: Severity::Warning,
+        explanation: "\
+A function defined in the same source is called with more arguments than it
+has parameters, so the extra ones are evaluated and then dropped.
+
+    local function clamp(x, lo, hi) ... end
+    clamp(value, 0, 1, 10, 20)
+
+Only calls of a local function, or of one defined on a local table like
+`function M.update(dt)`, by its name are checked. A definition with `:` counts
+its `self`. Functions taking `...` are left alone, as are functions assigned
+to anywhere, since they could be something else by the time of the call.
+Silence it with `-W no-too-many-arguments`.",
+    },
+    Code {
+        code: "W208",
+        name: "too-few-arguments",
+        severity: Severity::Warning,
+        explanation: "\
+A function defined in the same source is called with fewer arguments than it
+has parameters, so the missing ones are nil.
+
+    local function clamp(x, lo, hi) ... end
+    clamp(value, 0)
+
+Leaving out trailing arguments is often on purpose, so this is off unless
+turned on with `-W too-few-arguments`. A call whose last argument is a call or
+`...` can pass any number of values and is left alone. The calls checked are
+the same as for W207.",
+    },
+    Code {
+        code: "E999",
+        name: "internal-error",
+        severity: Severity::Error,
+        explanation: "\
+The compiler crashed on the source, which is a bug in the compiler and not in
+the code. The command line prints a report when it happens, with the path of a
+file holding the details and a copy of the source. Please report it, with
+that file if the source can be shared.",
+    },
+];
+
+/// Looks a diagnostic up by its code or its name.
+pub fn lookup(code_or_name: &str) -> Option<&'static Code> {
+    CODES
+        .iter()
+        .find(|c| c.code.eq_ignore_ascii_case(code_or_name) || c.name == code_or_name)
+}
+
+/// What to do with a warning, as chosen with `-W`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// Parses a `-W` argument, `name`, `no-name` or `error=name`, into a code and a level.
+    pub fn parse_flag(flag: &str) -> Result<(&'static str, Level), String> {
+        let (name, level) = if let Some(name) = flag.strip_prefix("error=") {
+            (name, Level::Deny)
+        } else if let Some(name) = flag.strip_prefix("no-") {
+            (name, Level::Allow)
+        } else {
+            (flag, Level::Warn)
+        };
+
+        match lookup(name) {
+            Some(c) if c.severity == Severity::Warning => Ok((c.code, level)),
+            Some(c) => Err(format!("'{}' is an error and can't be changed", c.name)),
+            None => Err(format!("unknown warning '{name}'")),
+        }
+    }
+}
+
+/// Returns whether a warning that's off by default was turned on, by `-W name` or
+/// `-W error=name` coming after any `-W no-name`.
+pub fn is_requested(code: &str, levels: &[(&'static str, Level)]) -> bool {
+    matches!(
+        levels.iter().rev().find(|(c, _)| *c == code),
+        Some((_, Level::Warn | Level::Deny))
+    )
+}
+
+/// Drops the warnings that are allowed and turns the denied ones into errors.
+///
+/// Later entries of `levels` take precedence over earlier ones.
+pub fn apply_levels(diagnostics: &mut Vec<Diagnostic>, levels: &[(&'static str, Level)]) {
+    diagnostics.retain_mut(|d| {
+        if d.severity != Severity::Warning {
+            return true;
+        }
+
+        match levels.iter().rev().find(|(code, _)| *code == d.code) {
+            Some((_, Level::Allow)) => false,
+            Some((_, Level::Deny)) => {
+                d.severity = Severity::Error;
+                true
+            }
+            _ => true,
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Span;
+
+    fn warning(code: &'static str) -> Diagnostic {
+        Diagnostic::warning(code, String::new(), Span::default())
+    }
+
+    #[test]
+    fn codes_and_names_are_unique_and_explained() {
+        for (i, c) in CODES.iter().enumerate() {
+            assert!(
+                CODES[i + 1..]
+                    .iter()
+                    .all(|other| other.code != c.code && other.name != c.name),
+                "{} {}",
+                c.code,
+                c.name
+            );
+            // warnings start with a W and errors with an E.
+            let letter = match c.severity {
+                Severity::Warning => 'W',
+                _ => 'E',
+            };
+            assert!(c.code.starts_with(letter), "{}", c.code);
+            assert!(!c.explanation.trim().is_empty(), "{}", c.code);
+        }
+    }
+
+    #[test]
+    fn diagnostics_are_looked_up_by_code_or_name() {
+        assert_eq!(lookup("W200").unwrap().name, "module-not-found");
+        assert_eq!(lookup("w200").unwrap().name, "module-not-found");
+        assert_eq!(lookup("module-not-found").unwrap().code, "W200");
+        assert!(lookup("Module-Not-Found").is_none());
+        assert!(lookup("W999").is_none());
+    }
+
+    #[test]
+    fn flags_parse_into_a_level() {
+        assert_eq!(Level::parse_flag("naming"), Ok(("W203", Level::Warn)));
+        assert_eq!(Level::parse_flag("no-W200"), Ok(("W200", Level::Allow)));
+        assert_eq!(
+            Level::parse_flag("error=module-not-found"),
+            Ok(("W200", Level::Deny))
+        );
+        assert_eq!(
+            Level::parse_flag("no-syntax-error"),
+            Err("'syntax-error' is an error and can't be changed".to_string())
+        );
+        assert_eq!(
+            Level::parse_flag("error=nope"),
+            Err("unknown warning 'nope'".to_string())
+        );
+    }
+
+    #[test]
+    fn the_last_level_of_a_warning_counts() {
+        let mut diagnostics = vec![
+            warning("W200"),
+            warning("W201"),
+            warning("W203"),
+            Diagnostic::error("E100", String::new(), Span::default()),
+        ];
+        let levels = [
+            ("W200", Level::Deny),
+            ("W201", Level::Deny),
+            ("W201", Level::Allow),
+            // errors can't be allowed.
+            ("E100", Level::Allow),
+        ];
+        apply_levels(&mut diagnostics, &levels);
+
+        let left = diagnostics
+            .iter()
+            .map(|d| (d.code, d.severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            left,
+            [
+                ("W200", Severity::Error),
+                ("W203", Severity::Warning),
+                ("E100", Severity::Error)
+            ]
+        );
+
+        assert!(is_requested(
+            "W204",
+            &[("W204", Level::Allow), ("W204", Level::Deny)]
+        ));
+        assert!(!is_requested(
+            "W204",
+            &[("W204", Level::Warn), ("W204", Level::Allow)]
+        ));
+        assert!(!is_requested("W204", &[("W203", Level::Warn)]));
+    }
+}
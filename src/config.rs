@@ -0,0 +1,457 @@
+use std::path::{Path, PathBuf};
+
+use crate::cli::Options;
+use lua_compiler::codes::Level;
+use lua_compiler::diagnostic::Diagnostic;
+use lua_compiler::lexer::Span;
+use lua_compiler::snippet::LineIndex;
+
+/// The name of the project configuration file.
+pub const FILE_NAME: &str = "luacompiler.toml";
+
+/// A value in the configuration file, only the parts of TOML the settings need are supported.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<(Value, Span)>),
+}
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+// a key with its table prefix, and the value it's set to, along with where both are.
+type Entry = (String, Span, Value, Span);
+
+/// Finds the nearest configuration file in the directory of `path` or any directory above it.
+pub fn find(path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    let start = if path.is_dir() { &path } else { path.parent()? };
+
+    start
+        .ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Reads the settings of a configuration file into `Options`.
+///
+/// The error points at the offending key or value of the file.
+pub fn parse(source: &str) -> Result<Options, Box<Diagnostic>> {
+    let mut reader = Reader {
+        source,
+        offset: 0,
+        index: LineIndex::new(source),
+    };
+    let mut options = Options::default();
+
+    for (key, key_span, value, value_span) in reader.entries()? {
+        // reports that the value of the key isn't the kind the setting needs.
+        let expected = |what: &str, value: &Value| {
+            reader.error(
+                format!("'{key}' expects {what}, found {}", value.describe()),
+                value_span,
+            )
+        };
+
+        match key.as_str() {
+            "include" | "exclude" => {
+                let Value::Array(items) = &value else {
+                    return Err(expected("an array of globs", &value));
+                };
+
+                let mut globs = Vec::new();
+                for (item, span) in items {
+                    match item {
+                        Value::String(glob) => globs.push(glob.clone()),
+                        _ => {
+                            return Err(reader.error(
+                                format!("'{key}' expects globs, found {}", item.describe()),
+                                *span,
+                            ))
+                        }
+                    }
+                }
+
+                if key == "include" {
+                    options.include = globs;
+                } else {
+                    options.exclude = globs;
+                }
+            }
+            "jobs" => match value {
+                Value::Integer(n) if n > 0 => options.jobs = Some(n as usize),
+                _ => return Err(expected("a positive integer", &value)),
+            },
+            "warnings-as-errors" => match value {
+                Value::Boolean(b) => options.warnings_as_errors = b,
+                _ => return Err(expected("a boolean", &value)),
+            },
+            key if key.starts_with("warnings.") => {
+                let name = &key["warnings.".len()..];
+                let level = match value {
+                    Value::Boolean(true) => Level::Warn,
+                    Value::Boolean(false) => Level::Allow,
+                    Value::String(ref s) if s == "allow" => Level::Allow,
+                    Value::String(ref s) if s == "warn" => Level::Warn,
+                    Value::String(ref s) if s == "error" => Level::Deny,
+                    _ => {
+                        return Err(expected(
+                            "\"allow\", \"warn\", \"error\" or a boolean",
+                            &value,
+                        ))
+                    }
+                };
+
+                // the flag parser already knows which names are warnings.
+                let (code, _) = Level::parse_flag(name).map_err(|e| reader.error(e, key_span))?;
+                options.warnings.push((code, level));
+            }
+            _ => return Err(reader.error(format!("unknown key '{key}'"), key_span)),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Fills in what the command line left out from the settings of a configuration file.
+pub fn apply(cli: Options, config: Options) -> Options {
+    Options {
+        include: match cli.include.is_empty() {
+            true => config.include,
+            false => cli.include,
+        },
+        exclude: match cli.exclude.is_empty() {
+            true => config.exclude,
+            false => cli.exclude,
+        },
+        jobs: cli.jobs.or(config.jobs),
+        warnings_as_errors: cli.warnings_as_errors || config.warnings_as_errors,
+        // the flags come last so they win over the file.
+        warnings: config.warnings.into_iter().chain(cli.warnings).collect(),
+        ..cli
+    }
+}
+
+/// Reads `key = value` entries, along with `[table]` headers that prefix the keys after them.
+struct Reader<'a> {
+    source: &'a str,
+    offset: usize,
+    index: LineIndex,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.offset..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+
+    fn span(&self, start: usize) -> Span {
+        let (line, column) = self.index.position(start, self.source);
+        Span {
+            line,
+            column,
+            start,
+            end: self.offset.max(start + 1).min(self.source.len()),
+        }
+    }
+
+    fn error(&self, message: String, span: Span) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error("E300", message, span))
+    }
+
+    /// Skips spaces and comments, and line breaks too if `newlines` is set.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => {}
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, expected: char, what: &str) -> Result<(), Box<Diagnostic>> {
+        let start = self.offset;
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            _ => Err(self.error(format!("expected {what}"), self.span(start))),
+        }
+    }
+
+    fn key(&mut self) -> Result<(String, Span), Box<Diagnostic>> {
+        let start = self.offset;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            self.bump();
+        }
+
+        if self.offset == start {
+            return Err(self.error("expected a key".to_string(), self.span(start)));
+        }
+
+        Ok((
+            self.source[start..self.offset].to_string(),
+            self.span(start),
+        ))
+    }
+
+    fn entries(&mut self) -> Result<Vec<Entry>, Box<Diagnostic>> {
+        let mut entries = Vec::new();
+        let mut table = String::new();
+
+        loop {
+            self.skip_whitespace(true);
+            if self.peek().is_none() {
+                return Ok(entries);
+            }
+
+            if self.peek() == Some('[') {
+                self.bump();
+                let (name, _) = self.key()?;
+                self.expect(']', "']' after the table name")?;
+                table = format!("{name}.");
+            } else {
+                let (key, key_span) = self.key()?;
+                self.skip_whitespace(false);
+                self.expect('=', "'=' after the key")?;
+                self.skip_whitespace(false);
+
+                let start = self.offset;
+                let value = self.value()?;
+                let value_span = self.span(start);
+
+                entries.push((format!("{table}{key}"), key_span, value, value_span));
+            }
+
+            // every entry ends the line it's on.
+            self.skip_whitespace(false);
+            if self.peek().is_some() {
+                self.expect('\n', "a line break")?;
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Box<Diagnostic>> {
+        let start = self.offset;
+
+        match self.peek() {
+            Some('"') => {
+                self.bump();
+                let mut string = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => return Ok(Value::String(string)),
+                        Some('\\') => match self.bump() {
+                            Some('"') => string.push('"'),
+                            Some('\\') => string.push('\\'),
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            _ => {
+                                return Err(self.error(
+                                    "unknown escape sequence".to_string(),
+                                    self.span(self.offset - 1),
+                                ))
+                            }
+                        },
+                        Some('\n') | None => {
+                            return Err(self.error("unclosed string".to_string(), self.span(start)))
+                        }
+                        Some(c) => string.push(c),
+                    }
+                }
+            }
+            Some('[') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace(true);
+                    if self.peek() == Some(']') {
+                        self.bump();
+                        return Ok(Value::Array(items));
+                    }
+
+                    let item_start = self.offset;
+                    let item = self.value()?;
+                    items.push((item, self.span(item_start)));
+
+                    self.skip_whitespace(true);
+                    if self.peek() != Some(']') {
+                        self.expect(',', "',' or ']' in the array")?;
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c.is_ascii_alphabetic() => {
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    self.bump();
+                }
+
+                match &self.source[start..self.offset] {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    word => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| {
+                            self.error(format!("invalid value '{word}'"), self.span(start))
+                        }),
+                }
+            }
+            _ => Err(self.error("expected a value".to_string(), self.span(start))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parses a config that should be malformed, returning where and why.
+    fn error(source: &str) -> (usize, usize, String) {
+        let d = parse(source).unwrap_err();
+        (d.span.line, d.span.column, d.message)
+    }
+
+    #[test]
+    fn every_setting_is_read() {
+        let options = parse(
+            "# the settings of the project\n\
+             include = [\"src/**/*.lua\", \"*.lua\"]\n\
+             exclude = [\n  \"vendor/**\",\n]\n\
+             jobs = 4\n\
+             warnings-as-errors = true\n\
+             \n\
+             [warnings]\n\
+             naming = true\n\
+             W200 = \"allow\"\n\
+             whitespace = \"error\" # a trailing comment\n",
+        )
+        .unwrap();
+
+        assert_eq!(options.include, ["src/**/*.lua", "*.lua"]);
+        assert_eq!(options.exclude, ["vendor/**"]);
+        assert_eq!(options.jobs, Some(4));
+        assert!(options.warnings_as_errors);
+        assert_eq!(
+            options.warnings,
+            [
+                ("W203", Level::Warn),
+                ("W200", Level::Allow),
+                ("W205", Level::Deny)
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_settings_point_at_the_offending_key_or_value() {
+        let cases = [
+            ("colour = true\n", (1, 1, "unknown key 'colour'")),
+            (
+                "jobs = 0\n",
+                (1, 8, "'jobs' expects a positive integer, found an integer"),
+            ),
+            (
+                "include = \"*.lua\"\n",
+                (1, 11, "'include' expects an array of globs, found a string"),
+            ),
+            (
+                "exclude = [\"a\", 1]\n",
+                (1, 17, "'exclude' expects globs, found an integer"),
+            ),
+            (
+                "[warnings]\nnaming = 1\n",
+                (
+                    2,
+                    10,
+                    "'warnings.naming' expects \"allow\", \"warn\", \"error\" or a boolean, found an integer",
+                ),
+            ),
+            (
+                "[warnings]\nnope = true\n",
+                (2, 1, "unknown warning 'nope'"),
+            ),
+            ("jobs 4\n", (1, 6, "expected '=' after the key")),
+            ("jobs = 4 5\n", (1, 10, "expected a line break")),
+            ("include = [\"a\n", (1, 12, "unclosed string")),
+        ];
+        for (source, (line, column, message)) in cases {
+            assert_eq!(
+                error(source),
+                (line, column, message.to_string()),
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_command_line_wins_over_the_config() {
+        let config =
+            parse("include = [\"a\"]\nexclude = [\"b\"]\njobs = 2\n[warnings]\nnaming = true\n")
+                .unwrap();
+        let cli = Options {
+            exclude: vec!["c".to_string()],
+            jobs: Some(8),
+            warnings: vec![("W203", Level::Allow)],
+            ..Options::default()
+        };
+
+        let options = apply(cli, config);
+        assert_eq!(options.include, ["a"]);
+        assert_eq!(options.exclude, ["c"]);
+        assert_eq!(options.jobs, Some(8));
+        // the flag comes last, so it's the level that counts.
+        assert_eq!(
+            options.warnings,
+            [("W203", Level::Warn), ("W203", Level::Allow)]
+        );
+    }
+
+    #[test]
+    fn the_nearest_config_above_a_file_is_found() {
+        let dir =
+            std::env::temp_dir().join(format!("lua-compiler-config-find-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a/b")).unwrap();
+        std::fs::write(dir.join(FILE_NAME), "").unwrap();
+
+        assert_eq!(find(&dir.join("a/b/main.lua")), Some(dir.join(FILE_NAME)));
+        assert_eq!(find(&dir.join("a/b")), Some(dir.join(FILE_NAME)));
+
+        std::fs::write(dir.join("a").join(FILE_NAME), "").unwrap();
+        assert_eq!(
+            find(&dir.join("a/b/main.lua")),
+            Some(dir.join("a").join(FILE_NAME))
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+use crate::lexer::Token;
+use crate::parser::ASTNode;
+
+/// The value of an expression that's known without running anything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Value {
+    /// Returns whether the value counts as true, which is anything but nil and false.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    /// Returns the number the value is, or the one a string converts to in arithmetic.
+    pub fn to_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => string_to_number(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the string the value is, or the one a number converts to in a concatenation.
+    fn to_concat_string(&self) -> Option<String> {
+        match self {
+            Value::String(s) => Some(s.clone()),
+            // what a NaN is spelled depends on the C library.
+            Value::Number(n) if n.is_nan() => None,
+            Value::Number(n) => Some(format_number(*n)),
+            _ => None,
+        }
+    }
+}
+
+/// Shows the value the way `tostring` does.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::String(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+/// Evaluates the expression if it's made of literals, with the semantics of Lua 5.1.
+///
+/// Anything that depends on the program is None, names, calls, tables, functions and `...`,
+/// and so is an operation that would raise an error, like adding a boolean. `and` and `or`
+/// only need their right side when the left one doesn't decide, so `false and f()` is false.
+///
+/// ```
+/// use lua_compiler::constant::{const_eval, Value};
+/// use lua_compiler::lexer::Lexer;
+/// use lua_compiler::parser::Parser;
+///
+/// let tokens = Lexer::new("2 ^ 10 .. ' bytes'").tokenize().unwrap();
+/// let tree = Parser::new(tokens).parse_expression().unwrap();
+/// assert_eq!(const_eval(&tree), Some(Value::String("1024 bytes".to_string())));
+/// ```
+pub fn const_eval(node: &ASTNode) -> Option<Value> {
+    match node {
+        // parentheses keep the value of what they hold.
+        ASTNode::Expression(inner) | ASTNode::PrefixExpression(inner) => const_eval(inner),
+        ASTNode::Token(token) => match token {
+            Token::NIL => Some(Value::Nil),
+            Token::TRUE => Some(Value::Boolean(true)),
+            Token::FALSE => Some(Value::Boolean(false)),
+            Token::NUMBER(n) => Some(Value::Number(*n)),
+            // bytes that aren't UTF-8 were replaced when decoded, the value isn't known.
+            Token::STRING(s) if !s.contains(char::REPLACEMENT_CHARACTER) => {
+                Some(Value::String(s.clone()))
+            }
+            _ => None,
+        },
+        ASTNode::BinaryOp {
+            left,
+            binary_operator,
+            right,
+        } => {
+            let ASTNode::Token(operator) = &**binary_operator else {
+                return None;
+            };
+            match operator {
+                Token::AND => match const_eval(left)? {
+                    left if !left.is_truthy() => Some(left),
+                    _ => const_eval(right),
+                },
+                Token::OR => match const_eval(left)? {
+                    left if left.is_truthy() => Some(left),
+                    _ => const_eval(right),
+                },
+                _ => binary(operator, &const_eval(left)?, &const_eval(right)?),
+            }
+        }
+        ASTNode::UnaryOp {
+            unary_operator,
+            right,
+        } => {
+            let ASTNode::Token(operator) = &**unary_operator else {
+                return None;
+            };
+            let operand = const_eval(right)?;
+            match operator {
+                Token::NOT => Some(Value::Boolean(!operand.is_truthy())),
+                Token::SUBTRACT => Some(Value::Number(-operand.to_number()?)),
+                Token::HASHTAG => match operand {
+                    Value::String(s) => Some(Value::Number(s.len() as f64)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Applies a binary operator other than `and` and `or` to two values.
+fn binary(operator: &Token, left: &Value, right: &Value) -> Option<Value> {
+    let arithmetic =
+        |f: fn(f64, f64) -> f64| Some(Value::Number(f(left.to_number()?, right.to_number()?)));
+
+    match operator {
+        Token::ADD => arithmetic(|a, b| a + b),
+        Token::SUBTRACT => arithmetic(|a, b| a - b),
+        Token::MULTIPLY => arithmetic(|a, b| a * b),
+        Token::DIVIDE => arithmetic(|a, b| a / b),
+        // the result has the sign of the divisor, unlike Rust's `%`.
+        Token::MODULO => arithmetic(|a, b| a - (a / b).floor() * b),
+        Token::XOR => arithmetic(f64::powf),
+        Token::CONCAT => Some(Value::String(
+            left.to_concat_string()? + &right.to_concat_string()?,
+        )),
+        // values of different types are never equal, a string isn't converted.
+        Token::EQ => Some(Value::Boolean(left == right)),
+        Token::NEQ => Some(Value::Boolean(left != right)),
+        Token::LESS_THAN => compare(left, right, |o| o.is_lt()),
+        Token::LESS_EQUAL => compare(left, right, |o| o.is_le()),
+        Token::GREATER_THAN => compare(left, right, |o| o.is_gt()),
+        Token::GREATER_EQUAL => compare(left, right, |o| o.is_ge()),
+        _ => None,
+    }
+}
+
+/// Orders two numbers or two strings, anything else can't be compared.
+fn compare(left: &Value, right: &Value, f: fn(std::cmp::Ordering) -> bool) -> Option<Value> {
+    match (left, right) {
+        // a comparison with NaN is false whichever way it goes.
+        (Value::Number(a), Value::Number(b)) => {
+            Some(Value::Boolean(a.partial_cmp(b).is_some_and(f)))
+        }
+        // the reference compares with strcoll, which is byte by byte in the C locale.
+        (Value::String(a), Value::String(b)) => {
+            Some(Value::Boolean(f(a.as_bytes().cmp(b.as_bytes()))))
+        }
+        _ => None,
+    }
+}
+
+/// Converts a string to a number the way arithmetic does, a decimal or a hexadecimal integer
+/// with whitespace around it.
+fn string_to_number(s: &str) -> Option<f64> {
+    let s = s.trim_matches(|c: char| matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0b' | '\x0c'));
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).ok().map(|n| n as f64);
+    }
+    // Rust also reads `inf` and `nan`, which Lua doesn't.
+    let is_decimal = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    if !is_decimal {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Formats a number like the reference does, with C's `%.14g`.
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return "nan".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    // the exponent once the number is rounded to 14 significant digits.
+    let scientific = format!("{n:.13e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+
+    let trim = |digits: &str| match digits.contains('.') {
+        true => digits
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+        false => digits.to_string(),
+    };
+
+    if !(-4..14).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim(mantissa), exponent.abs())
+    } else {
+        trim(&format!("{n:.*}", (13 - exponent) as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn eval(source: &str) -> Option<Value> {
+        let tokens = Lexer::new(source).tokenize().expect("the expression lexes");
+        let tree = Parser::new(tokens)
+            .parse_expression()
+            .expect("the expression parses");
+        const_eval(&tree)
+    }
+
+    fn number(source: &str) -> f64 {
+        match eval(source) {
+            Some(Value::Number(n)) => n,
+            other => panic!("{source} is {other:?}"),
+        }
+    }
+
+    fn string(source: &str) -> String {
+        match eval(source) {
+            Some(Value::String(s)) => s,
+            other => panic!("{source} is {other:?}"),
+        }
+    }
+
+    #[test]
+    fn literals_are_their_own_value() {
+        assert_eq!(eval("nil"), Some(Value::Nil));
+        assert_eq!(eval("true"), Some(Value::Boolean(true)));
+        assert_eq!(eval("((false))"), Some(Value::Boolean(false)));
+        assert_eq!(number("0x10"), 16.0);
+        // the escapes are already decoded, whichever way the string is written.
+        assert_eq!(string(r#""a\tb""#), "a\tb");
+        assert_eq!(string("[[\nline]]"), "line");
+        // a byte that isn't UTF-8 has no known value.
+        assert_eq!(eval(r#""\255""#), None);
+    }
+
+    #[test]
+    fn arithmetic_follows_lua() {
+        assert_eq!(number("1 + 2 * 3"), 7.0);
+        assert_eq!(number("10 - 4 - 3"), 3.0);
+        assert_eq!(number("7 / 2"), 3.5);
+        assert_eq!(number("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(number("-2 ^ 2"), -4.0);
+        // the remainder has the sign of the divisor.
+        assert_eq!(number("5 % 3"), 2.0);
+        assert_eq!(number("-5 % 3"), 1.0);
+        assert_eq!(number("5 % -3"), -1.0);
+        assert_eq!(number("5.5 % 2"), 1.5);
+        // dividing by zero is a number, not an error.
+        assert_eq!(number("1 / 0"), f64::INFINITY);
+        assert_eq!(number("-1 / 0"), f64::NEG_INFINITY);
+        assert!(number("0 / 0").is_nan());
+    }
+
+    #[test]
+    fn strings_are_converted_in_arithmetic_and_numbers_in_concatenation() {
+        assert_eq!(number("'10' + 1"), 11.0);
+        assert_eq!(number("' 0x1A ' * '2'"), 52.0);
+        assert_eq!(number("-'3'"), -3.0);
+        assert_eq!(eval("'ten' + 1"), None);
+        assert_eq!(eval("'inf' + 1"), None);
+        assert_eq!(eval("true + 1"), None);
+
+        assert_eq!(string("'a' .. 'b' .. 'c'"), "abc");
+        assert_eq!(string("1 .. ''"), "1");
+        assert_eq!(string("0.1 .. ''"), "0.1");
+        assert_eq!(string("1e15 .. ''"), "1e+15");
+        assert_eq!(string("2^53 .. ''"), "9.007199254741e+15");
+        assert_eq!(string("1/3 .. ''"), "0.33333333333333");
+        assert_eq!(string("1e-5 .. ''"), "1e-05");
+        assert_eq!(string("1/0 .. ''"), "inf");
+        assert_eq!(eval("0/0 .. ''"), None);
+        assert_eq!(eval("nil .. 'x'"), None);
+    }
+
+    #[test]
+    fn comparisons_need_values_of_one_type() {
+        let boolean = |source| match eval(source) {
+            Some(Value::Boolean(b)) => b,
+            other => panic!("{source} is {other:?}"),
+        };
+        assert!(boolean("1 == 1.0"));
+        assert!(boolean("'1' ~= 1"));
+        assert!(boolean("nil == nil"));
+        assert!(!boolean("0/0 == 0/0"));
+        assert!(boolean("1 < 2") && boolean("2 <= 2") && boolean("3 > 2") && boolean("2 >= 2"));
+        assert!(boolean("'a' < 'b'") && boolean("'Z' < 'a'") && boolean("'ab' > 'a'"));
+        assert!(!boolean("0/0 < 1") && !boolean("0/0 >= 1"));
+        assert_eq!(eval("1 < '2'"), None);
+        assert_eq!(eval("true < false"), None);
+    }
+
+    #[test]
+    fn logical_operators_only_look_as_far_as_they_need() {
+        assert_eq!(eval("nil and f()"), Some(Value::Nil));
+        assert_eq!(eval("false and f()"), Some(Value::Boolean(false)));
+        assert_eq!(number("1 and 2"), 2.0);
+        assert_eq!(number("0 or f()"), 0.0);
+        assert_eq!(string("nil or 'default'"), "default");
+        assert_eq!(eval("true and f()"), None);
+        assert_eq!(eval("x or 1"), None);
+
+        assert_eq!(eval("not nil"), Some(Value::Boolean(true)));
+        assert_eq!(eval("not 0"), Some(Value::Boolean(false)));
+        assert_eq!(number("#'héllo'"), 6.0);
+        assert_eq!(eval("#{}"), None);
+    }
+
+    #[test]
+    fn what_depends_on_the_program_is_not_constant() {
+        for source in [
+            "x",
+            "x + 1",
+            "f()",
+            "('a'):upper()",
+            "...",
+            "{1, 2}",
+            "function() end",
+            "t.k",
+            "-{}",
+            "not f()",
+        ] {
+            assert_eq!(eval(source), None, "{source}");
+        }
+    }
+}
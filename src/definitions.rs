@@ -0,0 +1,229 @@
+use crate::lexer::{Span, SpannedToken, Token};
+
+/// A function defined with a name, e.g. `function M.new()` or `local function helper()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    // the qualified name, e.g. `M.new` or `Class:method`.
+    pub name: String,
+    pub is_method: bool,
+    pub is_local: bool,
+    // covers the name as it was written.
+    pub name_span: Span,
+    // covers the whole definition, from `local` or `function` up to its `end`.
+    pub span: Span,
+}
+
+/// Finds every named function definition in the token stream.
+///
+/// This works on tokens so it also finds the functions of a source that doesn't parse. Besides
+/// `function name()` and `local function name()`, a function assigned to a name, like
+/// `M.new = function()`, counts as a definition too.
+pub fn find_functions(tokens: &[SpannedToken]) -> Vec<Function> {
+    let mut functions = Vec::new();
+
+    for (i, t) in tokens.iter().enumerate() {
+        if t.token != Token::FUNCTION {
+            continue;
+        }
+
+        let (name, is_method, name_span, first) =
+            if let Some((name, is_method, span)) = dotted_name(&tokens[i + 1..]) {
+                (name, is_method, span, i)
+            } else if i > 0 && tokens[i - 1].token == Token::ASSIGN {
+                // `name = function`, the name is read backwards from the `=`.
+                let Some((name, span, first)) = assigned_name(&tokens[..i - 1]) else {
+                    continue;
+                };
+                (name, false, span, first)
+            } else {
+                // an anonymous function, e.g. a callback.
+                continue;
+            };
+
+        let is_local = first > 0 && tokens[first - 1].token == Token::LOCAL;
+        let start = match is_local {
+            true => tokens[first - 1].span,
+            false => tokens[first].span,
+        };
+        // a function that is never closed runs until the end of the source.
+        let end = matching_end(tokens, i).unwrap_or(tokens[tokens.len() - 1].span);
+
+        functions.push(Function {
+            name,
+            is_method,
+            is_local,
+            name_span,
+            span: Span {
+                end: end.end,
+                ..start
+            },
+        });
+    }
+
+    functions
+}
+
+/// A variable of the main chunk, e.g. `local config = {}` or `VERSION = "1.0"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    // the qualified name, e.g. `M.default`.
+    pub name: String,
+    pub is_local: bool,
+    pub name_span: Span,
+}
+
+/// Finds the variables declared or assigned at the top level of the main chunk.
+///
+/// Names assigned a function are left to `find_functions`, as are the variables of nested
+/// blocks and functions.
+pub fn find_variables(tokens: &[SpannedToken]) -> Vec<Variable> {
+    let mut variables = Vec::new();
+    // how many blocks deep the current token is, and how many brackets of any kind.
+    let (mut blocks, mut brackets) = (0usize, 0usize);
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match tokens[i].token {
+            Token::FUNCTION | Token::DO | Token::IF | Token::REPEAT => blocks += 1,
+            Token::END | Token::UNTIL => blocks = blocks.saturating_sub(1),
+            Token::LEFT_PAREN | Token::LEFT_BRACE | Token::LEFT_BRACKET => brackets += 1,
+            Token::RIGHT_PAREN | Token::RIGHT_BRACE | Token::RIGHT_BRACKET => {
+                brackets = brackets.saturating_sub(1)
+            }
+            _ => {}
+        }
+
+        let continues_list = i > 0
+            && matches!(
+                tokens[i - 1].token,
+                Token::DOT | Token::COLON | Token::COMMA
+            );
+        if blocks > 0 || brackets > 0 || continues_list {
+            i += 1;
+            continue;
+        }
+
+        let is_local = tokens[i].token == Token::LOCAL;
+        let start = i + is_local as usize;
+        let Some((names, end)) = variable_list(&tokens[start..], is_local) else {
+            i += 1;
+            continue;
+        };
+
+        // `name = function` is a function definition rather than a variable.
+        let assigns_function = names.len() == 1
+            && tokens.get(start + end + 1).map(|t| &t.token) == Some(&Token::FUNCTION);
+        if !assigns_function {
+            variables.extend(names.into_iter().map(|(name, name_span)| Variable {
+                name,
+                is_local,
+                name_span,
+            }));
+        }
+        i = start + end;
+    }
+
+    variables
+}
+
+/// Reads `a, b.c =` from the start of the tokens, returning the names and the index of the
+/// token after them. A `local` list doesn't need the `=`, but can't have dotted names.
+fn variable_list(tokens: &[SpannedToken], is_local: bool) -> Option<(Vec<(String, Span)>, usize)> {
+    let mut names = Vec::new();
+    let mut i = 0;
+
+    loop {
+        let (name, is_method, span) = dotted_name(&tokens[i..])?;
+        if is_method || (is_local && name.contains('.')) {
+            return None;
+        }
+        names.push((name, span));
+        // every part of the name is a name and a separator, but the first.
+        i += tokens[i..]
+            .iter()
+            .take_while(|t| t.span.start < span.end)
+            .count();
+
+        match tokens.get(i).map(|t| &t.token) {
+            Some(Token::COMMA) => i += 1,
+            Some(Token::ASSIGN) => return Some((names, i)),
+            _ if is_local => return Some((names, i)),
+            _ => return None,
+        }
+    }
+}
+
+/// Reads `a.b.c` or `a.b:c` from the start of the tokens.
+fn dotted_name(tokens: &[SpannedToken]) -> Option<(String, bool, Span)> {
+    let Token::NAME(first) = &tokens.first()?.token else {
+        return None;
+    };
+
+    let mut name = first.clone();
+    let mut span = tokens[0].span;
+    let mut is_method = false;
+    let mut i = 1;
+
+    while let [separator, part, ..] = &tokens[i..] {
+        let separator = match separator.token {
+            Token::DOT => '.',
+            Token::COLON => ':',
+            _ => break,
+        };
+        let Token::NAME(part_name) = &part.token else {
+            break;
+        };
+
+        name.push(separator);
+        name.push_str(part_name);
+        span.end = part.span.end;
+        i += 2;
+
+        // a method name is always the last part.
+        if separator == ':' {
+            is_method = true;
+            break;
+        }
+    }
+
+    Some((name, is_method, span))
+}
+
+/// Reads `a.b.c` backwards from the end of the tokens, returning the index of the first name.
+fn assigned_name(tokens: &[SpannedToken]) -> Option<(String, Span, usize)> {
+    let mut first = tokens.len().checked_sub(1)?;
+    let Token::NAME(_) = tokens[first].token else {
+        return None;
+    };
+
+    while first >= 2
+        && tokens[first - 1].token == Token::DOT
+        && matches!(tokens[first - 2].token, Token::NAME(_))
+    {
+        first -= 2;
+    }
+
+    let (name, _, span) = dotted_name(&tokens[first..])?;
+    Some((name, span, first))
+}
+
+/// Finds the `end` that closes the block opened by the token at `open`.
+fn matching_end(tokens: &[SpannedToken], open: usize) -> Option<Span> {
+    let mut depth = 0;
+
+    for t in &tokens[open..] {
+        match t.token {
+            // `while` and `for` share the `end` of their `do`, so only it is counted.
+            Token::FUNCTION | Token::DO | Token::IF => depth += 1,
+            Token::END => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(t.span);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
@@ -0,0 +1,339 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use lua_compiler::diagnostic::{Diagnostic, DiagnosticSink, RenderingSink, SourceContext};
+use lua_compiler::term_color::*;
+use lua_compiler::{codes, json, require, suppress};
+use lua_compiler::{format_error, log_trace};
+
+use crate::{cli, emit_artifact, source, walk, ExitCode, Output};
+
+/// The modules of a project and who requires whom, found without running anything.
+#[derive(Default)]
+struct Graph {
+    // every module by its path, relative to the project when it's in it.
+    modules: BTreeSet<String>,
+    // (from, to, the name it was required by).
+    edges: Vec<(String, String, String)>,
+    // (from, the name of the module that couldn't be found).
+    missing: Vec<(String, String)>,
+}
+
+/// Writes the require graph of the file or of every file in the directory at `path`.
+pub fn emit(options: &cli::Options, path: &Path, out: &mut Output) -> ExitCode {
+    let inputs = match inputs(path, options) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            out.stderr.push_str(&format_error!("{e}.\n"));
+            return ExitCode::UsageError;
+        }
+    };
+
+    // modules are looked up from the project directory, like `check` does from the file's.
+    let base = match path.is_dir() {
+        true => path.to_path_buf(),
+        false => path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+    };
+    let name = |p: &Path| walk::relative_name(p, &base);
+
+    let mut graph = Graph::default();
+    let mut queue = inputs;
+    let mut seen = BTreeSet::new();
+    let mut result = ExitCode::Success;
+
+    while let Some(file) = queue.pop() {
+        if !seen.insert(file.canonicalize().unwrap_or_else(|_| file.clone())) {
+            continue;
+        }
+
+        let from = name(&file);
+        graph.modules.insert(from.clone());
+
+        let code = match source::read(&file) {
+            Ok(code) => code,
+            Err(e) => {
+                out.stderr.push_str(&format_error!("{e}.\n"));
+                result = result.max(ExitCode::UsageError);
+                continue;
+            }
+        };
+
+        let (tokens, _) = lua_compiler::Compiler::from(options).tokenize(&code);
+        let mut diagnostics = Vec::new();
+
+        let tokens = tokens.unwrap_or_default();
+        for r in require::find_requires(&tokens) {
+            match require::resolve(&r.name, &base) {
+                Some(to) => {
+                    log_trace!("{from}: '{}' resolved to '{}'.", r.name, to.display());
+                    graph.edges.push((from.clone(), name(&to), r.name));
+                    queue.push(to);
+                }
+                None => {
+                    let searched = require::candidates(&r.name, &base)
+                        .iter()
+                        .map(|p| name(p))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    diagnostics.emit(
+                        Diagnostic::warning(
+                            "W200",
+                            format!("module '{}' not found", r.name),
+                            r.span,
+                        )
+                        .with_note(format!("looked for {searched}"), None),
+                    );
+                    graph.missing.push((from.clone(), r.name));
+                }
+            }
+        }
+
+        suppress::apply(&mut diagnostics, &code, &tokens);
+        codes::apply_levels(&mut diagnostics, &options.warnings);
+        let ctx = SourceContext::new(&from, &code);
+        let mut sink = RenderingSink::new(&ctx, options.diagnostics_format, output_caps());
+        sink.show_suppressed = options.show_suppressed;
+        for d in diagnostics {
+            sink.emit(d);
+        }
+        out.push_diagnostics(options.diagnostics_format, &sink.finish().0);
+    }
+
+    graph.edges.sort();
+    graph.edges.dedup();
+    graph.missing.sort();
+    graph.missing.dedup();
+
+    let cycles = cycles(&graph);
+    for cycle in &cycles {
+        out.stderr.push_str(&format_error!(
+            "cyclic require between {}.\n",
+            cycle.join(", ")
+        ));
+        result = result.max(ExitCode::CompileError);
+    }
+
+    let artifact = match options.format {
+        cli::Format::Json => to_json(&graph, &cycles),
+        cli::Format::Text => to_dot(&graph, &cycles),
+    };
+    result.max(emit_artifact(
+        options.output.as_deref(),
+        path,
+        &artifact,
+        out,
+    ))
+}
+
+/// Finds the groups of modules that require each other, with Tarjan's algorithm.
+fn cycles(graph: &Graph) -> Vec<Vec<String>> {
+    struct State<'a> {
+        edges: HashMap<&'a str, Vec<&'a str>>,
+        index: HashMap<&'a str, usize>,
+        low: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        cycles: Vec<Vec<String>>,
+    }
+
+    fn visit<'a>(state: &mut State<'a>, module: &'a str) {
+        let index = state.index.len();
+        state.index.insert(module, index);
+        state.low.insert(module, index);
+        state.stack.push(module);
+
+        for to in state.edges.get(module).cloned().unwrap_or_default() {
+            if !state.index.contains_key(to) {
+                visit(state, to);
+                let low = state.low[module].min(state.low[to]);
+                state.low.insert(module, low);
+            } else if state.stack.contains(&to) {
+                let low = state.low[module].min(state.index[to]);
+                state.low.insert(module, low);
+            }
+        }
+
+        if state.low[module] == state.index[module] {
+            let at = state.stack.iter().rposition(|m| *m == module).unwrap_or(0);
+            let mut group = state
+                .stack
+                .split_off(at)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>();
+
+            // a module on its own is only a cycle if it requires itself.
+            let requires_itself = state.edges.get(module).is_some_and(|e| e.contains(&module));
+            if group.len() > 1 || requires_itself {
+                group.sort();
+                state.cycles.push(group);
+            }
+        }
+    }
+
+    let mut state = State {
+        edges: HashMap::new(),
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        cycles: Vec::new(),
+    };
+    for (from, to, _) in &graph.edges {
+        state
+            .edges
+            .entry(from.as_str())
+            .or_default()
+            .push(to.as_str());
+    }
+    for module in &graph.modules {
+        if !state.index.contains_key(module.as_str()) {
+            visit(&mut state, module);
+        }
+    }
+
+    state.cycles.sort();
+    state.cycles
+}
+
+fn to_dot(graph: &Graph, cycles: &[Vec<String>]) -> String {
+    let in_cycle = |from: &str, to: &str| {
+        cycles
+            .iter()
+            .any(|c| c.iter().any(|m| m == from) && c.iter().any(|m| m == to))
+    };
+
+    let mut out = String::from("digraph deps {\n");
+    for module in &graph.modules {
+        out.push_str(&format!("  {};\n", json::string(module)));
+    }
+
+    for (from, to, name) in &graph.edges {
+        let color = if in_cycle(from, to) {
+            ", color=red"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "  {} -> {} [label={}{color}];\n",
+            json::string(from),
+            json::string(to),
+            json::string(name)
+        ));
+    }
+
+    // the same name can be missing for several modules, it's drawn once.
+    let mut missing = BTreeMap::new();
+    for (from, name) in &graph.missing {
+        missing.entry(name).or_insert_with(Vec::new).push(from);
+    }
+    for (name, froms) in missing {
+        let node = json::string(&format!("{name} (not found)"));
+        out.push_str(&format!("  {node} [shape=box, style=dashed, color=red];\n"));
+        for from in froms {
+            out.push_str(&format!(
+                "  {} -> {node} [style=dashed];\n",
+                json::string(from)
+            ));
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+fn to_json(graph: &Graph, cycles: &[Vec<String>]) -> String {
+    let strings = |items: &mut dyn Iterator<Item = &String>| {
+        items
+            .map(|s| json::string(s))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let edges = graph
+        .edges
+        .iter()
+        .map(|(from, to, name)| {
+            format!(
+                "{{\"from\": {}, \"to\": {}, \"name\": {}}}",
+                json::string(from),
+                json::string(to),
+                json::string(name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let missing = graph
+        .missing
+        .iter()
+        .map(|(from, name)| {
+            format!(
+                "{{\"from\": {}, \"name\": {}}}",
+                json::string(from),
+                json::string(name)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let cycles = cycles
+        .iter()
+        .map(|c| format!("[{}]", strings(&mut c.iter())))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "{{\"modules\": [{}], \"edges\": [{edges}], \"missing\": [{missing}], \"cycles\": [{cycles}]}}\n",
+        strings(&mut graph.modules.iter())
+    )
+}
+
+/// Returns the files of the directory at `path` that the options select, or the file itself.
+fn inputs(path: &Path, options: &cli::Options) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    walk::collect_files(path, &options.include, &options.exclude)
+        .map_err(|e| format!("could not read '{}': {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &str)]) -> Graph {
+        let mut graph = Graph::default();
+        for (from, to) in edges {
+            graph.modules.insert(from.to_string());
+            graph.modules.insert(to.to_string());
+            graph
+                .edges
+                .push((from.to_string(), to.to_string(), to.to_string()));
+        }
+        graph
+    }
+
+    #[test]
+    fn a_diamond_has_no_cycles() {
+        let diamond = graph(&[("main", "a"), ("main", "b"), ("a", "c"), ("b", "c")]);
+        assert!(cycles(&diamond).is_empty());
+    }
+
+    #[test]
+    fn every_group_of_modules_requiring_each_other_is_a_cycle() {
+        let graph = graph(&[
+            ("main", "a"),
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "a"),
+            ("main", "d"),
+            ("d", "d"),
+            ("main", "e"),
+            ("e", "f"),
+            ("f", "e"),
+        ]);
+        assert_eq!(
+            cycles(&graph),
+            [vec!["a", "b", "c"], vec!["d"], vec!["e", "f"]]
+        );
+    }
+}
@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+
+use crate::json;
+use crate::lexer::Span;
+use crate::snippet::{render_snippet, LineIndex, GUTTER};
+use crate::term_color::*;
+
+/// How bad a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found in the source, reported by one of the compiler's stages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // a stable identifier for the kind of problem, e.g. "E001".
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    // other places in the same source the diagnostic points at.
+    pub labels: Vec<Label>,
+    pub notes: Vec<Note>,
+    pub suggestion: Option<String>,
+    // silenced by a suppression comment, see `suppress.rs`.
+    pub suppressed: bool,
+}
+
+/// A secondary place in the source that a diagnostic points at, e.g. "block opened here".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Extra context for a diagnostic, optionally pointing at another part of the same source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message,
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestion: None,
+            suppressed: false,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, message, span)
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label { span, message });
+        self
+    }
+
+    pub fn with_note(mut self, message: String, span: Option<Span>) -> Self {
+        self.notes.push(Note { message, span });
+        self
+    }
+}
+
+/// Where the stages of the compiler put the diagnostics they find.
+pub trait DiagnosticSink {
+    fn emit(&mut self, diagnostic: Diagnostic);
+}
+
+// the in-memory sink, which keeps everything for the caller to look at.
+impl DiagnosticSink for Vec<Diagnostic> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        self.push(diagnostic);
+    }
+}
+
+/// A sink that collects the diagnostics of one source and renders them all at once, in the
+/// order they appear in the source.
+pub struct RenderingSink<'a> {
+    ctx: &'a SourceContext<'a>,
+    renderer: Box<dyn DiagnosticRenderer + 'a>,
+    // repeats of the same problem beyond this many are folded into the first one.
+    pub group_after: Option<usize>,
+    // render the suppressed diagnostics too, instead of dropping them.
+    pub show_suppressed: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> RenderingSink<'a> {
+    pub fn new(ctx: &'a SourceContext<'a>, format: DiagnosticsFormat, caps: OutputCaps) -> Self {
+        Self::with_renderer(ctx, format.renderer(caps))
+    }
+
+    /// Makes a sink that renders with a renderer of the caller's instead of a format's.
+    pub fn with_renderer(
+        ctx: &'a SourceContext<'a>,
+        renderer: Box<dyn DiagnosticRenderer + 'a>,
+    ) -> Self {
+        Self {
+            ctx,
+            renderer,
+            group_after: None,
+            show_suppressed: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Renders everything that was emitted, returning the output along with the diagnostics
+    /// as they were rendered.
+    pub fn finish(mut self) -> (String, Vec<Diagnostic>) {
+        if !self.show_suppressed {
+            self.diagnostics.retain(|d| !d.suppressed);
+        }
+        let diagnostics = organize(self.diagnostics, self.group_after);
+        let output = diagnostics
+            .iter()
+            .map(|d| self.renderer.render(d, self.ctx))
+            .collect();
+        (output, diagnostics)
+    }
+}
+
+impl DiagnosticSink for RenderingSink<'_> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+}
+
+/// Sorts diagnostics by their position in the source and drops exact duplicates.
+///
+/// With `group_after`, a code and message that comes up more often than that is reported once,
+/// with the other places it happened as notes.
+pub fn organize(mut diagnostics: Vec<Diagnostic>, group_after: Option<usize>) -> Vec<Diagnostic> {
+    // the sort is stable, so diagnostics at the same place keep the order they were found in.
+    diagnostics.sort_by_key(|d| (d.span.start, d.span.end, d.severity));
+    diagnostics.dedup();
+
+    let Some(threshold) = group_after else {
+        return diagnostics;
+    };
+
+    let mut counts = HashMap::new();
+    for d in &diagnostics {
+        *counts.entry((d.code, d.message.clone())).or_insert(0) += 1;
+    }
+
+    let mut grouped: Vec<Diagnostic> = Vec::new();
+    // where the first of each group ended up in `grouped`.
+    let mut firsts: HashMap<_, usize> = HashMap::new();
+
+    for d in diagnostics {
+        let key = (d.code, d.message.clone());
+        let count = counts[&key];
+
+        match firsts.get(&key) {
+            Some(&i) if count > threshold => grouped[i].notes.push(Note {
+                message: "also here".to_string(),
+                span: Some(d.span),
+            }),
+            _ => {
+                firsts.insert(key, grouped.len());
+                let mut d = d;
+                if count > threshold {
+                    // keep the message's full stop at the end, if it has one.
+                    let (message, stop) = match d.message.strip_suffix('.') {
+                        Some(message) => (message, "."),
+                        None => (d.message.as_str(), ""),
+                    };
+                    let more = match count - 1 {
+                        1 => "1 more occurrence".to_string(),
+                        n => format!("{n} more occurrences"),
+                    };
+                    d.message = format!("{message} (and {more}){stop}");
+                }
+                grouped.push(d);
+            }
+        }
+    }
+
+    grouped
+}
+
+/// How diagnostics are written out, as chosen with `--diagnostics-format`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DiagnosticsFormat {
+    #[default]
+    Human,
+    // one JSON object per line.
+    Json,
+    // GitHub Actions workflow commands, which show up as annotations on the diff.
+    Github,
+    // one `file:line:column: severity: [code] message` line, for editors and grep.
+    Short,
+}
+
+impl DiagnosticsFormat {
+    /// Returns the renderer of the format, `caps` only matters to the human one.
+    pub fn renderer(self, caps: OutputCaps) -> Box<dyn DiagnosticRenderer> {
+        match self {
+            DiagnosticsFormat::Human => Box::new(HumanRenderer { caps }),
+            DiagnosticsFormat::Json => Box::new(JsonRenderer),
+            DiagnosticsFormat::Github => Box::new(GithubRenderer),
+            DiagnosticsFormat::Short => Box::new(ShortRenderer),
+        }
+    }
+}
+
+/// Turns a diagnostic into text, for embedders that want to present diagnostics their own
+/// way. Whatever is returned is written out as is, line break included.
+pub trait DiagnosticRenderer {
+    fn render(&self, diagnostic: &Diagnostic, ctx: &SourceContext) -> String;
+}
+
+// so that a borrowed renderer, like the one a compiler shares, can go in a sink.
+impl<R: DiagnosticRenderer + ?Sized> DiagnosticRenderer for &R {
+    fn render(&self, diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+        (**self).render(diagnostic, ctx)
+    }
+}
+
+/// Renders like [`render_human`].
+#[derive(Debug, Clone, Copy)]
+pub struct HumanRenderer {
+    pub caps: OutputCaps,
+}
+
+/// Renders like [`render_json`].
+#[derive(Debug, Clone, Copy)]
+pub struct JsonRenderer;
+
+/// Renders like [`render_github`].
+#[derive(Debug, Clone, Copy)]
+pub struct GithubRenderer;
+
+/// Renders like [`render_short`].
+#[derive(Debug, Clone, Copy)]
+pub struct ShortRenderer;
+
+impl DiagnosticRenderer for HumanRenderer {
+    fn render(&self, diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+        render_human(diagnostic, ctx, self.caps)
+    }
+}
+
+impl DiagnosticRenderer for JsonRenderer {
+    fn render(&self, diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+        render_json(diagnostic, ctx)
+    }
+}
+
+impl DiagnosticRenderer for GithubRenderer {
+    fn render(&self, diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+        render_github(diagnostic, ctx)
+    }
+}
+
+impl DiagnosticRenderer for ShortRenderer {
+    fn render(&self, diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+        render_short(diagnostic, ctx)
+    }
+}
+
+/// The source a set of diagnostics refers to.
+pub struct SourceContext<'a> {
+    pub file: &'a str,
+    pub source: &'a str,
+    pub index: LineIndex,
+}
+
+impl<'a> SourceContext<'a> {
+    pub fn new(file: &'a str, source: &'a str) -> Self {
+        Self {
+            file,
+            source,
+            index: LineIndex::new(source),
+        }
+    }
+
+    /// Returns the 1-based start and end positions of a span as `(line, column)` pairs.
+    fn positions(&self, span: Span) -> ((usize, usize), (usize, usize)) {
+        (
+            self.index.position(span.start, self.source),
+            self.index.position(span.end, self.source),
+        )
+    }
+}
+
+/// Renders a diagnostic the way a person reads it, with the offending source underlined.
+pub fn render_human(diagnostic: &Diagnostic, ctx: &SourceContext, caps: OutputCaps) -> String {
+    let severity = match diagnostic.severity {
+        Severity::Error => Style::new().fg(Color::Red).bold().apply_with("error", caps),
+        Severity::Warning => Style::new()
+            .fg(Color::Yellow)
+            .bold()
+            .apply_with("warning", caps),
+    };
+
+    let ((line, column), _) = ctx.positions(diagnostic.span);
+
+    let mut out = format!(
+        "{severity}: [{}] {}\n {} {}:{line}:{column}\n",
+        Style::new()
+            .fg(Color::Grey)
+            .apply_with(diagnostic.code, caps),
+        // bold in the terminal's own color, so it reads on light and dark backgrounds.
+        Style::new().bold().apply_with(&diagnostic.message, caps),
+        GUTTER.apply_with("-->", caps),
+        ctx.file,
+    );
+    out.push_str(&render_snippet(
+        diagnostic.span,
+        &diagnostic.labels,
+        &ctx.index,
+        ctx.source,
+        caps,
+    ));
+
+    for note in &diagnostic.notes {
+        out.push_str(&format!(
+            "{}: {}\n",
+            Style::new().fg(Color::Cyan).bold().apply_with("note", caps),
+            note.message
+        ));
+        if let Some(span) = note.span {
+            out.push_str(&render_snippet(span, &[], &ctx.index, ctx.source, caps));
+        }
+    }
+
+    if let Some(suggestion) = &diagnostic.suggestion {
+        out.push_str(&format!(
+            "{}: {suggestion}\n",
+            Style::new()
+                .fg(Color::Green)
+                .bold()
+                .apply_with("help", caps)
+        ));
+    }
+
+    if diagnostic.suppressed {
+        out.push_str(&format!(
+            "{}: suppressed by a comment, it doesn't count\n",
+            Style::new().fg(Color::Cyan).bold().apply_with("note", caps)
+        ));
+    }
+
+    out
+}
+
+/// Renders a diagnostic as a single line JSON object.
+///
+/// The object has the keys `file`, `severity`, `code`, `message`, `start` and `end` (both
+/// `{"line", "column"}`, 1-based, the end is exclusive), `related` (the labels, objects with
+/// a `message`, `start` and `end`), `notes` (the same, but `start` and `end` are null if the
+/// note doesn't point anywhere), `suggestion` which may be null and `suppressed`, whether a
+/// comment silenced it.
+pub fn render_json(diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+    let ((line, column), (end_line, end_column)) = ctx.positions(diagnostic.span);
+
+    // a message along with the `start` and `end` keys of where it points at.
+    let located = |message: &str, span: Option<Span>| {
+        let (start, end) = match span {
+            Some(span) => {
+                let ((line, column), (end_line, end_column)) = ctx.positions(span);
+                (
+                    format!("{{\"line\": {line}, \"column\": {column}}}"),
+                    format!("{{\"line\": {end_line}, \"column\": {end_column}}}"),
+                )
+            }
+            None => ("null".to_string(), "null".to_string()),
+        };
+        format!(
+            "{{\"message\": {}, \"start\": {start}, \"end\": {end}}}",
+            json::string(message)
+        )
+    };
+
+    let related = diagnostic
+        .labels
+        .iter()
+        .map(|label| located(&label.message, Some(label.span)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let notes = diagnostic
+        .notes
+        .iter()
+        .map(|note| located(&note.message, note.span))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let suggestion = match &diagnostic.suggestion {
+        Some(s) => json::string(s),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\"file\": {}, \"severity\": \"{}\", \"code\": \"{}\", \"message\": {}, \
+         \"start\": {{\"line\": {line}, \"column\": {column}}}, \
+         \"end\": {{\"line\": {end_line}, \"column\": {end_column}}}, \"related\": [{related}], \
+         \"notes\": [{notes}], \
+         \"suggestion\": {suggestion}, \"suppressed\": {}}}\n",
+        json::string(ctx.file),
+        diagnostic.severity.as_str(),
+        diagnostic.code,
+        json::string(&diagnostic.message),
+        diagnostic.suppressed,
+    )
+}
+
+/// Renders the diagnostics as a JSON array of the objects `render_json` writes.
+pub fn render_json_array(diagnostics: &[Diagnostic], ctx: &SourceContext) -> String {
+    let objects = diagnostics
+        .iter()
+        .map(|d| render_json(d, ctx).trim_end().to_string())
+        .collect::<Vec<_>>();
+    format!("[{}]", objects.join(", "))
+}
+
+/// Escapes the characters that have a meaning in GitHub workflow commands.
+fn github_escape(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// Renders a diagnostic as a GitHub Actions `::error` or `::warning` annotation.
+pub fn render_github(diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+    let ((line, column), (end_line, end_column)) = ctx.positions(diagnostic.span);
+
+    // annotations only point at one place, so the labels and notes are folded into the message.
+    let mut message = diagnostic.message.clone();
+    for label in &diagnostic.labels {
+        let ((line, column), _) = ctx.positions(label.span);
+        message.push_str(&format!("\n{line}:{column}: {}", label.message));
+    }
+    for note in &diagnostic.notes {
+        message.push_str(&format!("\nnote: {}", note.message));
+    }
+
+    format!(
+        "::{} file={},line={line},col={column},endLine={end_line},endColumn={end_column},title={}::{}\n",
+        diagnostic.severity.as_str(),
+        github_escape(ctx.file),
+        diagnostic.code,
+        // the message isn't a property, so only the line breaks and percent signs are special.
+        message
+            .replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A"),
+    )
+}
+
+/// Renders a diagnostic on a single line, without its notes, the way compilers do for the
+/// tools that only look for `file:line:column:`.
+///
+/// ```
+/// use lua_compiler::diagnostic::{render_short, SourceContext};
+///
+/// let result = lua_compiler::compile_source("main.lua", "local x = = 1", &Default::default());
+/// let ctx = SourceContext::new("main.lua", "local x = = 1");
+/// let line = render_short(&result.diagnostics[0], &ctx);
+/// assert!(line.starts_with("main.lua:1:11: error: [E"));
+/// ```
+pub fn render_short(diagnostic: &Diagnostic, ctx: &SourceContext) -> String {
+    let ((line, column), _) = ctx.positions(diagnostic.span);
+    let suppressed = match diagnostic.suppressed {
+        true => " (suppressed)",
+        false => "",
+    };
+    format!(
+        "{}:{line}:{column}: {}: [{}] {}{suppressed}\n",
+        ctx.file,
+        diagnostic.severity.as_str(),
+        diagnostic.code,
+        // a message doesn't take more than its line.
+        diagnostic.message.replace('\n', " "),
+    )
+}
+
+/// Renders a diagnostic in the given format.
+pub fn render(
+    diagnostic: &Diagnostic,
+    ctx: &SourceContext,
+    format: DiagnosticsFormat,
+    caps: OutputCaps,
+) -> String {
+    format.renderer(caps).render(diagnostic, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "local x = = 1\n";
+
+    fn diagnostic() -> Diagnostic {
+        let span = Span {
+            line: 1,
+            column: 11,
+            start: 10,
+            end: 11,
+        };
+        Diagnostic::error("E100", "expected <exp>".to_string(), span)
+            .with_note("assignments take one '='".to_string(), None)
+    }
+
+    fn render(format: DiagnosticsFormat, color: bool) -> String {
+        let ctx = SourceContext::new("main.lua", SOURCE);
+        let caps = OutputCaps {
+            color,
+            unicode: true,
+        };
+        format.renderer(caps).render(&diagnostic(), &ctx)
+    }
+
+    #[test]
+    fn every_renderer_over_the_same_diagnostic() {
+        assert_eq!(
+            render(DiagnosticsFormat::Human, false),
+            "error: [E100] expected <exp>\n --> main.lua:1:11\n  |\n1 | local x = = 1\n  |           ^\n\
+             note: assignments take one '='\n"
+        );
+        assert_eq!(
+            render(DiagnosticsFormat::Json, false),
+            "{\"file\": \"main.lua\", \"severity\": \"error\", \"code\": \"E100\", \
+             \"message\": \"expected <exp>\", \"start\": {\"line\": 1, \"column\": 11}, \
+             \"end\": {\"line\": 1, \"column\": 12}, \"related\": [], \
+             \"notes\": [{\"message\": \"assignments take one '='\", \"start\": null, \"end\": null}], \
+             \"suggestion\": null, \"suppressed\": false}\n"
+        );
+        assert_eq!(
+            render(DiagnosticsFormat::Github, false),
+            "::error file=main.lua,line=1,col=11,endLine=1,endColumn=12,title=E100::\
+             expected <exp>%0Anote: assignments take one '='\n"
+        );
+        assert_eq!(
+            render(DiagnosticsFormat::Short, false),
+            "main.lua:1:11: error: [E100] expected <exp>\n"
+        );
+    }
+
+    #[test]
+    fn human_colors_go_by_the_caps_given() {
+        // whatever the process-wide caps are, the renderer's own decide.
+        let plain = render(DiagnosticsFormat::Human, false);
+        assert!(!plain.contains('\x1b'), "{plain:?}");
+
+        let colored = render(DiagnosticsFormat::Human, true);
+        assert!(colored.starts_with("\x1b[1;91merror\x1b[0m: [\x1b[90mE100\x1b[0m]"));
+        assert!(colored.contains("\x1b[1;96mnote\x1b[0m"), "{colored:?}");
+    }
+
+    #[test]
+    fn organized_diagnostics_are_in_source_order_without_duplicates() {
+        let at = |start: usize| Span {
+            line: 1,
+            column: start + 1,
+            start,
+            end: start + 1,
+        };
+        let unused =
+            |start| Diagnostic::warning("W100", "unused variable 'x'".to_string(), at(start));
+        let error = |start| Diagnostic::error("E100", "unexpected symbol".to_string(), at(start));
+
+        // found by different passes, so out of order, with one of them twice.
+        let found = vec![unused(9), error(4), unused(2), unused(9), error(2)];
+        let organized = organize(found, None);
+        let order = organized
+            .iter()
+            .map(|d| (d.span.start, d.code))
+            .collect::<Vec<_>>();
+        // an error comes before a warning at the same place.
+        assert_eq!(order, [(2, "E100"), (2, "W100"), (4, "E100"), (9, "W100")]);
+
+        // past the threshold, the repeats are notes of the first.
+        let found = vec![unused(9), unused(5), unused(2), error(4)];
+        let grouped = organize(found.clone(), Some(2));
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(
+            grouped[0].message,
+            "unused variable 'x' (and 2 more occurrences)"
+        );
+        let also = grouped[0]
+            .notes
+            .iter()
+            .map(|n| n.span.unwrap().start)
+            .collect::<Vec<_>>();
+        assert_eq!(also, [5, 9]);
+        assert_eq!(grouped[1].code, "E100");
+
+        // up to it, they're left alone.
+        assert_eq!(organize(found, Some(3)).len(), 4);
+    }
+
+    #[test]
+    fn labels_point_at_the_other_places_in_every_format() {
+        let source = "if a then\n  x = 1\n)\n";
+        let span = |line, start: usize, end| Span {
+            line,
+            column: 1,
+            start,
+            end,
+        };
+        let d = Diagnostic::error(
+            "E100",
+            "')' doesn't close the 'if'".to_string(),
+            span(3, 18, 19),
+        )
+        .with_label(span(1, 0, 2), "`if` opened here".to_string());
+        let ctx = SourceContext::new("main.lua", source);
+        let plain = OutputCaps {
+            color: false,
+            unicode: true,
+        };
+
+        assert_eq!(
+            DiagnosticsFormat::Human.renderer(plain).render(&d, &ctx),
+            "error: [E100] ')' doesn't close the 'if'\n --> main.lua:3:1\n  |\n\
+             1 | if a then\n  | -- `if` opened here\n\
+             ...\n\
+             3 | )\n  | ^\n"
+        );
+        let json = DiagnosticsFormat::Json.renderer(plain).render(&d, &ctx);
+        assert!(
+            json.contains(
+                "\"related\": [{\"message\": \"`if` opened here\", \
+                 \"start\": {\"line\": 1, \"column\": 1}, \"end\": {\"line\": 1, \"column\": 3}}]"
+            ),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn full_and_dumb_caps_render_the_same_diagnostic() {
+        let source = format!("local s = \"{}\" .. = 1\n", "a".repeat(100));
+        let at = source.find('=').unwrap() + 1;
+        let at = source[at..].find('=').unwrap() + at;
+        let d = Diagnostic::error(
+            "E100",
+            "unexpected symbol".to_string(),
+            Span {
+                line: 1,
+                column: at + 1,
+                start: at,
+                end: at + 1,
+            },
+        )
+        .with_note("a '..' needs a value on its right".to_string(), None);
+        let ctx = SourceContext::new("main.lua", &source);
+
+        let full = OutputCaps {
+            color: true,
+            unicode: true,
+        };
+        let dumb = OutputCaps {
+            color: false,
+            unicode: false,
+        };
+        let kept = "a".repeat(20);
+        assert_eq!(
+            DiagnosticsFormat::Human.renderer(full).render(&d, &ctx),
+            format!(
+                "\x1b[1;91merror\x1b[0m: [\x1b[90mE100\x1b[0m] \x1b[1munexpected symbol\x1b[0m\n \
+                 \x1b[2;90m-->\x1b[0m main.lua:1:117\n  \x1b[2;90m|\x1b[0m\n\
+                 \x1b[2;90m1\x1b[0m \x1b[2;90m|\x1b[0m …{kept}\" .. = 1\n  \
+                 \x1b[2;90m|\x1b[0m {:26}\x1b[1;91m^\x1b[0m\n\
+                 \x1b[1;96mnote\x1b[0m: a '..' needs a value on its right\n",
+                ""
+            )
+        );
+        // no escape sequences, and three dots for the `…` the caret is still under.
+        assert_eq!(
+            DiagnosticsFormat::Human.renderer(dumb).render(&d, &ctx),
+            format!(
+                "error: [E100] unexpected symbol\n --> main.lua:1:117\n  |\n\
+                 1 | ...{kept}\" .. = 1\n  | {:28}^\n\
+                 note: a '..' needs a value on its right\n",
+                ""
+            )
+        );
+    }
+}
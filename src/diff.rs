@@ -0,0 +1,245 @@
+use crate::lexer::Span;
+use crate::parser::ASTNode;
+use crate::snippet::LineIndex;
+
+/// A parsed source, as the diff needs it.
+pub struct Side<'a> {
+    pub source: &'a str,
+    pub ast: &'a ASTNode,
+    // where each statement of the main chunk is, see `Parser::statement_spans`.
+    pub statement_spans: &'a [Span],
+}
+
+/// How a statement differs between the two sources.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A statement of the main chunk that differs between the two sources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    // what the statement is, e.g. "function 'update'" or "statement".
+    pub what: String,
+    // the 1-based lines of the statement, in the new source unless it was removed.
+    pub lines: (usize, usize),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let lines = match self.lines {
+            (start, end) if start == end => format!("line {start}"),
+            (start, end) => format!("lines {start}–{end}"),
+        };
+
+        match self.kind {
+            ChangeKind::Added => write!(f, "{} added at {lines}", self.what),
+            ChangeKind::Removed => write!(f, "{} removed (was at {lines})", self.what),
+            ChangeKind::Modified => write!(f, "{} modified ({lines})", self.what),
+        }
+    }
+}
+
+/// A statement of the main chunk along with what it's matched on.
+struct Statement<'a> {
+    node: &'a ASTNode,
+    // the kind of statement and the name it defines, if any.
+    key: (&'static str, Option<String>),
+    lines: (usize, usize),
+}
+
+/// Compares the statements of the main chunks of two sources.
+///
+/// The syntax trees don't hold any formatting or comments, so a source that was only
+/// reformatted has no changes. Statements are aligned on their kind and the name they define,
+/// the longest sequence of those the two have in common, and the aligned pairs whose trees
+/// differ are reported as modified.
+pub fn diff(old: &Side, new: &Side) -> Vec<Change> {
+    let old = statements(old);
+    let new = statements(new);
+
+    // lengths of the longest common subsequences of keys, of every pair of suffixes.
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i].key == new[j].key {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    let change = |kind, statement: &Statement| Change {
+        kind,
+        what: describe(&statement.key),
+        lines: statement.lines,
+    };
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i].key == new[j].key {
+            if old[i].node != new[j].node {
+                changes.push(change(ChangeKind::Modified, &new[j]));
+            }
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            changes.push(change(ChangeKind::Added, &new[j]));
+            j += 1;
+        } else {
+            changes.push(change(ChangeKind::Removed, &old[i]));
+            i += 1;
+        }
+    }
+
+    changes
+}
+
+fn statements<'a>(side: &Side<'a>) -> Vec<Statement<'a>> {
+    let ASTNode::Chunk(statements, last) = side.ast else {
+        return Vec::new();
+    };
+
+    let index = LineIndex::new(side.source);
+    let lines = |i: usize| {
+        let span = side.statement_spans.get(i).copied().unwrap_or_default();
+        // the end is exclusive, so the statement ends on the line of the byte before it.
+        let end = span.end.saturating_sub(1).max(span.start);
+        (index.line_of(span.start), index.line_of(end))
+    };
+
+    statements
+        .iter()
+        .chain(last.as_deref())
+        .enumerate()
+        .map(|(i, node)| Statement {
+            node,
+            key: key(node),
+            lines: lines(i),
+        })
+        .collect()
+}
+
+/// Returns the kind of a statement and the name it defines.
+fn key(statement: &ASTNode) -> (&'static str, Option<String>) {
+    let node = match statement {
+        ASTNode::Statement(node) | ASTNode::LastStatement(node) => &**node,
+        node => node,
+    };
+
+    match node {
+        ASTNode::FunctionStatement { func_name, .. } => ("function", Some(dotted(func_name))),
+        ASTNode::LocalFunction { name, .. } => ("local function", Some(dotted(name))),
+        ASTNode::LocalVariable { name_list, .. } => ("local", Some(dotted(name_list))),
+        node => (node.name(), None),
+    }
+}
+
+/// Joins the names below a node the way they're written, e.g. `M.obj:method` or `a, b`.
+fn dotted(node: &ASTNode) -> String {
+    match node {
+        ASTNode::Name(name) => name.clone(),
+        ASTNode::FunctionName {
+            name,
+            members,
+            colon,
+        } => {
+            let mut out = dotted(name);
+            for member in members {
+                out.push('.');
+                out.push_str(&dotted(member));
+            }
+            if let Some(method) = colon {
+                out.push(':');
+                out.push_str(&dotted(method));
+            }
+            out
+        }
+        node => node
+            .children()
+            .into_iter()
+            .map(dotted)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+fn describe((kind, name): &(&'static str, Option<String>)) -> String {
+    match name {
+        Some(name) => format!("{kind} '{name}'"),
+        None => "statement".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompileResult, Compiler};
+
+    fn compile(source: &str) -> CompileResult {
+        Compiler::default().compile("main.lua", source)
+    }
+
+    fn side<'a>(source: &'a str, result: &'a CompileResult) -> Side<'a> {
+        Side {
+            source,
+            ast: result.ast.as_ref().unwrap(),
+            statement_spans: &result.statement_spans,
+        }
+    }
+
+    fn changes(old: &str, new: &str) -> Vec<String> {
+        let (a, b) = (compile(old), compile(new));
+        diff(&side(old, &a), &side(new, &b))
+            .iter()
+            .map(Change::to_string)
+            .collect()
+    }
+
+    const OLD: &str = "\
+local cache = {}
+
+local function update(dt)
+  cache.t = dt
+end
+
+function M.draw()
+  print(cache.t)
+end
+";
+
+    #[test]
+    fn a_reformatted_source_has_no_changes() {
+        let new = "local cache={}\n-- a comment\nlocal function update( dt ) cache.t=dt end\n\
+                   function M.draw() print( cache.t ) end";
+        assert!(changes(OLD, new).is_empty());
+    }
+
+    #[test]
+    fn statements_are_added_removed_and_modified() {
+        let new = "\
+local function update(dt)
+  cache.t = dt * 2
+end
+
+print(\"drawing\")
+
+function M.draw()
+  print(cache.t)
+end
+";
+        assert_eq!(
+            changes(OLD, new),
+            [
+                "local 'cache' removed (was at line 1)",
+                "local function 'update' modified (lines 1–3)",
+                "statement added at line 5",
+            ]
+        );
+    }
+}
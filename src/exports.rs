@@ -0,0 +1,275 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::require::{find_requires, Require};
+use crate::resolve::resolve;
+
+/// A field of the table a module returns, e.g. the `new` of `function M.new()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    pub name: String,
+    // covers the name of the field where it's first assigned.
+    pub span: Span,
+}
+
+/// How a module that requires another uses what it gets back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleUse {
+    pub require: Require,
+    // the fields read from the module, or None if it's used in a way that could read any,
+    // like being passed to a function.
+    pub fields: Option<BTreeSet<String>>,
+}
+
+/// Finds the fields of the table the module returns.
+///
+/// The module has to end with `return M` or `return { ... }`. The fields of `M` are the ones
+/// assigned with `M.name = ...` or `function M.name()`, and those of the table constructor `M`
+/// was declared with. A module that ends any other way exports nothing that can be told apart.
+pub fn find_exports(tokens: &[SpannedToken]) -> Vec<Export> {
+    let mut end = tokens.len();
+    if end > 0 && tokens[end - 1].token == Token::SEMICOLON {
+        end -= 1;
+    }
+
+    let mut exports = Vec::new();
+    match &tokens[..end] {
+        [.., t, SpannedToken {
+            token: Token::NAME(module),
+            ..
+        }] if t.token == Token::RETURN => {
+            for (i, t) in tokens.iter().enumerate() {
+                if !matches!(&t.token, Token::NAME(name) if name == module) {
+                    continue;
+                }
+                // `other.M` is someone else's field.
+                if i > 0 && matches!(tokens[i - 1].token, Token::DOT | Token::COLON) {
+                    continue;
+                }
+
+                let is_local = i >= 1 && tokens[i - 1].token == Token::LOCAL;
+                match &tokens[i + 1..] {
+                    [SpannedToken {
+                        token: Token::ASSIGN,
+                        ..
+                    }, brace, ..]
+                        if is_local && brace.token == Token::LEFT_BRACE =>
+                    {
+                        exports.extend(table_keys(tokens, i + 2));
+                    }
+                    [dot, SpannedToken {
+                        token: Token::NAME(field),
+                        span,
+                    }, after, ..]
+                        if matches!(dot.token, Token::DOT | Token::COLON) =>
+                    {
+                        let defines_function = i > 0 && tokens[i - 1].token == Token::FUNCTION;
+                        if after.token == Token::ASSIGN || defines_function {
+                            exports.push(Export {
+                                name: field.clone(),
+                                span: *span,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        [.., t] if t.token == Token::RIGHT_BRACE => {
+            // the constructor the module ends with, found back from its closing brace.
+            let mut depth = 0;
+            let mut open = None;
+            for (i, t) in tokens[..end].iter().enumerate().rev() {
+                match t.token {
+                    Token::RIGHT_BRACE | Token::RIGHT_BRACKET | Token::RIGHT_PAREN => depth += 1,
+                    Token::LEFT_BRACE | Token::LEFT_BRACKET | Token::LEFT_PAREN => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 {
+                    open = Some(i);
+                    break;
+                }
+            }
+
+            if let Some(open) = open.filter(|&i| i > 0 && tokens[i - 1].token == Token::RETURN) {
+                exports.extend(table_keys(tokens, open));
+            }
+        }
+        _ => {}
+    }
+
+    // a field assigned twice is exported once, where it's first assigned.
+    let mut seen = BTreeSet::new();
+    exports.retain(|e| seen.insert(e.name.clone()));
+    exports
+}
+
+/// Returns the `name = value` keys of the table constructor whose `{` is at `open`.
+fn table_keys(tokens: &[SpannedToken], open: usize) -> Vec<Export> {
+    let mut keys = Vec::new();
+    let mut depth = 0;
+
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        match &t.token {
+            Token::LEFT_BRACE | Token::LEFT_BRACKET | Token::LEFT_PAREN => depth += 1,
+            Token::RIGHT_BRACE | Token::RIGHT_BRACKET | Token::RIGHT_PAREN => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            Token::NAME(name) if depth == 1 => {
+                let starts_field = matches!(
+                    tokens[i - 1].token,
+                    Token::LEFT_BRACE | Token::COMMA | Token::SEMICOLON
+                );
+                let assigned = tokens.get(i + 1).map(|t| &t.token) == Some(&Token::ASSIGN);
+                if starts_field && assigned {
+                    keys.push(Export {
+                        name: name.clone(),
+                        span: t.span,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    keys
+}
+
+/// Finds every module the source requires, along with the fields it reads from each.
+///
+/// A field is read from `require "m".name`, or from a local the module is assigned to, as in
+/// `local m = require "m"` and then `m.name` or `m:name()`. Any other use of the module gives
+/// None, since what it's used for can't be followed without running the code.
+pub fn find_module_uses(tokens: &[SpannedToken]) -> Vec<ModuleUse> {
+    let by_start = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.span.start, i))
+        .collect::<HashMap<_, _>>();
+    let by_end = tokens
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.span.end, i))
+        .collect::<HashMap<_, _>>();
+    let bindings = resolve(tokens);
+
+    // the field after the token at `i`, as in `.name` or `:name`.
+    let field_after = |i: usize| match &tokens[i + 1..] {
+        [dot, SpannedToken {
+            token: Token::NAME(field),
+            ..
+        }, ..]
+            if matches!(dot.token, Token::DOT | Token::COLON) =>
+        {
+            Some(field.clone())
+        }
+        _ => None,
+    };
+
+    find_requires(tokens)
+        .into_iter()
+        .map(|require| {
+            let (Some(&first), Some(&last)) = (
+                by_start.get(&require.span.start),
+                by_end.get(&require.span.end),
+            ) else {
+                return ModuleUse {
+                    require,
+                    fields: None,
+                };
+            };
+
+            if let Some(field) = field_after(last) {
+                return ModuleUse {
+                    require,
+                    fields: Some(BTreeSet::from([field])),
+                };
+            }
+
+            // `local m = require "m"`, the module is read through `m`.
+            let local = match first.checked_sub(3).map(|i| &tokens[i..first]) {
+                Some(
+                    [SpannedToken {
+                        token: Token::LOCAL,
+                        ..
+                    }, name, SpannedToken {
+                        token: Token::ASSIGN,
+                        ..
+                    }],
+                ) if matches!(name.token, Token::NAME(_)) => {
+                    bindings.iter().find(|b| b.declaration == Some(name.span))
+                }
+                _ => None,
+            };
+
+            let fields = local.and_then(|binding| {
+                binding
+                    .references
+                    .iter()
+                    .map(|r| by_start.get(&r.start).and_then(|&i| field_after(i)))
+                    .collect::<Option<BTreeSet<_>>>()
+            });
+
+            ModuleUse { require, fields }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn exports(source: &str) -> Vec<String> {
+        find_exports(&Lexer::new(source).tokenize().unwrap())
+            .into_iter()
+            .map(|e| e.name)
+            .collect()
+    }
+
+    fn uses(source: &str) -> Vec<(String, Option<Vec<String>>)> {
+        find_module_uses(&Lexer::new(source).tokenize().unwrap())
+            .into_iter()
+            .map(|u| (u.require.name, u.fields.map(|f| f.into_iter().collect())))
+            .collect()
+    }
+
+    #[test]
+    fn the_fields_of_the_returned_table_are_exported() {
+        assert_eq!(
+            exports(
+                "local M = {version = 1, [k] = 2}\nfunction M.new() end\nfunction M:close() end\n\
+                 M.version = 2\nother.M.hidden = 3\nreturn M\n"
+            ),
+            ["version", "new", "close"]
+        );
+        assert_eq!(
+            exports("local x = 1\nreturn {run = run; size = #x, t = {inner = 1}};"),
+            ["run", "size", "t"]
+        );
+        // a module that returns anything else exports nothing that can be told apart.
+        assert!(exports("local M = {a = 1}\nreturn setmetatable(M, mt)\n").is_empty());
+        assert!(exports("print(1)\n").is_empty());
+    }
+
+    #[test]
+    fn the_fields_read_through_a_require_are_found() {
+        let fields = |names: &[&str]| Some(names.iter().map(|n| n.to_string()).collect());
+        assert_eq!(
+            uses(
+                "local a = require 'a'\nprint(a.x, a:y())\nlocal z = require('b').z\n\
+                 local c = require 'c'\nf(c)\nrequire 'd'\n"
+            ),
+            [
+                ("a".to_string(), fields(&["x", "y"])),
+                ("b".to_string(), fields(&["z"])),
+                // passed to a function, which could read anything.
+                ("c".to_string(), None),
+                ("d".to_string(), None),
+            ]
+        );
+    }
+}
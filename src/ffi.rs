@@ -0,0 +1,131 @@
+//! A C interface to the checker, for embedding it without spawning a process.
+//!
+//! ```c
+//! char *diagnostics = luac_check(source, strlen(source));
+//! puts(diagnostics);
+//! luac_free_string(diagnostics);
+//! ```
+//!
+//! `python/lua_compiler.py` wraps these with ctypes, for Python to call without a subprocess.
+
+use std::ffi::{c_char, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::diagnostic::{render_json_array, SourceContext};
+use crate::{ast, compile_source, json, CompileResult, Options};
+
+// the version as a C string, so it can be handed out without allocating.
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Checks `len` bytes of UTF-8 source, returning its diagnostics as a JSON array.
+///
+/// The elements are the `--diagnostics-format json` objects. If the source isn't UTF-8 or the
+/// checker crashes, an object with an `error` message is returned instead. The string has to be
+/// released with `luac_free_string`.
+///
+/// # Safety
+///
+/// `source` has to point to `len` readable bytes, it doesn't have to be null terminated.
+#[no_mangle]
+pub unsafe extern "C" fn luac_check(source: *const c_char, len: usize) -> *mut c_char {
+    call(source, len, check)
+}
+
+/// Parses `len` bytes of UTF-8 source, returning `{"ast": ..., "diagnostics": [...]}`.
+///
+/// The tree is in the form of `ast::to_json`, or null if the source didn't parse. Errors are
+/// reported the same way as by `luac_check`, and the string has to be released with
+/// `luac_free_string` too.
+///
+/// # Safety
+///
+/// `source` has to point to `len` readable bytes, it doesn't have to be null terminated.
+#[no_mangle]
+pub unsafe extern "C" fn luac_parse(source: *const c_char, len: usize) -> *mut c_char {
+    call(source, len, parse)
+}
+
+/// Releases a string returned by `luac_check` or `luac_parse`, null is ignored.
+///
+/// # Safety
+///
+/// `string` has to come from `luac_check` or `luac_parse` and can't be used after, or
+/// released twice.
+#[no_mangle]
+pub unsafe extern "C" fn luac_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Returns the version of the compiler, the string is static and must not be released.
+#[no_mangle]
+pub extern "C" fn luac_version() -> *const c_char {
+    VERSION.as_ptr().cast()
+}
+
+/// Runs `f` over the source, turning a crash or a source that isn't UTF-8 into an error.
+unsafe fn call(source: *const c_char, len: usize, f: fn(&str) -> String) -> *mut c_char {
+    let bytes = match source.is_null() {
+        true => &[][..],
+        false => std::slice::from_raw_parts(source.cast::<u8>(), len),
+    };
+
+    // unwinding into C is undefined behavior, so a crash has to stop here.
+    let result = catch_unwind(AssertUnwindSafe(|| match std::str::from_utf8(bytes) {
+        Ok(source) => f(source),
+        Err(_) => error("the source isn't valid UTF-8"),
+    }));
+
+    let json = result.unwrap_or_else(|_| error("internal compiler error"));
+    // json strings escape control characters, so there can't be a null byte in it.
+    CString::new(json).unwrap_or_default().into_raw()
+}
+
+fn check(source: &str) -> String {
+    let file = "<source>";
+    let result = compile_source(file, source, &Options::default());
+    diagnostics(file, source, &result)
+}
+
+fn parse(source: &str) -> String {
+    let file = "<source>";
+    let result = compile_source(file, source, &Options::default());
+    let ast = match &result.ast {
+        Some(ast) => ast::to_json(ast),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\"ast\": {ast}, \"diagnostics\": {}}}",
+        diagnostics(file, source, &result)
+    )
+}
+
+fn diagnostics(file: &str, source: &str, result: &CompileResult) -> String {
+    render_json_array(&result.diagnostics, &SourceContext::new(file, source))
+}
+
+fn error(message: &str) -> String {
+    format!("{{\"error\": {}}}", json::string(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_crash_is_caught_and_turned_into_an_error() {
+        let source = "return 1";
+        let json = unsafe {
+            let string = call(source.as_ptr().cast(), source.len(), |_| panic!("boom"));
+            let json = std::ffi::CStr::from_ptr(string)
+                .to_str()
+                .unwrap()
+                .to_string();
+            luac_free_string(string);
+            json
+        };
+        assert_eq!(json, "{\"error\": \"internal compiler error\"}");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::json;
+use crate::lexer::{find_comments, long_bracket, SpannedToken, Token};
+use crate::snippet::LineIndex;
+
+/// What a fold range covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FoldKind {
+    Function,
+    Do,
+    // a `while`, `for` or `repeat` loop.
+    Loop,
+    // one branch of an `if`, from its `if`, `elseif` or `else`.
+    If,
+    Comment,
+    String,
+}
+
+impl FoldKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FoldKind::Function => "function",
+            FoldKind::Do => "do",
+            FoldKind::Loop => "loop",
+            FoldKind::If => "if",
+            FoldKind::Comment => "comment",
+            FoldKind::String => "string",
+        }
+    }
+}
+
+/// A range of lines an editor can fold, both 1-based and inclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldRange {
+    pub kind: FoldKind,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// A block whose closing keyword hasn't been found yet.
+struct Open {
+    kind: FoldKind,
+    line: usize,
+    // a `while` or `for` whose `do` is still to come, which doesn't open a block of its own.
+    awaits_do: bool,
+}
+
+/// Finds the ranges of lines the source can be folded at: function bodies, `do` blocks, loops,
+/// the branches of an `if`, and the long comments and long strings spanning several lines.
+///
+/// A block's range ends on the line before its `end`, `until`, `elseif` or `else`, so that the
+/// keyword stays visible once it's folded, unless the keyword follows code on its line. This
+/// works on tokens so a source that doesn't parse still gets its folds, and a block that is
+/// never closed runs until the end of the source.
+///
+/// ```
+/// use lua_compiler::folds::{fold_ranges, FoldKind};
+/// use lua_compiler::lexer::Lexer;
+///
+/// let code = "function f()\n  if x then\n    y()\n  else\n    z()\n  end\nend\n";
+/// let tokens = Lexer::new(code).tokenize().unwrap();
+/// let folds = fold_ranges(code, &tokens)
+///     .iter()
+///     .map(|f| (f.kind, f.start_line, f.end_line))
+///     .collect::<Vec<_>>();
+/// assert_eq!(
+///     folds,
+///     [(FoldKind::Function, 1, 6), (FoldKind::If, 2, 3), (FoldKind::If, 4, 5)]
+/// );
+/// ```
+pub fn fold_ranges(source: &str, tokens: &[SpannedToken]) -> Vec<FoldRange> {
+    let index = LineIndex::new(source);
+    let mut folds = Vec::new();
+    let mut open: Vec<Open> = Vec::new();
+
+    let mut fold = |kind: FoldKind, start_line: usize, end_line: usize| {
+        if end_line > start_line {
+            folds.push(FoldRange {
+                kind,
+                start_line,
+                end_line,
+            });
+        }
+    };
+
+    for (i, t) in tokens.iter().enumerate() {
+        let line = index.line_of(t.span.start);
+        // the line the block before a closing keyword ends on, which is the keyword's own
+        // when code precedes it there, like `x = 1 end`.
+        let closed_at = match i.checked_sub(1).map(|i| &tokens[i]) {
+            Some(previous) if index.line_of(previous.span.start) == line => line,
+            _ => line - 1,
+        };
+        let opened = |kind: FoldKind, awaits_do: bool| Open {
+            kind,
+            line,
+            awaits_do,
+        };
+
+        match t.token {
+            Token::FUNCTION => open.push(opened(FoldKind::Function, false)),
+            Token::WHILE | Token::FOR => open.push(opened(FoldKind::Loop, true)),
+            Token::REPEAT => open.push(opened(FoldKind::Loop, false)),
+            Token::IF => open.push(opened(FoldKind::If, false)),
+            Token::DO => match open.last_mut() {
+                Some(block) if block.awaits_do => block.awaits_do = false,
+                _ => open.push(opened(FoldKind::Do, false)),
+            },
+            Token::ELSEIF | Token::ELSE => {
+                if let Some(block) = open.last_mut().filter(|b| b.kind == FoldKind::If) {
+                    fold(FoldKind::If, block.line, closed_at);
+                    block.line = line;
+                }
+            }
+            // a stray `end` of a source that doesn't parse is skipped.
+            Token::END | Token::UNTIL => {
+                if let Some(block) = open.pop() {
+                    fold(block.kind, block.line, closed_at);
+                }
+            }
+            Token::STRING(_) if source[t.span.start..].starts_with('[') => {
+                fold(
+                    FoldKind::String,
+                    line,
+                    index.line_of(t.span.end.max(t.span.start + 1) - 1),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    let last_line = index.line_of(source.trim_end().len());
+    for block in open {
+        fold(block.kind, block.line, last_line);
+    }
+
+    for c in find_comments(source, tokens) {
+        if long_bracket(&source[c.start + 2..c.end]).is_some() {
+            fold(
+                FoldKind::Comment,
+                index.line_of(c.start),
+                index.line_of(c.end.max(c.start + 1) - 1),
+            );
+        }
+    }
+
+    folds.sort_by_key(|f| (f.start_line, std::cmp::Reverse(f.end_line)));
+    folds
+}
+
+/// Renders the ranges as a JSON array.
+pub fn to_json(folds: &[FoldRange]) -> String {
+    let items = folds
+        .iter()
+        .map(|f| {
+            format!(
+                "{{\"kind\": {}, \"start_line\": {}, \"end_line\": {}}}",
+                json::string(f.kind.as_str()),
+                f.start_line,
+                f.end_line
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]\n", items.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn folds(source: &str) -> Vec<(FoldKind, usize, usize)> {
+        fold_ranges(source, &Lexer::new(source).tokenize().unwrap())
+            .iter()
+            .map(|f| (f.kind, f.start_line, f.end_line))
+            .collect()
+    }
+
+    #[test]
+    fn a_closing_keyword_after_code_stays_in_the_fold() {
+        assert_eq!(
+            folds("while x do\n  x = f() end\nrepeat\n  y()\nuntil y\n"),
+            [(FoldKind::Loop, 1, 2), (FoldKind::Loop, 3, 4)]
+        );
+        // a block on a single line doesn't fold.
+        assert!(folds("for i = 1, 2 do print(i) end\n").is_empty());
+    }
+
+    #[test]
+    fn a_source_that_does_not_parse_still_folds() {
+        // the unclosed function runs to the end, the stray `end` is skipped.
+        assert_eq!(
+            folds("end\nlocal function f()\n  do\n    x = = 1\n  end\n\n  y()\n\n"),
+            [(FoldKind::Function, 2, 7), (FoldKind::Do, 3, 4)]
+        );
+    }
+
+    #[test]
+    fn only_long_comments_and_strings_fold() {
+        assert_eq!(
+            folds("-- one\n-- two\nx = 'a\\\nb'\n--[==[\n]==]\ny = [[\n\n]]\n"),
+            [(FoldKind::Comment, 5, 6), (FoldKind::String, 7, 9)]
+        );
+    }
+
+    #[test]
+    fn json_has_the_kind_and_both_lines() {
+        let folds = [FoldRange {
+            kind: FoldKind::Do,
+            start_line: 2,
+            end_line: 4,
+        }];
+        assert_eq!(
+            to_json(&folds),
+            "[{\"kind\": \"do\", \"start_line\": 2, \"end_line\": 4}]\n"
+        );
+        assert_eq!(to_json(&[]), "[]\n");
+    }
+}
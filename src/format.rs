@@ -0,0 +1,206 @@
+use std::ops::Range;
+
+use crate::lexer::{comment_len, SpannedToken, Token};
+
+// what a line is indented with for every block it's in.
+const INDENT: &str = "    ";
+
+/// Returns how a token changes the nesting of the lines after it, as the number of blocks it
+/// closes and then opens. `else` does both, `elseif` leaves the opening to its `then`.
+fn nesting(token: &Token) -> (usize, usize) {
+    match token {
+        Token::LEFT_PAREN
+        | Token::LEFT_BRACKET
+        | Token::LEFT_BRACE
+        | Token::FUNCTION
+        | Token::DO
+        | Token::THEN
+        | Token::REPEAT => (0, 1),
+        Token::RIGHT_PAREN
+        | Token::RIGHT_BRACKET
+        | Token::RIGHT_BRACE
+        | Token::END
+        | Token::UNTIL
+        | Token::ELSEIF => (1, 0),
+        Token::ELSE => (1, 1),
+        _ => (0, 0),
+    }
+}
+
+/// Returns the byte ranges of the comments between the tokens.
+fn comments(source: &str, tokens: &[SpannedToken]) -> Vec<Range<usize>> {
+    let mut comments = Vec::new();
+    let mut gap_start = 0;
+
+    let ends = tokens.iter().map(|t| t.span.start).chain([source.len()]);
+    for (i, gap_end) in ends.enumerate() {
+        // between two tokens there's only whitespace and comments.
+        let mut at = gap_start;
+        while let Some(found) = source[at..gap_end].find("--") {
+            let start = at + found;
+            let end = (start + comment_len(&source[start..])).min(gap_end);
+            comments.push(start..end);
+            at = end;
+        }
+        gap_start = tokens.get(i).map_or(gap_end, |t| t.span.end);
+    }
+
+    comments
+}
+
+/// Re-indents the source by how deep each line is nested in blocks, brackets and tables, and
+/// strips the whitespace at the end of its lines.
+///
+/// Everything else is kept as written, so formatting doesn't change what the source means. A
+/// line that starts inside a string or comment keeps its indentation, and the lines after one
+/// that opens several blocks only go one level deeper. The tokens have to be the source's.
+///
+/// ```
+/// use lua_compiler::format::format;
+/// use lua_compiler::lexer::Lexer;
+///
+/// let source = "if x then\nf(function()\nreturn 1   \nend)\nelse\n  -- none\nend\n";
+/// let tokens = Lexer::new(source).tokenize().unwrap();
+/// assert_eq!(
+///     format(source, &tokens),
+///     "if x then\n    f(function()\n        return 1\n    end)\nelse\n    -- none\nend\n"
+/// );
+/// ```
+pub fn format(source: &str, tokens: &[SpannedToken]) -> String {
+    // what spans lines and has to be kept as it is, multiline strings and comments.
+    let verbatim = tokens
+        .iter()
+        .map(|t| t.span.start..t.span.end)
+        .chain(comments(source, tokens))
+        .filter(|range| source[range.clone()].contains('\n'))
+        .collect::<Vec<_>>();
+    let inside = |offset: usize| verbatim.iter().any(|r| r.start < offset && offset < r.end);
+
+    // the indentation of the line every open block started on, innermost last.
+    let mut open: Vec<usize> = Vec::new();
+    let mut next = tokens.iter().peekable();
+    let mut out = String::with_capacity(source.len());
+    let mut line_start = 0;
+
+    for line in source.split_inclusive('\n') {
+        let line_end = line_start + line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[text.len()..];
+
+        let mut on_line = Vec::new();
+        while let Some(t) = next.next_if(|t| t.span.start < line_end) {
+            on_line.push(&t.token);
+        }
+
+        // a line that closes a block first goes back to where the block was opened.
+        let depth = match on_line.first().map(|t| nesting(t)) {
+            Some((closes, _)) if closes > 0 => open.len().checked_sub(closes).map(|i| open[i]),
+            _ => open.last().map(|&depth| depth + 1),
+        }
+        .unwrap_or(0);
+
+        // the whitespace inside a string or comment is part of it.
+        let continued = inside(line_start);
+        let content = match continued {
+            true => text,
+            false => text.trim_start(),
+        };
+        let content = match inside(line_start + text.len()) {
+            true => content,
+            false => content.trim_end(),
+        };
+        if !continued && !content.is_empty() {
+            out.push_str(&INDENT.repeat(depth));
+        }
+        out.push_str(content);
+        out.push_str(ending);
+
+        for token in on_line {
+            let (closes, opens) = nesting(token);
+            open.truncate(open.len().saturating_sub(closes));
+            open.extend(std::iter::repeat_n(depth, opens));
+        }
+        line_start = line_end;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn formatted(source: &str) -> String {
+        format(source, &Lexer::new(source).tokenize().unwrap())
+    }
+
+    #[test]
+    fn indents_blocks() {
+        let cases = [
+            (
+                "function f(x)\nif x then\nreturn 1\nelseif y then\nreturn 2\nelse\nreturn 3\nend\nend\n",
+                "function f(x)\n    if x then\n        return 1\n    elseif y then\n        return 2\n    else\n        return 3\n    end\nend\n",
+            ),
+            (
+                "while x do\nrepeat\nx = x - 1\nuntil x < 0\nend",
+                "while x do\n    repeat\n        x = x - 1\n    until x < 0\nend",
+            ),
+            (
+                "local t = {\n1,\n{\n2,\n},\n}\n",
+                "local t = {\n    1,\n    {\n        2,\n    },\n}\n",
+            ),
+            ("for i = 1, 2 do end\nx = 1\n", "for i = 1, 2 do end\nx = 1\n"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(formatted(source), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn keeps_what_spans_lines() {
+        let source = "do\n  s = [[\n  kept   \n]]\n  --[[\n  also kept\n  ]]\nend\n";
+        assert_eq!(
+            formatted(source),
+            "do\n    s = [[\n  kept   \n]]\n    --[[\n  also kept\n  ]]\nend\n"
+        );
+
+        let source = "if x then\r\n  y = \"a\\\r\n  b\"  \r\n\r\nend\r\n";
+        assert_eq!(
+            formatted(source),
+            "if x then\r\n    y = \"a\\\r\n  b\"\r\n\r\nend\r\n"
+        );
+    }
+
+    #[test]
+    fn stray_closers_stay_at_the_margin() {
+        assert_eq!(formatted("  end\n  x = 1\n"), "end\nx = 1\n");
+    }
+
+    #[test]
+    fn formatting_keeps_the_semantic_hash() {
+        let hash = |source: &str| {
+            let ast = crate::Compiler::default().compile("main.lua", source).ast;
+            crate::ast::semantic_hash(&ast.unwrap())
+        };
+
+        let source = include_str!("../program.lua");
+        assert_eq!(hash(&formatted(source)), hash(source));
+    }
+
+    #[test]
+    fn fixes_what_the_whitespace_lint_warns_about() {
+        let source = "function f() \n  if x then\t\n\t\treturn 1\n  end\n\treturn [[a \nb]]\nend\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        assert_eq!(crate::whitespace::check(source, &tokens).len(), 2);
+
+        let fixed = formatted(source);
+        let tokens = Lexer::new(&fixed).tokenize().unwrap();
+        assert!(
+            crate::whitespace::check(&fixed, &tokens).is_empty(),
+            "{fixed}"
+        );
+        // the space within the long string is left.
+        assert!(fixed.contains("[[a \nb]]"), "{fixed}");
+    }
+}
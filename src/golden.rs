@@ -0,0 +1,255 @@
+use std::path::Path;
+
+use lua_compiler::diagnostic::{DiagnosticSink, RenderingSink, SourceContext};
+use lua_compiler::term_color::*;
+use lua_compiler::{codes, format_error, log_error, suppress};
+
+use crate::{cli, dump_tokens, plural, source, walk, ExitCode};
+
+// how many unchanged lines are shown around every change of a diff.
+const CONTEXT: usize = 3;
+
+/// Compiles every file of `dir` and compares what it printed with the `.expected` file next to
+/// it, or rewrites the expectations if `options.bless` is set.
+pub fn run(options: &cli::Options, dir: &Path) -> ExitCode {
+    let files = match walk::collect_files(dir, &options.include, &options.exclude) {
+        Ok(files) => files,
+        Err(e) => {
+            log_error!("could not read '{}': {e}.\n", dir.display());
+            return ExitCode::UsageError;
+        }
+    };
+
+    let (mut passed, mut failed, mut blessed) = (0, 0, 0);
+    let mut result = ExitCode::Success;
+
+    for file in &files {
+        let name = walk::relative_name(file, dir);
+        let expected_path = file.with_extension("expected");
+
+        let code = match source::read(file) {
+            Ok(code) => code,
+            Err(e) => {
+                log_error!("{e}.\n");
+                result = result.max(ExitCode::UsageError);
+                continue;
+            }
+        };
+        let actual = output(options, &name, &code);
+
+        if options.bless {
+            if let Err(e) = std::fs::write(&expected_path, &actual) {
+                log_error!("could not write '{}': {e}.\n", expected_path.display());
+                result = result.max(ExitCode::UsageError);
+                continue;
+            }
+            blessed += 1;
+            continue;
+        }
+
+        let expected = match std::fs::read_to_string(&expected_path) {
+            Ok(expected) => expected,
+            Err(_) => {
+                print!(
+                    "{}",
+                    format_error!("{name}: no expectation, run with --bless to create it.\n")
+                );
+                failed += 1;
+                continue;
+            }
+        };
+
+        if expected == actual {
+            println!("{} {name}", colored("ok", Color::Green));
+            passed += 1;
+            continue;
+        }
+
+        let expected_name = walk::relative_name(&expected_path, dir);
+        println!("{} {name}", colored("FAIL", Color::Red));
+        print!(
+            "{}",
+            unified_diff(&expected_name, &name, &expected, &actual)
+        );
+        failed += 1;
+    }
+
+    if options.bless {
+        println!("blessed {}.", plural(blessed, "expectation"));
+    } else {
+        println!("{passed} passed, {failed} failed.");
+    }
+
+    if failed > 0 {
+        result = result.max(ExitCode::CompileError);
+    }
+    result
+}
+
+/// What a file is expected to print: the artifact of the stage the options select, then the
+/// diagnostics.
+///
+/// The diagnostics are rendered without colors, and labeled with the path relative to the test
+/// directory, so the expectations don't depend on the terminal or where the tests were run from.
+fn output(options: &cli::Options, name: &str, code: &str) -> String {
+    let mut result = lua_compiler::Compiler::from(options).compile(name, code);
+    let tokens = result.tokens.as_deref().unwrap_or_default();
+    suppress::apply(&mut result.diagnostics, code, tokens);
+    codes::apply_levels(&mut result.diagnostics, &options.warnings);
+    let mut out = String::new();
+
+    // `check` is only interested in the diagnostics.
+    if !options.check {
+        match (&result.tokens, &result.ast) {
+            (Some(tokens), _) if options.dump_tokens => out.push_str(&dump_tokens(
+                tokens,
+                code,
+                options.format == cli::Format::Json,
+            )),
+            (_, Some(ast)) if !options.dump_tokens => out.push_str(&format!("{ast:#?}\n")),
+            _ => {}
+        }
+    }
+
+    let ctx = SourceContext::new(name, code);
+    let plain = OutputCaps {
+        color: false,
+        unicode: false,
+    };
+    let mut sink = RenderingSink::new(&ctx, options.diagnostics_format, plain);
+    sink.show_suppressed = options.show_suppressed;
+    for d in result.diagnostics {
+        sink.emit(d);
+    }
+    out.push_str(&sink.finish().0);
+    out
+}
+
+/// Compares two texts line by line, in the unified format of `diff -u`.
+pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // lengths of the longest common subsequences of every pair of suffixes.
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    // every line of both texts, as (' ' | '-' | '+', old index, new index).
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((' ', i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            // removals come first, the way `diff -u` lists them.
+            edits.push(('-', i, j));
+            i += 1;
+        } else {
+            edits.push(('+', i, j));
+            j += 1;
+        }
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let changed = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.0 != ' ')
+        .map(|(k, _)| k)
+        .collect::<Vec<_>>();
+
+    // changes closer than twice the context share a hunk.
+    let mut k = 0;
+    while k < changed.len() {
+        let mut last = k;
+        while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * CONTEXT {
+            last += 1;
+        }
+
+        let start = changed[k].saturating_sub(CONTEXT);
+        let end = (changed[last] + CONTEXT + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        let old_count = hunk.iter().filter(|e| e.0 != '+').count();
+        let new_count = hunk.iter().filter(|e| e.0 != '-').count();
+        // an empty range starts on the line before it, as `diff -u` does.
+        let old_start = hunk[0].1 + usize::from(old_count > 0);
+        let new_start = hunk[0].2 + usize::from(new_count > 0);
+        out.push_str(&format!(
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"
+        ));
+
+        for &(kind, i, j) in hunk {
+            let line = match kind {
+                ' ' => format!(" {}", old[i]),
+                '-' => colored(&format!("-{}", old[i]), Color::Red),
+                _ => colored(&format!("+{}", new[j]), Color::Green),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+
+        k = last + 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the diff colors its lines when the output can show it, which doesn't matter here.
+    fn diff(old: &str, new: &str) -> String {
+        unified_diff("a", "b", old, new)
+            .replace("\x1b[91m", "")
+            .replace("\x1b[92m", "")
+            .replace("\x1b[0m", "")
+    }
+
+    #[test]
+    fn identical_texts_have_no_hunks() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "--- a\n+++ b\n");
+    }
+
+    #[test]
+    fn changes_far_apart_get_hunks_of_their_own() {
+        let old = (1..=20).map(|n| format!("{n}\n")).collect::<String>();
+        let new = (1..=20)
+            .filter(|&n| n != 18)
+            .map(|n| match n {
+                2 => "two\n".to_string(),
+                n => format!("{n}\n"),
+            })
+            .collect::<String>();
+        assert_eq!(
+            diff(&old, &new),
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -15,6 +15,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"
+        );
+    }
+
+    #[test]
+    fn changes_close_together_share_a_hunk() {
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\n", "A\nb\nc\nd\nE\n"),
+            "--- a\n+++ b\n@@ -1,5 +1,5 @@\n-a\n+A\n b\n c\n d\n-e\n+E\n"
+        );
+    }
+
+    #[test]
+    fn an_empty_side_starts_on_the_line_before() {
+        assert_eq!(diff("", "a\n"), "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+a\n");
+        assert_eq!(diff("a\n", ""), "--- a\n+++ b\n@@ -1,1 +0,0 @@\n-a\n");
+    }
+}
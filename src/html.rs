@@ -0,0 +1,200 @@
+use crate::lexer::{comment_len, SpannedToken, Token};
+
+/// The stylesheet embedded in the page when no external one is given.
+pub const DEFAULT_CSS: &str = "\
+pre.lua { background: #fafafa; color: #24292e; padding: 1em; }
+pre.lua .kw { color: #d73a49; font-weight: bold; }
+pre.lua .str { color: #032f62; }
+pre.lua .num { color: #005cc5; }
+pre.lua .comment { color: #6a737d; font-style: italic; }
+pre.lua .name { color: #24292e; }
+";
+
+/// Renders the source as a standalone HTML page with its tokens highlighted.
+///
+/// The text inside the `<pre>` is exactly the source once the tags are stripped and the
+/// entities unescaped, comments and whitespace included. `css` links an external stylesheet
+/// instead of embedding `DEFAULT_CSS`.
+pub fn render(title: &str, source: &str, tokens: &[SpannedToken], css: Option<&str>) -> String {
+    let style = match css {
+        Some(href) => format!("<link rel=\"stylesheet\" href=\"{}\">", escape(href)),
+        None => format!("<style>\n{DEFAULT_CSS}</style>"),
+    };
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         {style}\n</head>\n<body>\n<pre class=\"lua\"><code>{}</code></pre>\n</body>\n</html>\n",
+        escape(title),
+        highlight(source, tokens)
+    )
+}
+
+/// Wraps every token of the source in a `<span>` of its class, leaving the rest as it is.
+pub fn highlight(source: &str, tokens: &[SpannedToken]) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    let mut offset = 0;
+
+    for t in tokens {
+        // the lexer drops whitespace and comments, they are whatever lies between two tokens.
+        if t.span.start > offset {
+            trivia(&source[offset..t.span.start], &mut out);
+        }
+
+        // overlapping spans would print the text twice.
+        let start = t.span.start.max(offset);
+        if t.span.end <= start {
+            continue;
+        }
+
+        let text = escape(&source[start..t.span.end]);
+        match class(&t.token) {
+            Some(class) => out.push_str(&format!("<span class=\"{class}\">{text}</span>")),
+            None => out.push_str(&text),
+        }
+        offset = t.span.end;
+    }
+
+    trivia(&source[offset..], &mut out);
+    out
+}
+
+/// The class a token is highlighted with, operators and punctuation aren't.
+fn class(token: &Token) -> Option<&'static str> {
+    let class = match token {
+        Token::STRING(_) => "str",
+        Token::NUMBER(_) => "num",
+        Token::NAME(_) => "name",
+        Token::AND
+        | Token::BREAK
+        | Token::DO
+        | Token::ELSE
+        | Token::ELSEIF
+        | Token::END
+        | Token::FALSE
+        | Token::FOR
+        | Token::FUNCTION
+        | Token::IF
+        | Token::IN
+        | Token::LOCAL
+        | Token::NIL
+        | Token::NOT
+        | Token::OR
+        | Token::REPEAT
+        | Token::RETURN
+        | Token::THEN
+        | Token::TRUE
+        | Token::UNTIL
+        | Token::WHILE => "kw",
+        _ => return None,
+    };
+    Some(class)
+}
+
+/// Writes the text between two tokens, with its comments wrapped in spans.
+fn trivia(text: &str, out: &mut String) {
+    let mut rest = text;
+
+    while let Some(start) = rest.find("--") {
+        out.push_str(&escape(&rest[..start]));
+        let comment = &rest[start..];
+
+        let len = comment_len(comment);
+
+        out.push_str(&format!(
+            "<span class=\"comment\">{}</span>",
+            escape(&comment[..len])
+        ));
+        rest = &comment[len..];
+    }
+
+    out.push_str(&escape(rest));
+}
+
+/// Escapes the characters that have a meaning in HTML.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn highlighted(source: &str) -> String {
+        let tokens = Lexer::new(source).tokenize().unwrap_or_default();
+        highlight(source, &tokens)
+    }
+
+    // drops the tags and turns the entities back into the characters they stand for.
+    fn text_of(html: &str) -> String {
+        let mut text = String::new();
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start + rest[start..].find('>').unwrap() + 1..];
+        }
+        text.push_str(rest);
+
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&amp;", "&")
+    }
+
+    #[test]
+    fn tokens_are_wrapped_in_the_span_of_their_class() {
+        assert_eq!(
+            highlighted("local s = \"a<b\" -- why\nreturn #s + 1"),
+            "<span class=\"kw\">local</span> <span class=\"name\">s</span> = \
+             <span class=\"str\">&quot;a&lt;b&quot;</span> <span class=\"comment\">-- why</span>\n\
+             <span class=\"kw\">return</span> #<span class=\"name\">s</span> + \
+             <span class=\"num\">1</span>"
+        );
+    }
+
+    #[test]
+    fn the_text_is_the_source_once_the_tags_are_stripped() {
+        let sources = [
+            include_str!("../program.lua"),
+            "--[==[ a long\ncomment ]==] local t = {[[x]], 'y'} -- <&>\n",
+            "if a < b and b > c then return \"&amp;\" end\n\n\t-- trailing",
+            "local s = \"ünïcode\" ..[[\nlong\n]]--[[ unclosed",
+            // a source the lexer gives up on is still all there.
+            "local s = \"unfinished\nlocal x = 1 @ 2\r\n",
+        ];
+        for source in sources {
+            assert_eq!(text_of(&highlighted(source)), source, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn the_page_embeds_or_links_the_stylesheet() {
+        let page = render("a<b>.lua", "x", &[], None);
+        assert!(page.contains("<title>a&lt;b&gt;.lua</title>"), "{page}");
+        assert!(
+            page.contains(&format!("<style>\n{DEFAULT_CSS}</style>")),
+            "{page}"
+        );
+        assert!(
+            page.contains("<pre class=\"lua\"><code>x</code></pre>"),
+            "{page}"
+        );
+
+        let page = render("main.lua", "x", &[], Some("style.css?a&b"));
+        assert!(
+            page.contains("<link rel=\"stylesheet\" href=\"style.css?a&amp;b\">"),
+            "{page}"
+        );
+        assert!(!page.contains("<style>"), "{page}");
+    }
+}
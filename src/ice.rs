@@ -0,0 +1,96 @@
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::io::Write;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+
+use lua_compiler::format_error;
+
+use crate::VERSION;
+
+thread_local! {
+    // the file this thread is compiling and its source, for a crash to say what it was on.
+    static COMPILING: RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+}
+
+/// Marks a file as being compiled on this thread until it's dropped, when the file compiled
+/// before it, if any, is put back.
+pub struct Compiling {
+    previous: Option<(String, String)>,
+}
+
+impl Compiling {
+    pub fn start(file: &str, code: &str) -> Compiling {
+        let current = Some((file.to_string(), code.to_string()));
+        Compiling {
+            previous: COMPILING.with(|c| c.replace(current)),
+        }
+    }
+}
+
+impl Drop for Compiling {
+    fn drop(&mut self) {
+        COMPILING.with(|c| *c.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Prints the internal compiler error report of a panic to stderr, and writes its details
+/// with a copy of the source to a file in the temp directory.
+///
+/// Runs in the panic hook, on the thread that panicked, so it mustn't panic itself.
+pub fn report(info: &PanicHookInfo) {
+    let payload = match info.payload().downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => info
+            .payload()
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown error".to_string()),
+    };
+    let location = info
+        .location()
+        .map_or("unknown".to_string(), |l| l.to_string());
+    // outside of `Compiler::compile` it was the command line around it.
+    let stage = lua_compiler::current_stage().unwrap_or("driver");
+    let compiling = COMPILING.with(|c| c.try_borrow().ok().and_then(|c| c.clone()));
+    let file = compiling
+        .as_ref()
+        .map_or("unknown", |(file, _)| file.as_str());
+
+    let details = format!(
+        "version: {VERSION}\n\
+         file: {file}\n\
+         stage: {stage}\n\
+         panic: {payload}\n\
+         location: {location}\n"
+    );
+
+    let mut report = format_error!("internal compiler error, this is a bug in lua-compiler.\n");
+    report.push_str(&details);
+    match write_details(&details, compiling.as_ref().map(|(_, code)| code.as_str())) {
+        Ok(path) => report.push_str(&format!("details: {}\n", path.display())),
+        Err(e) => report.push_str(&format!("details: couldn't be written, {e}\n")),
+    }
+    report.push_str(&format!(
+        "\nplease file an issue with lua-compiler {VERSION}, attaching the details file if the \
+         source can be shared.\n"
+    ));
+
+    // stderr could be closed, there's nothing more to do about it then.
+    let _ = std::io::stderr().write_all(report.as_bytes());
+}
+
+/// Writes the details, a backtrace and the source to `lua-compiler-ice-<pid>.txt` in the temp
+/// directory, returning its path.
+fn write_details(details: &str, code: Option<&str>) -> std::io::Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!("lua-compiler-ice-{}.txt", std::process::id()));
+
+    let mut contents = format!("{details}\nbacktrace:\n{}\n", Backtrace::force_capture());
+    match code {
+        Some(code) => contents.push_str(&format!("source:\n{code}")),
+        None => contents.push_str("source: none, no file was being compiled.\n"),
+    }
+
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
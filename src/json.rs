@@ -0,0 +1,259 @@
+/// Quotes and escapes a string so it can be embedded in a JSON document.
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // the rest of the control characters have to be written as unicode escapes.
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// A parsed JSON document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // the members in the order they were written.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the member `key` of an object, or None for anything else.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Writes the value back out as compact JSON.
+    pub fn to_json(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Boolean(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => string(s),
+            Value::Array(items) => {
+                let items = items.iter().map(Value::to_json).collect::<Vec<_>>();
+                format!("[{}]", items.join(","))
+            }
+            Value::Object(members) => {
+                let members = members
+                    .iter()
+                    .map(|(k, v)| format!("{}:{}", string(k), v.to_json()))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", members.join(","))
+            }
+        }
+    }
+}
+
+/// Parses a JSON document, returning None if it isn't valid.
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        cursor: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    (parser.cursor == parser.chars.len()).then_some(value)
+}
+
+// how deeply arrays and objects can nest, so a hostile document can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser {
+    chars: Vec<char>,
+    cursor: usize,
+    // the arrays and objects the parser is inside of.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.cursor).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.cursor += 1;
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.cursor += 1;
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: Value) -> Option<Value> {
+        for expected in word.chars() {
+            if self.bump()? != expected {
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+
+        if matches!(self.peek(), Some('[' | '{')) {
+            self.depth += 1;
+            if self.depth > MAX_DEPTH {
+                return None;
+            }
+            let value = self.container();
+            self.depth -= 1;
+            return value;
+        }
+
+        match self.peek()? {
+            'n' => self.expect_word("null", Value::Null),
+            't' => self.expect_word("true", Value::Boolean(true)),
+            'f' => self.expect_word("false", Value::Boolean(false)),
+            '"' => self.string().map(Value::String),
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = self.cursor;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                {
+                    self.cursor += 1;
+                }
+                let number = self.chars[start..self.cursor].iter().collect::<String>();
+                number.parse().ok().map(Value::Number)
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses an array or an object.
+    fn container(&mut self) -> Option<Value> {
+        match self.peek()? {
+            '[' => {
+                self.bump();
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.bump();
+                    return Some(Value::Array(items));
+                }
+
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.bump()? {
+                        ',' => continue,
+                        ']' => return Some(Value::Array(items)),
+                        _ => return None,
+                    }
+                }
+            }
+            '{' => {
+                self.bump();
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.bump();
+                    return Some(Value::Object(members));
+                }
+
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.bump()? != ':' {
+                        return None;
+                    }
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.bump()? {
+                        ',' => continue,
+                        '}' => return Some(Value::Object(members)),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.bump()? != '"' {
+            return None;
+        }
+
+        let mut string = String::new();
+        loop {
+            match self.bump()? {
+                '"' => return Some(string),
+                '\\' => match self.bump()? {
+                    '"' => string.push('"'),
+                    '\\' => string.push('\\'),
+                    '/' => string.push('/'),
+                    'b' => string.push('\u{8}'),
+                    'f' => string.push('\u{c}'),
+                    'n' => string.push('\n'),
+                    'r' => string.push('\r'),
+                    't' => string.push('\t'),
+                    'u' => {
+                        let high = self.hex4()?;
+                        // characters outside the basic plane are written as a surrogate pair.
+                        let code = if (0xd800..0xdc00).contains(&high) {
+                            if self.bump()? != '\\' || self.bump()? != 'u' {
+                                return None;
+                            }
+                            let low = self.hex4()?;
+                            0x10000 + ((high - 0xd800) << 10) + (low.checked_sub(0xdc00)? & 0x3ff)
+                        } else {
+                            high
+                        };
+                        string.push(char::from_u32(code)?);
+                    }
+                    _ => return None,
+                },
+                c if (c as u32) < 0x20 => return None,
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.bump()?.to_digit(16)?;
+        }
+        Some(code)
+    }
+}
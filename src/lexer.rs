@@ -1,6 +1,8 @@
-use std::{collections::HashMap, f64};
+use std::{borrow::Cow, f64, ops::Range};
 
-use crate::{log_error, term_color::*};
+use crate::cancel::CancellationToken;
+use crate::diagnostic::{Diagnostic, DiagnosticSink};
+use crate::strings;
 
 trait StrExt {
     fn remove_last(&self) -> &str;
@@ -15,9 +17,17 @@ impl StrExt for str {
     }
 }
 
-type Tokens = Vec<Token>;
+/// Returns what `while_peek` went over, without the character it stopped at.
+///
+/// There's no such character when it stopped at the end of the source, so the stack can't
+/// just lose its last one.
+fn consumed(stack: &str, n: isize) -> String {
+    stack.chars().take((n - 1).max(0) as usize).collect()
+}
+
+type Tokens = Vec<SpannedToken>;
 
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, Default, Clone, PartialEq)]
 pub enum Token {
     AND,
@@ -58,6 +68,7 @@ pub enum Token {
     LESS_EQUAL,
     CONCAT,
     DOTS,
+    // the value of the string, with its escapes decoded, the span has it as it's written.
     STRING(String),
     NAME(String),
     XOR,
@@ -74,22 +85,325 @@ pub enum Token {
     UNDEFINED,
 }
 
+impl Token {
+    /// Returns the name of the token's kind, without any payload.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Token::AND => "AND",
+            Token::END => "END",
+            Token::BREAK => "BREAK",
+            Token::DO => "DO",
+            Token::ELSE => "ELSE",
+            Token::ELSEIF => "ELSEIF",
+            Token::FALSE => "FALSE",
+            Token::FOR => "FOR",
+            Token::FUNCTION => "FUNCTION",
+            Token::IF => "IF",
+            Token::IN => "IN",
+            Token::LOCAL => "LOCAL",
+            Token::NIL => "NIL",
+            Token::NOT => "NOT",
+            Token::OR => "OR",
+            Token::REPEAT => "REPEAT",
+            Token::RETURN => "RETURN",
+            Token::THEN => "THEN",
+            Token::TRUE => "TRUE",
+            Token::UNTIL => "UNTIL",
+            Token::WHILE => "WHILE",
+            Token::NUMBER(_) => "NUMBER",
+            Token::ADD => "ADD",
+            Token::SUBTRACT => "SUBTRACT",
+            Token::MULTIPLY => "MULTIPLY",
+            Token::DIVIDE => "DIVIDE",
+            Token::LEFT_PAREN => "LEFT_PAREN",
+            Token::RIGHT_PAREN => "RIGHT_PAREN",
+            Token::LEFT_BRACKET => "LEFT_BRACKET",
+            Token::RIGHT_BRACKET => "RIGHT_BRACKET",
+            Token::LEFT_BRACE => "LEFT_BRACE",
+            Token::RIGHT_BRACE => "RIGHT_BRACE",
+            Token::GREATER_THAN => "GREATER_THAN",
+            Token::LESS_THAN => "LESS_THAN",
+            Token::GREATER_EQUAL => "GREATER_EQUAL",
+            Token::LESS_EQUAL => "LESS_EQUAL",
+            Token::CONCAT => "CONCAT",
+            Token::DOTS => "DOTS",
+            Token::STRING(_) => "STRING",
+            Token::NAME(_) => "NAME",
+            Token::XOR => "XOR",
+            Token::MODULO => "MODULO",
+            Token::HASHTAG => "HASHTAG",
+            Token::ASSIGN => "ASSIGN",
+            Token::EQ => "EQ",
+            Token::NEQ => "NEQ",
+            Token::SEMICOLON => "SEMICOLON",
+            Token::COLON => "COLON",
+            Token::COMMA => "COMMA",
+            Token::DOT => "DOT",
+            Token::UNDEFINED => "UNDEFINED",
+        }
+    }
+
+    /// Returns true for the reserved words, like `end` or `nil`, which can't be names.
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            Token::AND
+                | Token::END
+                | Token::BREAK
+                | Token::DO
+                | Token::ELSE
+                | Token::ELSEIF
+                | Token::FALSE
+                | Token::FOR
+                | Token::FUNCTION
+                | Token::IF
+                | Token::IN
+                | Token::LOCAL
+                | Token::NIL
+                | Token::NOT
+                | Token::OR
+                | Token::REPEAT
+                | Token::RETURN
+                | Token::THEN
+                | Token::TRUE
+                | Token::UNTIL
+                | Token::WHILE
+        )
+    }
+
+    /// Returns how the token is written in the source, e.g. `..` for CONCAT.
+    ///
+    /// Strings come back quoted and escaped, so they aren't always written the same way.
+    pub fn spelling(&self) -> Cow<'static, str> {
+        let text = match self {
+            Token::NAME(name) => return name.clone().into(),
+            Token::STRING(s) => return format!("{s:?}").into(),
+            Token::NUMBER(n) => return n.to_string().into(),
+            Token::AND => "and",
+            Token::END => "end",
+            Token::BREAK => "break",
+            Token::DO => "do",
+            Token::ELSE => "else",
+            Token::ELSEIF => "elseif",
+            Token::FALSE => "false",
+            Token::FOR => "for",
+            Token::FUNCTION => "function",
+            Token::IF => "if",
+            Token::IN => "in",
+            Token::LOCAL => "local",
+            Token::NIL => "nil",
+            Token::NOT => "not",
+            Token::OR => "or",
+            Token::REPEAT => "repeat",
+            Token::RETURN => "return",
+            Token::THEN => "then",
+            Token::TRUE => "true",
+            Token::UNTIL => "until",
+            Token::WHILE => "while",
+            Token::ADD => "+",
+            Token::SUBTRACT => "-",
+            Token::MULTIPLY => "*",
+            Token::DIVIDE => "/",
+            Token::LEFT_PAREN => "(",
+            Token::RIGHT_PAREN => ")",
+            Token::LEFT_BRACKET => "[",
+            Token::RIGHT_BRACKET => "]",
+            Token::LEFT_BRACE => "{",
+            Token::RIGHT_BRACE => "}",
+            Token::GREATER_THAN => ">",
+            Token::LESS_THAN => "<",
+            Token::GREATER_EQUAL => ">=",
+            Token::LESS_EQUAL => "<=",
+            Token::CONCAT => "..",
+            Token::DOTS => "...",
+            Token::XOR => "^",
+            Token::MODULO => "%",
+            Token::HASHTAG => "#",
+            Token::ASSIGN => "=",
+            Token::EQ => "==",
+            Token::NEQ => "~=",
+            Token::SEMICOLON => ";",
+            Token::COLON => ":",
+            Token::COMMA => ",",
+            Token::DOT => ".",
+            Token::UNDEFINED => "<eof>",
+        };
+        text.into()
+    }
+}
+
+/// The location of a token in the source text.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    // byte offsets into the source, the end is exclusive.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A token along with where it was found in the source text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
 fn is_end_of_line(c: char) -> bool {
-    match c {
-        '\n' => true,
-        _ => false,
+    matches!(c, '\n')
+}
+
+/// What a character can start, for the lexer to go straight to the right kind of token.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    Space,
+    Name,
+    Digit,
+    Quote,
+    // a comment or a subtraction.
+    Minus,
+    // a concatenation, varargs, a number or a field access.
+    Dot,
+    // a long string or an index.
+    Bracket,
+    // an operator or a delimiter that doesn't start anything else.
+    Operator,
+    // anything else, including every character outside of ASCII.
+    Other,
+}
+
+/// The class of every ASCII character.
+const CLASSES: [Class; 128] = {
+    let mut classes = [Class::Other; 128];
+    let mut c = 0;
+    while c < 128 {
+        classes[c] = match c as u8 {
+            // what `char::is_whitespace` takes for whitespace in ASCII.
+            b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ' => Class::Space,
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => Class::Name,
+            b'0'..=b'9' => Class::Digit,
+            b'"' | b'\'' => Class::Quote,
+            b'-' => Class::Minus,
+            b'.' => Class::Dot,
+            b'[' => Class::Bracket,
+            b'+' | b'*' | b'/' | b'(' | b')' | b'^' | b',' | b'#' | b';' | b':' | b']' | b'{'
+            | b'}' | b'%' | b'<' | b'>' | b'~' | b'=' => Class::Operator,
+            _ => Class::Other,
+        };
+        c += 1;
+    }
+    classes
+};
+
+/// Returns the keyword spelled `name`, if it's one.
+fn keyword(name: &str) -> Option<Token> {
+    Some(match name {
+        "and" => Token::AND,
+        "or" => Token::OR,
+        "while" => Token::WHILE,
+        "for" => Token::FOR,
+        "repeat" => Token::REPEAT,
+        "return" => Token::RETURN,
+        "then" => Token::THEN,
+        "true" => Token::TRUE,
+        "until" => Token::UNTIL,
+        "function" => Token::FUNCTION,
+        "if" => Token::IF,
+        "in" => Token::IN,
+        "local" => Token::LOCAL,
+        "nil" => Token::NIL,
+        "end" => Token::END,
+        "break" => Token::BREAK,
+        "do" => Token::DO,
+        "else" => Token::ELSE,
+        "elseif" => Token::ELSEIF,
+        "false" => Token::FALSE,
+        "not" => Token::NOT,
+        _ => return None,
+    })
+}
+
+/// Returns the level of the long bracket `text` starts with, the number of `=` between its
+/// two `[`, or None if it doesn't start with one.
+pub fn long_bracket(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix('[')?;
+    let level = rest.len() - rest.trim_start_matches('=').len();
+    rest[level..].starts_with('[').then_some(level)
+}
+
+/// Returns the length of the comment `text` starts with at its `--`, to the end of its line or
+/// past the closing bracket of a long one, or all of `text` if that never comes.
+///
+/// ```
+/// use lua_compiler::lexer::comment_len;
+///
+/// assert_eq!(comment_len("-- short\nx"), 8);
+/// assert_eq!(comment_len("--[=[ keeps ]] ]=] x"), 18);
+/// ```
+pub fn comment_len(text: &str) -> usize {
+    let body = &text[2..];
+    let Some(level) = long_bracket(body) else {
+        return text.find('\n').unwrap_or(text.len());
+    };
+
+    let close = format!("]{}]", "=".repeat(level));
+    let opening = 2 + level + 2;
+    text[opening..]
+        .find(&close)
+        .map_or(text.len(), |end| opening + end + close.len())
+}
+
+/// Finds the comments the lexer skipped, in what lies between the tokens of the source.
+///
+/// Each is the byte range from its `--` to the end of its line, or past the closing bracket of
+/// a long one.
+pub fn find_comments(source: &str, tokens: &[SpannedToken]) -> Vec<Range<usize>> {
+    let mut gaps = Vec::new();
+    let mut offset = 0;
+    for t in tokens {
+        gaps.push((offset, t.span.start.max(offset)));
+        offset = offset.max(t.span.end);
+    }
+    gaps.push((offset, source.len()));
+
+    let mut comments = Vec::new();
+    for (start, end) in gaps {
+        let Some(text) = source.get(start..end) else {
+            continue;
+        };
+
+        let mut at = 0;
+        while let Some(found) = text[at..].find("--") {
+            let comment_start = at + found;
+            at = comment_start + comment_len(&text[comment_start..]);
+            comments.push(start + comment_start..start + at);
+        }
     }
+    comments
 }
 
 /// This represents the state of our Lexer sa it's tokenizing the tape.
 pub struct Lexer {
     tape: String,
+    // the cursor counts characters, not bytes, so they're looked up here.
+    chars: Vec<char>,
     cursor: isize,
     line: usize,
     errored: bool,
     column: usize,
+    // byte offset of the character under the cursor, and of the one right after it.
+    offset: usize,
+    next_offset: usize,
+    // where the token that is currently being lexed starts, the end is filled in once known.
+    token_start: Span,
+    diagnostics: Vec<Diagnostic>,
+    // checked every `CANCEL_INTERVAL` tokens, lexing stops once it's cancelled.
+    cancellation: Option<CancellationToken>,
 }
 
+// how many tokens are lexed between two looks at the cancellation token.
+const CANCEL_INTERVAL: usize = 1024;
+
 impl Lexer {
     pub fn new(text: &str) -> Self {
         // starting at negative index is a little bit of a hack to make the code be slightly nicer.
@@ -98,25 +412,45 @@ impl Lexer {
             column: 0,
             errored: false,
             tape: text.to_string(),
+            chars: text.chars().collect(),
             cursor: -1,
+            offset: 0,
+            next_offset: 0,
+            token_start: Span::default(),
+            diagnostics: Vec::new(),
+            cancellation: None,
         }
     }
 
+    /// Makes `tokenize` stop early and return nothing once the token is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
     /// This will return true if the cursor is past the last character of the tape.
     fn is_end_of_file(&self) -> bool {
-        self.cursor as usize >= self.tape.len()
+        self.cursor as usize >= self.chars.len()
     }
 
     /// This will return true if n is past the last character of the tape.
     fn is_end_of_file_nth(&self, n: isize) -> bool {
-        n as usize >= self.tape.len()
+        n as usize >= self.chars.len()
     }
 
     /// Advances the cursor by one then returns the consumed character.
     fn advance(&mut self) -> Option<char> {
+        // leaving a line break starts the next line, whatever token it was part of, so long
+        // strings and comments count their lines too.
+        if self.peek_nth(0).is_some_and(is_end_of_line) {
+            self.line += 1;
+            self.column = 0;
+        }
+
         // increase our internal cursor by one.
-        self.cursor = self.cursor + 1;
+        self.cursor += 1;
         self.column += 1;
+        self.offset = self.next_offset;
 
         if self.is_end_of_file() {
             // if we're at the end of the file we can't advance.
@@ -124,7 +458,21 @@ impl Lexer {
         }
 
         // we know this will work since we do the bounds checking ourselves.
-        Some(self.tape.chars().nth(self.cursor as _).unwrap())
+        let c = self.chars[self.cursor as usize];
+        self.next_offset += c.len_utf8();
+        Some(c)
+    }
+
+    /// Moves the cursor `n` characters ahead like `advance_nth`, when they're all there, ASCII
+    /// and not line breaks, so that the offsets move by as many bytes.
+    fn skip_ascii(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.cursor += n as isize;
+        self.column += n;
+        self.offset = self.next_offset + n - 1;
+        self.next_offset = self.offset + 1;
     }
 
     fn advance_nth(&mut self, n: isize) -> Option<char> {
@@ -136,17 +484,101 @@ impl Lexer {
 
     /// This checks the next character in the tape but doesn't consume it.
     fn peek(&self) -> Option<char> {
-        self.tape.chars().nth(self.cursor as usize + 1)
+        self.peek_nth(1)
     }
 
-    /// This checks an arbitrary character in the tape but doesn't consume it.
+    /// This checks an arbitrary character in the tape but doesn't consume it, `n` can be
+    /// negative to look behind the cursor.
     fn peek_nth(&self, n: isize) -> Option<char> {
-        self.tape.chars().nth(self.cursor as usize + n as usize)
+        let i = usize::try_from(self.cursor + n).ok()?;
+        self.chars.get(i).copied()
+    }
+
+    /// Returns the problems found while tokenizing.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Reports an error for the token being lexed, which ends at the byte offset `end`.
+    fn report_error(&mut self, code: &'static str, message: String, end: usize) {
+        let span = Span {
+            end,
+            ..self.token_start
+        };
+        self.diagnostics
+            .emit(Diagnostic::error(code, message, span));
+        self.errored = true;
+    }
+
+    /// Reports every escape of the contents of the quoted string being lexed that isn't one,
+    /// at where it is in the string.
+    fn report_invalid_escapes(&mut self, contents: &str) {
+        // the contents start right after the opening quote.
+        let start = self.token_start.end;
+        for escape in strings::invalid_escapes(contents) {
+            let before = &contents[..escape.start];
+            // a backslash can carry the string over a line break.
+            let (line, column) = match before.rsplit_once('\n') {
+                Some((_, last)) => (
+                    self.token_start.line + before.matches('\n').count(),
+                    last.chars().count() + 1,
+                ),
+                None => (
+                    self.token_start.line,
+                    self.token_start.column + 1 + before.chars().count(),
+                ),
+            };
+
+            let text = &contents[escape.clone()];
+            let message = match text[1..].starts_with(|c: char| c.is_ascii_digit()) {
+                true => format!("decimal escape '{text}' is too large, the largest is '\\255'."),
+                false => format!("invalid escape sequence '{text}'."),
+            };
+            let span = Span {
+                line,
+                column,
+                start: start + escape.start,
+                end: start + escape.end,
+            };
+            self.diagnostics
+                .emit(Diagnostic::error("E004", message, span));
+            self.errored = true;
+        }
+    }
+
+    /// Returns the level of the long bracket the `[` under the cursor opens, the number of `=`
+    /// before its second `[`, or None if it doesn't open one.
+    fn long_bracket_level(&self) -> Option<usize> {
+        let level = (1..).take_while(|&n| self.peek_nth(n) == Some('=')).count();
+        (self.peek_nth(level as isize + 1) == Some('[')).then_some(level)
     }
 
-    /// Creates a substring given start on the tape, and the size.
-    fn sub_tape(&self, start: usize, size: usize) -> String {
-        self.tape[start..start + size].to_string()
+    /// Reads the long bracket opening at the cursor up to the closing one of the same level,
+    /// leaving the cursor on its last `]`, and returns what's between them.
+    ///
+    /// Like Lua, a line break right after the opening bracket isn't part of it. Returns None if
+    /// the source ends first.
+    fn read_long_bracket(&mut self, level: usize) -> Option<String> {
+        let level = level as isize;
+        self.advance_nth(level + 1);
+        match (self.peek(), self.peek_nth(2)) {
+            (Some('\r'), Some('\n')) => self.advance_nth(2),
+            (Some('\n'), _) => self.advance(),
+            _ => None,
+        };
+
+        let mut contents = String::new();
+        loop {
+            let c = self.advance()?;
+            let closes = c == ']'
+                && (1..=level).all(|n| self.peek_nth(n) == Some('='))
+                && self.peek_nth(level + 1) == Some(']');
+            if closes {
+                self.advance_nth(level + 1);
+                return Some(contents);
+            }
+            contents.push(c);
+        }
     }
 
     /// This will continue peaking until it can no longer peak.
@@ -157,13 +589,8 @@ impl Lexer {
     ) -> (isize, String) {
         let mut stack: String = String::new();
         let mut current_peek = 1;
-        loop {
-            // if there isn't a next character just return from the peek.
-            let current_char = match self.peek_nth(current_peek) {
-                Some(c) => c,
-                None => break,
-            };
-
+        // if there isn't a next character just return from the peek.
+        while let Some(current_char) = self.peek_nth(current_peek) {
             // push the newest character to thet stack.
             stack.push(current_char);
 
@@ -180,303 +607,1058 @@ impl Lexer {
         (current_peek, stack)
     }
 
-    /// Returns the char the cursor is currently pointing over
+    // Returns the char the cursor is currently pointing over
     // fn current_char(&self) -> char {
     //     // we know this can never fail
     //     self.tape.chars().nth(self.cursor as usize).unwrap()
     // }
 
     /// This transforms a string into a list of parsable tokens.
+    ///
+    /// Never panics, whatever the text, the problems are in `diagnostics` instead.
+    ///
+    /// ```
+    /// use lua_compiler::lexer::{Lexer, Token};
+    ///
+    /// let tokens = Lexer::new("[==[\nkeeps ]] and ]=]]==]").tokenize().unwrap();
+    /// assert_eq!(tokens[0].token, Token::STRING("keeps ]] and ]=]".to_string()));
+    ///
+    /// let mut lexer = Lexer::new("x = 1\ns = [=[ never closed ]]");
+    /// assert!(lexer.tokenize().is_none());
+    /// assert_eq!(lexer.diagnostics()[0].span.line, 2);
+    ///
+    /// // a long comment only ends at the closing bracket of its level.
+    /// let tokens = Lexer::new("--[=[ ]] ]==] ]=] x").tokenize().unwrap();
+    /// assert_eq!(tokens[0].token, Token::NAME("x".to_string()));
+    ///
+    /// let mut lexer = Lexer::new("x = 1\n--[[ never closed ]=]");
+    /// assert!(lexer.tokenize().is_none());
+    /// assert_eq!(lexer.diagnostics().len(), 1);
+    /// assert_eq!(lexer.diagnostics()[0].code, "E005");
+    ///
+    /// // names can go on past ASCII.
+    /// let tokens = Lexer::new("naïve = x٣").tokenize().unwrap();
+    /// assert_eq!(tokens[0].token, Token::NAME("naïve".to_string()));
+    /// assert_eq!(tokens[2].token, Token::NAME("x٣".to_string()));
+    ///
+    /// // a minus is always its own token, a negative number is a unary minus for the parser.
+    /// let kinds = |text| -> Vec<Token> {
+    ///     Lexer::new(text).tokenize().unwrap().into_iter().map(|t| t.token).collect()
+    /// };
+    /// let name = |n: &str| Token::NAME(n.to_string());
+    /// assert_eq!(kinds("a-1"), [name("a"), Token::SUBTRACT, Token::NUMBER(1.0)]);
+    /// assert_eq!(kinds("-5"), [Token::SUBTRACT, Token::NUMBER(5.0)]);
+    /// assert_eq!(
+    ///     kinds("for i=10,-1,-1 do"),
+    ///     [
+    ///         Token::FOR,
+    ///         name("i"),
+    ///         Token::ASSIGN,
+    ///         Token::NUMBER(10.0),
+    ///         Token::COMMA,
+    ///         Token::SUBTRACT,
+    ///         Token::NUMBER(1.0),
+    ///         Token::COMMA,
+    ///         Token::SUBTRACT,
+    ///         Token::NUMBER(1.0),
+    ///         Token::DO,
+    ///     ]
+    /// );
+    /// ```
     pub fn tokenize(&mut self) -> Option<Tokens> {
+        self.tokenize_with(Self::next_token)
+    }
+
+    /// Tokenizes the tape like `tokenize`, with `next_token` lexing each token.
+    fn tokenize_with(
+        &mut self,
+        next_token: fn(&mut Self, char) -> Option<Token>,
+    ) -> Option<Tokens> {
         // store a list of tokens that we've found while lexing.
         let mut tokens: Tokens = Vec::new();
 
-        let keywords = HashMap::from([
-            ("and", Token::AND),
-            ("or", Token::OR),
-            ("while", Token::WHILE),
-            ("for", Token::FOR),
-            ("repeat", Token::REPEAT),
-            ("return", Token::RETURN),
-            ("then", Token::THEN),
-            ("true", Token::TRUE),
-            ("until", Token::UNTIL),
-            ("function", Token::FUNCTION),
-            ("if", Token::IF),
-            ("in", Token::IN),
-            ("local", Token::LOCAL),
-            ("nil", Token::NIL),
-            ("end", Token::END),
-            ("break", Token::BREAK),
-            ("do", Token::DO),
-            ("else", Token::ELSE),
-            ("elseif", Token::ELSEIF),
-            ("false", Token::FALSE),
-            ("not", Token::NOT),
-        ]);
-
         // while we can still read characters from the tape.
         while let Some(c) = self.advance() {
-            // ignore characters that don't care about.
-            if c.is_whitespace() && !is_end_of_line(c) {
-                continue;
-            }
+            // remember where the token starts, since lexing it moves the cursor.
+            self.token_start = Span {
+                line: self.line,
+                column: self.column,
+                start: self.offset,
+                end: self.next_offset,
+            };
 
-            if is_end_of_line(c) {
-                self.line += 1;
-                // reset the column at the start of a new line.
-                self.column = 0;
-                continue;
-            }
+            if let Some(token) = next_token(self, c) {
+                tokens.push(SpannedToken {
+                    token,
+                    span: Span {
+                        end: self.next_offset,
+                        ..self.token_start
+                    },
+                });
 
-            // we got uhhh multiline comment here jit.
-            if c == '-' && self.peek().unwrap_or_default() == '-' {
-                if self.peek_nth(2).unwrap_or_default() == '['
-                    && self.peek_nth(3).unwrap_or_default() == '['
+                if tokens.len().is_multiple_of(CANCEL_INTERVAL)
+                    && self.cancellation.as_ref().is_some_and(|c| c.is_cancelled())
                 {
-                    let (n, _) = self.while_peek(
-                        |c, n| c == ']' && self.peek_nth(n as isize + 1).unwrap_or_default() == ']',
-                        |_| true,
-                    );
-
-                    self.advance_nth(n + 1);
-                    continue;
+                    return None;
                 }
             }
+        }
 
-            // check if we're currently starting a comment.
-            if c == '-' && self.peek().unwrap_or_default() == '-' {
-                // read until the end of the line.
-                let (n, _) = self.while_peek(|c, _| is_end_of_line(c), |_| true);
-                self.advance_nth(n - 1);
-                continue;
-            }
+        // if there was an error during lexing we still want to show all the error messages at
+        // once.
+        if self.errored {
+            return None;
+        }
+
+        Some(tokens)
+    }
 
-            // we got uhhh multiline string here jit.
-            if c == '[' && self.peek().unwrap_or_default() == '[' {
-                // we can consume since we know what the next char is.
-                self.advance();
+    /// Lexes the token starting at `c`, returns nothing if `c` doesn't start a token.
+    fn next_token(&mut self, c: char) -> Option<Token> {
+        // most characters are ASCII, whose class alone is enough to know what they start.
+        let class = match c.is_ascii() {
+            true => CLASSES[c as usize],
+            false => Class::Other,
+        };
 
-                let (n, string) = self.while_peek(
-                    |c, n| c == ']' && self.peek_nth(n as isize + 1).unwrap_or_default() == ']',
-                    |_| true,
-                );
+        match class {
+            Class::Space => None,
+            Class::Name => Some(self.name(c)),
+            Class::Digit => match c == '0' && matches!(self.peek(), Some('x' | 'X')) {
+                true => Some(self.hex_number()),
+                false => self.number(c),
+            },
+            Class::Quote => Some(self.quoted_string(c)),
+            Class::Minus => {
+                if self.peek().unwrap_or_default() == '-' {
+                    self.comment();
+                    return None;
+                }
+                self.operator(c)
+            }
+            Class::Dot => self
+                .dots()
+                .or_else(|| self.number(c))
+                .or_else(|| self.operator(c)),
+            Class::Bracket => match self.long_bracket_level() {
+                Some(level) => self.long_string(level),
+                None => self.operator(c),
+            },
+            Class::Operator => self.operator(c),
+            Class::Other => self.other_token(c),
+        }
+    }
 
-                let string = &string[..].remove_last();
-                tokens.push(Token::STRING(string.to_string()));
+    /// Lexes a token starting at a character that isn't in any class, the ones outside of
+    /// ASCII or that can't start a token.
+    fn other_token(&mut self, c: char) -> Option<Token> {
+        // ignore characters that don't care about.
+        if c.is_whitespace() {
+            return None;
+        }
 
-                self.advance_nth(n + 1);
-                continue;
+        // since numbers can be more then 1 character long we will handle it separately.
+        if c.is_numeric() {
+            if let Some(token) = self.number(c) {
+                return Some(token);
             }
+        }
 
-            if c == '"' || c == '\'' {
-                // collect the stack of chars into a string.
-                let (mut n, string) = self.while_peek(
-                    |c, n| {
-                        self.sub_tape((self.cursor as usize + n) - 2, 3) != "\\\r\n"
-                            && is_end_of_line(c)
-                    },
-                    |c| !(c == '"' || c == '\''),
-                );
+        // check to see if this is the start of an identifier.
+        if c.is_alphabetic() {
+            return Some(self.name(c));
+        }
+
+        self.operator(c)
+    }
 
-                // so this is a bool set if the peek is at the end of the line.
-                let end_of_line = is_end_of_line(string.chars().last().unwrap());
+    /// Skips the comment starting at the cursor, to the end of its line or past the closing
+    /// bracket of a long one.
+    fn comment(&mut self) {
+        let line = self.line;
+        // to the second `-`, what the comment starts with is right after it.
+        self.advance();
 
-                if self.is_end_of_file_nth(self.cursor + n) || end_of_line {
-                    log_error!(
-                        "[{}] unclosed string, starting at column {}, line {}.",
-                        colored("token", Color::Grey),
-                        self.column,
-                        self.line
+        // a long comment, `--[[` or with any number of `=` between the brackets.
+        if self.peek() == Some('[') {
+            self.advance();
+            if let Some(level) = self.long_bracket_level() {
+                if self.read_long_bracket(level).is_none() {
+                    self.report_error(
+                        "E005",
+                        format!("unfinished long comment starting at line {line}."),
+                        self.token_start.start + level + 4,
                     );
-                    self.errored = true;
-                    // we subtract two to account for the CRLF.
-                    if end_of_line {
-                        n -= 2;
-                    }
-                } else {
-                    let string = &string[..].remove_last();
-                    tokens.push(Token::STRING(string.to_string()));
                 }
-
-                self.advance_nth(n);
-                continue;
+                return;
             }
+        }
 
-            if c == '.' {
-                if self.peek().unwrap_or_default() == '.' {
-                    if self.peek_nth(2).unwrap_or_default() == '.' {
-                        tokens.push(Token::DOTS);
-                        self.advance_nth(2);
-                        continue;
-                    }
-                    tokens.push(Token::CONCAT);
-                    self.advance();
-                    continue;
-                }
+        // read until the end of the line.
+        let (n, _) = self.while_peek(|c, _| is_end_of_line(c), |_| true);
+        self.advance_nth(n - 1);
+    }
+
+    /// Lexes a long string, `[[` or with any number of `=` between the brackets.
+    fn long_string(&mut self, level: usize) -> Option<Token> {
+        let line = self.line;
+        let opening = self.token_start.start + level + 2;
+        match self.read_long_bracket(level) {
+            Some(string) => Some(Token::STRING(string)),
+            None => {
+                self.report_error(
+                    "E001",
+                    format!("unfinished long string starting at line {line}."),
+                    opening,
+                );
+                None
             }
+        }
+    }
 
-            // parse hexadecmial number.
-            if c == '0' && self.peek().unwrap_or_default() == 'x' {
-                // since we know now that it's a hex number we can consume the 'x'.
-                self.advance();
-                let (n, string) =
-                    self.while_peek(|c, _| is_end_of_line(c), |c| c.is_ascii_hexdigit());
-
-                let string = &string[..].remove_last();
-
-                let number = match i64::from_str_radix(string, 16) {
-                    Ok(n) => n as f64,
-                    Err(_) => {
-                        log_error!(
-                            "[{}] could not lex hexadecimal number. column {}, line {}.",
-                            colored("token", Color::Grey),
-                            self.column,
-                            self.line
-                        );
-                        self.errored = true;
-                        0.0
-                    }
+    /// Lexes a string quoted with `quote`, which can't span lines unless they're escaped.
+    fn quoted_string(&mut self, quote: char) -> Token {
+        // whether the character before the `n`th is escaped, by an odd number of backslashes
+        // that don't go back past the opening quote.
+        let escaped = |n: isize| {
+            let backslashes = (1..n)
+                .rev()
+                .take_while(|&i| self.peek_nth(i) == Some('\\'))
+                .count();
+            backslashes % 2 == 1
+        };
+        // whether the `n`th character is in the whitespace after a `\z`, which skips it
+        // line breaks included.
+        let skipped = |n: isize| {
+            let before = (1..n)
+                .rev()
+                .find(|&i| !self.peek_nth(i).is_some_and(char::is_whitespace));
+            matches!(before, Some(i) if self.peek_nth(i) == Some('z') && escaped(i))
+        };
+
+        // collect the stack of chars into a string, up to the same quote it was opened with.
+        let (n, string) = self.while_peek(
+            |c, n| {
+                let n = n as isize;
+                // a backslash right before the line break continues the string.
+                let line_break_escaped = match self.peek_nth(n - 1) {
+                    Some('\r') => escaped(n - 1),
+                    _ => escaped(n),
                 };
+                (c == quote && !escaped(n))
+                    || (is_end_of_line(c) && !line_break_escaped && !skipped(n))
+            },
+            |_| true,
+        );
 
-                tokens.push(Token::NUMBER(number));
+        // so this is a bool set if the peek is at the end of the line.
+        let end_of_line = string.chars().last().is_some_and(is_end_of_line);
+        let unfinished = self.is_end_of_file_nth(self.cursor + n) || end_of_line;
 
-                self.advance_nth(n - 1);
-                continue;
-            }
+        // what the string takes up after its opening quote, the line break isn't part of it.
+        let consumed = match end_of_line {
+            true => string.trim_end_matches('\n').trim_end_matches('\r'),
+            false => &string[..],
+        };
 
-            // since numbers can be more then 1 character long we will handle it separately.
-            if c.is_numeric() || c == '-' || c == '.' {
-                // read the rest of the number.
-                let (n, string) = self.while_peek(
-                    |c, _| is_end_of_line(c),
-                    |c| c.is_numeric() || c == 'e' || c == '.' || c == '-' || c == '_',
-                );
+        let token = if unfinished {
+            // like luac, the message quotes the string as far as the line goes.
+            let end = self.next_offset + consumed.len();
+            let text = self.tape[self.offset..end].to_string();
+            self.report_error("E001", format!("unfinished string near '{text}'."), end);
+            Token::STRING(strings::decode(consumed))
+        } else {
+            let contents = consumed.remove_last();
+            self.report_invalid_escapes(contents);
+            Token::STRING(strings::decode(contents))
+        };
 
-                let string = &string[..].remove_last().replace('_', "");
-
-                // if it's just a "modification" character move on dude, else parse.
-                if !((c == '-' || c == '.') && string.is_empty()) {
-                    let number = match format!("{c}{string}").parse::<f64>() {
-                        Ok(n) => n as f64,
-                        Err(_) => {
-                            log_error!(
-                                "[{}] could not lex number: '{c}{string}' at column {}, line {}.",
-                                colored("token", Color::Grey),
-                                self.column,
-                                self.line
-                            );
-                            self.errored = true;
-                            0.0
-                        }
-                    };
-
-                    tokens.push(Token::NUMBER(number));
-
-                    self.advance_nth(n - 1);
-                    continue;
-                }
+        // an unfinished string ends before the line break, which is lexed as usual.
+        self.advance_nth(consumed.chars().count() as isize);
+        token
+    }
+
+    /// Lexes `..` or `...`, returns nothing if the `.` under the cursor starts neither.
+    fn dots(&mut self) -> Option<Token> {
+        if self.peek().unwrap_or_default() != '.' {
+            return None;
+        }
+        if self.peek_nth(2).unwrap_or_default() == '.' {
+            self.advance_nth(2);
+            return Some(Token::DOTS);
+        }
+        self.advance();
+        Some(Token::CONCAT)
+    }
+
+    /// Lexes the hexadecimal number whose `0x` or `0X` starts at the cursor.
+    fn hex_number(&mut self) -> Token {
+        // since we know now that it's a hex number we can consume the 'x'.
+        self.advance();
+        let (n, string) = self.while_peek(|c, _| is_end_of_line(c), |c| c.is_ascii_hexdigit());
+
+        let string = &consumed(&string, n);
+
+        let number = match i64::from_str_radix(string, 16) {
+            Ok(n) => n as f64,
+            Err(_) => {
+                self.report_error(
+                    "E002",
+                    "could not lex hexadecimal number.".to_string(),
+                    self.next_offset + string.len(),
+                );
+                0.0
             }
+        };
 
-            // check to see if this is the start of an identifier.
-            if c.is_alphabetic() || c == '_' {
-                // read the rest of the identifier.
-                let (n, string) =
-                    self.while_peek(|c, _| is_end_of_line(c), |c| c.is_alphanumeric());
+        self.advance_nth(n - 1);
+        Token::NUMBER(number)
+    }
 
-                // complete the identifier.
-                let string = format!("{c}{}", &string[..].remove_last());
+    /// Lexes the decimal number starting at `c`, returns nothing if a `.` isn't followed by a
+    /// digit.
+    ///
+    /// A number is digits with an optional fraction after a `.`, then an optional exponent
+    /// after an `e` with an optional sign, so `1e5+x` stops before the `+`. Like Lua it takes
+    /// the letters, digits and `.` right after it too, so `1.2.3` is one malformed number.
+    /// A `-` before it is never part of it, the parser takes that for a unary minus.
+    fn number(&mut self, c: char) -> Option<Token> {
+        let is_digit = |c: Option<char>| c.is_some_and(char::is_numeric);
+        if c == '.' && !is_digit(self.peek()) {
+            return None;
+        }
 
-                if let Some(token) = keywords.get(&*string) {
-                    tokens.push(token.clone());
-                } else {
-                    tokens.push(Token::NAME(string))
-                }
+        // the index of the first character after the digits starting at `n`.
+        let digits = |mut n: isize| {
+            while is_digit(self.peek_nth(n)) {
+                n += 1;
+            }
+            n
+        };
 
-                self.advance_nth(n - 1);
-                continue;
+        // the integer part, the fraction itself when `c` is the `.`.
+        let mut n = digits(1);
+        if c != '.' && self.peek_nth(n) == Some('.') {
+            n = digits(n + 1);
+        }
+        let mut malformed = false;
+        if matches!(self.peek_nth(n), Some('e' | 'E')) {
+            n += 1;
+            if matches!(self.peek_nth(n), Some('+' | '-')) {
+                n += 1;
             }
+            malformed = !is_digit(self.peek_nth(n));
+            n = digits(n);
+        }
+        while self
+            .peek_nth(n)
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        {
+            malformed = true;
+            n += 1;
+        }
+
+        let text = (0..n).filter_map(|i| self.peek_nth(i)).collect::<String>();
+        let number = match malformed {
+            true => None,
+            false => text.parse::<f64>().ok(),
+        };
+        let number = number.unwrap_or_else(|| {
+            self.report_error(
+                "E002",
+                format!("malformed number near '{text}'."),
+                self.offset + text.len(),
+            );
+            0.0
+        });
+
+        self.advance_nth(n - 1);
+        Some(Token::NUMBER(number))
+    }
+
+    /// Lexes the name or keyword starting at `c`.
+    fn name(&mut self, c: char) -> Token {
+        // the ASCII part of the name is sliced out of the tape, the rest continues like any
+        // other name would.
+        let ascii = (1..)
+            .take_while(|&n| {
+                self.peek_nth(n)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+            .count();
+
+        if c.is_ascii()
+            && !self
+                .peek_nth(ascii as isize + 1)
+                .is_some_and(char::is_alphanumeric)
+        {
+            let name = &self.tape[self.offset..self.next_offset + ascii];
+            let token = keyword(name).unwrap_or_else(|| Token::NAME(name.to_string()));
+            self.skip_ascii(ascii);
+            return token;
+        }
+
+        self.scanned_name(c)
+    }
+
+    /// Lexes the name or keyword starting at `c` a character at a time, whatever it's made of.
+    fn scanned_name(&mut self, c: char) -> Token {
+        // read the rest of the identifier.
+        let (n, string) = self.while_peek(
+            |c, _| is_end_of_line(c),
+            |c| c.is_alphanumeric() || c == '_',
+        );
+
+        // complete the identifier.
+        let string = format!("{c}{}", consumed(&string, n));
+
+        self.advance_nth(n - 1);
+
+        keyword(&string).unwrap_or(Token::NAME(string))
+    }
+
+    /// Lexes the operator `c` is, or the one it starts, reporting it if it's none.
+    fn operator(&mut self, c: char) -> Option<Token> {
+        // we set this to a greater value if we match multicharacter tokens.
+        let mut skip_char = false;
 
-            // we set this to a greater value if we match multicharacter tokens.
-            let mut skip_char = false;
-
-            let token = match c {
-                '+' => Token::ADD,
-                '-' => Token::SUBTRACT,
-                '*' => Token::MULTIPLY,
-                '/' => Token::DIVIDE,
-                '(' => Token::LEFT_PAREN,
-                ')' => Token::RIGHT_PAREN,
-                '^' => Token::XOR,
-                '.' => Token::DOT,
-                ',' => Token::COMMA,
-                '#' => Token::HASHTAG,
-                ';' => Token::SEMICOLON,
-                ':' => Token::COLON,
-                ']' => Token::RIGHT_BRACKET,
-                '[' => Token::LEFT_BRACKET,
-                '{' => Token::LEFT_BRACE,
-                '}' => Token::RIGHT_BRACE,
-                '%' => Token::MODULO,
-                '<' => {
-                    if self.peek().unwrap_or_default() == '=' {
-                        skip_char = true;
-                        Token::LESS_EQUAL
-                    } else {
-                        Token::LESS_THAN
-                    }
+        let token = match c {
+            '+' => Token::ADD,
+            '-' => Token::SUBTRACT,
+            '*' => Token::MULTIPLY,
+            '/' => Token::DIVIDE,
+            '(' => Token::LEFT_PAREN,
+            ')' => Token::RIGHT_PAREN,
+            '^' => Token::XOR,
+            '.' => Token::DOT,
+            ',' => Token::COMMA,
+            '#' => Token::HASHTAG,
+            ';' => Token::SEMICOLON,
+            ':' => Token::COLON,
+            ']' => Token::RIGHT_BRACKET,
+            '[' => Token::LEFT_BRACKET,
+            '{' => Token::LEFT_BRACE,
+            '}' => Token::RIGHT_BRACE,
+            '%' => Token::MODULO,
+            '<' => {
+                if self.peek().unwrap_or_default() == '=' {
+                    skip_char = true;
+                    Token::LESS_EQUAL
+                } else {
+                    Token::LESS_THAN
                 }
-                '>' => {
-                    if self.peek().unwrap_or_default() == '=' {
-                        skip_char = true;
-                        Token::GREATER_EQUAL
-                    } else {
-                        Token::GREATER_THAN
-                    }
+            }
+            '>' => {
+                if self.peek().unwrap_or_default() == '=' {
+                    skip_char = true;
+                    Token::GREATER_EQUAL
+                } else {
+                    Token::GREATER_THAN
                 }
-                '~' => {
-                    if self.peek().unwrap_or_default() == '=' {
-                        skip_char = true;
-                        Token::NEQ
-                    } else {
-                        Token::UNDEFINED
-                    }
+            }
+            '~' => {
+                if self.peek().unwrap_or_default() == '=' {
+                    skip_char = true;
+                    Token::NEQ
+                } else {
+                    Token::UNDEFINED
                 }
-                '=' => {
-                    if self.peek().unwrap_or_default() == '=' {
-                        skip_char = true;
-                        Token::EQ
-                    } else {
-                        Token::ASSIGN
-                    }
+            }
+            '=' => {
+                if self.peek().unwrap_or_default() == '=' {
+                    skip_char = true;
+                    Token::EQ
+                } else {
+                    Token::ASSIGN
                 }
-                _ => Token::UNDEFINED,
-            };
+            }
+            _ => Token::UNDEFINED,
+        };
 
-            if token == Token::UNDEFINED {
-                // show an error message to the user if we don't know what they input.
-                log_error!(
-                    "[{}] undefined token '{c}' at column {}, line {}.",
-                    colored("token", Color::Grey),
-                    self.column,
-                    self.line
-                );
-                self.errored = true;
+        if token == Token::UNDEFINED {
+            // show an error message to the user if we don't know what they input.
+            self.report_error("E003", format!("undefined token '{c}'."), self.next_offset);
+        }
+
+        if skip_char {
+            self.advance();
+        }
+
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snippet::LineIndex;
+
+    // lexes a source that is a single string, returning its value.
+    fn string(source: &str) -> String {
+        let tokens = Lexer::new(source).tokenize().expect("the string lexes");
+        match &tokens[..] {
+            [SpannedToken {
+                token: Token::STRING(value),
+                ..
+            }] => value.clone(),
+            other => panic!("expected one string, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn strings_hold_their_decoded_value() {
+        let escapes = [
+            (r#""\n""#, "\n"),
+            (r#""\t""#, "\t"),
+            (r#""\r""#, "\r"),
+            (r#""\a""#, "\x07"),
+            (r#""\b""#, "\x08"),
+            (r#""\f""#, "\x0c"),
+            (r#""\v""#, "\x0b"),
+            (r#""\\""#, "\\"),
+            (r#""\"""#, "\""),
+            (r#"'\''"#, "'"),
+            (r#"'\"'"#, "\""),
+            (r#""\65\066\0671""#, "ABC1"),
+            (r#""\x41\x7a""#, "Az"),
+            (r#""\u{48}\u{e9}\u{20AC}""#, "Hé€"),
+            ("\"a\\z  \n  b\"", "ab"),
+            ("\"line\\\nbreak\"", "line\nbreak"),
+            (r#""he said \"hi\"""#, "he said \"hi\""),
+        ];
+        for (source, value) in escapes {
+            assert_eq!(string(source), value, "{source}");
+        }
+
+        // long strings have no escapes, and leave out the line break after the bracket.
+        assert_eq!(string("[[\n\\n]]"), "\\n");
+    }
+
+    #[test]
+    fn the_span_keeps_the_string_as_written() {
+        let source = r#"x = "a\tb""#;
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        assert_eq!(tokens[2].token, Token::STRING("a\tb".to_string()));
+        assert_eq!(
+            &source[tokens[2].span.start..tokens[2].span.end],
+            r#""a\tb""#
+        );
+    }
+
+    #[test]
+    fn an_unknown_escape_is_one_error_at_where_it_is() {
+        let mut lexer = Lexer::new("x = 1\ns = \"ok \\q\"");
+        assert!(lexer.tokenize().is_none());
+
+        let diagnostics = lexer.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "E004");
+        assert_eq!(diagnostics[0].message, "invalid escape sequence '\\q'.");
+        assert_eq!(
+            (diagnostics[0].span.line, diagnostics[0].span.column),
+            (2, 9)
+        );
+    }
+
+    #[test]
+    fn hex_numbers_take_either_prefix() {
+        for source in ["0x1F", "0X1F", "0x1f", "0X1f"] {
+            let tokens = Lexer::new(source).tokenize().expect("the number lexes");
+            assert_eq!(tokens.len(), 1, "{source}");
+            assert_eq!(tokens[0].token, Token::NUMBER(31.0), "{source}");
+        }
+    }
+
+    #[test]
+    fn decimal_numbers() {
+        let cases = [
+            ("1e+5", 1e5),
+            ("1E-2", 1e-2),
+            ("3.25", 3.25),
+            (".5", 0.5),
+            ("5.", 5.0),
+            ("007", 7.0),
+        ];
+        for (source, value) in cases {
+            let tokens = Lexer::new(source).tokenize().expect("the number lexes");
+            assert_eq!(tokens.len(), 1, "{source}");
+            assert_eq!(tokens[0].token, Token::NUMBER(value), "{source}");
+        }
+
+        // the sign of an exponent stops it, the rest is an operator and a name.
+        let tokens = Lexer::new("1e5+x").tokenize().unwrap();
+        let tokens = tokens.into_iter().map(|t| t.token).collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [Token::NUMBER(1e5), Token::ADD, Token::NAME("x".to_string())]
+        );
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for source in ["1_000", "1e", "1e+", "1.2.3", "3x", "0.5_"] {
+            let mut lexer = Lexer::new(source);
+            assert!(lexer.tokenize().is_none(), "{source}");
+
+            let diagnostics = lexer.diagnostics();
+            assert_eq!(diagnostics.len(), 1, "{source}");
+            assert_eq!(diagnostics[0].code, "E002", "{source}");
+            assert_eq!(
+                diagnostics[0].message,
+                format!("malformed number near '{source}'."),
+            );
+        }
+    }
+
+    // how the lexer dispatched before `CLASSES`, trying every kind of token in turn, kept to
+    // check the table against.
+    fn reference_token(lexer: &mut Lexer, c: char) -> Option<Token> {
+        if c.is_whitespace() {
+            return None;
+        }
+        if c == '-' && lexer.peek() == Some('-') {
+            lexer.comment();
+            return None;
+        }
+        if let Some(level) = (c == '[').then(|| lexer.long_bracket_level()).flatten() {
+            return lexer.long_string(level);
+        }
+        if c == '"' || c == '\'' {
+            return Some(lexer.quoted_string(c));
+        }
+        if let Some(token) = (c == '.').then(|| lexer.dots()).flatten() {
+            return Some(token);
+        }
+        if c == '0' && matches!(lexer.peek(), Some('x' | 'X')) {
+            return Some(lexer.hex_number());
+        }
+        if c.is_numeric() || c == '.' {
+            if let Some(token) = lexer.number(c) {
+                return Some(token);
             }
+        }
+        if c.is_alphabetic() || c == '_' {
+            return Some(lexer.scanned_name(c));
+        }
+        lexer.operator(c)
+    }
+
+    // the pieces the sources lexed both ways are made of, every kind of token and error.
+    const PIECES: &[&str] = &[
+        "local",
+        "function",
+        "end",
+        "x",
+        "_G",
+        "self",
+        "naïve",
+        "x٣",
+        "名前",
+        "ünïcödé_1",
+        "3",
+        "0.5",
+        ".5",
+        "1e5",
+        "1E-3",
+        "0x1F",
+        "0Xff",
+        "٣",
+        "1_000",
+        "1e",
+        "1.2.3",
+        "3x",
+        "0x",
+        "'a'",
+        "\"b\\n\"",
+        "'é'",
+        "\"\\q\"",
+        "'unfinished",
+        "\"a\\\nb\"",
+        "'\\z \n x'",
+        "[[long]]",
+        "[==[ ]] ]==]",
+        "[[ö\n]]",
+        "[=[ never closed",
+        "-- comment é",
+        "--[[ long\n ]]",
+        "--[==[ \n ]==]",
+        "--[[ never closed",
+        "+",
+        "-",
+        "*",
+        "/",
+        "%",
+        "^",
+        "#",
+        "==",
+        "~=",
+        "<=",
+        ">=",
+        "<",
+        ">",
+        "=",
+        "(",
+        ")",
+        "{",
+        "}",
+        "[",
+        "]",
+        ";",
+        ":",
+        ",",
+        ".",
+        "..",
+        "...",
+        "~",
+        "@",
+        "$",
+        "!",
+        "\u{1}",
+        "\u{a0}",
+        "\u{2003}",
+        "→",
+        "🙂",
+        " ",
+        "\t",
+        "\n",
+        "\r\n",
+        "\u{b}",
+        "\u{c}",
+    ];
+
+    /// A xorshift generator, to make the same sources on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    // what a source lexes to, with one way of dispatching.
+    fn lex_with(
+        source: &str,
+        next_token: fn(&mut Lexer, char) -> Option<Token>,
+    ) -> (Option<Tokens>, Vec<Diagnostic>) {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize_with(next_token);
+        (tokens, lexer.diagnostics)
+    }
+
+    #[test]
+    fn the_class_table_lexes_like_trying_every_token() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        // every piece on its own, then sources of pieces run together or apart.
+        let mut sources = PIECES.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        for _ in 0..5000 {
+            let separator = ["", " ", "\n"][rng.below(3)];
+            let pieces = (0..1 + rng.below(12)).map(|_| PIECES[rng.below(PIECES.len())]);
+            sources.push(pieces.collect::<Vec<_>>().join(separator));
+        }
+        sources.push(include_str!("../program.lua").to_string());
+
+        for source in &sources {
+            assert_eq!(
+                lex_with(source, Lexer::next_token),
+                lex_with(source, reference_token),
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_string_broken_across_a_line_is_one_error_and_lexing_goes_on() {
+        // the same with LF and CRLF line breaks, and an `@` to show where lexing got to.
+        for line_break in ["\n", "\r\n"] {
+            let source = [
+                "local a = \"broken",
+                "local b = 1",
+                "local c = 'also broken",
+                "f(a, b, c) @",
+                "",
+            ]
+            .join(line_break);
+            let mut lexer = Lexer::new(&source);
+            assert!(lexer.tokenize().is_none());
+
+            let found = lexer
+                .diagnostics()
+                .iter()
+                .map(|d| {
+                    (
+                        d.code,
+                        d.message.as_str(),
+                        d.span.line,
+                        d.span.column,
+                        &source[d.span.start..d.span.end],
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                [
+                    (
+                        "E001",
+                        "unfinished string near '\"broken'.",
+                        1,
+                        11,
+                        "\"broken"
+                    ),
+                    (
+                        "E001",
+                        "unfinished string near ''also broken'.",
+                        3,
+                        11,
+                        "'also broken"
+                    ),
+                    ("E003", "undefined token '@'.", 4, 12, "@"),
+                ],
+                "{line_break:?}"
+            );
+        }
+
+        // the fixture kept with the fuzz regressions.
+        let mut lexer = Lexer::new(include_str!("../fuzz/regressions/lexer/unfinished-strings"));
+        assert!(lexer.tokenize().is_none());
+        let codes = lexer
+            .diagnostics()
+            .iter()
+            .map(|d| d.code)
+            .collect::<Vec<_>>();
+        assert_eq!(codes, ["E001", "E001"]);
+    }
+
+    #[test]
+    fn a_backslash_continues_a_string_over_either_line_break() {
+        assert_eq!(string("\"a\\\nb\""), "a\nb");
+        assert_eq!(string("\"a\\\r\nb\""), "a\nb");
+
+        // the lines after it keep their positions.
+        let tokens = Lexer::new("s = \"a\\\nb\\\n\" x").tokenize().unwrap();
+        let x = tokens.last().unwrap();
+        assert_eq!(x.token, Token::NAME("x".to_string()));
+        assert_eq!((x.span.line, x.span.column), (3, 3));
+    }
 
-            tokens.push(token);
+    #[test]
+    fn a_string_ends_at_its_own_quote() {
+        assert_eq!(string(r#""it's""#), "it's");
+        assert_eq!(string(r#"'say "hi"'"#), "say \"hi\"");
+        // an even number of backslashes before a quote doesn't escape it.
+        assert_eq!(string(r#""a\\""#), "a\\");
+        assert_eq!(string(r#""a\\\"b""#), "a\\\"b");
+
+        let tokens = Lexer::new(r#"x = "a\\" .. 'b'"#).tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[4].token, Token::STRING("b".to_string()));
+    }
+
+    #[test]
+    fn every_token_starts_where_the_line_index_puts_it() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut sources = vec![
+            "local s = [[\ntwo\nlines]] x".to_string(),
+            "--[==[\n\n]==] y = 1".to_string(),
+            "a = 'é' b = \"c\\\r\nd\" e".to_string(),
+        ];
+        for _ in 0..3000 {
+            let separator = [" ", "\n", "\r\n", "\t"][rng.below(4)];
+            let pieces = (0..1 + rng.below(12)).map(|_| PIECES[rng.below(PIECES.len())]);
+            sources.push(pieces.collect::<Vec<_>>().join(separator));
+        }
+        sources.push(include_str!("../program.lua").to_string());
 
-            if skip_char {
-                self.advance();
+        for source in &sources {
+            // a source that doesn't lex has no tokens to look at.
+            let Some(tokens) = Lexer::new(source).tokenize() else {
+                continue;
+            };
+            let index = LineIndex::new(source);
+            for t in &tokens {
+                assert_eq!(
+                    (t.span.line, t.span.column),
+                    index.position(t.span.start, source),
+                    "{:?} in {source:?}",
+                    t.token
+                );
             }
         }
 
-        // if there was an error during lexing we still want to show all the error messages at
-        // once.
-        if self.errored {
-            return None;
+        // the name after a long string is on the string's last line.
+        let tokens = Lexer::new("local s = [[\ntwo\nlines]] x")
+            .tokenize()
+            .unwrap();
+        let x = tokens.last().unwrap();
+        assert_eq!((x.span.line, x.span.column), (3, 9));
+    }
+
+    #[test]
+    fn byte_escapes_go_up_to_255() {
+        assert_eq!(string(r#""\0""#), "\0");
+        assert_eq!(string(r#""\0001""#), "\u{0}1");
+        assert_eq!(string(r#""\27[1mbold\27[0m""#), "\x1b[1mbold\x1b[0m");
+        assert_eq!(string(r#""A\x41\65\x7e!""#), "AAA~!");
+        // a byte that isn't UTF-8 on its own.
+        assert_eq!(string(r#""\255""#), "\u{fffd}");
+
+        // the error covers the escape, wherever it is in the literal.
+        let cases = [
+            (
+                r#"s = "\256""#,
+                "decimal escape '\\256' is too large, the largest is '\\255'.",
+                6,
+                r"\256",
+            ),
+            (
+                r#"s = "ok \999 x""#,
+                "decimal escape '\\999' is too large, the largest is '\\255'.",
+                9,
+                r"\999",
+            ),
+            (
+                r#"s = "\x4g""#,
+                "invalid escape sequence '\\x4g'.",
+                6,
+                r"\x4g",
+            ),
+            (r#"s = "\x""#, "invalid escape sequence '\\x'.", 6, r"\x"),
+        ];
+        for (source, message, column, escape) in cases {
+            let mut lexer = Lexer::new(source);
+            assert!(lexer.tokenize().is_none(), "{source}");
+            let diagnostics = lexer.diagnostics();
+            assert_eq!(diagnostics.len(), 1, "{source}: {diagnostics:?}");
+            let d = &diagnostics[0];
+            assert_eq!((d.code, d.message.as_str()), ("E004", message), "{source}");
+            assert_eq!(d.span.column, column, "{source}");
+            assert_eq!(&source[d.span.start..d.span.end], escape, "{source}");
         }
+    }
 
-        Some(tokens)
+    #[test]
+    fn z_skips_the_whitespace_after_it_over_line_breaks() {
+        assert_eq!(string("\"hello \\z\n      world\""), "hello world");
+        assert_eq!(string("'a\\z\r\n\r\n\t b'"), "ab");
+        assert_eq!(string("\"a\\z\n\n\n\""), "a");
+        // only the whitespace is skipped.
+        assert_eq!(string("\"a \\zb\""), "a b");
+
+        // the skipped lines are counted, for the tokens after the string.
+        for br in ["\n", "\r\n"] {
+            let source = format!("s = \"one \\z{br}{br}    two\"{br}x = 1");
+            let tokens = Lexer::new(&source).tokenize().unwrap();
+            let x = &tokens[3];
+            assert_eq!(x.token, Token::NAME("x".to_string()), "{source:?}");
+            assert_eq!((x.span.line, x.span.column), (4, 1), "{source:?}");
+        }
+
+        // an escaped backslash then a `z` is no `\z`, and the line break ends the string.
+        let mut lexer = Lexer::new("s = \"a\\\\z\nb\"");
+        assert!(lexer.tokenize().is_none());
+        assert_eq!(lexer.diagnostics()[0].code, "E001");
+    }
+
+    #[test]
+    fn a_long_string_ends_at_the_bracket_of_its_level() {
+        assert_eq!(string("[[a]]"), "a");
+        assert_eq!(string("[=[a]]b]=]"), "a]]b");
+        assert_eq!(
+            string("[==[ can contain ]] safely ]==]"),
+            " can contain ]] safely "
+        );
+        assert_eq!(string("[==[]=]]===]]==]"), "]=]]===]");
+        assert_eq!(string("[[]]"), "");
+        // a bracket of a lower or higher level is part of the string.
+        assert_eq!(string("[=[[[x]]]=]"), "[[x]]");
+
+        // only the one line break right after the opening bracket is left out.
+        assert_eq!(string("[==[\nx]==]"), "x");
+        assert_eq!(string("[==[\r\nx\r\n]==]"), "x\r\n");
+        assert_eq!(string("[[\n\ntwo]]"), "\ntwo");
+        assert_eq!(string("[[ \nx]]"), " \nx");
+
+        // what follows the string is lexed as usual, on the string's last line.
+        let tokens = Lexer::new("s = [=[\na]]\n]=] .. t").tokenize().unwrap();
+        assert_eq!(tokens[2].token, Token::STRING("a]]\n".to_string()));
+        assert_eq!(tokens[3].token, Token::CONCAT);
+        assert_eq!((tokens[4].span.line, tokens[4].span.column), (3, 8));
+    }
+
+    #[test]
+    fn an_unfinished_long_string_is_reported_at_its_opening_bracket() {
+        for (source, line, column) in [
+            ("x = 1\ns = [=[ never closed ]]\ny = 2", 2, 5),
+            ("[==[\n\n\n]=]", 1, 1),
+            ("  [[", 1, 3),
+        ] {
+            let mut lexer = Lexer::new(source);
+            assert!(lexer.tokenize().is_none(), "{source:?}");
+            let diagnostics = lexer.diagnostics();
+            assert_eq!(diagnostics.len(), 1, "{source:?}: {diagnostics:?}");
+            let d = &diagnostics[0];
+            assert_eq!(d.code, "E001");
+            assert_eq!(
+                d.message,
+                format!("unfinished long string starting at line {line}.")
+            );
+            assert_eq!((d.span.line, d.span.column), (line, column), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn a_long_comment_ends_at_the_bracket_of_its_level() {
+        let names = |source: &str| -> Vec<Token> {
+            Lexer::new(source)
+                .tokenize()
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token)
+                .collect()
+        };
+        let x = || Token::NAME("x".to_string());
+
+        // a `]]` inside a level-1 comment doesn't end it.
+        assert_eq!(names("--[=[ a ]] b ]=] x"), [x()]);
+        assert_eq!(names("--[==[\n]]\n]=]\n]==]\nx"), [x()]);
+        assert_eq!(names("--[[ ]=] ]] x"), [x()]);
+        // without a second bracket, it's a comment to the end of the line.
+        assert_eq!(names("--[= ]] x\nx"), [x()]);
+        assert_eq!(names("--[ x\nx"), [x()]);
+
+        let tokens = Lexer::new("--[=[\n]]\n]=] x").tokenize().unwrap();
+        assert_eq!((tokens[0].span.line, tokens[0].span.column), (3, 5));
+
+        let source = "a --[=[ ]] ]=] b -- c\n--[[\n]]";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let comments = find_comments(source, &tokens)
+            .into_iter()
+            .map(|c| &source[c])
+            .collect::<Vec<_>>();
+        assert_eq!(comments, ["--[=[ ]] ]=]", "-- c", "--[[\n]]"]);
+    }
+
+    #[test]
+    fn an_unfinished_long_comment_is_one_error_at_its_opening_bracket() {
+        for source in [
+            "x = 1\n--[=[ ]] ]==]\n",
+            "x = 1\n--[[",
+            "x = 1\n--[==[\n\n]=] y",
+        ] {
+            let mut lexer = Lexer::new(source);
+            assert!(lexer.tokenize().is_none(), "{source:?}");
+            let diagnostics = lexer.diagnostics();
+            assert_eq!(diagnostics.len(), 1, "{source:?}: {diagnostics:?}");
+            let d = &diagnostics[0];
+            assert_eq!(d.code, "E005");
+            assert_eq!(d.message, "unfinished long comment starting at line 2.");
+            assert_eq!((d.span.line, d.span.column), (2, 1), "{source:?}");
+
+            // the comment runs to the end of the source.
+            let start = source.find("--").unwrap();
+            assert_eq!(comment_len(&source[start..]), source.len() - start);
+        }
     }
 }
@@ -0,0 +1,627 @@
+//! The lexer, parser and diagnostics of the compiler, for embedding it in other tools.
+//!
+//! Nothing here prints or touches the file system, the problems found in a source are returned
+//! as [`Diagnostic`]s for the caller to render however it likes.
+//!
+//! The lexer and the parser never panic, whatever the input. Anything they can't make sense
+//! of is a diagnostic, including code nested more than 200 levels deep, which keeps them within
+//! a few megabytes of stack even in a debug build.
+//!
+//! ```
+//! use lua_compiler::lexer::{Lexer, Token};
+//! use lua_compiler::parser::Parser;
+//!
+//! let tokens = Lexer::new("local x = 1").tokenize().unwrap();
+//! assert_eq!(tokens[0].token, Token::LOCAL);
+//!
+//! let ast = Parser::new(tokens).parse();
+//! assert!(ast.is_some());
+//! ```
+//!
+//! [`Compiler`] runs the whole pipeline at once, configured with its builder.
+
+pub mod arity;
+pub mod ast;
+pub mod balance;
+pub mod cancel;
+pub mod chunk;
+pub mod codes;
+pub mod constant;
+pub mod definitions;
+pub mod diagnostic;
+pub mod diff;
+pub mod exports;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod folds;
+pub mod format;
+pub mod html;
+pub mod json;
+pub mod lexer;
+pub mod loc;
+pub mod loop_concat;
+pub mod naming;
+pub mod number_style;
+pub mod parser;
+pub mod pattern;
+pub mod require;
+pub mod resolve;
+pub mod snippet;
+pub mod strings;
+pub mod suppress;
+pub mod tags;
+pub mod term_color;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod whitespace;
+
+use std::cell::Cell;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use balance::Unbalanced;
+pub use cancel::{CancellationToken, Cancelled};
+use codes::Level;
+pub use diagnostic::{Diagnostic, Label, Note, Severity};
+use diagnostic::{DiagnosticRenderer, DiagnosticSink, HumanRenderer, RenderingSink, SourceContext};
+use lexer::{Span, SpannedToken};
+use naming::NamingStyle;
+use parser::ASTNode;
+
+/// What `compile_source` should do besides lexing and parsing.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Options {
+    // what to do with each kind of warning, later entries win over earlier ones.
+    pub warnings: Vec<(&'static str, Level)>,
+    // the style declared names are checked against, they aren't checked if None.
+    pub naming_style: Option<NamingStyle>,
+}
+
+/// Everything a compilation of one source produced.
+#[derive(Debug, Clone)]
+pub struct CompileResult {
+    // the name the source was compiled as, used when the diagnostics are rendered.
+    pub name: String,
+    // the tokens, if the source could be lexed.
+    pub tokens: Option<Vec<SpannedToken>>,
+    // the syntax tree, if the source could be parsed.
+    pub ast: Option<ASTNode>,
+    // the statements parsed before an error when the source couldn't be, for the callers that
+    // make do with part of the tree, like an editor. None if `ast` is the whole tree or the
+    // source couldn't be lexed.
+    pub partial_ast: Option<ASTNode>,
+    // where each statement of the main chunk is, as far as the parser got.
+    pub statement_spans: Vec<Span>,
+    // sorted by where they are in the source.
+    pub diagnostics: Vec<Diagnostic>,
+    // how long each stage that ran took, if the compiler was asked to time them.
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+impl CompileResult {
+    /// Returns true if any of the diagnostics is an error.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error && !d.suppressed)
+    }
+}
+
+/// Starts timing a stage, if the timings are wanted or going to be in the debug log.
+fn start_timer(wanted: bool) -> Option<Instant> {
+    // there is no clock on wasm32-unknown-unknown, `Instant::now` panics there.
+    let wanted = wanted || term_color::verbosity() >= term_color::Verbosity::Debug;
+    (wanted && !cfg!(target_arch = "wasm32")).then(Instant::now)
+}
+
+/// Runs the stages of the compiler over sources, all configured the same way.
+///
+/// ```
+/// use lua_compiler::codes::Level;
+/// use lua_compiler::Compiler;
+///
+/// let compiler = Compiler::builder()
+///     .warning("W200", Level::Deny)
+///     .timings(true)
+///     .build();
+///
+/// let result = compiler.compile("main.lua", "local x = 1");
+/// assert!(result.ast.is_some());
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Compiler {
+    options: Options,
+    timings: bool,
+    renderer: Renderer,
+    // the stage to panic in, to try out how a crash is reported.
+    panic_at: Option<&'static str>,
+}
+
+thread_local! {
+    // the stage `Compiler::compile` is running on this thread, if any.
+    static STAGE: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Returns the stage of the compilation running on this thread, `lex`, `parse` or `check`, or
+/// None outside of one. Meant for a panic hook, which runs on the thread that panicked.
+pub fn current_stage() -> Option<&'static str> {
+    STAGE.with(Cell::get)
+}
+
+/// The renderer a compiler was given, compilers with the same one are equal.
+#[derive(Default, Clone)]
+struct Renderer(Option<Arc<dyn DiagnosticRenderer + Send + Sync>>);
+
+impl std::fmt::Debug for Renderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Renderer(custom)"),
+            None => f.write_str("Renderer(human)"),
+        }
+    }
+}
+
+impl PartialEq for Renderer {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+/// Configures a [`Compiler`], everything left out keeps its default.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CompilerBuilder {
+    compiler: Compiler,
+}
+
+impl CompilerBuilder {
+    /// Sets what to do with a kind of warning, given by its code. Later calls win.
+    pub fn warning(mut self, code: &'static str, level: Level) -> Self {
+        self.compiler.options.warnings.push((code, level));
+        self
+    }
+
+    /// Sets what to do with several kinds of warnings, like `warning` for each.
+    pub fn warnings(mut self, warnings: impl IntoIterator<Item = (&'static str, Level)>) -> Self {
+        self.compiler.options.warnings.extend(warnings);
+        self
+    }
+
+    /// Checks the names the sources declare against a style, off by default.
+    pub fn naming_style(mut self, style: Option<NamingStyle>) -> Self {
+        self.compiler.options.naming_style = style;
+        self
+    }
+
+    /// Records how long each stage takes in `CompileResult::timings`, off by default.
+    pub fn timings(mut self, timings: bool) -> Self {
+        self.compiler.timings = timings;
+        self
+    }
+
+    /// Sets how `Compiler::render` writes the diagnostics, the human format without colors by
+    /// default.
+    pub fn renderer(mut self, renderer: impl DiagnosticRenderer + Send + Sync + 'static) -> Self {
+        self.compiler.renderer = Renderer(Some(Arc::new(renderer)));
+        self
+    }
+
+    /// Makes `compile` panic when it gets to a stage, `lex`, `parse` or `check`, to test how
+    /// crashes are handled.
+    #[doc(hidden)]
+    pub fn panic_at(mut self, stage: Option<&'static str>) -> Self {
+        self.compiler.panic_at = stage;
+        self
+    }
+
+    pub fn build(self) -> Compiler {
+        self.compiler
+    }
+}
+
+impl Compiler {
+    pub fn builder() -> CompilerBuilder {
+        CompilerBuilder::default()
+    }
+
+    /// Returns the options the compiler was built with.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Renders the diagnostics of a result with the compiler's renderer, in the order they
+    /// appear in `source`, leaving out the suppressed ones.
+    ///
+    /// ```
+    /// use lua_compiler::diagnostic::ShortRenderer;
+    /// use lua_compiler::Compiler;
+    ///
+    /// let compiler = Compiler::builder().renderer(ShortRenderer).build();
+    /// let result = compiler.compile("main.lua", "local x = = 1");
+    /// assert!(compiler.render(&result, "local x = = 1").starts_with("main.lua:1:11: error:"));
+    ///
+    /// // the default human format is plain, whatever the process's terminal can show.
+    /// let rendered = Compiler::default().render(&result, "local x = = 1");
+    /// assert!(rendered.starts_with("error: [E100]"));
+    /// ```
+    pub fn render(&self, result: &CompileResult, source: &str) -> String {
+        let ctx = SourceContext::new(&result.name, source);
+        let mut sink = match &self.renderer.0 {
+            Some(renderer) => RenderingSink::with_renderer(&ctx, Box::new(renderer.as_ref())),
+            None => RenderingSink::with_renderer(
+                &ctx,
+                Box::new(HumanRenderer {
+                    caps: term_color::OutputCaps {
+                        color: false,
+                        unicode: true,
+                    },
+                }),
+            ),
+        };
+        for d in &result.diagnostics {
+            sink.emit(d.clone());
+        }
+        sink.finish().0
+    }
+
+    /// Tokenizes the source like `tokenize`, with the levels of the warnings applied.
+    ///
+    /// Never panics, whatever the input.
+    pub fn tokenize(&self, source: &str) -> (Option<Vec<SpannedToken>>, Vec<Diagnostic>) {
+        let (tokens, mut diagnostics) = tokenize(source);
+        codes::apply_levels(&mut diagnostics, &self.options.warnings);
+        (tokens, diagnostics)
+    }
+
+    /// Lexes and parses the source as a single expression, like the right side of an
+    /// assignment, returning its tree if it is one and what went wrong otherwise.
+    ///
+    /// ```
+    /// use lua_compiler::{ast, Compiler};
+    ///
+    /// let (tree, _) = Compiler::default().parse_expression("a + b * c");
+    /// assert_eq!(ast::to_parenthesized(&tree.unwrap()), "(a + (b * c))");
+    ///
+    /// let (tree, diagnostics) = Compiler::default().parse_expression("local x = 1");
+    /// assert!(tree.is_none());
+    /// assert_eq!(diagnostics[0].code, "E100");
+    /// ```
+    pub fn parse_expression(&self, source: &str) -> (Option<ASTNode>, Vec<Diagnostic>) {
+        let (tokens, mut diagnostics) = self.tokenize(source);
+        let tree = tokens.and_then(|tokens| {
+            let mut parser = parser::Parser::new(tokens);
+            let tree = parser.parse_expression();
+            diagnostics.extend_from_slice(parser.diagnostics());
+            tree
+        });
+        codes::apply_levels(&mut diagnostics, &self.options.warnings);
+        (tree, diagnostics)
+    }
+
+    /// Lexes and parses the source, `name` is what the diagnostics will call it.
+    ///
+    /// Requires aren't followed, the modules they name are up to the caller to find. Never
+    /// panics, whatever the input: a bug in the compiler that would is caught and reported as
+    /// an E999 internal error instead, with no tokens or tree.
+    ///
+    /// ```
+    /// use lua_compiler::Compiler;
+    ///
+    /// let compiler = Compiler::builder().panic_at(Some("parse")).build();
+    /// let result = compiler.compile("main.lua", "local x = 1");
+    /// assert_eq!(result.diagnostics[0].code, "E999");
+    /// assert!(result.ast.is_none());
+    /// ```
+    pub fn compile(&self, name: &str, source: &str) -> CompileResult {
+        match self.compile_cancellable(name, source, &CancellationToken::new()) {
+            Ok(result) => result,
+            // nothing else has the token to cancel it.
+            Err(Cancelled) => unreachable!("a compilation nobody can cancel was cancelled"),
+        }
+    }
+
+    /// Compiles the source like `compile`, unless the token is cancelled before it's done.
+    ///
+    /// The token is looked at while lexing, before every statement while parsing and between
+    /// the checks, so a compilation stops soon after it's cancelled, with nothing of what it
+    /// got through.
+    ///
+    /// ```
+    /// use lua_compiler::{CancellationToken, Cancelled, Compiler};
+    ///
+    /// let source = "function f(t)\n  return t.a + 1\nend\n".repeat(100_000);
+    /// let token = CancellationToken::new();
+    /// let compiling = std::thread::spawn({
+    ///     let token = token.clone();
+    ///     move || Compiler::default().compile_cancellable("big.lua", &source, &token)
+    /// });
+    /// token.cancel();
+    /// assert_eq!(compiling.join().unwrap().unwrap_err(), Cancelled);
+    ///
+    /// let token = CancellationToken::new();
+    /// let result = Compiler::default().compile_cancellable("main.lua", "local x = 1", &token);
+    /// assert!(result.unwrap().ast.is_some());
+    /// ```
+    pub fn compile_cancellable(
+        &self,
+        name: &str,
+        source: &str,
+        token: &CancellationToken,
+    ) -> Result<CompileResult, Cancelled> {
+        let result = catch_unwind(AssertUnwindSafe(|| self.run(name, source, token)));
+        let stage = STAGE.with(|s| s.replace(None));
+
+        result.unwrap_or_else(|payload| {
+            let message = match payload.downcast_ref::<&str>() {
+                Some(message) => message.to_string(),
+                None => payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .unwrap_or_else(|| "unknown error".to_string()),
+            };
+            let diagnostic = Diagnostic::error(
+                "E999",
+                format!(
+                    "internal compiler error in the {} stage: {message}.",
+                    stage.unwrap_or("unknown")
+                ),
+                Span::default(),
+            )
+            .with_note(
+                "this is a bug in the compiler, not in the source, please report it.".to_string(),
+                None,
+            );
+
+            Ok(CompileResult {
+                name: name.to_string(),
+                tokens: None,
+                ast: None,
+                partial_ast: None,
+                statement_spans: Vec::new(),
+                diagnostics: vec![diagnostic],
+                timings: Vec::new(),
+            })
+        })
+    }
+
+    /// Looks for the required modules in `base`, the way `require` would, returning the file
+    /// of each one found and a W200 warning for each one that isn't.
+    ///
+    /// ```
+    /// use lua_compiler::{require, Compiler};
+    ///
+    /// let (tokens, _) = Compiler::default().tokenize("local json = require 'json'");
+    /// let requires = require::find_requires(&tokens.unwrap());
+    /// let (found, missing) = Compiler::default().resolve_requires(requires, "/nowhere".as_ref());
+    /// assert!(found.is_empty());
+    /// assert_eq!(missing[0].message, "module 'json' not found");
+    /// ```
+    pub fn resolve_requires(
+        &self,
+        requires: Vec<require::Require>,
+        base: &Path,
+    ) -> (Vec<(require::Require, PathBuf)>, Vec<Diagnostic>) {
+        let mut found = Vec::new();
+        let mut diagnostics = Vec::new();
+
+        for r in requires {
+            let Some(path) = require::resolve(&r.name, base) else {
+                // it could still be a C module or come from LUA_PATH at runtime.
+                let searched = require::candidates(&r.name, base)
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                diagnostics.push(
+                    Diagnostic::warning("W200", format!("module '{}' not found", r.name), r.span)
+                        .with_note(format!("looked for {searched}"), None),
+                );
+                continue;
+            };
+            found.push((r, path));
+        }
+
+        codes::apply_levels(&mut diagnostics, &self.options.warnings);
+        (found, diagnostics)
+    }
+
+    /// Marks the warnings the suppression comments of the source silence, see
+    /// `suppress::apply`, and applies the levels of the warnings to the diagnostics found
+    /// besides `compile`'s, like those about the modules the source requires.
+    ///
+    /// Without its tokens, a source with suppression comments is lexed again to find them.
+    ///
+    /// ```
+    /// use lua_compiler::{require, Compiler};
+    ///
+    /// let compiler = Compiler::default();
+    /// let source = "require 'json' --luacompiler: disable-line W200\nrequire 'yaml'";
+    /// let requires = require::find_requires(&compiler.tokenize(source).0.unwrap());
+    /// let (_, mut missing) = compiler.resolve_requires(requires, "/nowhere".as_ref());
+    ///
+    /// compiler.suppress(&mut missing, source, None);
+    /// assert!(missing[0].suppressed && !missing[1].suppressed);
+    /// ```
+    pub fn suppress(
+        &self,
+        diagnostics: &mut Vec<Diagnostic>,
+        source: &str,
+        tokens: Option<&[SpannedToken]>,
+    ) {
+        let lexed;
+        let tokens = match tokens {
+            Some(tokens) => tokens,
+            None if source.contains(suppress::PREFIX) => {
+                lexed = tokenize(source).0.unwrap_or_default();
+                &lexed
+            }
+            None => &[],
+        };
+        suppress::apply(diagnostics, source, tokens);
+        codes::apply_levels(diagnostics, &self.options.warnings);
+    }
+
+    /// Records that the compilation got to a stage, for a crash to say where it happened.
+    fn enter(&self, stage: &'static str) {
+        STAGE.with(|s| s.set(Some(stage)));
+        if self.panic_at == Some(stage) {
+            panic!("asked to panic in the {stage} stage");
+        }
+    }
+
+    fn run(
+        &self,
+        name: &str,
+        source: &str,
+        token: &CancellationToken,
+    ) -> Result<CompileResult, Cancelled> {
+        let mut timings = Vec::new();
+        let mut stop_timer = |stage, started: Option<Instant>| {
+            let elapsed = started?.elapsed();
+            if self.timings {
+                timings.push((stage, elapsed));
+            }
+            Some(elapsed)
+        };
+
+        self.enter("lex");
+        let started = start_timer(self.timings);
+        let mut lexer = lexer::Lexer::new(source).with_cancellation(token.clone());
+        let tokens = lexer.tokenize();
+        token.check()?;
+        let mut diagnostics = lexer.diagnostics().to_vec();
+
+        if let (Some(tokens), Some(elapsed)) = (&tokens, stop_timer("lex", started)) {
+            log_debug!("{name}: lexed {} tokens in {elapsed:.2?}.", tokens.len());
+        }
+
+        let mut statement_spans = Vec::new();
+        let mut partial_ast = None;
+        let ast = tokens.as_ref().and_then(|tokens| {
+            // cloning the tokens for the parser takes a while, for nothing once cancelled.
+            if token.is_cancelled() {
+                return None;
+            }
+            self.enter("parse");
+            let started = start_timer(self.timings);
+            let mut parser = parser::Parser::new(tokens.clone()).with_cancellation(token.clone());
+            let (ast, complete) = parser.parse_partial();
+            let mut parse_errors = parser.diagnostics().to_vec();
+            if !complete && !token.is_cancelled() {
+                explain_imbalance(tokens, &mut parse_errors);
+            }
+            diagnostics.extend(parse_errors);
+            statement_spans = parser.statement_spans().to_vec();
+            if let Some(elapsed) = stop_timer("parse", started) {
+                log_debug!("{name}: parsed in {elapsed:.2?}.");
+            }
+            match complete {
+                true => Some(ast),
+                false => {
+                    partial_ast = Some(ast);
+                    None
+                }
+            }
+        });
+
+        // a cancelled parse stops wherever it was, with errors that aren't the source's.
+        token.check()?;
+
+        self.enter("check");
+        // a source that doesn't parse has enough to fix before its names.
+        if let (Some(tokens), Some(_), Some(style)) = (&tokens, &ast, self.options.naming_style) {
+            diagnostics.extend(naming::check(tokens, style));
+            token.check()?;
+        }
+        if let (Some(tokens), Some(_)) = (&tokens, &ast) {
+            diagnostics.extend(loop_concat::check(tokens));
+            token.check()?;
+            let too_few = codes::is_requested("W208", &self.options.warnings);
+            diagnostics.extend(arity::check(tokens, too_few));
+            token.check()?;
+        }
+        if let Some(tokens) = tokens.as_deref() {
+            if codes::is_requested("W204", &self.options.warnings) {
+                diagnostics.extend(number_style::check(source, tokens));
+                token.check()?;
+            }
+            if codes::is_requested("W205", &self.options.warnings) {
+                diagnostics.extend(whitespace::check(source, tokens));
+                token.check()?;
+            }
+        }
+
+        codes::apply_levels(&mut diagnostics, &self.options.warnings);
+
+        Ok(CompileResult {
+            name: name.to_string(),
+            tokens,
+            ast,
+            partial_ast,
+            statement_spans,
+            diagnostics: diagnostic::organize(diagnostics, None),
+            timings,
+        })
+    }
+}
+
+/// Improves the errors of a parse that failed with what `balance::check` finds: the errors at
+/// the end of the source are replaced with the block that's left open, and one at a closer
+/// that doesn't match points at the opener it should have closed.
+fn explain_imbalance(tokens: &[SpannedToken], errors: &mut Vec<Diagnostic>) {
+    let Some(unbalanced) = balance::check(tokens) else {
+        return;
+    };
+
+    match &unbalanced {
+        Unbalanced::Unclosed(_) => {
+            // the parser reports every block it was in as it runs out of tokens.
+            let end = tokens.last().map_or(0, |t| t.span.end);
+            let before = errors.len();
+            errors.retain(|d| d.span.start < end);
+            if errors.len() < before {
+                errors.push(unbalanced.diagnostic());
+            }
+        }
+        Unbalanced::Mismatched { closer, .. } => {
+            let at = errors
+                .iter()
+                .position(|d| d.span.start == closer.span.start && d.labels.is_empty());
+            if let Some(i) = at {
+                errors[i] = unbalanced.diagnostic();
+            }
+        }
+    }
+}
+
+/// Tokenizes the source, returning the tokens and what went wrong along the way.
+///
+/// Never panics, whatever the input.
+pub fn tokenize(source: &str) -> (Option<Vec<SpannedToken>>, Vec<Diagnostic>) {
+    let mut lexer = lexer::Lexer::new(source);
+    let tokens = lexer.tokenize();
+    (tokens, lexer.diagnostics().to_vec())
+}
+
+/// Lexes and parses the source with the given options, the same as a `Compiler` built with
+/// them would.
+///
+/// ```
+/// use lua_compiler::{compile_source, Options};
+///
+/// let result = compile_source("main.lua", "local x = 1 print(", &Options::default());
+/// assert!(result.ast.is_none());
+/// assert!(result.tokens.is_some() && result.partial_ast.is_some());
+/// assert_eq!(result.diagnostics[0].code, "E100");
+/// ```
+pub fn compile_source(name: &str, source: &str, options: &Options) -> CompileResult {
+    Compiler::builder()
+        .warnings(options.warnings.iter().copied())
+        .naming_style(options.naming_style)
+        .build()
+        .compile(name, source)
+}
@@ -0,0 +1,296 @@
+use crate::json;
+use crate::lexer::{find_comments, SpannedToken, Token};
+use crate::snippet::LineIndex;
+
+/// What a source is made of, counted from its tokens and its comments.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub lines: usize,
+    pub blank: usize,
+    // lines with a comment and no code.
+    pub comment: usize,
+    // lines with a token on them, even if a comment follows it.
+    pub code: usize,
+    pub keywords: usize,
+    pub identifiers: usize,
+    // numbers, strings, nil, true and false.
+    pub literals: usize,
+    // the operators and the punctuation.
+    pub operators: usize,
+    // the 1-based number of the longest line, and its length in characters.
+    pub longest_line: (usize, usize),
+}
+
+impl Stats {
+    /// Adds the counts of another source, keeping the longer of the two longest lines.
+    pub fn add(&mut self, other: &Stats) {
+        self.lines += other.lines;
+        self.blank += other.blank;
+        self.comment += other.comment;
+        self.code += other.code;
+        self.keywords += other.keywords;
+        self.identifiers += other.identifiers;
+        self.literals += other.literals;
+        self.operators += other.operators;
+        if other.longest_line.1 > self.longest_line.1 {
+            self.longest_line = other.longest_line;
+        }
+    }
+}
+
+/// Counts the lines and the tokens of the source.
+///
+/// A line is code if a token is on it, a string spanning lines makes each of them code. Of the
+/// rest, a line that's only whitespace is blank, even within a long comment, and any other is
+/// a comment.
+pub fn count(source: &str, tokens: &[SpannedToken]) -> Stats {
+    let index = LineIndex::new(source);
+    let mut stats = Stats {
+        lines: source.lines().count(),
+        ..Stats::default()
+    };
+
+    // covers the lines from the one `start` is on to the one the last byte before `end` is on.
+    let lines_of =
+        |start: usize, end: usize| index.line_of(start) - 1..index.line_of(end.max(start + 1) - 1);
+
+    let mut code = vec![false; stats.lines];
+    for t in tokens {
+        for line in lines_of(t.span.start, t.span.end) {
+            if let Some(line) = code.get_mut(line) {
+                *line = true;
+            }
+        }
+
+        match t.token {
+            Token::NAME(_) => stats.identifiers += 1,
+            Token::NUMBER(_) | Token::STRING(_) | Token::NIL | Token::TRUE | Token::FALSE => {
+                stats.literals += 1
+            }
+            Token::AND
+            | Token::BREAK
+            | Token::DO
+            | Token::ELSE
+            | Token::ELSEIF
+            | Token::END
+            | Token::FOR
+            | Token::FUNCTION
+            | Token::IF
+            | Token::IN
+            | Token::LOCAL
+            | Token::NOT
+            | Token::OR
+            | Token::REPEAT
+            | Token::RETURN
+            | Token::THEN
+            | Token::UNTIL
+            | Token::WHILE => stats.keywords += 1,
+            _ => stats.operators += 1,
+        }
+    }
+
+    let mut comment = vec![false; stats.lines];
+    for c in find_comments(source, tokens) {
+        for line in lines_of(c.start, c.end) {
+            if let Some(line) = comment.get_mut(line) {
+                *line = true;
+            }
+        }
+    }
+
+    for (i, text) in source.lines().enumerate() {
+        if code[i] {
+            stats.code += 1;
+        } else if text.trim().is_empty() {
+            stats.blank += 1;
+        } else if comment[i] {
+            stats.comment += 1;
+        } else {
+            // what the lexer skipped without it being a comment, which there shouldn't be.
+            stats.code += 1;
+        }
+
+        let length = text.chars().count();
+        if length > stats.longest_line.1 {
+            stats.longest_line = (i + 1, length);
+        }
+    }
+
+    stats
+}
+
+/// Finds the longest line of all the files, along with the file it's in.
+fn longest_line(files: &[(String, Stats)]) -> Option<(&str, usize, usize)> {
+    files
+        .iter()
+        .filter(|(_, s)| s.longest_line.1 > 0)
+        .max_by_key(|(_, s)| s.longest_line.1)
+        .map(|(file, s)| (file.as_str(), s.longest_line.0, s.longest_line.1))
+}
+
+/// Renders the counts as a table of one row per file, and a total row when there are several.
+pub fn to_table(files: &[(String, Stats)]) -> String {
+    let mut total = Stats::default();
+    for (_, stats) in files {
+        total.add(stats);
+    }
+
+    let mut rows = files
+        .iter()
+        .map(|(f, s)| (f.as_str(), s))
+        .collect::<Vec<_>>();
+    if files.len() > 1 {
+        rows.push(("total", &total));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(f, _)| f.chars().count())
+        .max()
+        .unwrap_or(0);
+    let width = width.max("file".len());
+
+    let mut out = format!(
+        "{:<width$}  {:>7}  {:>7}  {:>7}  {:>7}  {:>8}  {:>11}  {:>8}  {:>9}\n",
+        "file",
+        "lines",
+        "blank",
+        "comment",
+        "code",
+        "keywords",
+        "identifiers",
+        "literals",
+        "operators"
+    );
+    for (file, s) in rows {
+        out.push_str(&format!(
+            "{file:<width$}  {:>7}  {:>7}  {:>7}  {:>7}  {:>8}  {:>11}  {:>8}  {:>9}\n",
+            s.lines, s.blank, s.comment, s.code, s.keywords, s.identifiers, s.literals, s.operators
+        ));
+    }
+
+    if let Some((file, line, length)) = longest_line(files) {
+        out.push_str(&format!(
+            "\nthe longest line is {file}:{line}, {length} characters.\n"
+        ));
+    }
+    out
+}
+
+/// Renders the counts as a JSON object, with the counts of every file and their total.
+pub fn to_json(files: &[(String, Stats)]) -> String {
+    let counts = |s: &Stats| {
+        format!(
+            "\"lines\": {}, \"blank\": {}, \"comment\": {}, \"code\": {}, \"keywords\": {}, \"identifiers\": {}, \"literals\": {}, \"operators\": {}",
+            s.lines, s.blank, s.comment, s.code, s.keywords, s.identifiers, s.literals, s.operators
+        )
+    };
+
+    let mut total = Stats::default();
+    let items = files
+        .iter()
+        .map(|(file, s)| {
+            total.add(s);
+            format!(
+                "{{\"file\": {}, {}, \"longest_line\": {{\"line\": {}, \"length\": {}}}}}",
+                json::string(file),
+                counts(s),
+                s.longest_line.0,
+                s.longest_line.1
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let longest = match longest_line(files) {
+        Some((file, line, length)) => format!(
+            "{{\"file\": {}, \"line\": {line}, \"length\": {length}}}",
+            json::string(file)
+        ),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\"files\": [{}], \"total\": {{{}, \"longest_line\": {longest}}}}}\n",
+        items.join(", "),
+        counts(&total)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn stats(source: &str) -> Stats {
+        count(source, &Lexer::new(source).tokenize().unwrap())
+    }
+
+    #[test]
+    fn every_line_is_blank_comment_or_code() {
+        let s = stats(
+            "-- a comment\n\
+             \n\
+             local x = 1 -- code then a comment\n\
+             --[[ a long\n\
+             \n\
+             comment ]]\n\
+             local s = [[\n\
+             \n\
+             ]] --[[ after ]] y = 2\n   \n",
+        );
+        assert_eq!(
+            (s.lines, s.blank, s.comment, s.code),
+            // the blank line of the long comment is blank, the one of the string is code.
+            (10, 3, 3, 4)
+        );
+    }
+
+    #[test]
+    fn tokens_are_counted_by_category() {
+        let s = stats("local t = {n = nil, 'a', 1.5} if not t then return end\n");
+        assert_eq!(s.keywords, 6);
+        assert_eq!(s.identifiers, 3);
+        assert_eq!(s.literals, 3);
+        // `=` `{` `=` `,` `,` `}`.
+        assert_eq!(s.operators, 6);
+    }
+
+    #[test]
+    fn the_longest_line_is_counted_in_characters() {
+        assert_eq!(
+            stats("x = 1\nlocal s = 'ééé'\ny = 2\n").longest_line,
+            (2, 15)
+        );
+        assert_eq!(stats("").longest_line, (0, 0));
+    }
+
+    #[test]
+    fn files_add_up_to_the_total() {
+        let files = vec![
+            ("a.lua".to_string(), stats("x = 1\n\n")),
+            ("lib/b.lua".to_string(), stats("-- b\nreturn 'longer'\n")),
+        ];
+        assert_eq!(
+            to_table(&files),
+            "\
+file         lines    blank  comment     code  keywords  identifiers  literals  operators
+a.lua            2        1        0        1         0            1         1          1
+lib/b.lua        2        0        1        1         1            0         1          0
+total            4        1        1        2         1            1         2          1
+
+the longest line is lib/b.lua:2, 15 characters.
+"
+        );
+        assert!(to_json(&files).ends_with(
+            "\"total\": {\"lines\": 4, \"blank\": 1, \"comment\": 1, \"code\": 2, \"keywords\": 1, \
+             \"identifiers\": 1, \"literals\": 2, \"operators\": 1, \"longest_line\": \
+             {\"file\": \"lib/b.lua\", \"line\": 2, \"length\": 15}}}\n"
+        ));
+        assert_eq!(
+            to_json(&[]),
+            "{\"files\": [], \"total\": {\"lines\": 0, \"blank\": 0, \"comment\": 0, \"code\": 0, \
+             \"keywords\": 0, \"identifiers\": 0, \"literals\": 0, \"operators\": 0, \
+             \"longest_line\": null}}\n"
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::resolve::resolve;
+
+/// A `while`, `for` or `repeat` loop of the source.
+struct Loop {
+    // from its keyword to its `end` or `until`.
+    span: Span,
+    // from its keyword to its `do`, or only the `repeat`.
+    header: Span,
+    // the index of the `function` the loop is in, None for the main chunk.
+    function: Option<usize>,
+}
+
+/// An assignment to a single variable or field, `s = ...` or `self.buffer = ...`.
+struct Assignment {
+    // the indices of the tokens of the target and of the expression assigned to it.
+    target: Range<usize>,
+    value: Range<usize>,
+    function: Option<usize>,
+    // the index of the keyword of the innermost loop whose body it's in.
+    in_loop: Option<usize>,
+}
+
+/// Returns whether a token is an operator that binds looser than `..`.
+fn binds_looser(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::AND
+            | Token::OR
+            | Token::EQ
+            | Token::NEQ
+            | Token::LESS_THAN
+            | Token::GREATER_THAN
+            | Token::LESS_EQUAL
+            | Token::GREATER_EQUAL
+    )
+}
+
+/// Returns the index right after the expression starting at `start`, or None if it isn't one
+/// this can follow, like one with a function in it.
+///
+/// An expression ends where a token can't go on with it, before a keyword, or at an operand
+/// right after another one, like the `print` in `s = s .. x print(s)`.
+fn expression_end(tokens: &[SpannedToken], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    // whether the token before is the end of an operand, so what follows is a new statement
+    // unless it's an operator or the arguments of a call.
+    let mut after_operand = false;
+
+    for (i, t) in tokens.iter().enumerate().skip(start) {
+        match &t.token {
+            Token::LEFT_PAREN | Token::LEFT_BRACKET | Token::LEFT_BRACE => depth += 1,
+            Token::RIGHT_PAREN | Token::RIGHT_BRACKET | Token::RIGHT_BRACE => {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+                after_operand = depth == 0;
+            }
+            // a function body has statements of its own.
+            Token::FUNCTION => return None,
+            _ if depth > 0 => {}
+            Token::NAME(_)
+            | Token::NUMBER(_)
+            | Token::TRUE
+            | Token::FALSE
+            | Token::NIL
+            | Token::DOTS => {
+                if after_operand {
+                    return Some(i);
+                }
+                after_operand = true;
+            }
+            // a string right after an operand is the argument of a call.
+            Token::STRING(_) => after_operand = true,
+            Token::NOT | Token::HASHTAG | Token::DOT | Token::COLON | Token::CONCAT => {
+                after_operand = false
+            }
+            token if binds_looser(token) => after_operand = false,
+            Token::ADD
+            | Token::SUBTRACT
+            | Token::MULTIPLY
+            | Token::DIVIDE
+            | Token::MODULO
+            | Token::XOR => after_operand = false,
+            _ => return Some(i),
+        }
+    }
+    Some(tokens.len())
+}
+
+/// Finds the loops of the source by the index of their keyword, and its assignments, along
+/// with the function each is in.
+fn find(tokens: &[SpannedToken]) -> (HashMap<usize, Loop>, Vec<Assignment>) {
+    let mut loops = HashMap::new();
+    let mut assignments = Vec::new();
+
+    // the blocks around the current token: the index of their keyword, and where the body of
+    // a loop starts, None while a `while` or `for` is still before its `do`.
+    let mut blocks: Vec<(usize, Option<usize>)> = Vec::new();
+    // the functions around the current token, with how many brackets were open at each.
+    let mut functions: Vec<(usize, usize)> = Vec::new();
+    let mut brackets = 0;
+
+    for (i, t) in tokens.iter().enumerate() {
+        match t.token {
+            Token::WHILE | Token::FOR => blocks.push((i, None)),
+            Token::REPEAT => blocks.push((i, Some(i + 1))),
+            Token::DO => match blocks.last_mut() {
+                Some((opener, body @ None))
+                    if matches!(tokens[*opener].token, Token::WHILE | Token::FOR) =>
+                {
+                    *body = Some(i + 1)
+                }
+                _ => blocks.push((i, None)),
+            },
+            Token::FUNCTION => {
+                blocks.push((i, None));
+                functions.push((i, brackets));
+            }
+            Token::IF => blocks.push((i, None)),
+            Token::END | Token::UNTIL => {
+                let Some((opener, body)) = blocks.pop() else {
+                    continue;
+                };
+                if tokens[opener].token == Token::FUNCTION {
+                    functions.pop();
+                }
+                let is_loop = matches!(
+                    tokens[opener].token,
+                    Token::WHILE | Token::FOR | Token::REPEAT
+                );
+                if let (true, Some(body)) = (is_loop, body) {
+                    loops.insert(
+                        opener,
+                        Loop {
+                            span: Span {
+                                end: t.span.end,
+                                ..tokens[opener].span
+                            },
+                            header: Span {
+                                end: tokens[body - 1].span.end,
+                                ..tokens[opener].span
+                            },
+                            function: functions.last().map(|(f, _)| *f),
+                        },
+                    );
+                }
+            }
+            Token::LEFT_PAREN | Token::LEFT_BRACKET | Token::LEFT_BRACE => brackets += 1,
+            Token::RIGHT_PAREN | Token::RIGHT_BRACKET | Token::RIGHT_BRACE => {
+                brackets = brackets.saturating_sub(1)
+            }
+            _ => {}
+        }
+
+        // a target starts a statement, it doesn't go on from a field, a list, a declaration or
+        // a `for`, and it isn't the key of a table's field.
+        let starts_target = matches!(t.token, Token::NAME(_))
+            && !matches!(
+                i.checked_sub(1).map(|p| &tokens[p].token),
+                Some(Token::DOT | Token::COLON | Token::COMMA | Token::LOCAL | Token::FOR)
+            )
+            && brackets == functions.last().map_or(0, |(_, b)| *b);
+        if !starts_target {
+            continue;
+        }
+
+        let mut end = i + 1;
+        while let [dot, name, ..] = &tokens[end.min(tokens.len())..] {
+            if dot.token != Token::DOT || !matches!(name.token, Token::NAME(_)) {
+                break;
+            }
+            end += 2;
+        }
+        if tokens.get(end).map(|t| &t.token) != Some(&Token::ASSIGN) {
+            continue;
+        }
+        if let Some(value_end) = expression_end(tokens, end + 1) {
+            assignments.push(Assignment {
+                target: i..end,
+                value: end + 1..value_end,
+                function: functions.last().map(|(f, _)| *f),
+                in_loop: blocks
+                    .iter()
+                    .rev()
+                    .find(|(opener, body)| {
+                        body.is_some()
+                            && matches!(
+                                tokens[*opener].token,
+                                Token::WHILE | Token::FOR | Token::REPEAT
+                            )
+                    })
+                    .map(|(opener, _)| *opener),
+            });
+        }
+    }
+
+    (loops, assignments)
+}
+
+/// Returns whether the value of an assignment is a concatenation with its target as one of the
+/// operands, at the top of the expression.
+fn concatenates_target(tokens: &[SpannedToken], assignment: &Assignment) -> bool {
+    let value = &tokens[assignment.value.clone()];
+    let target = &tokens[assignment.target.clone()];
+
+    let mut operands = vec![Vec::new()];
+    let mut depth = 0;
+    for t in value {
+        match t.token {
+            Token::LEFT_PAREN | Token::LEFT_BRACKET | Token::LEFT_BRACE => depth += 1,
+            Token::RIGHT_PAREN | Token::RIGHT_BRACKET | Token::RIGHT_BRACE => depth -= 1,
+            Token::CONCAT if depth == 0 => {
+                operands.push(Vec::new());
+                continue;
+            }
+            // `s .. x == y` compares the concatenation, which isn't what's assigned.
+            ref token if depth == 0 && binds_looser(token) => return false,
+            _ => {}
+        }
+        operands.last_mut().unwrap().push(&t.token);
+    }
+
+    operands.len() > 1
+        && operands.iter().any(|operand| {
+            operand.len() == target.len() && operand.iter().zip(target).all(|(a, b)| **a == b.token)
+        })
+}
+
+/// Warns about the strings built up with `..` in a loop, `s = s .. piece`, which copies all of
+/// `s` on every iteration.
+///
+/// The loop is the innermost around the assignment in the same function, one in a function
+/// the loop only defines doesn't count. A target declared in the loop starts over on every
+/// iteration and is left alone.
+///
+/// ```
+/// use lua_compiler::lexer::Lexer;
+/// use lua_compiler::loop_concat;
+///
+/// let warnings = |source| loop_concat::check(&Lexer::new(source).tokenize().unwrap());
+///
+/// let source = "local s = ''\nfor i = 1, 10 do\n  s = s .. i\nend";
+/// assert_eq!(warnings(source)[0].span.line, 3);
+///
+/// let source = "for i = 1, 10 do\n  local s = ''\n  s = s .. i\n  print(s)\nend";
+/// assert!(warnings(source).is_empty());
+///
+/// // the inner loop is the one the note points at.
+/// let source = "s = ''\nwhile x do\n  for i = 1, 10 do\n    s = s .. i\n  end\nend";
+/// let found = warnings(source);
+/// assert_eq!(found.len(), 1);
+/// assert_eq!(found[0].notes[0].span.unwrap().line, 3);
+/// ```
+pub fn check(tokens: &[SpannedToken]) -> Vec<Diagnostic> {
+    let (loops, assignments) = find(tokens);
+    // where the variable named at each place is declared, None for a global.
+    let declarations: HashMap<usize, Option<Span>> = resolve(tokens)
+        .iter()
+        .flat_map(|b| b.occurrences().map(|s| (s.start, b.declaration)))
+        .collect();
+    let mut diagnostics = Vec::new();
+
+    for assignment in &assignments {
+        let Some(l) = assignment.in_loop.and_then(|opener| loops.get(&opener)) else {
+            continue;
+        };
+        if l.function != assignment.function || !concatenates_target(tokens, assignment) {
+            continue;
+        }
+
+        let name = tokens[assignment.target.start].span;
+        let declared_in_loop = declarations
+            .get(&name.start)
+            .copied()
+            .flatten()
+            .is_some_and(|d| d.start >= l.span.start && d.end <= l.span.end);
+        if declared_in_loop {
+            continue;
+        }
+
+        let target = tokens[assignment.target.clone()]
+            .iter()
+            .map(|t| t.token.spelling())
+            .collect::<String>();
+        let last = &tokens[assignment.value.end - 1];
+        let mut d = Diagnostic::warning(
+            "W206",
+            format!("'{target}' is built up with '..' in a loop"),
+            Span {
+                end: last.span.end,
+                ..name
+            },
+        )
+        .with_note(
+            format!("every iteration copies all of '{target}' so far."),
+            Some(l.header),
+        );
+        d.suggestion =
+            Some("collect the pieces in a table and join them with table.concat".to_string());
+        diagnostics.push(d);
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn warnings(source: &str) -> Vec<Diagnostic> {
+        check(&Lexer::new(source).tokenize().unwrap())
+    }
+
+    #[test]
+    fn concatenating_onto_the_target_in_a_loop_warns() {
+        let found = warnings("local s = ''\nwhile x do\n  s = s .. x\nend");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].code, "W206");
+        assert_eq!(found[0].message, "'s' is built up with '..' in a loop");
+        assert_eq!(found[0].notes[0].span.unwrap().line, 2);
+
+        // the target can be any operand, and a field.
+        assert_eq!(warnings("repeat\n  s = x .. s\nuntil x").len(), 1);
+        let found = warnings("for i = 1, 3 do\n  self.buffer = self.buffer .. i\nend");
+        assert_eq!(
+            found[0].message,
+            "'self.buffer' is built up with '..' in a loop"
+        );
+    }
+
+    #[test]
+    fn other_assignments_dont_warn() {
+        // not in a loop, not the target, or not what's assigned.
+        assert!(warnings("s = s .. x").is_empty());
+        assert!(warnings("for i = 1, 3 do\n  s = t .. x\nend").is_empty());
+        assert!(warnings("for i = 1, 3 do\n  s = s .. x == y\nend").is_empty());
+        assert!(warnings("for i = 1, 3 do\n  s = f(s .. x)\nend").is_empty());
+    }
+
+    #[test]
+    fn a_loop_outside_the_function_doesnt_count() {
+        let source = "for i = 1, 3 do\n  local f = function()\n    s = s .. i\n  end\nend";
+        assert!(warnings(source).is_empty());
+
+        let source = "function f()\n  for i = 1, 3 do\n    s = s .. i\n  end\nend";
+        assert_eq!(warnings(source).len(), 1);
+    }
+
+    #[test]
+    fn a_target_declared_in_the_loop_doesnt_warn() {
+        let source = "for i = 1, 3 do\n  local s = ''\n  s = s .. i\nend";
+        assert!(warnings(source).is_empty());
+
+        // one declared before the loop with the same name still does.
+        let source = "local s = ''\nfor i = 1, 3 do\n  s = s .. i\nend\nlocal s = ''";
+        assert_eq!(warnings(source).len(), 1);
+    }
+
+    #[test]
+    fn every_warning_of_a_long_source_is_found() {
+        let source = "local s = ''\nfor i = 1, 3 do\n  s = s .. i\nend\n".repeat(2000);
+        assert_eq!(warnings(&source).len(), 2000);
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Stdout, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use lua_compiler::codes::Level;
+use lua_compiler::definitions::find_functions;
+use lua_compiler::diagnostic::{Diagnostic, Severity};
+use lua_compiler::folds::{fold_ranges, FoldKind};
+use lua_compiler::json::{self, Value};
+use lua_compiler::snippet::LineIndex;
+use lua_compiler::{codes, log_debug, log_error, log_trace, suppress, CancellationToken};
+
+use crate::{cli, ExitCode, VERSION, WORKER_STACK_SIZE};
+
+// the JSON-RPC error codes the server answers with.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const INTERNAL_ERROR: i32 = -32603;
+const SERVER_NOT_INITIALIZED: i32 = -32002;
+
+// the longest message body the server reads, far more than any source an editor would send.
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+// the `SymbolKind`s of the protocol.
+const SYMBOL_METHOD: u8 = 6;
+const SYMBOL_FUNCTION: u8 = 12;
+
+/// The state of a language server session.
+struct Server<'a> {
+    options: &'a cli::Options,
+    // the text of every open document by its uri, as the editor last sent it.
+    documents: HashMap<String, String>,
+    // the compilation of every open document that may still publish its diagnostics.
+    compiling: HashMap<String, CancellationToken>,
+    // where the messages go, shared with the compilations publishing from their thread.
+    output: Arc<Mutex<Stdout>>,
+    initialized: bool,
+    shut_down: bool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // a message is written under the lock in one go, a panic can't leave one half sent.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs a language server on stdin and stdout until the client tells it to exit.
+///
+/// Documents are synced in full on every change, and each one is compiled on a thread of its
+/// own that publishes the diagnostics, unless a newer change cancels it first.
+pub fn run(options: &cli::Options) -> ExitCode {
+    let mut input = std::io::stdin().lock();
+    let output = Arc::new(Mutex::new(std::io::stdout()));
+    let mut server = Server {
+        options,
+        documents: HashMap::new(),
+        compiling: HashMap::new(),
+        output: Arc::clone(&output),
+        initialized: false,
+        shut_down: false,
+    };
+
+    loop {
+        let body = match read_message(&mut input) {
+            Ok(Some(body)) => body,
+            Ok(None) => {
+                log_debug!("the client closed the connection.");
+                return ExitCode::Success;
+            }
+            // the message can't be answered without knowing where it ends, so it's dropped.
+            Err(ReadError::Dropped(e)) => {
+                log_error!("invalid message: {e}.\n");
+                continue;
+            }
+            Err(ReadError::TooLong(length)) => {
+                log_error!("skipping a message of {length} bytes.\n");
+                let message = format!(
+                    "the message is {length} bytes, more than the {MAX_MESSAGE_LENGTH} bytes \
+                     the server reads"
+                );
+                let reply = error_response(&Value::Null, INVALID_REQUEST, &message);
+                if let Err(e) = write_message(&mut *lock(&output), &reply) {
+                    log_error!("could not write to the client: {e}.\n");
+                    return ExitCode::InternalError;
+                }
+                // the body is read in pieces and thrown away, a client that never sends it all
+                // is only waited for until it closes the connection.
+                if let Err(e) =
+                    std::io::copy(&mut (&mut input).take(length as u64), &mut std::io::sink())
+                {
+                    log_error!("could not read from the client: {e}.\n");
+                }
+                continue;
+            }
+        };
+
+        // a bug in handling one message is reported, and the server goes on with the next.
+        let handled = catch_unwind(AssertUnwindSafe(|| server.handle(&body)));
+        let (messages, exit) = handled.unwrap_or_else(|_| {
+            let reply = error_response(&Value::Null, INTERNAL_ERROR, "internal error");
+            (vec![reply], None)
+        });
+        for message in messages {
+            if let Err(e) = write_message(&mut *lock(&output), &message) {
+                log_error!("could not write to the client: {e}.\n");
+                return ExitCode::InternalError;
+            }
+        }
+
+        if let Some(code) = exit {
+            return code;
+        }
+    }
+}
+
+/// Why the next message couldn't be read.
+enum ReadError {
+    // the message can't be made sense of, so it's dropped without an answer.
+    Dropped(String),
+    // the headers were read but the body, of that many bytes, wasn't.
+    TooLong(usize),
+}
+
+/// Reads the body of the next message, or None once the input is closed.
+fn read_message(input: &mut impl BufRead) -> Result<Option<String>, ReadError> {
+    let mut length = None;
+
+    // the headers end with an empty line.
+    loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(ReadError::Dropped(e.to_string())),
+        }
+
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or(ReadError::Dropped(
+        "missing or invalid Content-Length header".to_string(),
+    ))?;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(ReadError::TooLong(length));
+    }
+    let mut body = vec![0; length];
+    input
+        .read_exact(&mut body)
+        .map_err(|e| ReadError::Dropped(e.to_string()))?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|_| ReadError::Dropped("the body isn't valid UTF-8".to_string()))
+}
+
+fn write_message(output: &mut impl Write, body: &str) -> std::io::Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn response(id: &Value, result: &str) -> String {
+    format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{result}}}",
+        id.to_json()
+    )
+}
+
+fn error_response(id: &Value, code: i32, message: &str) -> String {
+    format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":{},\"error\":{{\"code\":{code},\"message\":{}}}}}",
+        id.to_json(),
+        json::string(message)
+    )
+}
+
+fn notification(method: &str, params: &str) -> String {
+    format!("{{\"jsonrpc\":\"2.0\",\"method\":\"{method}\",\"params\":{params}}}")
+}
+
+impl Server<'_> {
+    /// Handles one message, returning the messages to send back and the exit code if the
+    /// server should stop.
+    fn handle(&mut self, body: &str) -> (Vec<String>, Option<ExitCode>) {
+        let Some(message) = json::parse(body) else {
+            return (
+                vec![error_response(&Value::Null, PARSE_ERROR, "invalid JSON")],
+                None,
+            );
+        };
+
+        let id = message.get("id");
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // a response to a request of ours, the server doesn't send any.
+            if message.get("result").is_some() || message.get("error").is_some() {
+                return (Vec::new(), None);
+            }
+            let id = id.unwrap_or(&Value::Null);
+            return (
+                vec![error_response(id, INVALID_REQUEST, "missing method")],
+                None,
+            );
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+        log_trace!("received '{method}'.");
+
+        if method == "exit" {
+            // the protocol asks for 1 when the client didn't shut the server down first.
+            let code = match self.shut_down {
+                true => ExitCode::Success,
+                false => ExitCode::CompileError,
+            };
+            return (Vec::new(), Some(code));
+        }
+
+        let Some(id) = id else {
+            return (self.notify(method, params), None);
+        };
+
+        let reply = if self.shut_down {
+            error_response(id, INVALID_REQUEST, "the server was shut down")
+        } else if !self.initialized && method != "initialize" {
+            error_response(id, SERVER_NOT_INITIALIZED, "the server isn't initialized")
+        } else {
+            match method {
+                "initialize" => {
+                    self.initialized = true;
+                    response(
+                        id,
+                        &format!(
+                            "{{\"capabilities\":{{\"textDocumentSync\":1,\
+                             \"documentSymbolProvider\":true,\"foldingRangeProvider\":true}},\
+                             \"serverInfo\":{{\"name\":\"lua-compiler\",\"version\":\"{VERSION}\"}}}}"
+                        ),
+                    )
+                }
+                "shutdown" => {
+                    self.shut_down = true;
+                    response(id, "null")
+                }
+                "textDocument/documentSymbol" => match self.document_symbols(params) {
+                    Some(symbols) => response(id, &symbols),
+                    None => error_response(id, INVALID_PARAMS, "unknown document"),
+                },
+                "textDocument/foldingRange" => match self.folding_ranges(params) {
+                    Some(ranges) => response(id, &ranges),
+                    None => error_response(id, INVALID_PARAMS, "unknown document"),
+                },
+                _ => error_response(id, METHOD_NOT_FOUND, &format!("unknown method '{method}'")),
+            }
+        };
+
+        (vec![reply], None)
+    }
+
+    /// Handles a notification, which never gets a response.
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<String> {
+        if !self.initialized || self.shut_down {
+            return Vec::new();
+        }
+
+        let uri = params
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(Value::as_str);
+        let Some(uri) = uri else {
+            return Vec::new();
+        };
+
+        let text = match method {
+            "textDocument/didOpen" => params
+                .get("textDocument")
+                .and_then(|d| d.get("text"))
+                .and_then(Value::as_str),
+            // the whole text is sent on every change, so the last one is all that matters.
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Value::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text"))
+                .and_then(Value::as_str),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.cancel(uri);
+                return vec![publish(uri, "[]")];
+            }
+            _ => return Vec::new(),
+        };
+
+        let Some(text) = text else {
+            return Vec::new();
+        };
+        self.documents.insert(uri.to_string(), text.to_string());
+        self.compile(uri, text);
+        Vec::new()
+    }
+
+    /// Compiles a document on a thread that publishes its diagnostics, after cancelling the
+    /// compilation of the text it replaces.
+    fn compile(&mut self, uri: &str, text: &str) {
+        self.cancel(uri);
+        let token = CancellationToken::new();
+        self.compiling.insert(uri.to_string(), token.clone());
+
+        let compiler = lua_compiler::Compiler::from(self.options);
+        let warnings = self.options.warnings.clone();
+        let output = Arc::clone(&self.output);
+        let (uri, text) = (uri.to_string(), text.to_string());
+        // the parser can nest deep enough to need more than the 2 MiB threads get by default.
+        let spawned = std::thread::Builder::new()
+            .stack_size(WORKER_STACK_SIZE)
+            .spawn(move || {
+                let Some(message) = diagnostics(&compiler, &warnings, &uri, &text, &token) else {
+                    return;
+                };
+                let mut output = lock(&output);
+                // cancelled since the compilation finished, the diagnostics are out of date.
+                if token.is_cancelled() {
+                    return;
+                }
+                if let Err(e) = write_message(&mut *output, &message) {
+                    log_error!("could not write to the client: {e}.\n");
+                }
+            });
+
+        if let Err(e) = spawned {
+            log_error!("could not start compiling a document: {e}.\n");
+        }
+    }
+
+    /// Cancels the compilation of a document, if there's one that could still publish.
+    fn cancel(&mut self, uri: &str) {
+        if let Some(token) = self.compiling.remove(uri) {
+            // one that's publishing holds the output, so it's done before this returns and
+            // nothing it sends can come after what's sent next.
+            let _output = lock(&self.output);
+            token.cancel();
+        }
+    }
+
+    fn document_symbols(&self, params: &Value) -> Option<String> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let text = self.documents.get(uri)?;
+        let index = LineIndex::new(text);
+
+        let tokens = lua_compiler::Compiler::from(self.options)
+            .tokenize(text)
+            .0
+            .unwrap_or_default();
+        let symbols = find_functions(&tokens)
+            .iter()
+            .map(|f| {
+                let kind = match f.is_method {
+                    true => SYMBOL_METHOD,
+                    false => SYMBOL_FUNCTION,
+                };
+                format!(
+                    "{{\"name\":{},\"kind\":{kind},\"range\":{},\"selectionRange\":{}}}",
+                    json::string(&f.name),
+                    range(&index, text, f.span.start, f.span.end),
+                    range(&index, text, f.name_span.start, f.name_span.end),
+                )
+            })
+            .collect::<Vec<_>>();
+        Some(format!("[{}]", symbols.join(",")))
+    }
+
+    fn folding_ranges(&self, params: &Value) -> Option<String> {
+        let uri = params.get("textDocument")?.get("uri")?.as_str()?;
+        let text = self.documents.get(uri)?;
+
+        let tokens = lua_compiler::Compiler::from(self.options)
+            .tokenize(text)
+            .0
+            .unwrap_or_default();
+        let ranges = fold_ranges(text, &tokens)
+            .iter()
+            .map(|f| {
+                // the protocol only has a kind for comments among ours, the rest are regions.
+                let kind = match f.kind {
+                    FoldKind::Comment => "comment",
+                    _ => "region",
+                };
+                format!(
+                    "{{\"startLine\":{},\"endLine\":{},\"kind\":\"{kind}\"}}",
+                    f.start_line - 1,
+                    f.end_line - 1
+                )
+            })
+            .collect::<Vec<_>>();
+        Some(format!("[{}]", ranges.join(",")))
+    }
+}
+
+/// Compiles a document and builds the notification publishing its diagnostics, or returns None
+/// if the compilation was cancelled.
+fn diagnostics(
+    compiler: &lua_compiler::Compiler,
+    warnings: &[(&'static str, Level)],
+    uri: &str,
+    text: &str,
+    token: &CancellationToken,
+) -> Option<String> {
+    let mut result = compiler.compile_cancellable(uri, text, token).ok()?;
+    let tokens = result.tokens.as_deref().unwrap_or_default();
+    suppress::apply(&mut result.diagnostics, text, tokens);
+    codes::apply_levels(&mut result.diagnostics, warnings);
+    let index = LineIndex::new(text);
+
+    // an editor has no use for what the user asked to be silenced.
+    let diagnostics = result
+        .diagnostics
+        .iter()
+        .filter(|d| !d.suppressed)
+        .map(|d| lsp_diagnostic(d, uri, text, &index))
+        .collect::<Vec<_>>();
+    Some(publish(uri, &format!("[{}]", diagnostics.join(","))))
+}
+
+fn publish(uri: &str, diagnostics: &str) -> String {
+    notification(
+        "textDocument/publishDiagnostics",
+        &format!(
+            "{{\"uri\":{},\"diagnostics\":{diagnostics}}}",
+            json::string(uri)
+        ),
+    )
+}
+
+/// Converts a diagnostic to the protocol's, with the notes folded into its message.
+fn lsp_diagnostic(d: &Diagnostic, uri: &str, text: &str, index: &LineIndex) -> String {
+    let severity = match d.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+
+    let mut message = d.message.clone();
+    for note in &d.notes {
+        message.push_str(&format!("\nnote: {}", note.message));
+    }
+
+    let related = d
+        .labels
+        .iter()
+        .map(|label| {
+            format!(
+                "{{\"location\":{{\"uri\":{},\"range\":{}}},\"message\":{}}}",
+                json::string(uri),
+                range(index, text, label.span.start, label.span.end),
+                json::string(&label.message)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        "{{\"range\":{},\"severity\":{severity},\"code\":\"{}\",\"source\":\"lua-compiler\",\
+         \"message\":{},\"relatedInformation\":[{}]}}",
+        range(index, text, d.span.start, d.span.end),
+        d.code,
+        json::string(&message),
+        related.join(",")
+    )
+}
+
+/// Builds a protocol range, whose lines start at 0 and whose columns count UTF-16 code units.
+fn range(index: &LineIndex, text: &str, start: usize, end: usize) -> String {
+    let position = |offset: usize| {
+        let offset = offset.min(text.len());
+        let line = index.line_of(offset);
+        let (line_start, _) = index.line_range(line, text);
+        let character = text
+            .get(line_start..offset)
+            .map_or(0, |s| s.encode_utf16().count());
+        format!("{{\"line\":{},\"character\":{character}}}", line - 1)
+    };
+
+    format!(
+        "{{\"start\":{},\"end\":{}}}",
+        position(start),
+        position(end)
+    )
+}
@@ -1,49 +1,872 @@
-mod lexer;
-mod parser;
-mod term_color;
+mod artifact;
+mod bundle;
+mod cache;
+mod cli;
+mod config;
+mod deps;
+mod golden;
+mod ice;
+mod lsp;
+mod rename;
+mod source;
+mod stats;
+mod unused;
+mod walk;
+mod watch;
 
-use std::env::{self, args};
-use term_color::*;
+use lua_compiler::chunk::ChunkName;
+use lua_compiler::diagnostic::{
+    self, Diagnostic, DiagnosticSink, DiagnosticsFormat, RenderingSink, Severity, SourceContext,
+};
+use lua_compiler::lexer::SpannedToken;
+use lua_compiler::term_color::*;
+use lua_compiler::{ast, codes, constant, folds, html, json, parser, require, strings, tags};
+use lua_compiler::{format_error, format_success, log_debug, log_error, log_success, log_trace};
+use std::collections::HashSet;
+use std::env::args;
+use std::io::IsTerminal;
+use std::panic::catch_unwind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
 
 // get the version number of the compiler.
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-fn main() {
-    // print the compiler banner to the console.
-    println!(
-        "{}Version: {VERSION}\n",
-        r#"
-█░░ █░█ ▄▀█   █▀▀ █▀█ █▀▄▀█ █▀█ █ █░░ █▀▀ █▀█
-█▄▄ █▄█ █▀█   █▄▄ █▄█ █░▀░█ █▀▀ █ █▄▄ ██▄ █▀▄
-"#
-    );
+const BANNER: &str = r#"
+█░░ █░█ ▄▀█   █▀▀ █▀█ █▀▄▀█ █▀█ █ █░░ █▀▀ █▀█
+█▄▄ █▄█ █▀█   █▄▄ █▄█ █░▀░█ █▀▀ █ █▄▄ ██▄ █▀▄
+"#;
+
+// the code of the diagnostic the library turns a panic into.
+const INTERNAL_ERROR: &str = "E999";
+
+// the stack of every thread compiling files in parallel, see `compile_dir`.
+const WORKER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// The documented exit codes of the compiler process.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ExitCode {
+    // the source compiled without any errors.
+    Success = 0,
+    // the source had lexing, parsing or semantic errors.
+    CompileError = 1,
+    // the compiler was invoked incorrectly, e.g. a bad flag or a missing file.
+    UsageError = 2,
+    // the compiler itself crashed.
+    InternalError = 3,
+}
+
+impl ExitCode {
+    fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+/// Lists every token on its own line as `line:col KIND "lexeme"`, or as a JSON array.
+fn dump_tokens(tokens: &[SpannedToken], code: &str, as_json: bool) -> String {
+    let mut out = String::new();
+
+    if as_json {
+        out.push_str("[\n");
+        for (i, t) in tokens.iter().enumerate() {
+            // every element but the last one needs a trailing comma.
+            let separator = if i + 1 < tokens.len() { "," } else { "" };
+            out.push_str(&format!(
+                "  {{\"line\": {}, \"column\": {}, \"kind\": \"{}\", \"lexeme\": {}}}{separator}\n",
+                t.span.line,
+                t.span.column,
+                t.token.kind(),
+                json::string(&code[t.span.start..t.span.end]),
+            ));
+        }
+        out.push_str("]\n");
+        return out;
+    }
+
+    for t in tokens {
+        out.push_str(&format!(
+            "{}:{} {} {:?}\n",
+            t.span.line,
+            t.span.column,
+            t.token.kind(),
+            &code[t.span.start..t.span.end]
+        ));
+    }
+    out
+}
+
+/// Adds the artifact to the output, or writes it to the `-o` path if one was given.
+fn emit_artifact(
+    output: Option<&Path>,
+    input: &Path,
+    contents: &str,
+    out: &mut Output,
+) -> ExitCode {
+    match output {
+        Some(path) => {
+            if let Err(e) = artifact::write(path, input, contents) {
+                out.stderr.push_str(&format_error!("{e}.\n"));
+                return ExitCode::UsageError;
+            }
+        }
+        None => out.stdout.push_str(contents),
+    }
+    ExitCode::Success
+}
+
+/// What a compilation prints, held back so files compiled in parallel don't interleave.
+///
+/// Only the artifact and JSON diagnostics go to stdout, the other diagnostics and messages about
+/// the run go to stderr so they don't end up in a pipeline.
+#[derive(Default, Clone)]
+struct Output {
+    stdout: String,
+    stderr: String,
+}
+
+impl Output {
+    /// Adds rendered diagnostics, to stdout when they're JSON for another program to read, and
+    /// to stderr with the other messages otherwise.
+    fn push_diagnostics(&mut self, format: DiagnosticsFormat, rendered: &str) {
+        match format {
+            DiagnosticsFormat::Json => self.stdout.push_str(rendered),
+            _ => self.stderr.push_str(rendered),
+        }
+    }
+
+    fn print(&self) {
+        print!("{}", self.stdout);
+        eprint!("{}", self.stderr);
+    }
+}
+
+/// Formats a count with the singular or plural form of `noun`.
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        n => format!("{n} {noun}s"),
+    }
+}
+
+/// Adds how many errors and warnings the run produced to the output, and picks the matching
+/// exit code.
+fn summarize(
+    options: &cli::Options,
+    quiet: bool,
+    diagnostics: &[Diagnostic],
+    out: &mut Output,
+) -> ExitCode {
+    let count = |severity| {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity && !d.suppressed)
+            .count()
+    };
+    let (mut errors, mut warnings) = (count(Severity::Error), count(Severity::Warning));
+
+    if options.warnings_as_errors {
+        errors += warnings;
+        warnings = 0;
+    }
+
+    if errors == 0 && warnings == 0 {
+        if !quiet {
+            out.stderr
+                .push_str(&format_success!("finished compilation.\n"));
+        }
+        return ExitCode::Success;
+    }
+
+    // the machine readable formats have to stay parsable, so they don't get a summary.
+    if options.diagnostics_format == DiagnosticsFormat::Human {
+        let errors = match errors {
+            0 => plural(errors, "error"),
+            n => colored(&plural(n, "error"), Color::Red),
+        };
+
+        let warnings = match warnings {
+            0 => plural(warnings, "warning"),
+            n => colored(&plural(n, "warning"), Color::Yellow),
+        };
+
+        out.stderr
+            .push_str(&format!("finished with {errors}, {warnings}.\n\n"));
+    }
+
+    // a crash the library caught is still the compiler's fault, not the source's.
+    if diagnostics.iter().any(|d| d.code == INTERNAL_ERROR) {
+        ExitCode::InternalError
+    } else if errors > 0 {
+        ExitCode::CompileError
+    } else {
+        ExitCode::Success
+    }
+}
+
+/// The files of a program that are compiled together, found by following its requires.
+struct Program {
+    // the directory modules are looked up in, the one of the file being compiled.
+    base: PathBuf,
+    // the modules whose requires are being followed, outermost first, to catch cycles.
+    chain: Vec<(String, PathBuf)>,
+    // every file that has been checked, so a module required twice is only checked once.
+    loaded: HashSet<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Builds the compiler the options of the command line ask for.
+impl From<&cli::Options> for lua_compiler::Compiler {
+    fn from(options: &cli::Options) -> Self {
+        lua_compiler::Compiler::builder()
+            .warnings(options.warnings.iter().copied())
+            .naming_style(options.naming_style)
+            .panic_at(options.debug_panic)
+            .build()
+    }
+}
+
+/// Parses one file of the program and renders its diagnostics, then does the same for every
+/// module it requires.
+fn check_module(
+    options: &cli::Options,
+    program: &mut Program,
+    file: &str,
+    code: &str,
+    out: &mut Output,
+) -> Option<parser::ASTNode> {
+    let _compiling = ice::Compiling::start(file, code);
+    let compiler = lua_compiler::Compiler::from(options);
+
+    // only checking can be skipped, the artifacts need the syntax tree.
+    let cache = cache::dir(options)
+        .filter(|_| options.check)
+        .map(|dir| (cache::key(code, options), dir));
+
+    let cached = cache.as_ref().and_then(|(key, dir)| cache::load(dir, *key));
+    if cached.is_some() {
+        log_debug!("{file}: unchanged since it was last checked, using the cache.");
+    }
+
+    let (ast, tokens, mut diagnostics, requires) = match cached {
+        Some(entry) => (None, None, entry.diagnostics, entry.requires),
+        None => {
+            let lua_compiler::CompileResult {
+                tokens,
+                ast,
+                diagnostics,
+                ..
+            } = compiler.compile(file, code);
+
+            let requires = tokens
+                .as_deref()
+                .map(require::find_requires)
+                .unwrap_or_default();
+
+            // a crash says nothing about the source, the next run should try again.
+            let crashed = diagnostics.iter().any(|d| d.code == INTERNAL_ERROR);
+            if let Some((key, dir)) = cache.as_ref().filter(|_| !crashed) {
+                let entry = cache::Entry {
+                    diagnostics,
+                    requires,
+                };
+                cache::store(dir, *key, &entry);
+                (ast, tokens, entry.diagnostics, entry.requires)
+            } else {
+                (ast, tokens, diagnostics, requires)
+            }
+        }
+    };
+
+    let (found, missing) = compiler.resolve_requires(requires, &program.base);
+    diagnostics.extend(missing);
+    let mut dependencies = Vec::new();
+
+    for (r, path) in found {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        log_trace!("{file}: '{}' resolved to '{}'.", r.name, path.display());
+
+        if let Some(i) = program.chain.iter().position(|(_, p)| *p == canonical) {
+            let cycle = program.chain[i..]
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .chain([r.name.as_str()])
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            diagnostics.emit(Diagnostic::error(
+                "E201",
+                format!("cyclic require: {cycle}"),
+                r.span,
+            ));
+            continue;
+        }
+
+        match source::read(&path) {
+            Ok(code) => dependencies.push((r.name, path, canonical, code)),
+            Err(e) => diagnostics.emit(Diagnostic::error(
+                "E202",
+                format!("could not read module '{}': {e}", r.name),
+                r.span,
+            )),
+        }
+    }
+
+    // a cached source wasn't lexed, the compiler lexes it again if it has suppression comments.
+    compiler.suppress(&mut diagnostics, code, tokens.as_deref());
+
+    // everything is rendered at once, after all the stages that could report problems ran.
+    let ctx = SourceContext::new(file, code);
+    let mut sink = RenderingSink::new(&ctx, options.diagnostics_format, output_caps());
+    sink.group_after = options.group_repeated;
+    sink.show_suppressed = options.show_suppressed;
+    for d in diagnostics {
+        sink.emit(d);
+    }
+    let (rendered, diagnostics) = sink.finish();
+    out.push_diagnostics(options.diagnostics_format, &rendered);
+    program.diagnostics.extend(diagnostics);
+
+    for (name, path, canonical, code) in dependencies {
+        // a module can be required by several files, it only has to be checked the first time.
+        if !program.loaded.insert(canonical.clone()) {
+            continue;
+        }
+
+        let file = path.display().to_string();
 
-    if args().len() <= 1 {
-        log_error!("no source file provided.\n");
-        std::process::exit(-1);
+        program.chain.push((name, canonical));
+        check_module(options, program, &file, &code, out);
+        program.chain.pop();
     }
 
+    ast
+}
+
+/// Runs the whole pipeline over the file at `path`, collecting everything it prints in `out`.
+fn compile(options: &cli::Options, path: &Path, quiet: bool, out: &mut Output) -> ExitCode {
     // attempt to read the lua file's bytes.
-    let code = std::fs::read_to_string(args().collect::<Vec<_>>()[1].clone()).unwrap_or_else(|e| {
+    let code = match source::read(path) {
+        Ok(code) => code,
+        Err(e) => {
+            out.stderr.push_str(&format_error!("{e}.\n"));
+            return ExitCode::UsageError;
+        }
+    };
+
+    // the artifacts that only need the tokens. a source that doesn't lex is compiled as
+    // usual, so its errors are reported.
+    let from_tokens = matches!(
+        options.emit,
+        cli::Emit::Html | cli::Emit::Tags | cli::Emit::Strings | cli::Emit::Folds
+    );
+    if !options.check && (options.dump_tokens || from_tokens) {
+        if let (Some(tokens), _) = lua_compiler::Compiler::from(options).tokenize(&code) {
+            let file = path.display().to_string();
+            let artifact = match options.emit {
+                _ if options.dump_tokens => {
+                    dump_tokens(&tokens, &code, options.format == cli::Format::Json)
+                }
+                cli::Emit::Html => html::render(&file, &code, &tokens, options.html_css.as_deref()),
+                cli::Emit::Tags if options.etags => tags::etags(&file, &code, &tokens),
+                cli::Emit::Tags => tags::ctags(&file, &code, &tokens),
+                cli::Emit::Strings => {
+                    let strings = strings::find_strings(&code, &tokens)
+                        .into_iter()
+                        .filter(|s| !(options.skip_require_strings && s.is_require))
+                        .filter(|s| !(options.skip_key_strings && s.is_key))
+                        .collect::<Vec<_>>();
+                    match options.format {
+                        cli::Format::Json => strings::to_json(&file, &code, &strings),
+                        cli::Format::Text => strings::to_tsv(&file, &code, &strings),
+                    }
+                }
+                cli::Emit::Folds => folds::to_json(&folds::fold_ranges(&code, &tokens)),
+                cli::Emit::Unspecified
+                | cli::Emit::Ast
+                | cli::Emit::Dot
+                | cli::Emit::Deps
+                | cli::Emit::Bundle
+                | cli::Emit::Hash
+                | cli::Emit::Loc => {
+                    unreachable!("these aren't rendered from the tokens")
+                }
+            };
+            return emit_artifact(options.output.as_deref(), path, &artifact, out);
+        }
+    }
+
+    // an editor piping an unsaved buffer tells us which file it really is.
+    let named = match options.stdin_filename.as_deref() {
+        Some(name) if path == Path::new("-") => name,
+        _ => path,
+    };
+
+    // named the way reference Lua names its chunks, so the diagnostics read like its errors.
+    let file = match named.to_str() {
+        Some("-") => ChunkName::stdin(),
+        _ => ChunkName::from(named),
+    }
+    .to_string();
+
+    // modules are looked up next to the file being compiled, or in the working directory for
+    // an unnamed stdin.
+    let base = match named.parent() {
+        Some(p) if named != Path::new("-") => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    let mut program = Program {
+        base,
+        chain: vec![(file.clone(), canonical.clone())],
+        loaded: HashSet::from([canonical]),
+        diagnostics: Vec::new(),
+    };
+
+    let ast = check_module(options, &mut program, &file, &code, out);
+    let diagnostics = program.diagnostics;
+
+    // checking stops once the diagnostics are out.
+    if options.check {
+        return summarize(options, quiet, &diagnostics, out);
+    }
+
+    if let Some(ast) = ast {
+        let artifact = match options.emit {
+            cli::Emit::Dot => Some(ast::to_dot_with_max_depth(&ast, options.dot_max_depth)),
+            // named like sha256sum does, so the hashes of a directory can be told apart.
+            cli::Emit::Hash => Some(format!("{:016x}  {file}\n", ast::semantic_hash(&ast))),
+            cli::Emit::Ast => Some(format!("{ast:#?}\n")),
+            // without --emit the tree is only written out when asked to with -o.
+            _ if options.output.is_some() => Some(format!("{ast:#?}\n")),
+            _ => None,
+        };
+
+        if let Some(artifact) = artifact {
+            let code = emit_artifact(options.output.as_deref(), path, &artifact, out);
+            if code != ExitCode::Success {
+                return code;
+            }
+        }
+
+        log_debug!("{file}: finished constructing the syntax tree.");
+        log_trace!("{file}: syntax tree: {ast:#?}");
+    }
+
+    summarize(options, quiet, &diagnostics, out)
+}
+
+/// Fills in the options the command line left out from the nearest `luacompiler.toml`.
+fn load_config(options: cli::Options) -> cli::Options {
+    let stdin = Path::new("-");
+
+    // the config is looked up from the input, or from where stdin claims to come from.
+    let start = match (options.input.as_deref(), options.stdin_filename.as_deref()) {
+        (Some(input), Some(name)) if input == stdin => name.to_path_buf(),
+        (Some(input), _) if input != stdin => input.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let Some(path) = config::find(&start) else {
+        log_trace!(
+            "no {} found above '{}'.",
+            config::FILE_NAME,
+            start.display()
+        );
+        return options;
+    };
+    log_debug!("using the settings in '{}'.", path.display());
+
+    let source = source::read(&path).unwrap_or_else(|e| {
         log_error!("{e}.\n");
-        std::process::exit(-1);
+        ExitCode::UsageError.exit();
     });
 
-    // tokenize the user generated code.
-    let tokens = lexer::Lexer::new(&code).tokenize().unwrap_or_else(|| {
-        println!();
-        std::process::exit(-1);
+    match config::parse(&source) {
+        Ok(config) => config::apply(options, config),
+        Err(d) => {
+            let file = path.display().to_string();
+            let ctx = SourceContext::new(&file, &source);
+            let mut out = Output::default();
+            out.push_diagnostics(
+                options.diagnostics_format,
+                &diagnostic::render(&d, &ctx, options.diagnostics_format, output_caps()),
+            );
+            out.print();
+            ExitCode::UsageError.exit();
+        }
+    }
+}
+
+/// Prints the expression with every operation in parentheses, after its syntax tree if
+/// `with_tree`, or why it doesn't parse.
+fn explain_parse(options: &cli::Options, expression: &str, with_tree: bool) -> ExitCode {
+    let compiler = lua_compiler::Compiler::from(options);
+    let (tokens, mut diagnostics) = compiler.tokenize(expression);
+
+    let tree = tokens.and_then(|tokens| {
+        let mut parser = lua_compiler::parser::Parser::new(tokens);
+        let tree = parser.parse_expression();
+        diagnostics.extend_from_slice(parser.diagnostics());
+        tree
     });
 
-    log_success!("finished tokenization: {:?}.", tokens);
+    match tree {
+        Some(parsed) => {
+            if with_tree {
+                print!("{}", ast::to_tree(&parsed));
+            }
+            println!("{}", ast::to_parenthesized(&parsed));
+            // a string is quoted so that it can't be mistaken for a number or a keyword.
+            match constant::const_eval(&parsed) {
+                Some(constant::Value::String(s)) => println!("= {}", strings::escape(&s, '"')),
+                Some(value) => println!("= {value}"),
+                None => {}
+            }
+            ExitCode::Success
+        }
+        None => {
+            let ctx = SourceContext::new("<expr>", expression);
+            let mut out = Output::default();
+            for d in &diagnostics {
+                out.push_diagnostics(
+                    options.diagnostics_format,
+                    &diagnostic::render(d, &ctx, options.diagnostics_format, output_caps()),
+                );
+            }
+            out.print();
+            ExitCode::CompileError
+        }
+    }
+}
+
+/// Prints the statements that differ between two files, the exit code says whether any do.
+fn diff_files(options: &cli::Options, old: &Path, new: &Path, quiet: bool) -> ExitCode {
+    let mut sources = Vec::new();
+    for path in [old, new] {
+        match source::read(path) {
+            Ok(code) => sources.push((path.display().to_string(), code)),
+            Err(e) => {
+                log_error!("{e}.\n");
+                return ExitCode::UsageError;
+            }
+        }
+    }
+
+    let compiler = lua_compiler::Compiler::from(options);
+    let results = sources
+        .iter()
+        .map(|(file, code)| compiler.compile(file, code))
+        .collect::<Vec<_>>();
+
+    let mut sides = Vec::new();
+    for ((file, code), result) in sources.iter().zip(&results) {
+        let Some(ast) = &result.ast else {
+            let ctx = SourceContext::new(file, code);
+            let mut out = Output::default();
+            for d in &result.diagnostics {
+                out.push_diagnostics(
+                    options.diagnostics_format,
+                    &diagnostic::render(d, &ctx, options.diagnostics_format, output_caps()),
+                );
+            }
+            out.print();
+            // like diff, trouble is 2 so it can't be mistaken for a difference.
+            return ExitCode::UsageError;
+        };
+
+        sides.push(lua_compiler::diff::Side {
+            source: code,
+            ast,
+            statement_spans: &result.statement_spans,
+        });
+    }
+
+    // the same hash means the same trees, which can't have any statements that differ.
+    let changes = match ast::semantic_hash(sides[0].ast) == ast::semantic_hash(sides[1].ast) {
+        true => {
+            log_debug!("the syntax trees hash the same, skipping the comparison.");
+            Vec::new()
+        }
+        false => lua_compiler::diff::diff(&sides[0], &sides[1]),
+    };
+    for change in &changes {
+        println!("{change}");
+    }
+
+    if changes.is_empty() {
+        if !quiet {
+            log_success!("no differences.\n");
+        }
+        return ExitCode::Success;
+    }
+    ExitCode::CompileError
+}
+
+/// Compiles every matching file under the directory `dir`, then prints the overall result.
+fn compile_dir(options: &cli::Options, dir: &Path, quiet: bool) -> ExitCode {
+    let files = match walk::collect_files(dir, &options.include, &options.exclude) {
+        Ok(files) => files,
+        Err(e) => {
+            log_error!("could not read '{}': {e}.\n", dir.display());
+            return ExitCode::UsageError;
+        }
+    };
+
+    let mut failed = 0;
+    let mut result = ExitCode::Success;
+
+    let jobs = options
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, files.len().max(1));
+
+    log_debug!(
+        "compiling {} on {}.",
+        plural(files.len(), "file"),
+        plural(jobs, "thread")
+    );
+
+    // every file is compiled on its own, so the workers only have to agree on who takes which.
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (next, files) = (&next, &files);
+            // the parser can nest deep enough to need more than the 2 MiB threads get by
+            // default, so they get as much as the main thread.
+            std::thread::Builder::new()
+                .stack_size(WORKER_STACK_SIZE)
+                .spawn_scoped(scope, move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(i) else {
+                        break;
+                    };
+
+                    let mut out = Output::default();
+                    let code = compile(options, file, quiet, &mut out);
+                    // the receiver lives until every worker is done, so this can't fail.
+                    let _ = sender.send((i, out, code));
+                })
+                .expect("could not start a worker thread");
+        }
+        drop(sender);
+
+        // files finish in any order but are printed in path order, so the output is the same
+        // no matter how many jobs there are.
+        let mut done = vec![None; files.len()];
+        let mut printed = 0;
+
+        for (i, out, code) in receiver {
+            done[i] = Some((out, code));
+
+            while let Some((out, code)) = done.get_mut(printed).and_then(Option::take) {
+                out.print();
+                if code != ExitCode::Success {
+                    failed += 1;
+                    // the worst outcome decides the exit code, e.g. an unreadable file over a bad one.
+                    result = result.max(code);
+                }
+                printed += 1;
+            }
+        }
+    });
+
+    // what's unused can only be told once every file of the project is known.
+    if !options.entries.is_empty() {
+        let mut out = Output::default();
+        result = result.max(unused::check(options, dir, &files, &mut out));
+        out.print();
+    }
+
+    if options.diagnostics_format == DiagnosticsFormat::Human && (!quiet || failed > 0) {
+        eprintln!(
+            "checked {}, {} failed.",
+            plural(files.len(), "file"),
+            failed
+        );
+    }
+
+    result
+}
+
+fn main() {
+    // a panic is a bug in the compiler, not in the user's code, so report it as such. the hook
+    // only reports it, the panic still unwinds so the library can turn it into a diagnostic.
+    std::panic::set_hook(Box::new(ice::report));
+
+    if catch_unwind(run).is_err() {
+        ExitCode::InternalError.exit();
+    }
+}
+
+/// Does what the command line asks for, exiting with the code of how it went.
+fn run() {
+    let options = cli::parse(args().skip(1)).unwrap_or_else(|e| {
+        log_error!("{e}, see --help.\n");
+        ExitCode::UsageError.exit();
+    });
 
-    // parse the user generated code.
-    let ast = parser::Parser::new(tokens).parse().unwrap_or_else(|| {
-        println!();
-        std::process::exit(-1);
+    set_output_caps(OutputCaps::detect(options.color));
+    set_verbosity(match options.verbose {
+        0 if options.quiet => Verbosity::Quiet,
+        0 => Verbosity::Normal,
+        1 => Verbosity::Debug,
+        _ => Verbosity::Trace,
     });
 
-    log_success!("finished constructing syntax tree: {ast:#?}.");
+    if options.help {
+        print!("{}", cli::help());
+        return;
+    }
+
+    if options.version {
+        // the art is only there to look nice, so leave it out when asked to or when the
+        // terminal can't show its block characters.
+        if !options.no_banner && !options.quiet && output_caps().unicode {
+            println!("{BANNER}");
+        }
+        println!("lua-compiler {VERSION}");
+        return;
+    }
+
+    if let Some(code) = &options.explain {
+        let Some(c) = codes::lookup(code) else {
+            log_error!("there is no diagnostic '{code}'.\n");
+            ExitCode::UsageError.exit();
+        };
+        println!(
+            "{} ({}), {}\n\n{}",
+            c.code,
+            c.name,
+            c.severity.as_str(),
+            c.explanation
+        );
+        return;
+    }
+
+    if let Some(expression) = &options.explain_parse {
+        explain_parse(&options, expression, false).exit();
+    }
+
+    if let Some(expression) = &options.explain_ast {
+        explain_parse(&options, expression, true).exit();
+    }
+
+    let options = load_config(options);
+
+    // when the messages are captured by a script or an editor only the diagnostics are printed.
+    // the same goes for when the artifact is written to a file.
+    // and when the diagnostics are meant for another program.
+    let quiet = verbosity() == Verbosity::Quiet
+        || options.output.is_some()
+        || options.diagnostics_format != DiagnosticsFormat::Human
+        || !std::io::stderr().is_terminal();
+
+    if options.lsp {
+        if options.input.is_some() {
+            log_error!("the language server doesn't take a file, the editor sends them.\n");
+            ExitCode::UsageError.exit();
+        }
+        lsp::run(&options).exit();
+    }
+
+    if options.diff {
+        let (Some(old), Some(new)) = (options.input.as_deref(), options.new_input.as_deref())
+        else {
+            log_error!("diff needs the old and the new file, see --help.\n");
+            ExitCode::UsageError.exit();
+        };
+        diff_files(&options, old, new, quiet).exit();
+    }
+
+    if options.rename {
+        let (Some(path), Some(at), Some(new_name)) = (
+            options.input.as_deref(),
+            options.rename_at,
+            options.new_name.as_deref(),
+        ) else {
+            log_error!("rename needs the file, the position of the variable and its new name, see --help.\n");
+            ExitCode::UsageError.exit();
+        };
+        rename::run(&options, path, at, new_name).exit();
+    }
+
+    if options.test {
+        let Some(dir) = options.input.as_deref().filter(|p| p.is_dir()) else {
+            log_error!("test needs the directory of the files to check, see --help.\n");
+            ExitCode::UsageError.exit();
+        };
+        golden::run(&options, dir).exit();
+    }
+
+    let Some(path) = options.input.as_deref() else {
+        log_error!("no source file provided, see --help.\n");
+        ExitCode::UsageError.exit();
+    };
+
+    if options.watch && path == Path::new("-") {
+        log_error!("stdin can't be watched for changes.\n");
+        ExitCode::UsageError.exit();
+    }
+
+    if options.stdin_filename.is_some() && path != Path::new("-") {
+        log_error!("--stdin-filename only applies when the source is read from stdin.\n");
+        ExitCode::UsageError.exit();
+    }
+
+    let is_dir = path.is_dir();
+
+    // the dependency graph and the counts are one artifact for the whole directory.
+    let deps = options.emit == cli::Emit::Deps;
+    let bundle = options.emit == cli::Emit::Bundle;
+    let whole = deps || bundle || options.emit == cli::Emit::Loc;
+
+    if is_dir && options.output.is_some() && !whole {
+        log_error!("-o can't be used when compiling a directory.\n");
+        ExitCode::UsageError.exit();
+    }
+
+    if !is_dir && !options.entries.is_empty() {
+        log_error!("--entry only applies when compiling a directory.\n");
+        ExitCode::UsageError.exit();
+    }
+
+    if bundle && (is_dir || path == Path::new("-")) {
+        log_error!(
+            "--emit=bundle needs the entry file of the project, the requires are looked up next to it.\n"
+        );
+        ExitCode::UsageError.exit();
+    }
+
+    if deps && path == Path::new("-") {
+        log_error!(
+            "--emit=deps needs a file or a directory, the requires are looked up next to it.\n"
+        );
+        ExitCode::UsageError.exit();
+    }
+
+    let compile_input = || match (is_dir, whole) {
+        (true, false) => compile_dir(&options, path, quiet),
+        (_, true) | (false, false) => {
+            let mut out = Output::default();
+            let code = match options.emit {
+                cli::Emit::Deps => deps::emit(&options, path, &mut out),
+                cli::Emit::Bundle => bundle::emit(&options, path, &mut out),
+                cli::Emit::Loc => stats::emit(&options, path, &mut out),
+                _ => compile(&options, path, quiet, &mut out),
+            };
+            out.print();
+            code
+        }
+    };
+
+    if options.watch {
+        watch::watch(path, || compile_input() == ExitCode::Success);
+    }
 
-    log_success!("finished compilation.\n");
+    compile_input().exit();
 }
@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+
+use crate::definitions::find_functions;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::resolve::{resolve, BindingKind};
+
+/// The convention the names a source declares are held to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NamingStyle {
+    SnakeCase,
+    CamelCase,
+}
+
+impl NamingStyle {
+    /// Returns the style as it's spelled on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NamingStyle::SnakeCase => "snake_case",
+            NamingStyle::CamelCase => "camelCase",
+        }
+    }
+
+    /// Returns whether the name follows the style.
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            NamingStyle::SnakeCase => !name.chars().any(|c| c.is_ascii_uppercase()),
+            NamingStyle::CamelCase => {
+                !name.contains('_') && !name.starts_with(|c: char| c.is_ascii_uppercase())
+            }
+        }
+    }
+
+    /// Respells the name in the style, e.g. `parseHTTPReply` as `parse_http_reply`.
+    pub fn respell(&self, name: &str) -> String {
+        let words = words(name);
+        match self {
+            NamingStyle::SnakeCase => words.join("_"),
+            NamingStyle::CamelCase => words
+                .iter()
+                .enumerate()
+                .map(|(i, word)| match i {
+                    0 => word.clone(),
+                    _ => {
+                        let mut chars = word.chars();
+                        chars.next().map_or(String::new(), |first| {
+                            first.to_ascii_uppercase().to_string() + chars.as_str()
+                        })
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Splits a name into its lower case words, at underscores and where the case changes.
+fn words(name: &str) -> Vec<String> {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut words = vec![String::new()];
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            words.push(String::new());
+            continue;
+        }
+        // a word starts at an upper case letter after a lower case one or a digit, or at the
+        // last letter of an acronym, the `R` of `HTTPReply`.
+        let previous = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let starts_word = c.is_ascii_uppercase()
+            && previous.is_some_and(|p| {
+                p.is_ascii_lowercase()
+                    || p.is_ascii_digit()
+                    || (p.is_ascii_uppercase() && next.is_some_and(char::is_ascii_lowercase))
+            });
+        if starts_word {
+            words.push(String::new());
+        }
+        words.last_mut().unwrap().push(c.to_ascii_lowercase());
+    }
+
+    words.retain(|w| !w.is_empty());
+    words
+}
+
+/// Returns whether the name is left alone whatever the style, a constant like `MAX_SIZE`, a
+/// single letter or a name starting with `_`.
+fn is_exempt(name: &str) -> bool {
+    name.chars().count() == 1
+        || name.starts_with('_')
+        || !name.chars().any(|c| c.is_ascii_lowercase())
+}
+
+/// Warns about the locals, parameters, loop variables and function names the source declares
+/// that don't follow the style.
+///
+/// Each segment of a function's name is checked, except the first of a global one. Globals
+/// aren't checked, they are often what another API expects to find.
+pub fn check(tokens: &[SpannedToken], style: NamingStyle) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut seen = HashSet::new();
+
+    let mut warn = |what: &str, name: &str, span: Span| {
+        if is_exempt(name) || style.allows(name) || !seen.insert((span.start, span.end)) {
+            return;
+        }
+        let mut d = Diagnostic::warning(
+            "W203",
+            format!("{what} '{name}' isn't {}", style.as_str()),
+            span,
+        );
+        d.suggestion = Some(format!("rename it to '{}'", style.respell(name)));
+        diagnostics.push(d);
+    };
+
+    for binding in resolve(tokens) {
+        let what = match binding.kind {
+            BindingKind::Local => "local",
+            BindingKind::Parameter => "parameter",
+            BindingKind::LoopVariable => "loop variable",
+            _ => continue,
+        };
+        if let Some(span) = binding.declaration {
+            warn(what, &binding.name, span);
+        }
+    }
+
+    for function in find_functions(tokens) {
+        // a local function is a local, which the bindings already covered.
+        if function.is_local {
+            continue;
+        }
+        let segments = tokens.iter().filter(|t| {
+            t.span.start >= function.name_span.start && t.span.end <= function.name_span.end
+        });
+        for t in segments
+            .filter(|t| matches!(t.token, Token::NAME(_)))
+            .skip(1)
+        {
+            if let Token::NAME(name) = &t.token {
+                warn("function", name, t.span);
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.span.start);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // the names that are warned about, with the suggestion.
+    fn check_names(source: &str, style: NamingStyle) -> Vec<(String, String)> {
+        check(&Lexer::new(source).tokenize().unwrap(), style)
+            .into_iter()
+            .map(|d| (d.message, d.suggestion.unwrap_or_default()))
+            .collect()
+    }
+
+    const SOURCE: &str = "\
+local itemCount, item_total = 0, 0
+local function parseReply(raw_text) end
+function Module.sub_module.doThing(self) end
+function obj:get_value() end
+for keyName, v in pairs(t) do end
+globalThing = 1
+";
+
+    #[test]
+    fn snake_case_is_held_to() {
+        assert_eq!(
+            check_names(SOURCE, NamingStyle::SnakeCase),
+            [
+                (
+                    "local 'itemCount' isn't snake_case",
+                    "rename it to 'item_count'"
+                ),
+                (
+                    "local 'parseReply' isn't snake_case",
+                    "rename it to 'parse_reply'"
+                ),
+                (
+                    "function 'doThing' isn't snake_case",
+                    "rename it to 'do_thing'"
+                ),
+                (
+                    "loop variable 'keyName' isn't snake_case",
+                    "rename it to 'key_name'"
+                ),
+            ]
+            .map(|(m, s)| (m.to_string(), s.to_string()))
+        );
+    }
+
+    #[test]
+    fn camel_case_is_held_to() {
+        assert_eq!(
+            check_names(SOURCE, NamingStyle::CamelCase),
+            [
+                (
+                    "local 'item_total' isn't camelCase",
+                    "rename it to 'itemTotal'"
+                ),
+                (
+                    "parameter 'raw_text' isn't camelCase",
+                    "rename it to 'rawText'"
+                ),
+                (
+                    "function 'sub_module' isn't camelCase",
+                    "rename it to 'subModule'"
+                ),
+                (
+                    "function 'get_value' isn't camelCase",
+                    "rename it to 'getValue'"
+                ),
+            ]
+            .map(|(m, s)| (m.to_string(), s.to_string()))
+        );
+    }
+
+    #[test]
+    fn constants_single_letters_and_underscores_are_exempt() {
+        let source = "local MAX_SIZE, X, _unusedThing, _ = 1, 2, 3, 4\nlocal function f(N) end\n";
+        assert!(check_names(source, NamingStyle::SnakeCase).is_empty());
+        assert!(check_names(source, NamingStyle::CamelCase).is_empty());
+        // the first segment of a global function is someone else's.
+        assert!(check_names("function someApi.x() end\n", NamingStyle::SnakeCase).is_empty());
+    }
+
+    #[test]
+    fn names_are_split_into_words() {
+        assert_eq!(
+            NamingStyle::SnakeCase.respell("parseHTTPReply"),
+            "parse_http_reply"
+        );
+        assert_eq!(NamingStyle::SnakeCase.respell("utf8Decode"), "utf8_decode");
+        assert_eq!(NamingStyle::CamelCase.respell("to__json_v2"), "toJsonV2");
+        assert_eq!(NamingStyle::CamelCase.respell("HTTPServer"), "httpServer");
+    }
+}
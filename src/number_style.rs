@@ -0,0 +1,213 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Span, SpannedToken, Token};
+
+/// A way of spelling number literals that a source can be inconsistent about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Spelling {
+    // the letters of a hexadecimal literal, `0xff` or `0xFF`.
+    LowerHex,
+    UpperHex,
+    // a fraction under 1, `0.5` or `.5`.
+    LeadingZero,
+    NoLeadingZero,
+}
+
+impl Spelling {
+    fn describe(&self) -> &'static str {
+        match self {
+            Spelling::LowerHex => "has lower case hex digits",
+            Spelling::UpperHex => "has upper case hex digits",
+            Spelling::LeadingZero => "has a leading zero",
+            Spelling::NoLeadingZero => "has no leading zero",
+        }
+    }
+
+    /// Respells the digits of a literal, without its sign, in this way.
+    fn respell(&self, digits: &str) -> String {
+        match self {
+            Spelling::LowerHex => format!("{}{}", &digits[..2], digits[2..].to_ascii_lowercase()),
+            Spelling::UpperHex => format!("{}{}", &digits[..2], digits[2..].to_ascii_uppercase()),
+            Spelling::LeadingZero => format!("0{digits}"),
+            Spelling::NoLeadingZero => digits[1..].to_string(),
+        }
+    }
+}
+
+/// Returns the digits of a hexadecimal literal after its `0x` or `0X`.
+fn hex_digits(digits: &str) -> Option<&str> {
+    digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+}
+
+/// Returns how the digits of a literal are spelled, a hexadecimal one with digits of both cases
+/// counting as neither.
+fn spelling(digits: &str) -> Option<Spelling> {
+    if let Some(hex) = hex_digits(digits) {
+        let lower = hex.chars().any(|c| c.is_ascii_lowercase());
+        let upper = hex.chars().any(|c| c.is_ascii_uppercase());
+        return match (lower, upper) {
+            (true, false) => Some(Spelling::LowerHex),
+            (false, true) => Some(Spelling::UpperHex),
+            _ => None,
+        };
+    }
+    if digits.starts_with("0.") {
+        Some(Spelling::LeadingZero)
+    } else if digits.starts_with('.') {
+        Some(Spelling::NoLeadingZero)
+    } else {
+        None
+    }
+}
+
+/// Parses digits the way the lexer does, to make sure a respelled literal means the same.
+fn value_of(digits: &str) -> Option<f64> {
+    match hex_digits(digits) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok().map(|n| n as f64),
+        None => digits.parse().ok(),
+    }
+}
+
+/// Warns about the number literals spelled differently from the first one of the source
+/// spelled either way, for the case of hex digits and the leading zero of a fraction.
+///
+/// The warnings only suggest a spelling that reads back as the very same number. A literal
+/// with hex digits of both cases is left alone, there's no telling which it meant.
+pub fn check(source: &str, tokens: &[SpannedToken]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // the first hexadecimal literal and the first fraction, which the others are held to.
+    let (mut first_hex, mut first_fraction): (Option<(Spelling, Span)>, Option<_>) = (None, None);
+
+    for t in tokens {
+        let Token::NUMBER(value) = t.token else {
+            continue;
+        };
+        let Some(raw) = source.get(t.span.start..t.span.end) else {
+            continue;
+        };
+        let Some(found) = spelling(raw) else {
+            continue;
+        };
+
+        let first = match found {
+            Spelling::LowerHex | Spelling::UpperHex => &mut first_hex,
+            Spelling::LeadingZero | Spelling::NoLeadingZero => &mut first_fraction,
+        };
+        let Some((expected, first_span)) = *first else {
+            *first = Some((found, t.span));
+            continue;
+        };
+        if found == expected {
+            continue;
+        }
+
+        let first_raw = &source[first_span.start..first_span.end];
+        let mut d = Diagnostic::warning(
+            "W204",
+            format!("'{raw}' {}, unlike '{first_raw}'", found.describe()),
+            t.span,
+        )
+        .with_note(
+            format!("'{first_raw}' {}.", expected.describe()),
+            Some(first_span),
+        );
+
+        let respelled = expected.respell(raw);
+        if value_of(&respelled).map(f64::to_bits) == Some(value.to_bits()) {
+            d.suggestion = Some(format!("write it '{respelled}'"));
+        }
+        diagnostics.push(d);
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // the message and the suggestion of each warning.
+    fn warnings(source: &str) -> Vec<(String, Option<String>)> {
+        check(source, &Lexer::new(source).tokenize().unwrap())
+            .into_iter()
+            .map(|d| (d.message, d.suggestion))
+            .collect()
+    }
+
+    #[test]
+    fn the_first_literal_of_each_kind_sets_the_style() {
+        let source = "a = 0xff\nb = 0.5\nc = 0XFF00\nd = .25\ne = 0xab\nf = 0.75\n";
+        assert_eq!(
+            warnings(source),
+            [
+                (
+                    "'0XFF00' has upper case hex digits, unlike '0xff'".to_string(),
+                    Some("write it '0Xff00'".to_string())
+                ),
+                (
+                    "'.25' has no leading zero, unlike '0.5'".to_string(),
+                    Some("write it '0.25'".to_string())
+                ),
+            ]
+        );
+
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let d = &check(source, &tokens)[0];
+        assert_eq!(d.code, "W204");
+        assert_eq!(d.span.line, 3);
+        assert_eq!(d.notes[0].message, "'0xff' has lower case hex digits.");
+        assert_eq!(d.notes[0].span.map(|s| s.line), Some(1));
+    }
+
+    #[test]
+    fn literals_that_are_spelled_neither_way_are_left_alone() {
+        // digits only, both cases of hex digits, and a fraction over 1.
+        assert!(warnings("a = 0xff\nb = 0x12\nc = 0xAbC\nd = 1.5\ne = 10\nf = 1e3\n").is_empty());
+        assert!(warnings("a = .5\nb = .25\n").is_empty());
+    }
+
+    #[test]
+    fn a_respelling_keeps_every_digit_and_the_value() {
+        // a fraction with more digits than an f64 holds, which reformatting the value would
+        // round, so only the leading zero may change.
+        let digits = "1000000000000000055511151231257827";
+        let source = format!("a = .5\nb = 0.{digits}\nc = -0.{digits}e-3\n");
+        assert_eq!(
+            warnings(&source),
+            [
+                (
+                    format!("'0.{digits}' has a leading zero, unlike '.5'"),
+                    Some(format!("write it '.{digits}'"))
+                ),
+                // the sign is a token of its own and stays where it is.
+                (
+                    format!("'0.{digits}e-3' has a leading zero, unlike '.5'"),
+                    Some(format!("write it '.{digits}e-3'"))
+                ),
+            ]
+        );
+        for (_, suggestion) in warnings(&source) {
+            let respelled = suggestion.unwrap();
+            let respelled = respelled
+                .trim_start_matches("write it '")
+                .trim_end_matches('\'');
+            let original = format!("0{respelled}");
+            assert_eq!(
+                value_of(respelled).map(f64::to_bits),
+                value_of(&original).map(f64::to_bits)
+            );
+        }
+    }
+
+    #[test]
+    fn respellings_are_read_back_the_way_the_lexer_reads_them() {
+        assert_eq!(value_of("0x7fffffffffffffff"), Some(i64::MAX as f64));
+        // more than an i64 holds, which the lexer doesn't read either.
+        assert_eq!(value_of("0x8000000000000000"), None);
+        assert_eq!(Spelling::UpperHex.respell("0xab"), "0xAB");
+        assert_eq!(Spelling::LowerHex.respell("0XAB"), "0Xab");
+        assert_eq!(Spelling::NoLeadingZero.respell("0.5"), ".5");
+    }
+}
@@ -1,18 +1,34 @@
-use std::thread::current;
+use crate::cancel::CancellationToken;
+use crate::diagnostic::{Diagnostic, DiagnosticSink};
+use crate::lexer::{Span, SpannedToken, Token};
 
-use crate::lexer::Token;
-use crate::{log_error, term_color::*};
+// how deeply expressions and blocks can nest before the parser gives up, rather than run out
+// of stack. luac has the same limit.
+const MAX_NESTING: usize = 200;
 
 #[derive(Clone)]
 pub struct Parser {
     tokens: Vec<Token>,
+    // where each token was found, kept apart so matching on tokens stays simple.
+    spans: Vec<Span>,
     cursor: usize,
     errored: bool,
+    diagnostics: Vec<Diagnostic>,
+    // how many blocks deep the parser is, the main chunk is at 0.
+    depth: usize,
+    // where each statement of the main chunk starts and ends.
+    statement_spans: Vec<Span>,
+    // how many expressions and blocks the parser is inside of, see `MAX_NESTING`.
+    nesting: usize,
+    // set once the nesting limit was hit, nothing is reported after that.
+    gave_up: bool,
+    // checked before every statement, the parser stops once it's cancelled.
+    cancellation: Option<CancellationToken>,
 }
 
 type MaybeASTNode = Option<ASTNode>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ASTNode {
     Chunk(Vec<ASTNode>, Option<Box<ASTNode>>),
     Block(Box<ASTNode>),
@@ -155,23 +171,143 @@ pub enum ASTNode {
     Token(Token),
 }
 
+/// Returns whether the token joins two operands, like `+` or `==`.
+fn is_binary_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::OR
+            | Token::AND
+            | Token::LESS_THAN
+            | Token::LESS_EQUAL
+            | Token::GREATER_THAN
+            | Token::GREATER_EQUAL
+            | Token::NEQ
+            | Token::EQ
+            | Token::CONCAT
+            | Token::ADD
+            | Token::SUBTRACT
+            | Token::MULTIPLY
+            | Token::DIVIDE
+            | Token::MODULO
+            | Token::XOR
+    )
+}
+
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        let (tokens, spans) = tokens.into_iter().map(|t| (t.token, t.span)).unzip();
         Self {
             tokens,
+            spans,
             cursor: 0,
             errored: false,
+            diagnostics: Vec::new(),
+            depth: 0,
+            statement_spans: Vec::new(),
+            nesting: 0,
+            gave_up: false,
+            cancellation: None,
+        }
+    }
+
+    /// Makes the parser stop at the next statement once the token is cancelled, leaving the
+    /// tree incomplete.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Returns the problems found while parsing.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Returns where each statement of the main chunk is, the last statement included.
+    pub fn statement_spans(&self) -> &[Span] {
+        &self.statement_spans
+    }
+
+    /// Returns the span of the token that was just accepted, or of the current one before the
+    /// first token.
+    fn previous_span(&self) -> Span {
+        match self.cursor.checked_sub(1).and_then(|i| self.spans.get(i)) {
+            Some(span) => *span,
+            None => self.current_span(),
+        }
+    }
+
+    /// Returns the span of the current token, or an empty span after the last one at the end.
+    fn current_span(&self) -> Span {
+        match self.spans.get(self.cursor) {
+            Some(span) => *span,
+            None => self
+                .spans
+                .last()
+                .map(|s| Span { start: s.end, ..*s })
+                .unwrap_or_default(),
+        }
+    }
+
+    fn report_error(&mut self, message: String) {
+        if self.gave_up {
+            return;
+        }
+        let span = self.current_span();
+        self.diagnostics
+            .emit(Diagnostic::error("E100", message, span));
+        self.errored = true;
+    }
+
+    /// Expects the `end` of a block, pointing back at the keyword that opened it if it's missing.
+    fn expect_end(&mut self, opened: Span, keyword: &str) {
+        if !self.accept(Token::END) && !self.gave_up {
+            let diagnostic = Diagnostic::error(
+                "E100",
+                format!("expected symbol: END, found {:?}.", self.current()),
+                self.current_span(),
+            )
+            .with_label(opened, format!("`{keyword}` opened here"));
+
+            self.diagnostics.emit(diagnostic);
+            self.errored = true;
+        }
+    }
+
+    /// Runs `parse` one level deeper, or reports an error once the nesting limit is reached.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> MaybeASTNode) -> MaybeASTNode {
+        if self.nesting >= MAX_NESTING {
+            self.report_error(format!(
+                "too many nested expressions and blocks, the limit is {MAX_NESTING}."
+            ));
+            // every level above would report what it expected, which says nothing new.
+            self.gave_up = true;
+            return None;
         }
+
+        self.nesting += 1;
+        let tree = parse(self);
+        self.nesting -= 1;
+        tree
+    }
+
+    /// Runs `parse`, undoing what it consumed and reported if it didn't find anything.
+    fn speculate(&mut self, parse: impl FnOnce(&mut Self) -> MaybeASTNode) -> MaybeASTNode {
+        let (cursor, errored, reported) = (self.cursor, self.errored, self.diagnostics.len());
+        let tree = parse(self);
+        if tree.is_none() && !self.gave_up {
+            self.cursor = cursor;
+            self.errored = errored;
+            self.diagnostics.truncate(reported);
+        }
+        tree
     }
 
     fn report_expected_error(&mut self, expected: &str) {
-        log_error!(
-            "[{}] expected {:?}, found {:?}.",
-            colored("parser", Color::Grey),
+        self.report_error(format!(
+            "expected {:?}, found {:?}.",
             expected,
-            self.current(),
-        );
-        self.errored = true;
+            self.current()
+        ));
     }
 
     fn is_eof(&self) -> bool {
@@ -179,10 +315,12 @@ impl Parser {
     }
 
     /// Checks then next token.
+    #[allow(dead_code)]
     fn peek(&self) -> Option<Token> {
         self.tokens.get(self.cursor + 1).cloned()
     }
 
+    #[allow(dead_code)]
     fn peek_expression(&mut self, n: Option<usize>) -> MaybeASTNode {
         let mut fork = self.clone();
         for _ in 0..n.unwrap_or(0) {
@@ -209,12 +347,23 @@ impl Parser {
         !self.is_eof() && self.current() == token
     }
 
+    /// Moves to the next token, staying at the end once there, where `current` is UNDEFINED.
     fn advance(&mut self) {
-        self.cursor += 1;
+        if !self.is_eof() {
+            self.cursor += 1;
+        }
     }
 
-    fn backtrack(&mut self) {
-        self.cursor -= 1;
+    /// Moves back to the previous token, returning false if there is none.
+    #[allow(dead_code)]
+    fn backtrack(&mut self) -> bool {
+        match self.cursor.checked_sub(1) {
+            Some(cursor) => {
+                self.cursor = cursor;
+                true
+            }
+            None => false,
+        }
     }
 
     fn accept(&mut self, token: Token) -> bool {
@@ -228,13 +377,11 @@ impl Parser {
 
     fn expect(&mut self, token: Token) {
         if !self.accept(token.clone()) {
-            log_error!(
-                "[auto: {}] expected symbol: {:?}, found {:?}.",
-                colored("parser", Color::Grey),
+            self.report_error(format!(
+                "expected symbol: {:?}, found {:?}.",
                 token,
-                self.current(),
-            );
-            self.errored = true;
+                self.current()
+            ));
         }
     }
 
@@ -263,14 +410,36 @@ impl Parser {
         None
     }
 
-    fn namelist(&mut self) -> MaybeASTNode {
+    /// Parses a name where nothing else can be. A reserved word there is reported and taken as
+    /// the name, so that `local end = 1` gets one error rather than whatever follows from it.
+    fn name_or_keyword(&mut self) -> MaybeASTNode {
         if let Some(name) = self.name() {
+            return Some(name);
+        }
+
+        let token = self.current();
+        if !token.is_keyword() || self.gave_up {
+            return None;
+        }
+        let word = token.spelling().into_owned();
+        self.diagnostics.emit(Diagnostic::error(
+            "E101",
+            format!("'{word}' is a reserved word and cannot be used as a name."),
+            self.current_span(),
+        ));
+        self.errored = true;
+        self.advance();
+        Some(ASTNode::Name(word))
+    }
+
+    fn namelist(&mut self) -> MaybeASTNode {
+        if let Some(name) = self.name_or_keyword() {
             let mut name_list = Vec::new();
 
             while self.accept(Token::COMMA) {
-                let name = self.name().or_else(|| {
+                let name = self.name_or_keyword().or_else(|| {
                     self.report_expected_error("<name>");
-                    return None;
+                    None
                 })?;
                 name_list.push(name);
             }
@@ -284,43 +453,40 @@ impl Parser {
         None
     }
 
-    fn varlist(&mut self) -> MaybeASTNode {
-        if let Some(var) = self.var() {
-            let mut var_list = Vec::new();
+    /// Parses the rest of a varlist, after its first variable.
+    fn varlist(&mut self, variable: ASTNode) -> MaybeASTNode {
+        let mut var_list = Vec::new();
 
-            while self.accept(Token::COMMA) {
-                let var = self.var().or_else(|| {
-                    self.report_expected_error("<var>");
-                    return None;
-                })?;
-                var_list.push(var);
-            }
-
-            return Some(ASTNode::VariableList {
-                variable: Box::new(var),
-                tail_list: var_list,
-            });
+        while self.accept(Token::COMMA) {
+            let var = self.var().or_else(|| {
+                self.report_expected_error("<var>");
+                None
+            })?;
+            var_list.push(var);
         }
 
-        None
+        Some(ASTNode::VariableList {
+            variable: Box::new(variable),
+            tail_list: var_list,
+        })
     }
 
     fn funcname(&mut self) -> MaybeASTNode {
-        if let Some(name) = self.name() {
+        if let Some(name) = self.name_or_keyword() {
             let mut name_list = Vec::new();
 
             while self.accept(Token::DOT) {
-                let name = self.name().or_else(|| {
+                let name = self.name_or_keyword().or_else(|| {
                     self.report_expected_error("<name>");
-                    return None;
+                    None
                 })?;
                 name_list.push(name);
             }
 
             let col_name = if self.accept(Token::COLON) {
-                Some(self.name().or_else(|| {
+                Some(self.name_or_keyword().or_else(|| {
                     self.report_expected_error("<name>");
-                    return None;
+                    None
                 })?)
             } else {
                 None
@@ -354,7 +520,7 @@ impl Parser {
         if self.accept(Token::LEFT_BRACKET) {
             let exp1 = self.exp().or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
 
             self.expect(Token::RIGHT_BRACKET);
@@ -362,7 +528,7 @@ impl Parser {
 
             let exp2 = self.exp().or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
 
             return Some(ASTNode::Field(Box::new(ASTNode::FieldA {
@@ -371,12 +537,19 @@ impl Parser {
             })));
         }
 
-        if let Some(name) = self.name() {
+        // `{end = 1}` is a reserved word taken for a field name, anywhere else it starts an
+        // expression.
+        // without the `=` it's a positional field, whose expression can start with a name.
+        let name = match self.peek() {
+            Some(Token::ASSIGN) => self.name_or_keyword(),
+            _ => None,
+        };
+        if let Some(name) = name {
             self.expect(Token::ASSIGN);
 
             let exp = self.exp().or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
 
             return Some(ASTNode::Field(Box::new(ASTNode::FieldB {
@@ -399,7 +572,7 @@ impl Parser {
             while let Some(fieldsep) = self.fieldsep() {
                 let field = self.field().or_else(|| {
                     self.report_expected_error("<field>");
-                    return None;
+                    None
                 })?;
 
                 fieldseps.push((fieldsep, field))
@@ -432,10 +605,7 @@ impl Parser {
             let exp_list = self.explist1();
             self.expect(Token::RIGHT_PAREN);
             return Some(ASTNode::Args(Box::new(ASTNode::ArgsParamList(
-                match exp_list {
-                    Some(t) => Some(Box::new(t)),
-                    None => None,
-                },
+                exp_list.map(Box::new),
             ))));
         }
 
@@ -451,114 +621,85 @@ impl Parser {
         None
     }
 
-    fn functioncall(&mut self) -> Option<ASTNode> {
-        if let Some(prefix_exp) = self.prefixexp() {
-            let args = self.args().or_else(|| {
-                self.report_expected_error("<args>");
-                return None;
-            })?;
-
-            return Some(ASTNode::FunctionCall(Box::new(
-                ASTNode::PrefixExpressionArgs {
-                    prefix_expression: Box::new(prefix_exp),
-                    arguments: Box::new(args),
-                },
-            )));
-        }
-
-        if let Some(prefix_exp) = self.prefixexp() {
-            self.expect(Token::COLON);
-
-            let name = match self.name() {
-                Some(args) => args,
-                None => {
-                    self.report_expected_error("<name>");
-                    return None;
-                }
-            };
+    fn var(&mut self) -> Option<ASTNode> {
+        self.speculate(|p| match p.prefixexp()? {
+            ASTNode::PrefixExpression(node) if matches!(*node, ASTNode::Variable(_)) => Some(*node),
+            _ => None,
+        })
+    }
 
-            let args = match self.args() {
-                Some(args) => args,
+    /// Parses a name or a parenthesized expression, followed by any number of indexes, fields
+    /// and calls.
+    ///
+    /// The grammar has prefix expressions start with a variable or a call, which both start
+    /// with a prefix expression, so they're parsed from left to right in a loop instead.
+    fn prefixexp(&mut self) -> Option<ASTNode> {
+        let mut tree = if let Some(name) = self.name() {
+            ASTNode::PrefixExpression(Box::new(ASTNode::Variable(Box::new(name))))
+        } else if self.accept(Token::LEFT_PAREN) {
+            let exp = match self.exp() {
+                Some(exp) => exp,
                 None => {
-                    self.report_expected_error("<args>");
+                    self.report_expected_error("<exp>");
                     return None;
                 }
             };
+            self.expect(Token::RIGHT_PAREN);
+            ASTNode::PrefixExpression(Box::new(exp))
+        } else {
+            return None;
+        };
 
-            return Some(ASTNode::FunctionCall(Box::new(
-                ASTNode::PrefixExpressionNameArgs {
-                    prefix_expression: Box::new(prefix_exp),
-                    name: Box::new(name),
-                    arguments: Box::new(args),
-                },
-            )));
-        }
-
-        None
-    }
-
-    fn var(&mut self) -> Option<ASTNode> {
-        if let Some(tree) = self.name() {
-            return Some(ASTNode::Variable(Box::new(tree)));
-        }
-
-        if let Some(tree) = self.prefixexp() {
-            if self.accept(Token::LEFT_BRACKET) {
+        loop {
+            let suffix = if self.accept(Token::LEFT_BRACKET) {
                 let exp = self.exp().or_else(|| {
                     self.report_expected_error("<exp>");
-                    return None;
+                    None
                 })?;
 
                 self.expect(Token::RIGHT_BRACKET);
 
-                return Some(ASTNode::Variable(Box::new(
-                    ASTNode::PrefixExpressionBracketsExpression {
-                        prefix_expression: Box::new(tree),
-                        expression: Box::new(exp),
-                    },
-                )));
-            }
-
-            if self.accept(Token::DOT) {
-                let name = self.name().or_else(|| {
+                ASTNode::Variable(Box::new(ASTNode::PrefixExpressionBracketsExpression {
+                    prefix_expression: Box::new(tree),
+                    expression: Box::new(exp),
+                }))
+            } else if self.accept(Token::DOT) {
+                let name = self.name_or_keyword().or_else(|| {
                     self.report_expected_error("<name>");
-                    return None;
+                    None
                 })?;
 
-                return Some(ASTNode::Variable(Box::new(
-                    ASTNode::PrefixExpressionDotName {
-                        prefix_expression: Box::new(tree),
-                        name: Box::new(name),
-                    },
-                )));
-            }
-        }
-
-        None
-    }
-
-    fn prefixexp(&mut self) -> Option<ASTNode> {
-        if let Some(tree) = self.var() {
-            return Some(ASTNode::PrefixExpression(Box::new(tree)));
-        }
+                ASTNode::Variable(Box::new(ASTNode::PrefixExpressionDotName {
+                    prefix_expression: Box::new(tree),
+                    name: Box::new(name),
+                }))
+            } else if self.accept(Token::COLON) {
+                let name = self.name_or_keyword().or_else(|| {
+                    self.report_expected_error("<name>");
+                    None
+                })?;
 
-        if let Some(tree) = self.functioncall() {
-            return Some(ASTNode::PrefixExpression(Box::new(tree)));
-        }
+                let args = self.args().or_else(|| {
+                    self.report_expected_error("<args>");
+                    None
+                })?;
 
-        if self.accept(Token::LEFT_PAREN) {
-            let exp = match self.exp() {
-                Some(exp) => exp,
-                None => {
-                    self.report_expected_error("<exp>");
-                    return None;
-                }
+                ASTNode::FunctionCall(Box::new(ASTNode::PrefixExpressionNameArgs {
+                    prefix_expression: Box::new(tree),
+                    name: Box::new(name),
+                    arguments: Box::new(args),
+                }))
+            } else if let Some(args) = self.args() {
+                ASTNode::FunctionCall(Box::new(ASTNode::PrefixExpressionArgs {
+                    prefix_expression: Box::new(tree),
+                    arguments: Box::new(args),
+                }))
+            } else {
+                return Some(tree);
             };
-            self.expect(Token::RIGHT_PAREN);
-            return Some(ASTNode::PrefixExpression(Box::new(exp)));
-        }
 
-        None
+            tree = ASTNode::PrefixExpression(Box::new(suffix));
+        }
     }
 
     fn parlist1(&mut self) -> MaybeASTNode {
@@ -585,16 +726,19 @@ impl Parser {
         None
     }
 
-    fn funcbody(&mut self) -> MaybeASTNode {
+    /// Parses `( parlist ) block end`, `opened` is the `function` keyword the body belongs to.
+    fn funcbody(&mut self, opened: Span) -> MaybeASTNode {
         if self.accept(Token::LEFT_PAREN) {
             let parameter_list = self.parlist1();
             self.expect(Token::RIGHT_PAREN);
 
             let block = self.block().or_else(|| {
                 self.report_expected_error("<block>");
-                return None;
+                None
             })?;
 
+            self.expect_end(opened, "function");
+
             return Some(ASTNode::FunctionBody {
                 parameter_list: parameter_list.map(Box::new),
                 block: Box::new(block),
@@ -605,9 +749,10 @@ impl Parser {
 
     fn function(&mut self) -> Option<ASTNode> {
         if self.accept(Token::FUNCTION) {
-            let funcbody = self.funcbody().or_else(|| {
+            let opened = self.previous_span();
+            let funcbody = self.funcbody(opened).or_else(|| {
                 self.report_expected_error("<funcbody>");
-                return None;
+                None
             })?;
 
             return Some(ASTNode::Function {
@@ -617,145 +762,92 @@ impl Parser {
         None
     }
 
-    fn exp_or(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_and() {
-            if self.accept(Token::OR) {
-                let exp = self.exp_and().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
-
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(Token::OR)),
-                    right: Box::new(exp),
-                })));
-            } else {
+    /// Parses operands of `operand` joined by any of `operators`, grouping them to the left.
+    fn left_binary(
+        &mut self,
+        operand: fn(&mut Self) -> MaybeASTNode,
+        operators: &[Token],
+    ) -> MaybeASTNode {
+        let mut tree = operand(self)?;
+        loop {
+            let current_token = self.current();
+            if !operators.iter().any(|op| self.accept(op.clone())) {
                 return Some(tree);
             }
+
+            let exp = operand(self).or_else(|| {
+                self.report_expected_error("<exp>");
+                None
+            })?;
+
+            tree = ASTNode::Expression(Box::new(ASTNode::BinaryOp {
+                left: Box::new(tree),
+                binary_operator: Box::new(ASTNode::Token(current_token)),
+                right: Box::new(exp),
+            }));
         }
-        None
     }
 
-    fn exp_and(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_eqaulity() {
-            if self.accept(Token::AND) {
-                let exp = self.exp_eqaulity().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
+    fn exp_or(&mut self) -> MaybeASTNode {
+        self.left_binary(Self::exp_and, &[Token::OR])
+    }
 
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(Token::AND)),
-                    right: Box::new(exp),
-                })));
-            } else {
-                return Some(tree);
-            }
-        }
-        None
+    fn exp_and(&mut self) -> MaybeASTNode {
+        self.left_binary(Self::exp_eqaulity, &[Token::AND])
     }
 
     fn exp_eqaulity(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_concat() {
-            let current_token = self.current();
-            if self.accept(Token::GREATER_THAN)
-                || self.accept(Token::LESS_THAN)
-                || self.accept(Token::LESS_EQUAL)
-                || self.accept(Token::GREATER_EQUAL)
-                || self.accept(Token::NEQ)
-                || self.accept(Token::EQ)
-            {
-                let exp = self.exp_concat().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
-
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(current_token)),
-                    right: Box::new(exp),
-                })));
-            } else {
-                return Some(tree);
-            }
-        }
-        None
+        self.left_binary(
+            Self::exp_concat,
+            &[
+                Token::GREATER_THAN,
+                Token::LESS_THAN,
+                Token::LESS_EQUAL,
+                Token::GREATER_EQUAL,
+                Token::NEQ,
+                Token::EQ,
+            ],
+        )
     }
 
-    // NOTE: make this right associative in a second.
+    // `..` groups to the right, `a .. b .. c` is `a .. (b .. c)`.
     fn exp_concat(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_term() {
-            if self.accept(Token::CONCAT) {
-                let exp = self.exp_term().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
-
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(Token::CONCAT)),
-                    right: Box::new(exp),
-                })));
-            } else {
-                return Some(tree);
-            }
+        let tree = self.exp_term()?;
+        if !self.accept(Token::CONCAT) {
+            return Some(tree);
         }
-        None
+
+        let exp = self.nested(Self::exp_concat).or_else(|| {
+            self.report_expected_error("<exp>");
+            None
+        })?;
+
+        Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
+            left: Box::new(tree),
+            binary_operator: Box::new(ASTNode::Token(Token::CONCAT)),
+            right: Box::new(exp),
+        })))
     }
 
     fn exp_term(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_factor() {
-            let current_token = self.current();
-            if self.accept(Token::ADD) || self.accept(Token::SUBTRACT) {
-                let exp = self.exp_factor().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
-
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(current_token)),
-                    right: Box::new(exp),
-                })));
-            } else {
-                return Some(tree);
-            }
-        }
-        None
+        self.left_binary(Self::exp_factor, &[Token::ADD, Token::SUBTRACT])
     }
 
     fn exp_factor(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_unary() {
-            let current_token = self.current();
-            if self.accept(Token::MULTIPLY)
-                || self.accept(Token::DIVIDE)
-                || self.accept(Token::MODULO)
-            {
-                let exp = self.exp_unary().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
-
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(current_token)),
-                    right: Box::new(exp),
-                })));
-            } else {
-                return Some(tree);
-            }
-        }
-        None
+        self.left_binary(
+            Self::exp_unary,
+            &[Token::MULTIPLY, Token::DIVIDE, Token::MODULO],
+        )
     }
 
+    // the operand of a unary operator can be another one, and binds looser than `^`, so `-a ^ b`
+    // is `-(a ^ b)`.
     fn exp_unary(&mut self) -> MaybeASTNode {
         let current_token = self.current();
         if self.accept(Token::NOT) || self.accept(Token::HASHTAG) || self.accept(Token::SUBTRACT) {
-            let exp = self.exp_exponent().or_else(|| {
+            let exp = self.nested(Self::exp_unary).or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
 
             return Some(ASTNode::Expression(Box::new(ASTNode::UnaryOp {
@@ -764,41 +856,38 @@ impl Parser {
             })));
         }
 
-        if let Some(tree) = self.exp_exponent() {
-            return Some(tree);
-        }
-
-        None
+        self.exp_exponent()
     }
 
+    // `^` groups to the right and takes a unary operand on its right, as in `2 ^ -1`.
     fn exp_exponent(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.exp_primary() {
-            if self.accept(Token::XOR) {
-                let exp = self.exp_primary().or_else(|| {
-                    self.report_expected_error("<exp>");
-                    return None;
-                })?;
-
-                return Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
-                    left: Box::new(tree),
-                    binary_operator: Box::new(ASTNode::Token(Token::XOR)),
-                    right: Box::new(exp),
-                })));
-            } else {
-                return Some(tree);
-            }
+        let tree = self.exp_primary()?;
+        if !self.accept(Token::XOR) {
+            return Some(tree);
         }
-        None
+
+        let exp = self.nested(Self::exp_unary).or_else(|| {
+            self.report_expected_error("<exp>");
+            None
+        })?;
+
+        Some(ASTNode::Expression(Box::new(ASTNode::BinaryOp {
+            left: Box::new(tree),
+            binary_operator: Box::new(ASTNode::Token(Token::XOR)),
+            right: Box::new(exp),
+        })))
     }
 
     fn exp_primary(&mut self) -> MaybeASTNode {
-        let found_terminal = match self.current() {
-            Token::NUMBER(_) => true,
-            Token::STRING(_) => true,
-            Token::NAME(_) => true,
-            Token::NIL | Token::FALSE | Token::TRUE | Token::DOTS => true,
-            _ => false,
-        };
+        let found_terminal = matches!(
+            self.current(),
+            Token::NUMBER(_)
+                | Token::STRING(_)
+                | Token::NIL
+                | Token::FALSE
+                | Token::TRUE
+                | Token::DOTS
+        );
 
         if found_terminal {
             let current_token = self.current();
@@ -806,24 +895,6 @@ impl Parser {
             return Some(ASTNode::Expression(Box::new(ASTNode::Token(current_token))));
         }
 
-        if self.accept(Token::LEFT_PAREN) {
-            let exp = self.exp_or().or_else(|| {
-                self.report_expected_error("<exp>");
-                return None;
-            })?;
-            self.expect(Token::RIGHT_PAREN);
-            return Some(ASTNode::Expression(Box::new(exp)));
-        }
-
-        None
-    }
-
-    // parse an expression.
-    fn exp(&mut self) -> Option<ASTNode> {
-        if let Some(tree) = self.exp_or() {
-            return Some(ASTNode::Expression(Box::new(tree)));
-        }
-
         if let Some(tree) = self.function() {
             return Some(ASTNode::Expression(Box::new(tree)));
         }
@@ -832,6 +903,7 @@ impl Parser {
             return Some(ASTNode::Expression(Box::new(tree)));
         }
 
+        // names and parentheses, along with the fields, indexes and calls that follow them.
         if let Some(tree) = self.prefixexp() {
             return Some(ASTNode::Expression(Box::new(tree)));
         }
@@ -839,8 +911,21 @@ impl Parser {
         None
     }
 
+    // parse an expression.
+    fn exp(&mut self) -> Option<ASTNode> {
+        self.nested(Self::any_exp)
+    }
+
+    fn any_exp(&mut self) -> Option<ASTNode> {
+        let tree = self.exp_or()?;
+        Some(ASTNode::Expression(Box::new(tree)))
+    }
+
     fn stat(&mut self) -> MaybeASTNode {
+        let start = self.cursor;
+
         if self.accept(Token::DO) {
+            let opened = self.previous_span();
             let block = match self.block() {
                 Some(block) => block,
                 None => {
@@ -849,15 +934,16 @@ impl Parser {
                 }
             };
 
-            self.expect(Token::END);
+            self.expect_end(opened, "do");
 
             return Some(ASTNode::Statement(Box::new(ASTNode::Do(Box::new(block)))));
         }
 
         if self.accept(Token::WHILE) {
+            let opened = self.previous_span();
             let exp = self.exp().or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
             self.expect(Token::DO);
             let block = match self.block() {
@@ -868,7 +954,7 @@ impl Parser {
                 }
             };
 
-            self.expect(Token::END);
+            self.expect_end(opened, "while");
 
             return Some(ASTNode::Statement(Box::new(ASTNode::While {
                 expression: Box::new(exp),
@@ -879,18 +965,16 @@ impl Parser {
         if self.accept(Token::REPEAT) {
             let block = self.block().or_else(|| {
                 self.report_expected_error("<block>");
-                return None;
+                None
             })?;
 
             self.expect(Token::UNTIL);
 
             let exp = self.exp().or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
 
-            self.expect(Token::END);
-
             return Some(ASTNode::Statement(Box::new(ASTNode::Repeat {
                 block: Box::new(block),
                 expression: Box::new(exp),
@@ -898,16 +982,17 @@ impl Parser {
         }
 
         if self.accept(Token::IF) {
+            let opened = self.previous_span();
             let exp = self.exp().or_else(|| {
                 self.report_expected_error("<exp>");
-                return None;
+                None
             })?;
 
             self.expect(Token::THEN);
 
             let block = self.block().or_else(|| {
                 self.report_expected_error("<block>");
-                return None;
+                None
             })?;
 
             let mut else_ifs = Vec::new();
@@ -915,14 +1000,14 @@ impl Parser {
             while self.accept(Token::ELSEIF) {
                 let exp = self.exp().or_else(|| {
                     self.report_expected_error("<exp>");
-                    return None;
+                    None
                 })?;
 
                 self.expect(Token::THEN);
 
                 let block = self.block().or_else(|| {
                     self.report_expected_error("<block>");
-                    return None;
+                    None
                 })?;
 
                 else_ifs.push((exp, block));
@@ -931,13 +1016,13 @@ impl Parser {
             let else_block = if self.accept(Token::ELSE) {
                 Some(self.block().or_else(|| {
                     self.report_expected_error("<block>");
-                    return None;
+                    None
                 })?)
             } else {
                 None
             };
 
-            self.expect(Token::END);
+            self.expect_end(opened, "if");
 
             return Some(ASTNode::Statement(Box::new(ASTNode::If {
                 expression: Box::new(exp),
@@ -948,23 +1033,29 @@ impl Parser {
         }
 
         if self.accept(Token::FOR) {
-            // numeric for.
-            if let Some(name) = self.name() {
+            let opened = self.previous_span();
+            // numeric for, whose name a reserved word can only be taken for before its `=`.
+            // without the `=` it's a generic for, whose names are left to the namelist.
+            let name = match self.peek() {
+                Some(Token::ASSIGN) => self.name_or_keyword(),
+                _ => None,
+            };
+            if let Some(name) = name {
                 self.expect(Token::ASSIGN);
                 let exp = self.exp().or_else(|| {
                     self.report_expected_error("<exp>");
-                    return None;
+                    None
                 })?;
                 self.expect(Token::COMMA);
                 let exp2 = self.exp().or_else(|| {
                     self.report_expected_error("<exp>");
-                    return None;
+                    None
                 })?;
 
                 let exp3 = if self.accept(Token::COMMA) {
                     Some(self.exp().or_else(|| {
                         self.report_expected_error("<exp>");
-                        return None;
+                        None
                     })?)
                 } else {
                     None
@@ -974,10 +1065,10 @@ impl Parser {
 
                 let block = self.block().or_else(|| {
                     self.report_expected_error("<block>");
-                    return None;
+                    None
                 })?;
 
-                self.expect(Token::END);
+                self.expect_end(opened, "for");
 
                 return Some(ASTNode::Statement(Box::new(ASTNode::ForNumeric {
                     name: Box::new(name),
@@ -994,17 +1085,17 @@ impl Parser {
 
                 let exp_list = self.explist1().or_else(|| {
                     self.report_expected_error("<explist1>");
-                    return None;
+                    None
                 })?;
 
                 self.expect(Token::DO);
 
                 let block = self.block().or_else(|| {
                     self.report_expected_error("<block>");
-                    return None;
+                    None
                 })?;
 
-                self.expect(Token::END);
+                self.expect_end(opened, "for");
 
                 // return Some(ASTNode::Statement(Box::new()));
                 return Some(ASTNode::Statement(Box::new(ASTNode::ForGeneric {
@@ -1013,17 +1104,21 @@ impl Parser {
                     do_block: Box::new(block),
                 })));
             }
+
+            self.report_expected_error("<name>");
+            return None;
         }
 
         if self.accept(Token::FUNCTION) {
+            let opened = self.previous_span();
             let func_name = self.funcname().or_else(|| {
                 self.report_expected_error("<funcname>");
-                return None;
+                None
             })?;
 
-            let func_body = self.funcbody().or_else(|| {
+            let func_body = self.funcbody(opened).or_else(|| {
                 self.report_expected_error("<funcbody>");
-                return None;
+                None
             })?;
 
             return Some(ASTNode::Statement(Box::new(ASTNode::FunctionStatement {
@@ -1034,13 +1129,14 @@ impl Parser {
 
         if self.accept(Token::LOCAL) {
             if self.accept(Token::FUNCTION) {
-                let name = self.name().or_else(|| {
+                let opened = self.previous_span();
+                let name = self.name_or_keyword().or_else(|| {
                     self.report_expected_error("<name>");
-                    return None;
+                    None
                 })?;
-                let func_body = self.funcbody().or_else(|| {
+                let func_body = self.funcbody(opened).or_else(|| {
                     self.report_expected_error("<funcbody>");
-                    return None;
+                    None
                 })?;
 
                 return Some(ASTNode::Statement(Box::new(ASTNode::LocalFunction {
@@ -1061,27 +1157,92 @@ impl Parser {
                 })));
             }
 
-            if let Some(function_call) = self.functioncall() {
-                return Some(ASTNode::Statement(Box::new(function_call)));
-            }
+            self.report_expected_error("<name>");
+            return None;
+        }
+
+        // a call and an assignment both start with a prefix expression, which is parsed once
+        // and then told apart. parsing it again for each would double the work with every
+        // level of functions nested in it.
+        let starts_prefixexp = matches!(self.current(), Token::NAME(_) | Token::LEFT_PAREN);
+        let Some(ASTNode::PrefixExpression(prefix)) = self.speculate(Self::prefixexp) else {
+            // any expression starting there would have failed the same way.
+            return match starts_prefixexp {
+                true => None,
+                false => self.unexpected_expression(start),
+            };
+        };
 
+        match *prefix {
+            // `f() == 1` is a comparison that starts with a call, not a call.
+            ASTNode::FunctionCall(_) if !is_binary_operator(&self.current()) => {
+                Some(ASTNode::Statement(prefix))
+            }
             // varlist1 `=´ explist1.
-            if let Some(var_list) = self.varlist() {
+            ASTNode::Variable(_) if self.is_match(Token::ASSIGN) || self.is_match(Token::COMMA) => {
+                let var_list = self.varlist(*prefix)?;
                 self.expect(Token::ASSIGN);
 
                 let exp_list = self.explist1().or_else(|| {
                     self.report_expected_error("<explist1>");
-                    return None;
+                    None
                 })?;
 
-                return Some(ASTNode::Statement(Box::new(ASTNode::LValueAssign {
+                Some(ASTNode::Statement(Box::new(ASTNode::LValueAssign {
                     var_list: Box::new(var_list),
                     expression_list: Box::new(exp_list),
-                })));
+                })))
             }
+            _ => self.unexpected_expression(start),
         }
+    }
 
-        None
+    /// Reports the expression starting at the token `start` as a statement, which only a call
+    /// can be.
+    ///
+    /// Returns None, without reporting anything, if there is no expression there.
+    fn unexpected_expression(&mut self, start: usize) -> MaybeASTNode {
+        let resume = self.cursor;
+        self.cursor = start;
+        let from = self.current_span();
+
+        let Some(exp) = self.speculate(Self::exp) else {
+            self.cursor = resume;
+            return None;
+        };
+
+        let span = Span {
+            end: self.previous_span().end,
+            ..from
+        };
+        let message = format!(
+            "syntax error near '{}': unexpected expression, only function calls can be used as statements.",
+            self.current().spelling()
+        );
+        let mut diagnostic = Diagnostic::error("E100", message, span);
+
+        // the expression nodes wrap each other, the operator is below all of them.
+        let mut top = &exp;
+        while let ASTNode::Expression(inner) = top {
+            top = inner;
+        }
+        if let ASTNode::BinaryOp {
+            binary_operator, ..
+        } = top
+        {
+            if **binary_operator == ASTNode::Token(Token::EQ) {
+                diagnostic =
+                    diagnostic.with_note("did you mean '=' (assignment)?".to_string(), None);
+            }
+        }
+
+        if !self.gave_up {
+            self.diagnostics.emit(diagnostic);
+            self.errored = true;
+        }
+
+        // parsing goes on after it, so what follows is checked too.
+        Some(ASTNode::Statement(Box::new(exp)))
     }
 
     fn laststat(&mut self) -> MaybeASTNode {
@@ -1103,30 +1264,51 @@ impl Parser {
     }
 
     fn block(&mut self) -> MaybeASTNode {
-        if let Some(tree) = self.chunk() {
+        self.depth += 1;
+        let chunk = self.nested(Self::chunk);
+        self.depth -= 1;
+
+        if let Some(tree) = chunk {
             return Some(ASTNode::Block(Box::new(tree)));
         }
         None
     }
 
+    /// Remembers where the statement that started at `start` is, if it's in the main chunk.
+    fn record_statement(&mut self, start: Span) {
+        if self.depth == 0 && self.cursor > 0 {
+            let end = self.previous_span().end;
+            self.statement_spans.push(Span { end, ..start });
+        }
+    }
+
     fn chunk(&mut self) -> MaybeASTNode {
         let mut statements = Vec::new();
 
-        while let Some(tree) = self.stat() {
+        loop {
+            if self.cancellation.as_ref().is_some_and(|c| c.is_cancelled()) {
+                self.errored = true;
+                break;
+            }
+            let start = self.current_span();
+            let Some(tree) = self.stat() else {
+                break;
+            };
+            self.record_statement(start);
+
             // optional, no need to do anything.
             self.accept(Token::SEMICOLON);
             statements.push(tree);
         }
 
+        let start = self.current_span();
         let last_statement = self.laststat();
+        if last_statement.is_some() {
+            self.record_statement(start);
+            self.accept(Token::SEMICOLON);
+        }
 
-        let chunk = ASTNode::Chunk(
-            statements.clone(),
-            match last_statement.clone() {
-                Some(t) => Some(Box::new(t)),
-                None => None,
-            },
-        );
+        let chunk = ASTNode::Chunk(statements, last_statement.map(Box::new));
 
         // if statements.is_empty() && last_statement.is_none() {
         //     None
@@ -1135,15 +1317,339 @@ impl Parser {
         // }
     }
 
+    /// Parses the tokens as a chunk.
+    ///
+    /// Never panics, whatever the tokens, the problems are in `diagnostics` instead.
     pub fn parse(&mut self) -> MaybeASTNode {
-        let chunk = self.chunk();
+        let (chunk, complete) = self.parse_partial();
+        complete.then_some(chunk)
+    }
+
+    /// Parses the tokens as a chunk like `parse`, but keeps what it got through when there's an
+    /// error, along with whether that's the whole chunk.
+    ///
+    /// The partial chunk has the statements parsed before the parser stopped, a statement with
+    /// an error in it is left out.
+    pub fn parse_partial(&mut self) -> (ASTNode, bool) {
+        let chunk = self
+            .chunk()
+            .unwrap_or_else(|| ASTNode::Chunk(Vec::new(), None));
+
+        // the chunk stops at the first thing it can't parse, which means the rest is invalid.
+        if !self.is_eof() {
+            self.report_expected_error("<eof>");
+        }
+
+        (chunk, !self.errored)
+    }
+
+    /// Parses the tokens as a single expression, like the right side of an assignment.
+    pub fn parse_expression(&mut self) -> MaybeASTNode {
+        // an expression that went wrong inside has already said where.
+        let tree = self.exp().or_else(|| {
+            if !self.errored {
+                self.report_expected_error("<exp>");
+            }
+            None
+        });
 
-        println!("{:#?}", chunk);
+        if !self.is_eof() && !self.errored {
+            self.report_expected_error("<eof>");
+        }
 
         if self.errored {
             None
         } else {
-            chunk
+            tree
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parses the source, returning whether it parsed and the codes of what went wrong.
+    fn parse(source: &str) -> (bool, Vec<&'static str>) {
+        let (tokens, _) = crate::tokenize(source);
+        let mut parser = Parser::new(tokens.expect("the source lexes"));
+        let parsed = parser.parse().is_some();
+        (
+            parsed,
+            parser.diagnostics().iter().map(|d| d.code).collect(),
+        )
+    }
+
+    // parses the source, returning everything that went wrong.
+    fn diagnostics_of(source: &str) -> Vec<Diagnostic> {
+        let (tokens, _) = crate::tokenize(source);
+        let mut parser = Parser::new(tokens.expect("the source lexes"));
+        parser.parse();
+        parser.diagnostics().to_vec()
+    }
+
+    #[test]
+    fn repeat_ends_at_until() {
+        assert_eq!(parse("repeat x = x + 1 until x > 3"), (true, vec![]));
+        assert_eq!(parse("repeat until true\nlocal y = 1"), (true, vec![]));
+        assert!(!parse("repeat x = x + 1 until x > 3 end").0);
+    }
+
+    #[test]
+    fn last_statement_takes_a_semicolon() {
+        assert_eq!(parse("return 1;"), (true, vec![]));
+        assert_eq!(parse("while true do break; end"), (true, vec![]));
+        assert!(!parse("return 1;;").0);
+    }
+
+    #[test]
+    fn positional_fields_can_start_with_a_name() {
+        let sources = [
+            "local t = {a, b}",
+            "local t = {f(x), y}",
+            "local t = {x == 1}",
+            "local t = {a.b, c[1], d:e()}",
+            "local t = {a = 1, b, [c] = d}",
+        ];
+        for source in sources {
+            assert_eq!(parse(source), (true, vec![]), "{source}");
+        }
+
+        // a reserved word before `=` is still taken for the name, and reported once.
+        assert_eq!(parse("local t = {end = 1}"), (false, vec!["E101"]));
+    }
+
+    #[test]
+    fn generic_for_takes_a_namelist() {
+        assert_eq!(parse("for k, v in pairs(t) do end"), (true, vec![]));
+        assert_eq!(parse("for k in f do end"), (true, vec![]));
+        assert_eq!(parse("for i = 1, 2 do end"), (true, vec![]));
+    }
+
+    #[test]
+    fn reserved_words_as_names_are_reported_where_they_are() {
+        // the source, and the 1-based column of the reserved word.
+        let cases = [
+            ("local end = 1", 7),
+            ("local a, nil = 1, 2", 10),
+            ("local function do() end", 16),
+            ("function f(a, then) end", 15),
+            ("function while() end", 10),
+            ("function t.for() end", 12),
+            ("function t:repeat() end", 12),
+            ("x = t.function", 7),
+            ("t:end()", 3),
+            ("local t = {until = 1}", 12),
+        ];
+        for (source, column) in cases {
+            let diagnostics = diagnostics_of(source);
+            assert_eq!(diagnostics.len(), 1, "{source}: {diagnostics:?}");
+            assert_eq!(diagnostics[0].code, "E101", "{source}");
+            assert_eq!(diagnostics[0].span.column, column, "{source}");
+        }
+    }
+
+    #[test]
+    fn only_reserved_words_are_keywords() {
+        // `1e999` is spelled `inf`, which isn't a reserved word.
+        assert!(!Token::NUMBER(f64::INFINITY).is_keyword());
+        assert!(!Token::NAME("end".to_string()).is_keyword());
+        assert!(Token::END.is_keyword() && Token::NIL.is_keyword());
+
+        let (_, codes) = parse("local 1e999 = 2");
+        assert!(!codes.contains(&"E101"), "{codes:?}");
+    }
+
+    #[test]
+    fn only_calls_can_be_statements() {
+        for source in ["x + 1", "a.b", "(f)", "f() .. 1", "1 + 2", "-x"] {
+            let diagnostics = diagnostics_of(source);
+            assert_eq!(diagnostics.len(), 1, "{source}: {diagnostics:?}");
+            assert_eq!(diagnostics[0].code, "E100", "{source}");
+            assert!(
+                diagnostics[0].message.contains(
+                    "unexpected expression, only function calls can be used as statements"
+                ),
+                "{source}: {}",
+                diagnostics[0].message
+            );
+            // it points at the whole expression.
+            let span = diagnostics[0].span;
+            assert_eq!((span.start, span.end), (0, source.len()), "{source}");
+            assert!(diagnostics[0].notes.is_empty(), "{source}");
+        }
+
+        let diagnostics = diagnostics_of("x + 1\ny = 2");
+        assert!(diagnostics[0].message.starts_with("syntax error near 'y'"));
+
+        for source in ["f()", "a.b:c 's'", "(f)()", "a, b.c = 1, 2", "t[1] = f{}"] {
+            assert_eq!(parse(source), (true, vec![]), "{source}");
+        }
+    }
+
+    #[test]
+    fn a_comparison_statement_hints_at_assignment() {
+        for source in ["local x\nx == 1", "f() == 1", "t.a == {}"] {
+            let diagnostics = diagnostics_of(source);
+            assert_eq!(diagnostics.len(), 1, "{source}: {diagnostics:?}");
+            assert_eq!(diagnostics[0].notes.len(), 1, "{source}");
+            assert_eq!(
+                diagnostics[0].notes[0].message,
+                "did you mean '=' (assignment)?"
+            );
+        }
+
+        // only `==` is taken for a mistyped `=`.
+        assert!(diagnostics_of("x ~= 1")[0].notes.is_empty());
+        assert!(diagnostics_of("x == 1 and y")[0].notes.is_empty());
+    }
+
+    #[test]
+    fn nested_statements_are_parsed_once() {
+        // parsing every level twice would take 2^40 times as long as this.
+        let depth = 40;
+        let source = format!(
+            "{}y = 1{}",
+            "x[function() ".repeat(depth),
+            " end] = 1".repeat(depth)
+        );
+        assert_eq!(parse(&source), (true, vec![]));
+    }
+
+    // the binary operators of the manual's precedence table, from the loosest, with whether
+    // they're right associative.
+    const BINARY: &[(&[&str], bool)] = &[
+        (&["or"], false),
+        (&["and"], false),
+        (&["<", ">", "<=", ">=", "~=", "=="], false),
+        (&[".."], true),
+        (&["+", "-"], false),
+        (&["*", "/", "%"], false),
+        (&["^"], true),
+    ];
+    // the unary operators bind tighter than every binary one but `^`.
+    const UNARY: &[&str] = &["not", "#", "-"];
+
+    // parses the source as an expression, returning it with every operation in parentheses.
+    fn parenthesized(source: &str) -> String {
+        let (tokens, _) = crate::tokenize(source);
+        let mut parser = Parser::new(tokens.expect("the source lexes"));
+        let tree = parser.parse_expression().expect("the expression parses");
+        crate::ast::to_parenthesized(&tree)
+    }
+
+    // where a binary operator is in the table, and whether it's right associative.
+    fn precedence(operator: &str) -> (usize, bool) {
+        BINARY
+            .iter()
+            .position(|(operators, _)| operators.contains(&operator))
+            .map(|level| (level, BINARY[level].1))
+            .unwrap()
+    }
+
+    fn unary(operator: &str, operand: &str) -> String {
+        let space = if operator == "not" { " " } else { "" };
+        format!("({operator}{space}{operand})")
+    }
+
+    #[test]
+    fn every_pair_of_binary_operators_groups_like_the_manual() {
+        let operators = BINARY.iter().flat_map(|(operators, _)| operators.iter());
+        for first in operators.clone() {
+            for second in operators.clone() {
+                let ((left, _), (right, right_associative)) =
+                    (precedence(first), precedence(second));
+                // the second goes first if it binds tighter, or as tight and to the right.
+                let expected = match right > left || (right == left && right_associative) {
+                    true => format!("(a {first} (b {second} c))"),
+                    false => format!("((a {first} b) {second} c)"),
+                };
+                let source = format!("a {first} b {second} c");
+                assert_eq!(parenthesized(&source), expected, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_unary_operator_groups_like_the_manual_with_every_other() {
+        let binary = BINARY.iter().flat_map(|(operators, _)| operators.iter());
+        for operator in UNARY {
+            for other in binary.clone() {
+                // only `^` binds tighter than a unary operator, on either side of it.
+                let source = format!("{operator} a {other} b");
+                let expected = match *other {
+                    "^" => unary(operator, "(a ^ b)"),
+                    _ => format!("({} {other} b)", unary(operator, "a")),
+                };
+                assert_eq!(parenthesized(&source), expected, "{source}");
+
+                let source = format!("a {other} {operator} b");
+                let expected = format!("(a {other} {})", unary(operator, "b"));
+                assert_eq!(parenthesized(&source), expected, "{source}");
+            }
+
+            for inner in UNARY {
+                let source = format!("{operator} {inner} a");
+                let expected = unary(operator, &unary(inner, "a"));
+                assert_eq!(parenthesized(&source), expected, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn the_cursor_stays_within_the_tokens() {
+        let (tokens, _) = crate::tokenize("x");
+        let mut parser = Parser::new(tokens.unwrap());
+        let first = parser.current_span();
+
+        // before the first token there's nothing to go back to, or to have just accepted.
+        assert!(!parser.backtrack());
+        assert_eq!(parser.cursor, 0);
+        assert_eq!(parser.previous_span(), first);
+
+        // past the last token it stays at the end.
+        parser.advance();
+        parser.advance();
+        assert!(parser.is_eof());
+        assert_eq!(parser.cursor, 1);
+        assert_eq!(parser.current(), Token::UNDEFINED);
+        assert_eq!(parser.peek(), None);
+        assert_eq!(
+            (parser.current_span().start, parser.current_span().end),
+            (1, 1)
+        );
+        assert_eq!(parser.previous_span(), first);
+        assert!(parser.backtrack());
+        assert_eq!(parser.current(), Token::NAME("x".to_string()));
+
+        // no tokens at all.
+        let mut parser = Parser::new(Vec::new());
+        assert!(!parser.backtrack());
+        parser.advance();
+        assert_eq!(parser.cursor, 0);
+        assert_eq!(parser.previous_span(), Span::default());
+    }
+
+    #[test]
+    fn sources_cut_off_at_either_end_are_reported() {
+        for source in [
+            ")",
+            "until",
+            "end",
+            "function a.",
+            "x = 1 + -",
+            "local",
+            "f(",
+            "t[",
+            "::",
+        ] {
+            let (parsed, codes) = parse(source);
+            assert!(!parsed, "{source}");
+            assert!(!codes.is_empty(), "{source}");
+
+            let (tokens, _) = crate::tokenize(source);
+            let mut parser = Parser::new(tokens.unwrap());
+            assert!(parser.parse_expression().is_none(), "{source}");
         }
     }
 }
@@ -0,0 +1,718 @@
+//! Lua patterns, what `string.find`, `match`, `gmatch` and `gsub` search with.
+//!
+//! This follows the matcher of the reference implementation, Lua 5.4's `lstrlib.c`, so it
+//! works on bytes and backtracks the same way. Offsets are 0-based byte offsets, where Lua
+//! counts from 1.
+//!
+//! ```
+//! use lua_compiler::pattern::{self, Replacement};
+//!
+//! let found = pattern::find(b"key = value", b"(%w+)%s*=%s*(%w+)", 0).unwrap().unwrap();
+//! assert_eq!(found.range, 0..11);
+//!
+//! let (swapped, n) = pattern::gsub(b"hello world", b"(%w+)", Replacement::Text(b"<%1>"), None).unwrap();
+//! assert_eq!((swapped.as_slice(), n), (&b"<hello> <world>"[..], 2));
+//! ```
+
+use std::ops::Range;
+
+// the same limits as the reference implementation.
+const MAX_CAPTURES: usize = 32;
+const MAX_CALLS: usize = 200;
+
+/// What a capture of the pattern caught.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture {
+    Text(Range<usize>),
+    // an empty capture `()`, the offset it was at.
+    Position(usize),
+}
+
+/// Where the pattern matched, along with its captures in the order they were opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub range: Range<usize>,
+    pub captures: Vec<Capture>,
+}
+
+impl Match {
+    /// Returns what `string.match` would, the captures or the whole match if there are none.
+    pub fn values(&self) -> Vec<Capture> {
+        match self.captures.is_empty() {
+            true => vec![Capture::Text(self.range.clone())],
+            false => self.captures.clone(),
+        }
+    }
+}
+
+/// What `gsub` replaces every match with.
+pub enum Replacement<'a> {
+    // `%0` to `%9` in it stand for the captures, `%%` for a `%`.
+    Text(&'a [u8]),
+    // called with the values of the match, None keeps the match as it is. A table replacement
+    // is a function that looks up the first value.
+    Function(&'a mut dyn FnMut(&[Capture]) -> Option<Vec<u8>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Length {
+    Unfinished,
+    Position,
+    Bytes(usize),
+}
+
+struct Matcher<'a> {
+    subject: &'a [u8],
+    pattern: &'a [u8],
+    // where every capture opened so far starts, and how long it is.
+    captures: Vec<(usize, Length)>,
+    calls: usize,
+}
+
+type Step = Result<Option<usize>, String>;
+
+impl Matcher<'_> {
+    /// Matches the pattern from `p` against the subject from `s`, returning where it ends.
+    fn do_match(&mut self, mut s: usize, mut p: usize) -> Step {
+        self.calls += 1;
+        if self.calls > MAX_CALLS {
+            return Err("pattern too complex".to_string());
+        }
+
+        let result = loop {
+            let Some(&c) = self.pattern.get(p) else {
+                break Some(s);
+            };
+
+            match c {
+                b'(' if self.pattern.get(p + 1) == Some(&b')') => {
+                    break self.start_capture(s, p + 2, Length::Position)?
+                }
+                b'(' => break self.start_capture(s, p + 1, Length::Unfinished)?,
+                b')' => break self.end_capture(s, p + 1)?,
+                b'$' if p + 1 == self.pattern.len() => {
+                    break (s == self.subject.len()).then_some(s)
+                }
+                b'%' if self.pattern.get(p + 1) == Some(&b'b') => match self.balance(s, p + 2)? {
+                    Some(end) => {
+                        s = end;
+                        p += 4;
+                        continue;
+                    }
+                    None => break None,
+                },
+                b'%' if self.pattern.get(p + 1) == Some(&b'f') => {
+                    p += 2;
+                    if self.pattern.get(p) != Some(&b'[') {
+                        return Err("missing '[' after '%f' in pattern".to_string());
+                    }
+                    let end = self.class_end(p)?;
+                    let previous = s.checked_sub(1).map_or(0, |i| self.subject[i]);
+                    let current = self.subject.get(s).copied().unwrap_or(0);
+                    if !self.bracket_class(previous, p, end - 1)
+                        && self.bracket_class(current, p, end - 1)
+                    {
+                        p = end;
+                        continue;
+                    }
+                    break None;
+                }
+                b'%' if self.pattern.get(p + 1).is_some_and(u8::is_ascii_digit) => {
+                    match self.back_reference(s, self.pattern[p + 1])? {
+                        Some(end) => {
+                            s = end;
+                            p += 2;
+                            continue;
+                        }
+                        None => break None,
+                    }
+                }
+                _ => {}
+            }
+
+            // a single character class, maybe followed by a quantifier.
+            let end = self.class_end(p)?;
+            let matches = s < self.subject.len() && self.single(self.subject[s], p, end);
+            match self.pattern.get(end) {
+                Some(b'?') => {
+                    if matches {
+                        if let Some(found) = self.do_match(s + 1, end + 1)? {
+                            break Some(found);
+                        }
+                    }
+                    p = end + 1;
+                }
+                Some(b'+') if matches => break self.max_expand(s + 1, p, end)?,
+                Some(b'+') => break None,
+                Some(b'*') => break self.max_expand(s, p, end)?,
+                Some(b'-') => break self.min_expand(s, p, end)?,
+                _ if matches => {
+                    s += 1;
+                    p = end;
+                }
+                _ => break None,
+            }
+        };
+
+        self.calls -= 1;
+        Ok(result)
+    }
+
+    /// Returns where the class starting at `p` ends, e.g. after the `]` of a set.
+    fn class_end(&self, mut p: usize) -> Result<usize, String> {
+        let ends_with_escape = || "malformed pattern (ends with '%')".to_string();
+        let c = self.pattern[p];
+        p += 1;
+
+        match c {
+            b'%' if p >= self.pattern.len() => Err(ends_with_escape()),
+            b'%' => Ok(p + 1),
+            b'[' => {
+                if self.pattern.get(p) == Some(&b'^') {
+                    p += 1;
+                }
+                // the first character is part of the set even if it's a `]`, as in `[]]`.
+                loop {
+                    if p >= self.pattern.len() {
+                        return Err("malformed pattern (missing ']')".to_string());
+                    }
+                    let c = self.pattern[p];
+                    p += 1;
+                    if c == b'%' && p < self.pattern.len() {
+                        p += 1;
+                    }
+                    if self.pattern.get(p) == Some(&b']') {
+                        return Ok(p + 1);
+                    }
+                }
+            }
+            _ => Ok(p),
+        }
+    }
+
+    /// Returns whether `c` is in the single class between `p` and `end`.
+    fn single(&self, c: u8, p: usize, end: usize) -> bool {
+        match self.pattern[p] {
+            b'.' => true,
+            b'%' => class(c, self.pattern[p + 1]),
+            b'[' => self.bracket_class(c, p, end - 1),
+            literal => literal == c,
+        }
+    }
+
+    /// Returns whether `c` is in the set between the `[` at `p` and the `]` at `close`.
+    fn bracket_class(&self, c: u8, mut p: usize, close: usize) -> bool {
+        let mut found = true;
+        p += 1;
+        if self.pattern[p] == b'^' {
+            found = false;
+            p += 1;
+        }
+
+        while p < close {
+            let at = self.pattern[p];
+            if at == b'%' && p + 1 < close {
+                if class(c, self.pattern[p + 1]) {
+                    return found;
+                }
+                p += 2;
+            } else if self.pattern.get(p + 1) == Some(&b'-') && p + 2 < close {
+                if at <= c && c <= self.pattern[p + 2] {
+                    return found;
+                }
+                p += 3;
+            } else {
+                if at == c {
+                    return found;
+                }
+                p += 1;
+            }
+        }
+        !found
+    }
+
+    fn max_expand(&mut self, s: usize, p: usize, end: usize) -> Step {
+        let mut count = 0;
+        while s + count < self.subject.len() && self.single(self.subject[s + count], p, end) {
+            count += 1;
+        }
+        // as many as possible, giving them back one by one until the rest matches.
+        loop {
+            if let Some(found) = self.do_match(s + count, end + 1)? {
+                return Ok(Some(found));
+            }
+            if count == 0 {
+                return Ok(None);
+            }
+            count -= 1;
+        }
+    }
+
+    fn min_expand(&mut self, mut s: usize, p: usize, end: usize) -> Step {
+        loop {
+            if let Some(found) = self.do_match(s, end + 1)? {
+                return Ok(Some(found));
+            }
+            if s < self.subject.len() && self.single(self.subject[s], p, end) {
+                s += 1;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn start_capture(&mut self, s: usize, p: usize, length: Length) -> Step {
+        if self.captures.len() >= MAX_CAPTURES {
+            return Err("too many captures".to_string());
+        }
+        self.captures.push((s, length));
+        let found = self.do_match(s, p)?;
+        if found.is_none() {
+            self.captures.pop();
+        }
+        Ok(found)
+    }
+
+    fn end_capture(&mut self, s: usize, p: usize) -> Step {
+        let Some(open) = self
+            .captures
+            .iter()
+            .rposition(|(_, l)| *l == Length::Unfinished)
+        else {
+            return Err("invalid pattern capture".to_string());
+        };
+
+        self.captures[open].1 = Length::Bytes(s - self.captures[open].0);
+        let found = self.do_match(s, p)?;
+        if found.is_none() {
+            self.captures[open].1 = Length::Unfinished;
+        }
+        Ok(found)
+    }
+
+    /// Matches `%bxy`, from an `x` up to the `y` that balances it.
+    fn balance(&self, s: usize, p: usize) -> Step {
+        let (Some(&open), Some(&close)) = (self.pattern.get(p), self.pattern.get(p + 1)) else {
+            return Err("malformed pattern (missing arguments to '%b')".to_string());
+        };
+        if self.subject.get(s) != Some(&open) {
+            return Ok(None);
+        }
+
+        let mut depth = 1;
+        for (i, &c) in self.subject.iter().enumerate().skip(s + 1) {
+            if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(Some(i + 1));
+                }
+            } else if c == open {
+                depth += 1;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Matches `%1` to `%9`, the same text as a capture that's already closed.
+    fn back_reference(&self, s: usize, digit: u8) -> Step {
+        let index = (digit as usize).wrapping_sub(b'1' as usize);
+        let (start, length) = match self.captures.get(index) {
+            Some((_, Length::Unfinished)) | None => {
+                return Err(format!("invalid capture index %{}", index.wrapping_add(1)))
+            }
+            // what a position capture caught can't be matched, like in the reference.
+            Some((_, Length::Position)) => return Ok(None),
+            Some(&(start, Length::Bytes(length))) => (start, length),
+        };
+
+        let caught = &self.subject[start..start + length];
+        Ok(self.subject[s..].starts_with(caught).then_some(s + length))
+    }
+
+    /// Returns the captures once the pattern matched `range`.
+    fn finish(&self, range: Range<usize>) -> Result<Match, String> {
+        let captures = self
+            .captures
+            .iter()
+            .map(|&(start, length)| match length {
+                Length::Unfinished => Err("unfinished capture".to_string()),
+                Length::Position => Ok(Capture::Position(start)),
+                Length::Bytes(length) => Ok(Capture::Text(start..start + length)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Match { range, captures })
+    }
+
+    fn reset(&mut self) {
+        self.captures.clear();
+        self.calls = 0;
+    }
+}
+
+/// Returns whether `c` is in the class of `%` followed by `name`, e.g. `%d`. An upper case
+/// name is the complement, and any other character stands for itself.
+fn class(c: u8, name: u8) -> bool {
+    let found = match name.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'g' => c.is_ascii_graphic(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        // what C's isspace takes, which has the vertical tab.
+        b's' => matches!(c, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r'),
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        _ => return name == c,
+    };
+    match name.is_ascii_uppercase() {
+        true => !found,
+        false => found,
+    }
+}
+
+/// Finds the first match of the pattern at or after `init`, like `string.find`.
+///
+/// A pattern starting with `^` only matches at `init`. The error is the message Lua would
+/// raise for a malformed pattern.
+pub fn find(subject: &[u8], pattern: &[u8], init: usize) -> Result<Option<Match>, String> {
+    if init > subject.len() {
+        return Ok(None);
+    }
+
+    let (anchored, pattern) = match pattern.strip_prefix(b"^") {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let mut matcher = Matcher {
+        subject,
+        pattern,
+        captures: Vec::new(),
+        calls: 0,
+    };
+
+    // an empty match at the very end counts too.
+    for start in init..=subject.len() {
+        matcher.reset();
+        if let Some(end) = matcher.do_match(start, 0)? {
+            return matcher.finish(start..end).map(Some);
+        }
+        if anchored {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the first occurrence of `needle` at or after `init`, like `string.find` with `plain`.
+pub fn find_plain(subject: &[u8], needle: &[u8], init: usize) -> Option<Range<usize>> {
+    if init > subject.len() {
+        return None;
+    }
+    if needle.is_empty() {
+        return Some(init..init);
+    }
+    subject[init..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| init + i..init + i + needle.len())
+}
+
+/// Iterates over the matches of a pattern, like `string.gmatch`.
+pub struct Matches<'a> {
+    matcher: Matcher<'a>,
+    next: usize,
+    // where the last match ended, an empty match right there would repeat it.
+    last_end: Option<usize>,
+    failed: bool,
+}
+
+impl Iterator for Matches<'_> {
+    type Item = Result<Match, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        while self.next <= self.matcher.subject.len() {
+            let start = self.next;
+            self.matcher.reset();
+            match self.matcher.do_match(start, 0) {
+                Ok(Some(end)) if Some(end) != self.last_end => {
+                    self.next = end;
+                    self.last_end = Some(end);
+                    return Some(self.matcher.finish(start..end));
+                }
+                Ok(_) => self.next += 1,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns every match of the pattern from the start of the subject, like `string.gmatch`.
+///
+/// As in Lua 5.4, a `^` at the start of the pattern isn't an anchor here, it stands for itself.
+pub fn gmatch<'a>(subject: &'a [u8], pattern: &'a [u8]) -> Matches<'a> {
+    Matches {
+        matcher: Matcher {
+            subject,
+            pattern,
+            captures: Vec::new(),
+            calls: 0,
+        },
+        next: 0,
+        last_end: None,
+        failed: false,
+    }
+}
+
+/// Replaces the matches of the pattern, at most `max` of them if given, like `string.gsub`.
+///
+/// Returns the new text along with how many matches were replaced.
+pub fn gsub(
+    subject: &[u8],
+    pattern: &[u8],
+    mut replacement: Replacement,
+    max: Option<usize>,
+) -> Result<(Vec<u8>, usize), String> {
+    let (anchored, pattern) = match pattern.strip_prefix(b"^") {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+    let mut matcher = Matcher {
+        subject,
+        pattern,
+        captures: Vec::new(),
+        calls: 0,
+    };
+
+    let mut out = Vec::with_capacity(subject.len());
+    let (mut s, mut count, mut last_end) = (0, 0, None);
+
+    while max.is_none_or(|max| count < max) {
+        matcher.reset();
+        match matcher.do_match(s, 0)? {
+            Some(end) if Some(end) != last_end => {
+                count += 1;
+                let found = matcher.finish(s..end)?;
+                let whole = &subject[s..end];
+                match &mut replacement {
+                    Replacement::Text(text) => substitute(&mut out, text, subject, &found)?,
+                    Replacement::Function(f) => match f(&found.values()) {
+                        Some(value) => out.extend_from_slice(&value),
+                        None => out.extend_from_slice(whole),
+                    },
+                }
+                s = end;
+                last_end = Some(end);
+            }
+            _ if s < subject.len() => {
+                out.push(subject[s]);
+                s += 1;
+            }
+            _ => break,
+        }
+        if anchored {
+            break;
+        }
+    }
+
+    out.extend_from_slice(&subject[s..]);
+    Ok((out, count))
+}
+
+/// Writes the replacement text of a match, with its `%` sequences filled in.
+fn substitute(out: &mut Vec<u8>, text: &[u8], subject: &[u8], found: &Match) -> Result<(), String> {
+    let mut bytes = text.iter();
+    while let Some(&c) = bytes.next() {
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+
+        let value = match bytes.next() {
+            Some(b'%') => {
+                out.push(b'%');
+                continue;
+            }
+            Some(b'0') => Capture::Text(found.range.clone()),
+            Some(&d @ b'1'..=b'9') => {
+                let index = (d - b'1') as usize;
+                match found.values().get(index) {
+                    Some(value) => value.clone(),
+                    None => return Err(format!("invalid capture index %{}", index + 1)),
+                }
+            }
+            _ => return Err("invalid use of '%' in replacement string".to_string()),
+        };
+
+        match value {
+            Capture::Text(range) => out.extend_from_slice(&subject[range]),
+            // a position is 1-based in Lua.
+            Capture::Position(at) => out.extend_from_slice((at + 1).to_string().as_bytes()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what `string.match` returns for the subject, every value joined by `|`.
+    fn matched(subject: &str, pattern: &str) -> Option<String> {
+        let subject = subject.as_bytes();
+        let found = find(subject, pattern.as_bytes(), 0).expect("the pattern is well formed")?;
+        let values = found.values().into_iter().map(|value| match value {
+            Capture::Text(range) => String::from_utf8_lossy(&subject[range]).into_owned(),
+            Capture::Position(at) => (at + 1).to_string(),
+        });
+        Some(values.collect::<Vec<_>>().join("|"))
+    }
+
+    fn check(cases: &[(&str, &str, Option<&str>)]) {
+        for &(subject, pattern, expected) in cases {
+            assert_eq!(
+                matched(subject, pattern).as_deref(),
+                expected,
+                "{subject:?} against {pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn classes() {
+        check(&[
+            ("ab12 cd", "%a+", Some("ab")),
+            ("ab12 cd", "%d+", Some("12")),
+            ("ab12 cd", "%s", Some(" ")),
+            ("ab12 cd", "%w+", Some("ab12")),
+            ("ab12 cd", "%l+", Some("ab")),
+            ("abCD", "%u+", Some("CD")),
+            ("a,b", "%p", Some(",")),
+            ("a\tb", "%c", Some("\t")),
+            ("xyz0fF", "%x+", Some("0fF")),
+            ("ab12", "%A+", Some("12")),
+            ("ab12", "%D+", Some("ab")),
+            ("a.b", "%.", Some(".")),
+            ("a.b", ".", Some("a")),
+            ("abc", "%d", None),
+        ]);
+    }
+
+    #[test]
+    fn sets() {
+        check(&[
+            ("hello world", "[aeiou]+", Some("e")),
+            ("hello world", "[^%s]+", Some("hello")),
+            ("x = 42;", "[0-9]+", Some("42")),
+            ("a-b", "[-]", Some("-")),
+            ("a]b", "[]]", Some("]")),
+            ("ab12_", "[%a_]+", Some("ab")),
+            ("ab12_", "[%d_]+", Some("12_")),
+            ("abc", "[^abc]", None),
+        ]);
+    }
+
+    #[test]
+    fn repetition() {
+        check(&[
+            ("aaab", "a*", Some("aaa")),
+            ("aaab", "a-b", Some("aaab")),
+            ("<a><b>", "<.->", Some("<a>")),
+            ("<a><b>", "<.*>", Some("<a><b>")),
+            ("color colour", "colou?r", Some("color")),
+            ("b", "a+", None),
+        ]);
+    }
+
+    #[test]
+    fn anchors() {
+        check(&[
+            ("hello", "^h", Some("h")),
+            ("hello", "^e", None),
+            ("hello", "o$", Some("o")),
+            ("hello", "l$", None),
+            ("hello", "^hello$", Some("hello")),
+            ("a$b", "a$b", Some("a$b")),
+            ("", "^$", Some("")),
+        ]);
+
+        // an anchored pattern only matches at `init`.
+        assert_eq!(find(b"abab", b"^b", 1).unwrap().unwrap().range, 1..2);
+        assert_eq!(find(b"abab", b"^b", 2).unwrap(), None);
+    }
+
+    #[test]
+    fn balanced() {
+        check(&[
+            ("f(a(b)c) d", "%b()", Some("(a(b)c)")),
+            ("x [[y]] z", "%b[]", Some("[[y]]")),
+            ("(open", "%b()", None),
+            ("'quoted' text", "%b''", Some("'quoted'")),
+        ]);
+    }
+
+    #[test]
+    fn frontier() {
+        check(&[
+            ("THE (quick) fox", "%f[%a]%a+", Some("THE")),
+            ("the quick", "%f[%a]q%a+", Some("quick")),
+            ("foo bar", "%f[%w]%w+$", Some("bar")),
+            ("hello", "%f[%A]", Some("")),
+            ("inside", "%f[%a]side", None),
+        ]);
+    }
+
+    #[test]
+    fn captures() {
+        check(&[
+            ("key = value", "(%w+)%s*=%s*(%w+)", Some("key|value")),
+            ("hello", "()ll()", Some("3|5")),
+            ("abcabc", "(a(b)c)", Some("abc|b")),
+            ("say 'hi' now", "(['\"])(.-)%1", Some("'|hi")),
+            ("aXa bYc", "(%a)%u%1", Some("a")),
+        ]);
+    }
+
+    #[test]
+    fn malformed_patterns() {
+        let cases = [
+            ("%", "malformed pattern (ends with '%')"),
+            ("[a", "malformed pattern (missing ']')"),
+            ("(a", "unfinished capture"),
+            ("a)", "invalid pattern capture"),
+            ("%b", "malformed pattern (missing arguments to '%b')"),
+            ("%fa", "missing '[' after '%f' in pattern"),
+            ("%1", "invalid capture index %1"),
+        ];
+        for (pattern, message) in cases {
+            assert_eq!(
+                find(b"abc", pattern.as_bytes(), 0),
+                Err(message.to_string()),
+                "{pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn gmatch_and_gsub() {
+        let words = gmatch(b"one two  three", b"%a+")
+            .map(|found| found.unwrap().range)
+            .collect::<Vec<_>>();
+        assert_eq!(words, [0..3, 4..7, 9..14]);
+
+        let (out, n) = gsub(b"abc", b"%w", Replacement::Text(b"%0%0"), Some(2)).unwrap();
+        assert_eq!((out.as_slice(), n), (&b"aabbc"[..], 2));
+
+        let (out, n) = gsub(b"abc", b"", Replacement::Text(b"-"), None).unwrap();
+        assert_eq!((out.as_slice(), n), (&b"-a-b-c-"[..], 4));
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use lua_compiler::snippet::LineIndex;
+use lua_compiler::{format_error, log_success, resolve};
+
+use crate::{artifact, cli, golden, plural, source, ExitCode};
+
+// `source::read` drops it, so it has to be put back when the file is rewritten.
+const BYTE_ORDER_MARK: &str = "\u{feff}";
+
+/// Renames the variable at the 1-based line and column of the file at `path`, or prints the
+/// change as a patch if `options.dry_run` is set.
+pub fn run(options: &cli::Options, path: &Path, at: (usize, usize), new_name: &str) -> ExitCode {
+    if path == Path::new("-") {
+        eprint!(
+            "{}",
+            format_error!("rename rewrites the file, it can't read it from stdin.\n")
+        );
+        return ExitCode::UsageError;
+    }
+
+    let code = match source::read(path) {
+        Ok(code) => code,
+        Err(e) => {
+            eprint!("{}", format_error!("{e}.\n"));
+            return ExitCode::UsageError;
+        }
+    };
+
+    let file = path.display().to_string();
+    let (line, column) = at;
+    let Some(offset) = offset(&code, line, column) else {
+        eprint!(
+            "{}",
+            format_error!("{file} has no line {line} with a column {column}.\n")
+        );
+        return ExitCode::UsageError;
+    };
+
+    let rename = match resolve::rename(&code, offset, new_name) {
+        Ok(rename) => rename,
+        Err(e) => {
+            eprint!(
+                "{}",
+                format_error!("can't rename at {file}:{line}:{column}, {e}.\n")
+            );
+            return ExitCode::UsageError;
+        }
+    };
+
+    if options.dry_run {
+        print!(
+            "{}",
+            golden::unified_diff(&file, &file, &code, &rename.source)
+        );
+        return ExitCode::Success;
+    }
+
+    let had_mark = std::fs::read(path).is_ok_and(|b| b.starts_with(BYTE_ORDER_MARK.as_bytes()));
+    let contents = match had_mark {
+        true => format!("{BYTE_ORDER_MARK}{}", rename.source),
+        false => rename.source,
+    };
+    if let Err(e) = artifact::replace(path, &contents) {
+        eprint!("{}", format_error!("{e}.\n"));
+        return ExitCode::UsageError;
+    }
+
+    if !options.quiet {
+        log_success!(
+            "renamed {} of '{}' to '{new_name}'.\n",
+            plural(rename.spans.len(), "occurrence"),
+            rename.old_name
+        );
+    }
+    ExitCode::Success
+}
+
+/// Returns the byte offset of a 1-based line and column, the column counted in characters.
+fn offset(code: &str, line: usize, column: usize) -> Option<usize> {
+    let index = LineIndex::new(code);
+    if line > index.line_of(code.len()) {
+        return None;
+    }
+    let (start, end) = index.line_range(line, code);
+    // the column just after the last character is where the cursor is at the end of the line.
+    code[start..end]
+        .char_indices()
+        .map(|(i, _)| start + i)
+        .chain([end])
+        .nth(column - 1)
+}
@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use crate::lexer::{Span, SpannedToken, Token};
+
+// where a module is looked for, `?` is replaced by the module name with its dots turned into
+// directory separators. the paths are relative to the directory of the file being compiled.
+pub const SEARCH_PATH: &[&str] = &["?.lua", "?/init.lua"];
+
+/// A `require` of a module whose name is a constant string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Require {
+    pub name: String,
+    // covers the whole call, from `require` up to the closing parenthesis if there is one.
+    pub span: Span,
+}
+
+/// Finds every `require "name"`, `require 'name'` and `require("name")` in the token stream.
+///
+/// Requires of computed names can't be resolved ahead of time and are left to the runtime.
+pub fn find_requires(tokens: &[SpannedToken]) -> Vec<Require> {
+    let mut requires = Vec::new();
+
+    for (i, t) in tokens.iter().enumerate() {
+        if !matches!(&t.token, Token::NAME(name) if name == "require") {
+            continue;
+        }
+
+        // `lib.require "x"` and `obj:require "x"` are someone else's function.
+        if i > 0 && matches!(tokens[i - 1].token, Token::DOT | Token::COLON) {
+            continue;
+        }
+
+        let rest = &tokens[i + 1..];
+        let (name, end) = match rest {
+            [SpannedToken {
+                token: Token::STRING(name),
+                span,
+            }, ..] => (name, span.end),
+            [SpannedToken {
+                token: Token::LEFT_PAREN,
+                ..
+            }, SpannedToken {
+                token: Token::STRING(name),
+                ..
+            }, SpannedToken {
+                token: Token::RIGHT_PAREN,
+                span,
+            }, ..] => (name, span.end),
+            _ => continue,
+        };
+
+        requires.push(Require {
+            name: name.clone(),
+            span: Span { end, ..t.span },
+        });
+    }
+
+    requires
+}
+
+/// Returns the files a module would be loaded from, in the order they are tried.
+pub fn candidates(name: &str, base: &Path) -> Vec<PathBuf> {
+    let name = name.replace('.', "/");
+    SEARCH_PATH
+        .iter()
+        .map(|template| base.join(template.replace('?', &name)))
+        .collect()
+}
+
+/// Looks the module up on the search path, returning the first file that exists.
+pub fn resolve(name: &str, base: &Path) -> Option<PathBuf> {
+    candidates(name, base).into_iter().find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn names(source: &str) -> Vec<String> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        find_requires(&tokens).into_iter().map(|r| r.name).collect()
+    }
+
+    #[test]
+    fn requires_of_constant_names_are_found() {
+        assert_eq!(
+            names("local a = require \"a\"\nlocal b = require 'b.c'\nlocal d = require(\"d\")\n"),
+            ["a", "b.c", "d"]
+        );
+        // computed names, and the functions of other tables called require.
+        assert!(names("local x = require(name)\nlocal y = require(\"a\" .. b)\n").is_empty());
+        assert!(names("lib.require \"a\"\nobj:require \"b\"\n").is_empty());
+    }
+
+    #[test]
+    fn the_span_covers_the_whole_call() {
+        let source = "local a = require(\"a\")\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let r = &find_requires(&tokens)[0];
+        assert_eq!(&source[r.span.start..r.span.end], "require(\"a\")");
+    }
+
+    #[test]
+    fn modules_are_looked_for_on_the_search_path() {
+        let base = Path::new("project");
+        assert_eq!(
+            candidates("lib.utils", base),
+            [base.join("lib/utils.lua"), base.join("lib/utils/init.lua")]
+        );
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::snippet::LineIndex;
+
+/// What made a name a variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingKind {
+    Local,
+    Parameter,
+    LoopVariable,
+    // the parameter a method gets without declaring it, e.g. in `function t:m()`.
+    SelfParameter,
+    // a name that isn't declared anywhere in the source.
+    Global,
+    // a name that isn't declared but has a local `_ENV` in scope, which makes it a field of
+    // that table rather than a global.
+    EnvironmentField,
+}
+
+/// A variable of the source, along with every place it's named.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+    // where the name is declared, globals and `self` don't have a declaration.
+    pub declaration: Option<Span>,
+    // every other occurrence of the name that refers to this variable, in source order.
+    pub references: Vec<Span>,
+    // the declaration of the `_ENV` an environment field is in, None for the other kinds.
+    pub environment: Option<Span>,
+}
+
+impl Binding {
+    /// Returns where the variable is named, its declaration first.
+    pub fn occurrences(&self) -> impl Iterator<Item = Span> + '_ {
+        self.declaration
+            .into_iter()
+            .chain(self.references.iter().copied())
+    }
+}
+
+/// Finds what every name of the source refers to, following Lua's scoping rules.
+///
+/// This works on tokens so it also resolves a source that doesn't parse. Fields, like the `b`
+/// of `a.b` or of `{b = 1}`, aren't variables and are left out. Every global of the same name
+/// is the same variable.
+///
+/// Globals are fields of `_ENV` as of Lua 5.2, so where a local named `_ENV` is in scope, an
+/// undeclared name is a field of that table instead. The ones of the same name through the
+/// same `_ENV` are the same variable, apart from the globals.
+pub fn resolve(tokens: &[SpannedToken]) -> Vec<Binding> {
+    let mut resolver = Resolver {
+        tokens,
+        bindings: Vec::new(),
+        globals: HashMap::new(),
+        fields: HashMap::new(),
+        scopes: vec![Vec::new()],
+        brackets: Vec::new(),
+        pending: Vec::new(),
+        loops: Vec::new(),
+        parameters_at: None,
+        declared: HashSet::new(),
+    };
+
+    for i in 0..tokens.len() {
+        resolver.step(i);
+    }
+
+    resolver.bindings
+}
+
+/// What to do once the expression a statement ends with is over.
+enum Action {
+    // bring the locals of `local a, b = ...` into scope, they aren't visible in their values.
+    Declare(Vec<usize>),
+    // close the block of a `repeat`, which lasts until the end of its `until` condition.
+    Close,
+}
+
+struct Pending {
+    // the token the expression starts after.
+    after: usize,
+    scopes: usize,
+    brackets: usize,
+    action: Action,
+}
+
+struct Resolver<'a> {
+    tokens: &'a [SpannedToken],
+    bindings: Vec<Binding>,
+    globals: HashMap<String, usize>,
+    // the environment fields by the binding of their `_ENV` and their name.
+    fields: HashMap<(usize, String), usize>,
+    // the bindings in scope in every block around the current token, innermost last.
+    scopes: Vec<Vec<usize>>,
+    // the brackets around the current token, innermost last.
+    brackets: Vec<Token>,
+    pending: Vec<Pending>,
+    // the variables of the `for` loops whose `do` hasn't been reached, with how many blocks
+    // deep they are.
+    loops: Vec<(usize, Vec<usize>)>,
+    // the index of the `(` the parameters of the next function start at, and whether it has
+    // an implicit `self`.
+    parameters_at: Option<(usize, bool)>,
+    // the names that were already handled as declarations.
+    declared: HashSet<usize>,
+}
+
+impl Resolver<'_> {
+    fn step(&mut self, i: usize) {
+        while let Some(pending) = self.pending.last() {
+            let over = i > pending.after
+                && (self.scopes.len() < pending.scopes
+                    || self.brackets.len() < pending.brackets
+                    || (self.scopes.len() == pending.scopes
+                        && self.brackets.len() == pending.brackets
+                        && self.starts_statement(i)));
+            if !over {
+                break;
+            }
+            match self.pending.pop().map(|p| p.action) {
+                Some(Action::Declare(locals)) => self.scope().extend(locals),
+                Some(Action::Close) => self.close(),
+                None => {}
+            }
+        }
+
+        if self.parameters_at.is_some_and(|(at, _)| at == i) {
+            self.parameters(i);
+        }
+
+        let token = &self.tokens[i].token;
+        match token {
+            Token::NAME(name) if !self.declared.contains(&i) && self.is_variable(i) => {
+                let name = name.clone();
+                self.reference(name, self.tokens[i].span);
+            }
+            Token::LOCAL => self.local(i),
+            Token::FUNCTION => self.function(i),
+            Token::FOR => self.for_loop(i),
+            Token::DO => {
+                // the variables of a `for` are only in scope in its body.
+                let locals = match self.loops.last() {
+                    Some((scopes, _)) if *scopes == self.scopes.len() => {
+                        self.loops.pop().map(|(_, l)| l).unwrap_or_default()
+                    }
+                    _ => Vec::new(),
+                };
+                self.scopes.push(locals);
+            }
+            Token::THEN | Token::REPEAT => self.scopes.push(Vec::new()),
+            Token::ELSE => {
+                self.close();
+                self.scopes.push(Vec::new());
+            }
+            Token::ELSEIF | Token::END => self.close(),
+            Token::UNTIL => self.pending.push(Pending {
+                after: i,
+                scopes: self.scopes.len(),
+                brackets: self.brackets.len(),
+                action: Action::Close,
+            }),
+            Token::LEFT_PAREN | Token::LEFT_BRACKET | Token::LEFT_BRACE => {
+                self.brackets.push(token.clone())
+            }
+            Token::RIGHT_PAREN | Token::RIGHT_BRACKET | Token::RIGHT_BRACE => {
+                self.brackets.pop();
+            }
+            _ => {}
+        }
+    }
+
+    fn scope(&mut self) -> &mut Vec<usize> {
+        // the main chunk is never closed, even by an `end` too many.
+        self.scopes
+            .last_mut()
+            .expect("the main chunk is always in scope")
+    }
+
+    fn close(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    fn token(&self, i: usize) -> Option<&Token> {
+        self.tokens.get(i).map(|t| &t.token)
+    }
+
+    /// Returns whether the name at `i` is a variable rather than the name of a field.
+    fn is_variable(&self, i: usize) -> bool {
+        let previous = i.checked_sub(1).and_then(|p| self.token(p));
+        if matches!(previous, Some(Token::DOT | Token::COLON)) {
+            return false;
+        }
+
+        // `{name = value}` sets a field, where `{[name] = value}` would use the variable.
+        let is_key = self.brackets.last() == Some(&Token::LEFT_BRACE)
+            && matches!(
+                previous,
+                Some(Token::LEFT_BRACE | Token::COMMA | Token::SEMICOLON)
+            )
+            && self.token(i + 1) == Some(&Token::ASSIGN);
+        !is_key
+    }
+
+    /// Returns whether a new statement starts at `i`, ending the expression before it.
+    fn starts_statement(&self, i: usize) -> bool {
+        // a name or a literal right after a complete expression can only start a new statement.
+        let after_expression = matches!(
+            i.checked_sub(1).and_then(|p| self.token(p)),
+            Some(
+                Token::NAME(_)
+                    | Token::NUMBER(_)
+                    | Token::STRING(_)
+                    | Token::NIL
+                    | Token::TRUE
+                    | Token::FALSE
+                    | Token::DOTS
+                    | Token::RIGHT_PAREN
+                    | Token::RIGHT_BRACKET
+                    | Token::RIGHT_BRACE
+                    | Token::END
+            )
+        );
+
+        match &self.tokens[i].token {
+            Token::LOCAL
+            | Token::RETURN
+            | Token::BREAK
+            | Token::DO
+            | Token::WHILE
+            | Token::REPEAT
+            | Token::IF
+            | Token::FOR
+            | Token::END
+            | Token::UNTIL
+            | Token::ELSE
+            | Token::ELSEIF
+            | Token::THEN
+            | Token::SEMICOLON => true,
+            Token::NAME(_)
+            | Token::FUNCTION
+            | Token::NUMBER(_)
+            | Token::NIL
+            | Token::TRUE
+            | Token::FALSE
+            | Token::DOTS
+            | Token::NOT
+            | Token::HASHTAG => after_expression,
+            _ => false,
+        }
+    }
+
+    fn reference(&mut self, name: String, span: Span) {
+        let local = |name: &str| {
+            self.scopes
+                .iter()
+                .rev()
+                .flat_map(|scope| scope.iter().rev())
+                .find(|&&b| self.bindings[b].name == name)
+                .copied()
+        };
+
+        // `_ENV` is only looked for when it's needed, a name that's rarely declared is looked
+        // for through every scope.
+        let binding = match local(&name).ok_or_else(|| local("_ENV")) {
+            Ok(b) => b,
+            Err(Some(environment)) => match self.fields.get(&(environment, name.clone())) {
+                Some(&b) => b,
+                None => {
+                    let declaration = self.bindings[environment].declaration;
+                    let b = self.declare(name.clone(), BindingKind::EnvironmentField, None);
+                    self.bindings[b].environment = declaration;
+                    self.fields.insert((environment, name), b);
+                    b
+                }
+            },
+            Err(None) => match self.globals.get(&name) {
+                Some(&b) => b,
+                None => {
+                    let b = self.declare(name.clone(), BindingKind::Global, None);
+                    self.globals.insert(name, b);
+                    b
+                }
+            },
+        };
+        self.bindings[binding].references.push(span);
+    }
+
+    fn declare(&mut self, name: String, kind: BindingKind, declaration: Option<Span>) -> usize {
+        self.bindings.push(Binding {
+            name,
+            kind,
+            declaration,
+            references: Vec::new(),
+            environment: None,
+        });
+        self.bindings.len() - 1
+    }
+
+    /// Declares the names of the list starting at `i`, e.g. `a, b` in `local a, b = 1, 2`,
+    /// returning their bindings and the index of the token after the list.
+    fn names(&mut self, mut i: usize, kind: BindingKind) -> (Vec<usize>, usize) {
+        let mut bindings = Vec::new();
+
+        while let Some(SpannedToken {
+            token: Token::NAME(name),
+            span,
+        }) = self.tokens.get(i)
+        {
+            bindings.push(self.declare(name.clone(), kind, Some(*span)));
+            self.declared.insert(i);
+            i += 1;
+            if self.token(i) != Some(&Token::COMMA) {
+                break;
+            }
+            i += 1;
+        }
+
+        (bindings, i)
+    }
+
+    fn local(&mut self, i: usize) {
+        // a local function can call itself, so its name is in scope in its body already.
+        if self.token(i + 1) == Some(&Token::FUNCTION) {
+            let (locals, _) = self.names(i + 2, BindingKind::Local);
+            self.scope().extend(locals.into_iter().take(1));
+            return;
+        }
+
+        let (locals, next) = self.names(i + 1, BindingKind::Local);
+        match self.token(next) {
+            Some(Token::ASSIGN) => self.pending.push(Pending {
+                after: next,
+                scopes: self.scopes.len(),
+                brackets: self.brackets.len(),
+                action: Action::Declare(locals),
+            }),
+            _ => self.scope().extend(locals),
+        }
+    }
+
+    fn function(&mut self, i: usize) {
+        // the name of a function statement, `a.b:c`, is read as usual. only its parameters
+        // and whether it's a method matter here.
+        let mut j = i + 1;
+        let mut is_method = false;
+        if let Some(Token::NAME(_)) = self.token(j) {
+            j += 1;
+            while let (Some(Token::DOT | Token::COLON), Some(Token::NAME(_))) =
+                (self.token(j), self.token(j + 1))
+            {
+                is_method |= self.token(j) == Some(&Token::COLON);
+                j += 2;
+            }
+        }
+
+        if self.token(j) == Some(&Token::LEFT_PAREN) {
+            self.parameters_at = Some((j, is_method));
+        }
+    }
+
+    /// Opens the block of a function at the `(` of its parameters, with them in scope.
+    fn parameters(&mut self, i: usize) {
+        let is_method = self.parameters_at.take().is_some_and(|(_, m)| m);
+        let mut parameters = Vec::new();
+        if is_method {
+            parameters.push(self.declare("self".to_string(), BindingKind::SelfParameter, None));
+        }
+        parameters.extend(self.names(i + 1, BindingKind::Parameter).0);
+        self.scopes.push(parameters);
+    }
+
+    fn for_loop(&mut self, i: usize) {
+        // the expressions up to the `do` are outside the loop, as is what they refer to.
+        let (variables, _) = self.names(i + 1, BindingKind::LoopVariable);
+        self.loops.push((self.scopes.len(), variables));
+    }
+}
+
+/// What renaming a variable changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub old_name: String,
+    // every occurrence of the variable, in source order.
+    pub spans: Vec<Span>,
+    // the source with the occurrences replaced by the new name.
+    pub source: String,
+}
+
+/// Renames the variable named at the byte offset, refusing what would change the meaning of
+/// the source.
+///
+/// The error is a message describing why the variable can't be renamed.
+pub fn rename(source: &str, offset: usize, new_name: &str) -> Result<Rename, String> {
+    let Some(tokens) = crate::tokenize(source).0 else {
+        return Err("the source has to lex to be renamed in".to_string());
+    };
+
+    let bindings = resolve(&tokens);
+    let binding = bindings
+        .iter()
+        .position(|b| {
+            b.occurrences()
+                .any(|s| s.start <= offset && offset <= s.end)
+        })
+        .ok_or("there is no variable there")?;
+    let old_name = bindings[binding].name.clone();
+
+    match crate::tokenize(new_name).0.as_deref() {
+        Some(
+            [SpannedToken {
+                token: Token::NAME(name),
+                ..
+            }],
+        ) if name == new_name => {}
+        Some([_]) if new_name.chars().all(|c| c.is_ascii_alphabetic()) => {
+            return Err(format!("'{new_name}' is a keyword"))
+        }
+        _ => return Err(format!("'{new_name}' is not a valid name")),
+    }
+
+    if bindings[binding].kind == BindingKind::SelfParameter {
+        return Err("'self' is the implicit parameter of a method, it isn't declared".to_string());
+    }
+    if old_name == new_name {
+        return Err(format!("the variable is already called '{new_name}'"));
+    }
+
+    let mut spans = bindings[binding].occurrences().collect::<Vec<_>>();
+    spans.sort_by_key(|s| s.start);
+
+    let mut renamed = String::with_capacity(source.len());
+    let mut copied = 0;
+    for span in &spans {
+        renamed.push_str(&source[copied..span.start]);
+        renamed.push_str(new_name);
+        copied = span.end;
+    }
+    renamed.push_str(&source[copied..]);
+
+    // every occurrence has to refer to the same variables as before, the others included.
+    let moved = |offset: usize| {
+        let before = spans.iter().filter(|s| s.start < offset).count();
+        offset - before * old_name.len() + before * new_name.len()
+    };
+    // a variable is known by where it's first named, which has to be the same after.
+    let firsts = |bindings: &[Binding], moved: &dyn Fn(usize) -> usize| {
+        let mut firsts = HashMap::new();
+        for binding in bindings {
+            let offsets = binding
+                .occurrences()
+                .map(|s| moved(s.start))
+                .collect::<Vec<_>>();
+            let first = offsets.iter().copied().min().unwrap_or_default();
+            firsts.extend(offsets.into_iter().map(|o| (o, first)));
+        }
+        firsts
+    };
+    let before = firsts(&bindings, &moved);
+    let after = crate::tokenize(&renamed)
+        .0
+        .map(|tokens| firsts(&resolve(&tokens), &|offset| offset))
+        .unwrap_or_default();
+
+    let mut offsets = before.keys().copied().collect::<Vec<_>>();
+    offsets.sort();
+    if let Some(offset) = offsets.into_iter().find(|o| before.get(o) != after.get(o)) {
+        let line = LineIndex::new(&renamed).line_of(offset);
+        let renamed_here = spans.iter().any(|s| moved(s.start) == offset);
+        return Err(match renamed_here {
+            true => format!(
+                "'{new_name}' already refers to another variable where '{old_name}' is used on line {line}"
+            ),
+            false => format!(
+                "the '{new_name}' on line {line} would refer to the renamed variable instead"
+            ),
+        });
+    }
+
+    Ok(Rename {
+        old_name,
+        spans,
+        source: renamed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // renames the variable at the first `at` in the source.
+    fn rename_at(source: &str, at: &str, new_name: &str) -> Result<String, String> {
+        let offset = source.find(at).unwrap();
+        rename(source, offset, new_name).map(|r| r.source)
+    }
+
+    #[test]
+    fn only_the_variable_at_the_offset_is_renamed() {
+        let source = "\
+local x = 1 -- x is one
+print(\"x\", x)
+do
+  local x = 2
+  print(x)
+end
+print(x, t.x, {x = x})
+";
+        assert_eq!(
+            rename_at(source, "x = 1", "count"),
+            Ok("\
+local count = 1 -- x is one
+print(\"x\", count)
+do
+  local x = 2
+  print(x)
+end
+print(count, t.x, {x = count})
+"
+            .to_string())
+        );
+
+        // the shadowing one has only its own block.
+        let inner = source.find("x = 2").unwrap();
+        let renamed = rename(source, inner, "y").unwrap();
+        assert_eq!(renamed.old_name, "x");
+        assert_eq!(renamed.spans.len(), 2);
+        assert!(renamed.source.contains("  local y = 2\n  print(y)\n"));
+    }
+
+    #[test]
+    fn parameters_loop_variables_and_globals_are_renamed() {
+        assert_eq!(
+            rename_at("local function f(a, b) return a + b end\n", "a,", "first"),
+            Ok("local function f(first, b) return first + b end\n".to_string())
+        );
+        assert_eq!(
+            rename_at("for i = 1, 3 do print(i) end\nprint(i)\n", "i =", "n"),
+            Ok("for n = 1, 3 do print(n) end\nprint(i)\n".to_string())
+        );
+        // every global of the same name is the same variable.
+        assert_eq!(
+            rename_at(
+                "function g() end\ng()\nlocal function f() g() end\n",
+                "g()\n",
+                "h"
+            ),
+            Ok("function h() end\nh()\nlocal function f() h() end\n".to_string())
+        );
+    }
+
+    #[test]
+    fn self_and_bad_names_are_refused() {
+        let method = "function t:m() return self.x end\n";
+        assert_eq!(
+            rename_at(method, "self", "this"),
+            Err("'self' is the implicit parameter of a method, it isn't declared".to_string())
+        );
+
+        let source = "local a = 1\nprint(a)\n";
+        assert_eq!(
+            rename_at(source, "a =", "end"),
+            Err("'end' is a keyword".to_string())
+        );
+        assert_eq!(
+            rename_at(source, "a =", "2x"),
+            Err("'2x' is not a valid name".to_string())
+        );
+        assert_eq!(
+            rename_at(source, "a =", "a"),
+            Err("the variable is already called 'a'".to_string())
+        );
+        assert_eq!(
+            rename_at(source, "print", "x.y"),
+            Err("'x.y' is not a valid name".to_string())
+        );
+        assert_eq!(
+            rename(source, 5, "b"),
+            Err("there is no variable there".to_string())
+        );
+    }
+
+    #[test]
+    fn a_name_that_would_capture_or_be_captured_is_refused() {
+        // the renamed variable would be shadowed by the inner `b`.
+        let source = "local a = 1\ndo\n  local b = 2\n  print(a, b)\nend\n";
+        assert_eq!(
+            rename_at(source, "a =", "b"),
+            Err("'b' already refers to another variable where 'a' is used on line 4".to_string())
+        );
+
+        // the global `c` would refer to the renamed local.
+        let source = "local a = 1\nprint(a, c)\n";
+        assert_eq!(
+            rename_at(source, "a =", "c"),
+            Err("the 'c' on line 2 would refer to the renamed variable instead".to_string())
+        );
+    }
+
+    // the bindings of a name, with the lines it's named on and the line of its `_ENV`.
+    fn bindings_of(source: &str, name: &str) -> Vec<(BindingKind, Vec<usize>, Option<usize>)> {
+        resolve(&Lexer::new(source).tokenize().unwrap())
+            .into_iter()
+            .filter(|b| b.name == name)
+            .map(|b| {
+                let lines = b.occurrences().map(|s| s.line).collect();
+                (b.kind, lines, b.environment.map(|s| s.line))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_local_env_sandboxes_the_globals_of_its_block() {
+        let source = "\
+x = 1
+do
+  local _ENV = {print = print, x = x}
+  x = 2
+  print(x)
+end
+print(x)
+";
+        assert_eq!(
+            bindings_of(source, "x"),
+            [
+                // the initializer still reads the outer environment.
+                (BindingKind::Global, vec![1, 3, 7], None),
+                (BindingKind::EnvironmentField, vec![4, 5], Some(3)),
+            ]
+        );
+        assert_eq!(
+            bindings_of(source, "print"),
+            [
+                (BindingKind::Global, vec![3, 7], None),
+                (BindingKind::EnvironmentField, vec![5], Some(3)),
+            ]
+        );
+        // a local is still a local inside the sandbox.
+        assert_eq!(
+            bindings_of("local y\ndo local _ENV = {} y = 1 end\n", "y"),
+            [(BindingKind::Local, vec![1, 2], None)]
+        );
+    }
+
+    #[test]
+    fn renaming_inside_a_sandbox_leaves_the_globals() {
+        let source = "x = 1\ndo local _ENV = {} x = 2 end\nprint(x)\n";
+        assert_eq!(
+            rename_at(source, "x = 2", "y"),
+            Ok("x = 1\ndo local _ENV = {} y = 2 end\nprint(x)\n".to_string())
+        );
+        assert_eq!(
+            rename_at(source, "x = 1", "y"),
+            Ok("y = 1\ndo local _ENV = {} x = 2 end\nprint(y)\n".to_string())
+        );
+    }
+}
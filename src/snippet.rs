@@ -0,0 +1,451 @@
+use crate::diagnostic::Label;
+use crate::lexer::Span;
+use crate::term_color::*;
+
+// lines longer than this many columns are cut down to a window around the span.
+const MAX_WIDTH: usize = 100;
+
+// how many columns a tab is expanded to.
+const TAB_WIDTH: usize = 4;
+
+/// The style of the line numbers and the `|` next to them.
+pub const GUTTER: Style = Style::new().fg(Color::Grey).dim();
+
+// the style of the carets and squiggles under the offending source.
+const UNDERLINE: Style = Style::new().fg(Color::Red).bold();
+
+// the style of the dashes under the other places a diagnostic points at, and their labels.
+const LABEL: Style = Style::new().fg(Color::Blue).bold();
+
+/// Maps byte offsets in a source text to lines.
+pub struct LineIndex {
+    // the byte offset at which every line starts.
+    line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+
+        Self {
+            line_starts,
+            len: source.len(),
+        }
+    }
+
+    /// Returns the 1-based line that contains the byte offset.
+    pub fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    /// Returns the 1-based line and column (counted in characters) of a byte offset.
+    pub fn position(&self, offset: usize, source: &str) -> (usize, usize) {
+        let offset = offset.min(self.len);
+        let line = self.line_of(offset);
+        let start = self.line_starts[line - 1];
+        (line, source[start..offset].chars().count() + 1)
+    }
+
+    /// Returns the byte range of a 1-based line, without its line break.
+    pub fn line_range(&self, line: usize, source: &str) -> (usize, usize) {
+        let start = self.line_starts[line.clamp(1, self.line_starts.len()) - 1];
+        let mut end = self
+            .line_starts
+            .get(line)
+            .map(|next| next - 1)
+            .unwrap_or(self.len);
+
+        // don't show the carriage return of a CRLF line break.
+        if source[start..end].ends_with('\r') {
+            end -= 1;
+        }
+
+        (start, end)
+    }
+}
+
+/// Expands tabs so the text lines up with the underline.
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// Returns the display width of a piece of text once tabs have been expanded.
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+/// Renders a single line with the byte range `from..to` of it underlined.
+fn render_line(
+    text: &str,
+    from: usize,
+    to: usize,
+    first: bool,
+    gutter: usize,
+    number: usize,
+    caps: OutputCaps,
+) -> String {
+    let mut before = expand_tabs(&text[..from]);
+    let mut marked = expand_tabs(&text[from..to]);
+    let mut after = expand_tabs(&text[to..]);
+
+    let ellipsis = if caps.unicode { "…" } else { "..." };
+
+    // cut very long lines down to a window around the marked text.
+    if width(&before) + width(&marked) + width(&after) > MAX_WIDTH {
+        let keep = MAX_WIDTH / 4;
+
+        if before.chars().count() > keep {
+            let skip = before.chars().count() - keep;
+            before = format!(
+                "{ellipsis}{}",
+                before.chars().skip(skip).collect::<String>()
+            );
+        }
+
+        if marked.chars().count() > MAX_WIDTH / 2 {
+            marked = format!(
+                "{}{ellipsis}",
+                marked.chars().take(MAX_WIDTH / 2).collect::<String>()
+            );
+        }
+
+        if after.chars().count() > keep {
+            after = format!("{}{ellipsis}", after.chars().take(keep).collect::<String>());
+        }
+    }
+
+    // the first line of a span starts with a caret, the rest only get the squiggle.
+    let underline = match marked.chars().count() {
+        0 if first => "^".to_string(),
+        0 => String::new(),
+        n if first => format!("^{}", "~".repeat(n - 1)),
+        n => "~".repeat(n),
+    };
+
+    let pipe = GUTTER.apply_with("|", caps);
+    format!(
+        "{} {pipe} {before}{marked}{after}\n{:gutter$} {pipe} {:pad$}{}\n",
+        GUTTER.apply_with(&format!("{number:>gutter$}"), caps),
+        "",
+        "",
+        UNDERLINE.apply_with(&underline, caps),
+        pad = before.chars().count(),
+    )
+}
+
+/// Renders the row under a line of source that marks the labeled spans on it with dashes,
+/// followed by their messages.
+fn render_labels(
+    text: &str,
+    line_start: usize,
+    labels: &[&Label],
+    gutter: usize,
+    caps: OutputCaps,
+) -> String {
+    let mut marks = String::new();
+    let mut column = 0;
+
+    for label in labels {
+        let from = label.span.start.clamp(line_start, line_start + text.len()) - line_start;
+        let to = label
+            .span
+            .end
+            .clamp(line_start + from, line_start + text.len())
+            - line_start;
+
+        let pad = width(&text[..from]).saturating_sub(column);
+        let dashes = width(&text[from..to]).max(1);
+        marks.push_str(&" ".repeat(pad));
+        marks.push_str(&"-".repeat(dashes));
+        column += pad + dashes;
+    }
+
+    // the labels of one line share the row, so their messages are put together.
+    let messages = labels
+        .iter()
+        .map(|l| l.message.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "{:gutter$} {} {} {}\n",
+        "",
+        GUTTER.apply_with("|", caps),
+        LABEL.apply_with(&marks, caps),
+        LABEL.apply_with(&messages, caps),
+    )
+}
+
+/// Renders the lines covered by `span` with a gutter of line numbers and the span underlined,
+/// along with the lines the labels point at.
+pub fn render_snippet(
+    span: Span,
+    labels: &[Label],
+    index: &LineIndex,
+    source: &str,
+    caps: OutputCaps,
+) -> String {
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+
+    let first_line = index.line_of(start);
+    // an exclusive end right after a line break still belongs to the line before it.
+    let last_line = index.line_of(end.saturating_sub(1).max(start));
+
+    let label_line = |label: &Label| index.line_of(label.span.start.min(source.len()));
+
+    // every line that gets shown, in order.
+    let mut lines = (first_line..=last_line)
+        .chain(labels.iter().map(label_line))
+        .collect::<Vec<_>>();
+    lines.sort();
+    lines.dedup();
+
+    let gutter = lines.last().unwrap_or(&last_line).to_string().len();
+    let mut out = format!("{:gutter$} {}\n", "", GUTTER.apply_with("|", caps));
+
+    for (i, &line) in lines.iter().enumerate() {
+        // a gap between lines is skipped over.
+        if i > 0 && line > lines[i - 1] + 1 {
+            out.push_str(&format!("{}\n", GUTTER.apply_with("...", caps)));
+        }
+
+        let (line_start, line_end) = index.line_range(line, source);
+        let text = &source[line_start..line_end];
+
+        if (first_line..=last_line).contains(&line) {
+            let from = start.clamp(line_start, line_end) - line_start;
+            let to = end.clamp(line_start, line_end) - line_start;
+
+            out.push_str(&render_line(
+                text,
+                from,
+                to,
+                line == first_line,
+                gutter,
+                line,
+                caps,
+            ));
+        } else {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                GUTTER.apply_with(&format!("{line:>gutter$}"), caps),
+                GUTTER.apply_with("|", caps),
+                expand_tabs(text)
+            ));
+        }
+
+        let mut on_line = labels
+            .iter()
+            .filter(|l| label_line(l) == line)
+            .collect::<Vec<_>>();
+        on_line.sort_by_key(|l| l.span.start);
+
+        if !on_line.is_empty() {
+            out.push_str(&render_labels(text, line_start, &on_line, gutter, caps));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: OutputCaps = OutputCaps {
+        color: false,
+        unicode: true,
+    };
+
+    fn span(range: std::ops::Range<usize>) -> Span {
+        Span {
+            start: range.start,
+            end: range.end,
+            ..Span::default()
+        }
+    }
+
+    // renders the snippet of the byte range of the source, with labels on the other ranges.
+    fn snippet(
+        source: &str,
+        marked: std::ops::Range<usize>,
+        labels: &[(std::ops::Range<usize>, &str)],
+        caps: OutputCaps,
+    ) -> String {
+        let labels = labels
+            .iter()
+            .map(|(range, message)| Label {
+                span: span(range.clone()),
+                message: message.to_string(),
+            })
+            .collect::<Vec<_>>();
+        render_snippet(span(marked), &labels, &LineIndex::new(source), source, caps)
+    }
+
+    #[test]
+    fn a_long_line_is_cut_down_around_the_span() {
+        let source = format!("local x = {} + y + {}", "a".repeat(80), "b".repeat(80));
+        let y = source.find('y').unwrap();
+
+        let shown = snippet(&source, y..y + 1, &[], PLAIN);
+        let before = format!("…{} + ", "a".repeat(22));
+        let after = format!(" + {}…", "b".repeat(22));
+        // the caret is under the `y`, past the `…` and the columns kept before it.
+        assert_eq!(
+            shown,
+            format!("  |\n1 | {before}y{after}\n  | {:26}^\n", "")
+        );
+
+        // a terminal that can't show `…` gets three dots.
+        let ascii = OutputCaps {
+            unicode: false,
+            ..PLAIN
+        };
+        let shown = snippet(&source, y..y + 1, &[], ascii);
+        assert!(
+            shown.contains(&format!("...{} + y + ", "a".repeat(22))),
+            "{shown}"
+        );
+        assert!(
+            shown.contains(&format!("{}...\n", "b".repeat(22))),
+            "{shown}"
+        );
+        assert!(shown.is_ascii());
+    }
+
+    #[test]
+    fn tabs_are_expanded_under_the_caret_too() {
+        let source = "\tif\tx then";
+        let x = source.find('x').unwrap();
+        let shown = snippet(source, x..x + 1, &[], PLAIN);
+        assert_eq!(shown, "  |\n1 |     if    x then\n  |           ^\n");
+    }
+
+    #[test]
+    fn the_carriage_return_of_a_crlf_line_isnt_shown() {
+        let source = "local x = 1\r\nlocal y = = 2\r\n";
+        let at = source.find("= 2").unwrap();
+        let shown = snippet(source, at..at + 1, &[], PLAIN);
+        assert_eq!(shown, "  |\n2 | local y = = 2\n  |           ^\n");
+        assert!(!shown.contains('\r'));
+    }
+
+    #[test]
+    fn a_span_over_several_lines_underlines_each() {
+        let source = "local t = {\n  1,\n  2\n}";
+        let open = source.find('{').unwrap();
+        let shown = snippet(source, open..source.len(), &[], PLAIN);
+        assert_eq!(
+            shown,
+            "  |\n\
+             1 | local t = {\n  |           ^\n\
+             2 |   1,\n  | ~~~~\n\
+             3 |   2\n  | ~~~\n\
+             4 | }\n  | ~\n"
+        );
+    }
+
+    #[test]
+    fn labels_on_other_lines_are_shown_with_a_gap_between() {
+        let source = "local x = 1\nlocal y = 2\nlocal z = 3\nlocal x = 4\n";
+        let second = source.rfind('x').unwrap();
+        let first = source.find('x').unwrap();
+        let shown = snippet(
+            source,
+            second..second + 1,
+            &[(first..first + 1, "first declared here")],
+            PLAIN,
+        );
+        assert_eq!(
+            shown,
+            "  |\n\
+             1 | local x = 1\n  |       - first declared here\n\
+             ...\n\
+             4 | local x = 4\n  |       ^\n"
+        );
+
+        // the labels of one line share its row, and a line right after isn't a gap.
+        let y = source.find('y').unwrap();
+        let shown = snippet(
+            source,
+            first..first + 1,
+            &[(y..y + 1, "here"), (y + 4..y + 5, "and here")],
+            PLAIN,
+        );
+        assert!(!shown.contains("..."), "{shown}");
+        assert!(
+            shown.ends_with("2 | local y = 2\n  |       -   - here, and here\n"),
+            "{shown}"
+        );
+    }
+
+    // compiles a broken source and renders the snippet of its first diagnostic.
+    fn first_error(source: &str) -> String {
+        let result = crate::Compiler::default().compile("main.lua", source);
+        let d = &result.diagnostics[0];
+        render_snippet(d.span, &[], &LineIndex::new(source), source, PLAIN)
+    }
+
+    #[test]
+    fn broken_inputs_render_their_offending_line() {
+        let cases = [
+            // lexer errors.
+            (
+                "local s = \"abc\n",
+                "  |\n1 | local s = \"abc\n  |           ^~~~\n",
+            ),
+            (
+                "local x = 1 @ 2\n",
+                "  |\n1 | local x = 1 @ 2\n  |             ^\n",
+            ),
+            (
+                "local s = [[abc\n",
+                "  |\n1 | local s = [[abc\n  |           ^~\n",
+            ),
+            // parser errors.
+            (
+                "local x = = 1\n",
+                "  |\n1 | local x = = 1\n  |           ^\n",
+            ),
+            ("if x then\n  y = 1\n", "  |\n1 | if x then\n  | ^~\n"),
+            // the gutter is as wide as the number of the line.
+            (
+                "\n\n\n\n\n\n\n\n\nlocal t = {1, 2\n",
+                "   |\n10 | local t = {1, 2\n   |           ^\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(first_error(source), expected, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn lines_and_columns_are_found_from_byte_offsets() {
+        let source = "ab\nçd\r\n\nx";
+        let index = LineIndex::new(source);
+        assert_eq!(index.line_of(0), 1);
+        assert_eq!(index.line_of(2), 1);
+        assert_eq!(index.line_of(3), 2);
+        assert_eq!(index.line_of(source.len()), 4);
+
+        // columns count characters, not bytes.
+        assert_eq!(index.position(source.find('d').unwrap(), source), (2, 2));
+        // an offset past the end is the end.
+        assert_eq!(index.position(100, source), (4, 2));
+
+        assert_eq!(index.line_range(1, source), (0, 2));
+        assert_eq!(
+            &source[index.line_range(2, source).0..index.line_range(2, source).1],
+            "çd"
+        );
+        assert_eq!(index.line_range(3, source), (8, 8));
+    }
+}
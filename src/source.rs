@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+// editors on windows like to start utf-8 files with one, it isn't part of the source.
+const BYTE_ORDER_MARK: char = '\u{feff}';
+
+// every file `read` was asked for since `take_read` was last called, see `watch.rs`.
+static READ: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Returns the files `read` was asked for since the last call, the ones it couldn't read too.
+pub fn take_read() -> BTreeSet<PathBuf> {
+    std::mem::take(&mut READ.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Reads the source from the file at `path`, or from stdin if the path is `-`.
+///
+/// The error is a message that says what went wrong in terms of the file, not the OS.
+pub fn read(path: &Path) -> Result<String, String> {
+    let code = if path == Path::new("-") {
+        let mut code = String::new();
+        std::io::stdin()
+            .read_to_string(&mut code)
+            .map_err(|e| describe_error(path, e))?;
+        code
+    } else {
+        READ.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path.to_path_buf());
+        std::fs::read_to_string(path).map_err(|e| describe_error(path, e))?
+    };
+
+    // strip it so the columns of the first line are right.
+    match code.strip_prefix(BYTE_ORDER_MARK) {
+        Some(rest) => Ok(rest.to_string()),
+        None => Ok(code),
+    }
+}
+
+/// Turns an io error into a message about the file it happened on.
+fn describe_error(path: &Path, e: std::io::Error) -> String {
+    let name = match path.to_str() {
+        Some("-") => "stdin".to_string(),
+        _ => format!("'{}'", path.display()),
+    };
+
+    match e.kind() {
+        ErrorKind::NotFound => match similar_file(path) {
+            Some(similar) => format!(
+                "{name} doesn't exist, maybe you meant '{}'",
+                similar.display()
+            ),
+            None => format!("{name} doesn't exist"),
+        },
+        ErrorKind::PermissionDenied => format!("{name} can't be read, permission denied"),
+        ErrorKind::IsADirectory => {
+            format!("{name} is a directory, pass it as the input to compile every file in it")
+        }
+        ErrorKind::InvalidData => format!("{name} isn't valid UTF-8"),
+        _ => format!("could not read {name}: {e}"),
+    }
+}
+
+/// Finds a file next to `path` whose name only differs by case or by its extension.
+fn similar_file(path: &Path) -> Option<PathBuf> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    let stem = path.file_stem()?.to_str()?.to_lowercase();
+
+    let mut entries = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    let lowercase = |s: Option<&std::ffi::OsStr>| s.and_then(|s| s.to_str()).map(str::to_lowercase);
+
+    // a difference in case is the likelier typo, so it's looked for first.
+    let same_name = entries
+        .iter()
+        .find(|p| lowercase(p.file_name()).as_deref() == Some(name.as_str()));
+    let same_stem = entries
+        .iter()
+        .find(|p| lowercase(p.file_stem()).as_deref() == Some(stem.as_str()));
+
+    let similar = same_name.or(same_stem)?;
+    Some(path.with_file_name(similar.file_name()?))
+}
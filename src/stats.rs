@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use lua_compiler::chunk::ChunkName;
+use lua_compiler::diagnostic::{DiagnosticSink, RenderingSink, SourceContext};
+use lua_compiler::format_error;
+use lua_compiler::loc;
+use lua_compiler::term_color::*;
+
+use crate::{cli, emit_artifact, source, walk, ExitCode, Output};
+
+/// Writes the line and token counts of the file or of every file in the directory at `path`,
+/// along with their total.
+pub fn emit(options: &cli::Options, path: &Path, out: &mut Output) -> ExitCode {
+    let files = match path.is_dir() {
+        true => match walk::collect_files(path, &options.include, &options.exclude) {
+            Ok(files) => files,
+            Err(e) => {
+                out.stderr.push_str(&format_error!(
+                    "could not read '{}': {e}.\n",
+                    path.display()
+                ));
+                return ExitCode::UsageError;
+            }
+        },
+        false => vec![path.to_path_buf()],
+    };
+
+    let compiler = lua_compiler::Compiler::from(options);
+    let mut counted = Vec::new();
+    let mut result = ExitCode::Success;
+
+    for file in &files {
+        let name = match file.to_str() {
+            Some("-") => ChunkName::stdin().to_string(),
+            _ if file == path => file.display().to_string(),
+            _ => walk::relative_name(file, path),
+        };
+
+        let code = match source::read(file) {
+            Ok(code) => code,
+            Err(e) => {
+                out.stderr.push_str(&format_error!("{e}.\n"));
+                result = result.max(ExitCode::UsageError);
+                continue;
+            }
+        };
+
+        // the comments are found between the tokens, so a file that doesn't lex can't be counted.
+        let (tokens, diagnostics) = compiler.tokenize(&code);
+        let Some(tokens) = tokens else {
+            let ctx = SourceContext::new(&name, &code);
+            let mut sink = RenderingSink::new(&ctx, options.diagnostics_format, output_caps());
+            for d in diagnostics {
+                sink.emit(d);
+            }
+            out.push_diagnostics(options.diagnostics_format, &sink.finish().0);
+            result = result.max(ExitCode::CompileError);
+            continue;
+        };
+
+        counted.push((name, loc::count(&code, &tokens)));
+    }
+
+    let artifact = match options.format {
+        cli::Format::Json => loc::to_json(&counted),
+        cli::Format::Text => loc::to_table(&counted),
+    };
+    result.max(emit_artifact(
+        options.output.as_deref(),
+        path,
+        &artifact,
+        out,
+    ))
+}
@@ -0,0 +1,502 @@
+use std::ops::Range;
+
+use crate::definitions::find_functions;
+use crate::json;
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::require::find_requires;
+use crate::snippet::LineIndex;
+
+/// A string literal of the source, as written and as the program sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLiteral {
+    // the value once the escapes are decoded.
+    pub value: String,
+    // the literal as it's written, quotes or brackets included.
+    pub raw: String,
+    pub span: Span,
+    // the innermost named function the literal is in, None in the main chunk.
+    pub function: Option<String>,
+    // whether it's the name of a module, as in `require "name"`.
+    pub is_require: bool,
+    // whether it's the key of a table, as in `t["key"]` or `{["key"] = 1}`.
+    pub is_key: bool,
+}
+
+/// Finds every string literal in the token stream, in source order.
+pub fn find_strings(source: &str, tokens: &[SpannedToken]) -> Vec<StringLiteral> {
+    let functions = find_functions(tokens);
+    let requires = find_requires(tokens);
+    let contains = |outer: Span, inner: Span| outer.start <= inner.start && inner.end <= outer.end;
+
+    let mut strings = Vec::new();
+    for (i, t) in tokens.iter().enumerate() {
+        let Token::STRING(value) = &t.token else {
+            continue;
+        };
+
+        let raw = source.get(t.span.start..t.span.end).unwrap_or_default();
+
+        // the functions are in source order, so the last one around the literal is innermost.
+        let function = functions
+            .iter()
+            .rev()
+            .find(|f| contains(f.span, t.span))
+            .map(|f| f.name.clone());
+
+        let is_key = i > 0
+            && tokens[i - 1].token == Token::LEFT_BRACKET
+            && tokens.get(i + 1).map(|t| &t.token) == Some(&Token::RIGHT_BRACKET);
+
+        strings.push(StringLiteral {
+            value: value.clone(),
+            raw: raw.to_string(),
+            span: t.span,
+            function,
+            is_require: requires.iter().any(|r| contains(r.span, t.span)),
+            is_key,
+        });
+    }
+
+    strings
+}
+
+/// Decodes the escape sequences of the contents of a quoted string.
+///
+/// `\ddd` and `\xXX` give bytes, which are read back as UTF-8 with anything invalid replaced,
+/// see `decode_bytes` for the bytes themselves. A malformed escape is kept as it's written.
+pub fn decode(contents: &str) -> String {
+    String::from_utf8_lossy(&decode_bytes(contents)).into_owned()
+}
+
+/// Decodes the escape sequences of the contents of a quoted string into the bytes the string
+/// holds, the way Lua sees it.
+///
+/// A decimal escape takes at most three digits, so a digit after them is a character of its
+/// own.
+///
+/// ```
+/// use lua_compiler::strings::decode_bytes;
+///
+/// assert_eq!(decode_bytes(r"\0\255"), [0, 255]);
+/// assert_eq!(decode_bytes(r"\0001"), b"\x001");
+/// assert_eq!(decode_bytes(r"\27[1m\x41!"), b"\x1b[1mA!");
+/// ```
+pub fn decode_bytes(contents: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(contents.len());
+    let push =
+        |out: &mut Vec<u8>, c: char| out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    let mut chars = contents.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            push(&mut out, c);
+            continue;
+        }
+
+        let Some(escape) = chars.next() else {
+            out.push(b'\\');
+            break;
+        };
+
+        match escape {
+            'a' => out.push(0x07),
+            'b' => out.push(0x08),
+            'f' => out.push(0x0c),
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'v' => out.push(0x0b),
+            '\\' | '"' | '\'' => push(&mut out, escape),
+            // a backslash before a line break keeps the line break, CRLF counting as one.
+            '\n' | '\r' => {
+                out.push(b'\n');
+                let pair = if escape == '\n' { '\r' } else { '\n' };
+                if chars.peek() == Some(&pair) {
+                    chars.next();
+                }
+            }
+            // `\z` skips the whitespace after it.
+            'z' => {
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            'x' => {
+                let digits = chars.clone().take(2).collect::<String>();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => {
+                        out.push(byte);
+                        chars.nth(1);
+                    }
+                    _ => out.extend_from_slice(b"\\x"),
+                }
+            }
+            'u' if chars.peek() == Some(&'{') => {
+                let digits = chars
+                    .clone()
+                    .skip(1)
+                    .take_while(|c| *c != '}')
+                    .collect::<String>();
+                let closed = chars.clone().nth(digits.len() + 1) == Some('}');
+                match u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(c) if closed => {
+                        push(&mut out, c);
+                        chars.nth(digits.len() + 1);
+                    }
+                    _ => out.extend_from_slice(b"\\u"),
+                }
+            }
+            // up to three decimal digits, the value of a byte.
+            '0'..='9' => {
+                let mut byte = escape.to_digit(10).unwrap_or_default();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(10)) {
+                        Some(digit) if byte * 10 + digit <= 255 => {
+                            byte = byte * 10 + digit;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                out.push(byte as u8);
+            }
+            _ => {
+                out.push(b'\\');
+                push(&mut out, escape);
+            }
+        }
+    }
+
+    out
+}
+
+/// Returns the byte ranges of the escapes in the contents of a quoted string that `decode`
+/// can't make sense of, like `\q`, `\x4` or `\300`, which reference Lua rejects.
+///
+/// ```
+/// use lua_compiler::strings::invalid_escapes;
+///
+/// assert_eq!(invalid_escapes(r"a\n\q\300"), vec![3..5, 5..9]);
+/// ```
+pub fn invalid_escapes(contents: &str) -> Vec<Range<usize>> {
+    let mut invalid = Vec::new();
+    let mut chars = contents.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        let Some((_, escape)) = chars.next() else {
+            break;
+        };
+        // where the escape ends once `n` more characters are taken.
+        let end_after = |chars: &mut std::iter::Peekable<std::str::CharIndices>, n: usize| {
+            let mut end = start + 1 + escape.len_utf8();
+            for _ in 0..n {
+                match chars.next() {
+                    Some((i, c)) => end = i + c.len_utf8(),
+                    None => break,
+                }
+            }
+            end
+        };
+
+        match escape {
+            'a' | 'b' | 'f' | 'n' | 'r' | 't' | 'v' | '\\' | '"' | '\'' | '\n' | '\r' | 'z' => {}
+            'x' => {
+                let digits = contents[start + 2..]
+                    .chars()
+                    .take(2)
+                    .take_while(char::is_ascii_hexdigit)
+                    .count();
+                match digits {
+                    2 => {
+                        chars.nth(1);
+                    }
+                    // up to the first character that isn't a digit, like reference Lua shows.
+                    n => invalid.push(start..end_after(&mut chars, n + 1)),
+                }
+            }
+            'u' => {
+                let rest = &contents[start + 2..];
+                let valid = rest
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .and_then(|(digits, _)| u32::from_str_radix(digits, 16).ok())
+                    .and_then(char::from_u32)
+                    .is_some();
+                let braces = match rest.strip_prefix('{') {
+                    Some(r) => r
+                        .find('}')
+                        .map_or(r.chars().count() + 1, |i| r[..i].chars().count() + 2),
+                    None => 0,
+                };
+                match valid {
+                    true => {
+                        chars.nth(braces - 1);
+                    }
+                    false => invalid.push(start..end_after(&mut chars, braces)),
+                }
+            }
+            // up to three decimal digits, which have to fit in a byte.
+            '0'..='9' => {
+                let digits = contents[start + 1..]
+                    .chars()
+                    .take(3)
+                    .take_while(char::is_ascii_digit)
+                    .collect::<String>();
+                let end = end_after(&mut chars, digits.len() - 1);
+                if digits.parse::<u32>().unwrap_or_default() > 255 {
+                    invalid.push(start..end);
+                }
+            }
+            _ => invalid.push(start..start + 1 + escape.len_utf8()),
+        }
+    }
+
+    invalid
+}
+
+/// Writes a value as a quoted string literal that `decode` reads back as the same value, with
+/// `quote` being `"` or `'`.
+///
+/// Backslashes, the quote and the control characters are escaped, with their letter when they
+/// have one and as three decimal digits otherwise, so that a digit after them can't be taken
+/// for part of the escape. Anything else, non-ASCII characters included, is written as it is.
+/// Unlike `string.format("%q")`, a line break is written `\n`, keeping the literal on one line.
+///
+/// ```
+/// use lua_compiler::lexer::{Lexer, Token};
+/// use lua_compiler::strings::escape;
+///
+/// let value = "say \"hi\"\tC:\\dir\u{1}2 né\r\n";
+/// let literal = escape(value, '"');
+/// assert_eq!(literal, r#""say \"hi\"\tC:\\dir\0012 né\r\n""#);
+///
+/// let tokens = Lexer::new(&literal).tokenize().unwrap();
+/// assert_eq!(tokens[0].token, Token::STRING(value.to_string()));
+/// ```
+pub fn escape(value: &str, quote: char) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push(quote);
+
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\x07' => out.push_str("\\a"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x0b' => out.push_str("\\v"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_ascii_control() => out.push_str(&format!("\\{:03}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push(quote);
+    out
+}
+
+/// Renders the literals as tab separated values, with a header line.
+///
+/// Tabs, line breaks and backslashes in the values are escaped so every literal is one line.
+pub fn to_tsv(file: &str, source: &str, strings: &[StringLiteral]) -> String {
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    };
+    let index = LineIndex::new(source);
+
+    let mut out = String::from("file\tline\tcolumn\tfunction\tvalue\traw\n");
+    for s in strings {
+        let (line, column) = index.position(s.span.start, source);
+        out.push_str(&format!(
+            "{}\t{line}\t{column}\t{}\t{}\t{}\n",
+            escape(file),
+            escape(s.function.as_deref().unwrap_or_default()),
+            escape(&s.value),
+            escape(&s.raw)
+        ));
+    }
+    out
+}
+
+/// Renders the literals as a JSON array of objects, one per literal.
+pub fn to_json(file: &str, source: &str, strings: &[StringLiteral]) -> String {
+    let index = LineIndex::new(source);
+
+    let items = strings
+        .iter()
+        .map(|s| {
+            let (line, column) = index.position(s.span.start, source);
+            let function = match &s.function {
+                Some(name) => json::string(name),
+                None => "null".to_string(),
+            };
+            format!(
+                "{{\"file\": {}, \"line\": {line}, \"column\": {column}, \"function\": {function}, \"value\": {}, \"raw\": {}}}",
+                json::string(file),
+                json::string(&s.value),
+                json::string(&s.raw)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!("[{}]\n", items.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn strings(source: &str) -> Vec<StringLiteral> {
+        find_strings(source, &Lexer::new(source).tokenize().unwrap())
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        assert_eq!(decode(r#"a\tb\\c\"d\'"#), "a\tb\\c\"d'");
+        assert_eq!(decode(r"\65\066\x43\u{44}\u{20AC}"), "ABCD€");
+        assert_eq!(decode("one\\z  \n   two"), "onetwo");
+        assert_eq!(decode("line\\\r\nbreak"), "line\nbreak");
+        // bytes that aren't UTF-8 are replaced, malformed escapes kept as written.
+        assert_eq!(decode(r"\255"), "\u{fffd}");
+        assert_eq!(decode(r"\q\x4g\u{zz}"), r"\q\x4g\u{zz}");
+    }
+
+    #[test]
+    fn literals_know_their_function_and_what_they_are_for() {
+        let found = strings(
+            "local m = require 'm'\nlocal function f()\n  return t['k'], {['j'] = 1}, [[\nlong]]\nend\n",
+        );
+        let summary = found
+            .iter()
+            .map(|s| {
+                (
+                    s.value.as_str(),
+                    s.function.as_deref(),
+                    s.is_require,
+                    s.is_key,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("m", None, true, false),
+                ("k", Some("f"), false, true),
+                ("j", Some("f"), false, true),
+                ("long", Some("f"), false, false),
+            ]
+        );
+        assert_eq!(found[3].raw, "[[\nlong]]");
+    }
+
+    #[test]
+    fn tsv_keeps_every_literal_on_one_line() {
+        let source = "x = 'a\\tb'\n";
+        let tsv = to_tsv("f\tg.lua", source, &strings(source));
+        assert_eq!(
+            tsv,
+            "file\tline\tcolumn\tfunction\tvalue\traw\nf\\tg.lua\t1\t5\t\ta\\tb\t'a\\\\tb'\n"
+        );
+    }
+
+    #[test]
+    fn only_the_quote_used_is_escaped() {
+        assert_eq!(escape("it's \"x\"", '"'), r#""it's \"x\"""#);
+        assert_eq!(escape("it's \"x\"", '\''), r#"'it\'s "x"'"#);
+        // a digit after a decimal escape can't be taken for part of it.
+        assert_eq!(escape("\u{0}1\u{7f}9", '"'), r#""\0001\1279""#);
+        assert_eq!(escape("é€\u{10348}", '\''), "'é€\u{10348}'");
+    }
+
+    #[test]
+    fn escaped_strings_lex_back_to_their_value() {
+        // xorshift, so every run tries the same strings.
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        // what's hard to escape, and a few that aren't.
+        let alphabet = [
+            '"',
+            '\'',
+            '\\',
+            '\n',
+            '\r',
+            '\t',
+            '\0',
+            '\x07',
+            '\x08',
+            '\x0b',
+            '\x0c',
+            '\x1b',
+            '\x7f',
+            '0',
+            '1',
+            '9',
+            'a',
+            'z',
+            ' ',
+            '[',
+            ']',
+            '=',
+            '-',
+            'é',
+            '€',
+            '\u{10348}',
+            '\u{fffd}',
+        ];
+
+        for _ in 0..2000 {
+            let length = (next() % 24) as usize;
+            let value = (0..length)
+                .map(|_| alphabet[(next() % alphabet.len() as u64) as usize])
+                .collect::<String>();
+            for quote in ['"', '\''] {
+                let literal = escape(&value, quote);
+                let tokens = Lexer::new(&literal)
+                    .tokenize()
+                    .unwrap_or_else(|| panic!("{literal} doesn't lex"));
+                assert_eq!(tokens.len(), 1, "{literal}");
+                assert_eq!(tokens[0].token, Token::STRING(value.clone()), "{literal}");
+                assert_eq!(decode(&literal[1..literal.len() - 1]), value, "{literal}");
+            }
+        }
+    }
+
+    #[test]
+    fn byte_escapes_give_the_bytes_themselves() {
+        assert_eq!(decode_bytes(r"\0"), [0]);
+        assert_eq!(decode_bytes(r"\255"), [255]);
+        assert_eq!(decode_bytes(r"\xff\xFF\x00"), [255, 255, 0]);
+        // three digits at most, the fourth is a character of its own.
+        assert_eq!(decode_bytes(r"\0001"), [0, b'1']);
+        assert_eq!(decode_bytes(r"\1234"), [123, b'4']);
+        // 256 doesn't fit, so the escape stops at `25` and the `6` follows it.
+        assert_eq!(decode_bytes(r"\256"), [25, b'6']);
+        assert_eq!(decode_bytes(r"a\9b\x41c"), b"a\tbAc");
+        // UTF-8 text around the escapes is kept as its bytes.
+        assert_eq!(decode_bytes(r"é\233"), [0xc3, 0xa9, 233]);
+
+        assert_eq!(invalid_escapes(r"\0\255\0001"), Vec::<Range<usize>>::new());
+        assert_eq!(invalid_escapes(r"\256"), vec![0..4]);
+        assert_eq!(invalid_escapes(r"ok\x4g"), vec![2..6]);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::codes;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lexer::{find_comments, Span, SpannedToken};
+use crate::snippet::LineIndex;
+
+/// What starts a suppression comment, after the `--`.
+pub const PREFIX: &str = "luacompiler:";
+
+/// A comment that silences warnings on one line, e.g. `--luacompiler: disable-line W200`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    // the 1-based line whose warnings are silenced.
+    pub line: usize,
+    // the codes of the warnings, every warning on the line if empty.
+    pub codes: Vec<&'static str>,
+    // covers the comment.
+    pub span: Span,
+}
+
+/// Finds the suppression comments of the source, along with the problems in them.
+///
+/// `disable-line` applies to the line the comment is on, `disable-next-line` to the one after.
+/// Either can be followed by comma separated codes or names of warnings.
+pub fn find_directives(source: &str, tokens: &[SpannedToken]) -> (Vec<Directive>, Vec<Diagnostic>) {
+    let index = LineIndex::new(source);
+    let mut directives = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for comment in find_comments(source, tokens) {
+        let body = source[comment.clone()].trim_start_matches('-').trim();
+        let Some(directive) = body.strip_prefix(PREFIX) else {
+            continue;
+        };
+
+        let (line, column) = index.position(comment.start, source);
+        let span = Span {
+            line,
+            column,
+            start: comment.start,
+            end: comment.end,
+        };
+
+        let directive = directive.trim();
+        let (kind, list) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        let target = match kind {
+            "disable-line" => line,
+            "disable-next-line" => line + 1,
+            _ => {
+                diagnostics.push(
+                    Diagnostic::warning("W001", format!("unknown suppression '{kind}'"), span)
+                        .with_note(
+                            "expected disable-line or disable-next-line".to_string(),
+                            None,
+                        ),
+                );
+                continue;
+            }
+        };
+
+        let mut codes = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match codes::lookup(name) {
+                Some(c) if c.severity == Severity::Warning => codes.push(c.code),
+                Some(c) => diagnostics.push(Diagnostic::warning(
+                    "W001",
+                    format!("'{}' is an error, it can't be suppressed", c.name),
+                    span,
+                )),
+                None => diagnostics.push(Diagnostic::warning(
+                    "W001",
+                    format!("unknown warning '{name}' in suppression"),
+                    span,
+                )),
+            }
+        }
+
+        // a list of nothing but unknown names shouldn't silence everything.
+        if codes.is_empty() && !list.trim().is_empty() {
+            continue;
+        }
+
+        directives.push(Directive {
+            line: target,
+            codes,
+            span,
+        });
+    }
+
+    (directives, diagnostics)
+}
+
+/// Marks the warnings the suppression comments of the source silence as suppressed, and warns
+/// about the comments that don't silence anything.
+///
+/// A warning that `-W` turned into an error is still a warning to the comments. Errors can't
+/// be suppressed.
+pub fn apply(diagnostics: &mut Vec<Diagnostic>, source: &str, tokens: &[SpannedToken]) {
+    // most sources have none, so their comments aren't looked through.
+    if !source.contains(PREFIX) {
+        return;
+    }
+
+    let (directives, problems) = find_directives(source, tokens);
+    let index = LineIndex::new(source);
+    let mut used = vec![false; directives.len()];
+
+    for d in diagnostics.iter_mut() {
+        let is_warning = codes::lookup(d.code).is_some_and(|c| c.severity == Severity::Warning);
+        if !is_warning {
+            continue;
+        }
+
+        let line = index.line_of(d.span.start);
+        for (i, directive) in directives.iter().enumerate() {
+            if directive.line == line
+                && (directive.codes.is_empty() || directive.codes.contains(&d.code))
+            {
+                d.suppressed = true;
+                used[i] = true;
+            }
+        }
+    }
+
+    for (directive, used) in directives.iter().zip(used) {
+        if !used {
+            diagnostics.push(Diagnostic::warning(
+                "W001",
+                format!(
+                    "unused suppression, line {} has no warning it silences",
+                    directive.line
+                ),
+                directive.span,
+            ));
+        }
+    }
+    diagnostics.extend(problems);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+
+    // the codes of the diagnostics once the comments are applied, with whether they're
+    // suppressed.
+    fn check(source: &str) -> Vec<(usize, &'static str, bool)> {
+        let result = Compiler::default().compile("test.lua", source);
+        let mut diagnostics = result.diagnostics;
+        apply(&mut diagnostics, source, &result.tokens.unwrap_or_default());
+        diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.code, d.suppressed))
+            .collect()
+    }
+
+    const LOOP: &str = "for i = 1, 3 do s = s .. i end";
+
+    #[test]
+    fn a_directive_silences_its_own_line_or_the_next() {
+        assert_eq!(check(&format!("{LOOP}\n")), [(1, "W206", false)]);
+        assert_eq!(
+            check(&format!("{LOOP} --luacompiler: disable-line W206\n")),
+            [(1, "W206", true)]
+        );
+        assert_eq!(
+            check(&format!(
+                "--luacompiler: disable-next-line concat-in-loop\n{LOOP}\n"
+            )),
+            [(2, "W206", true)]
+        );
+        // without codes every warning of the line is silenced.
+        assert_eq!(
+            check(&format!(
+                "local function f(a) end\n{LOOP} f(1, 2) -- luacompiler: disable-line\n"
+            )),
+            [(2, "W206", true), (2, "W207", true)]
+        );
+    }
+
+    #[test]
+    fn only_the_named_codes_are_silenced() {
+        assert_eq!(
+            check(&format!(
+                "local function f(a) end\n{LOOP} f(1, 2) --luacompiler: disable-line W207\n"
+            )),
+            [(2, "W206", false), (2, "W207", true)]
+        );
+        // the wrong code leaves the warning and is itself unused.
+        assert_eq!(
+            check(&format!("{LOOP} --luacompiler: disable-line W207\n")),
+            [(1, "W206", false), (1, "W001", false)]
+        );
+    }
+
+    #[test]
+    fn unused_and_malformed_directives_are_reported() {
+        let messages = |source: &str| {
+            let tokens = crate::lexer::Lexer::new(source).tokenize().unwrap();
+            let mut diagnostics = Vec::new();
+            apply(&mut diagnostics, source, &tokens);
+            diagnostics
+                .into_iter()
+                .map(|d| d.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            messages("x = 1 --luacompiler: disable-next-line\n"),
+            ["unused suppression, line 2 has no warning it silences"]
+        );
+        assert_eq!(
+            messages("--luacompiler: enable-line W206\n"),
+            ["unknown suppression 'enable-line'"]
+        );
+        assert_eq!(
+            messages("x = 1 --luacompiler: disable-line no-such-thing\n"),
+            ["unknown warning 'no-such-thing' in suppression"]
+        );
+        assert_eq!(
+            messages("x = 1 --luacompiler: disable-line syntax-error\n"),
+            ["'syntax-error' is an error, it can't be suppressed"]
+        );
+        // a directive in a string isn't a comment.
+        assert!(messages("x = '--luacompiler: disable-line'\n").is_empty());
+    }
+
+    #[test]
+    fn errors_are_never_suppressed() {
+        let lines = check("x = = 1 --luacompiler: disable-line\n");
+        assert!(lines
+            .iter()
+            .any(|&(_, code, suppressed)| code.starts_with('E') && !suppressed));
+    }
+}
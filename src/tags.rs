@@ -0,0 +1,133 @@
+use crate::definitions::{find_functions, find_variables};
+use crate::lexer::{Span, SpannedToken};
+use crate::snippet::LineIndex;
+
+/// A named definition, as both tag formats need it.
+struct Tag<'a> {
+    name: String,
+    // `f` for functions, `m` for methods and `v` for variables, as ctags abbreviates them.
+    kind: char,
+    line: usize,
+    // the text of the line the definition is on, and where on it the name ends.
+    text: &'a str,
+    name_end: usize,
+    line_start: usize,
+}
+
+fn collect<'a>(source: &'a str, tokens: &[SpannedToken]) -> Vec<Tag<'a>> {
+    let index = LineIndex::new(source);
+    let tag = |name: String, kind, span: Span| {
+        let line = index.line_of(span.start);
+        let (start, end) = index.line_range(line, source);
+        Tag {
+            name,
+            kind,
+            line,
+            text: &source[start..end],
+            name_end: span.end.min(end) - start,
+            line_start: start,
+        }
+    };
+
+    let functions = find_functions(tokens).into_iter().map(|f| {
+        let kind = if f.is_method { 'm' } else { 'f' };
+        tag(f.name, kind, f.name_span)
+    });
+    let variables = find_variables(tokens)
+        .into_iter()
+        .map(|v| tag(v.name, 'v', v.name_span));
+
+    let mut tags = functions.chain(variables).collect::<Vec<_>>();
+    tags.sort_by(|a, b| a.name.cmp(&b.name).then(a.line.cmp(&b.line)));
+    tags
+}
+
+/// Renders the definitions of a source as a ctags file, sorted by name.
+pub fn ctags(file: &str, source: &str, tokens: &[SpannedToken]) -> String {
+    let mut out = String::from(
+        "!_TAG_FILE_FORMAT\t2\t/extended format; --format=1 will not append ;\" to lines/\n\
+         !_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n",
+    );
+
+    for tag in collect(source, tokens) {
+        // the search pattern matches the whole line, so its own delimiters have to be escaped.
+        let pattern = tag.text.replace('\\', "\\\\").replace('/', "\\/");
+        out.push_str(&format!(
+            "{}\t{file}\t/^{pattern}$/;\"\t{}\tline:{}\n",
+            tag.name, tag.kind, tag.line
+        ));
+    }
+
+    out
+}
+
+/// Renders the definitions of a source as an Emacs TAGS file.
+pub fn etags(file: &str, source: &str, tokens: &[SpannedToken]) -> String {
+    let mut section = String::new();
+
+    // every tag is listed in the order it's found in, with the line up to its name.
+    let mut tags = collect(source, tokens);
+    tags.sort_by_key(|t| (t.line, t.name_end));
+
+    for tag in tags {
+        section.push_str(&format!(
+            "{}\u{7f}{}\u{1}{},{}\n",
+            &tag.text[..tag.name_end],
+            tag.name,
+            tag.line,
+            tag.line_start
+        ));
+    }
+
+    // the size of the section is in bytes and doesn't count its own header.
+    format!("\u{c}\n{file},{}\n{section}", section.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn tags(source: &str) -> Vec<(String, char, usize)> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        collect(source, &tokens)
+            .into_iter()
+            .map(|t| (t.name, t.kind, t.line))
+            .collect()
+    }
+
+    #[test]
+    fn dotted_and_method_names_are_qualified() {
+        assert_eq!(
+            tags("function a.b.c() end\nfunction a.b:d() end\nlocal e = function() end\n"),
+            [
+                ("a.b.c".to_string(), 'f', 1),
+                ("a.b:d".to_string(), 'm', 2),
+                ("e".to_string(), 'f', 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_variables_of_the_main_chunk_are_tagged() {
+        assert_eq!(
+            tags("local a = 1\ndo local b = 2 end\nfunction f() local c = 3 end\nd = {e = 4}\n"),
+            [
+                ("a".to_string(), 'v', 1),
+                ("d".to_string(), 'v', 4),
+                ("f".to_string(), 'f', 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_etags_section_counts_its_bytes() {
+        let source = "local ü = 1\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let section = "local ü\u{7f}ü\u{1}1,0\n";
+        assert_eq!(
+            etags("a.lua", source, &tokens),
+            format!("\u{c}\na.lua,{}\n{section}", section.len())
+        );
+    }
+}
@@ -1,58 +1,485 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// whether escape sequences should be emitted, this is decided once by `set_output_caps`.
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+// whether characters outside of ascii can be shown, also decided by `set_output_caps`.
+static UNICODE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+// the `Verbosity` messages are logged at, as a number so it can live in an atomic.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// How much the compiler says about what it's doing, each level includes the ones below it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // only diagnostics.
+    Quiet,
+    // diagnostics and the outcome of the run.
+    #[default]
+    Normal,
+    // what each stage did and how long it took, on stderr.
+    Debug,
+    // the details of every step, on stderr.
+    Trace,
+}
+
+/// Sets the level the `log_*!` macros are filtered by for the rest of the run.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Returns the level set by `set_verbosity`.
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Debug,
+        _ => Verbosity::Trace,
+    }
+}
+
+/// When colored output should be used, as chosen with `--color`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    // color only when writing to a terminal and `NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Parses the value of `--color`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(Self::Auto),
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+}
+
+/// What the terminal the output goes to can show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputCaps {
+    // escape sequences for colors and styles.
+    pub color: bool,
+    // characters outside of ascii, like `…`.
+    pub unicode: bool,
+}
+
+impl OutputCaps {
+    /// Works out the capabilities from the color choice and the environment.
+    pub fn detect(choice: ColorChoice) -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        // https://no-color.org: any non-empty value turns color off.
+        let no_color = var("NO_COLOR").is_some();
+        // a dumb terminal understands neither escape sequences nor anything beyond ascii.
+        let dumb = var("TERM").as_deref() == Some("dumb");
+        // diagnostics are the bulk of what's colored, and they go to stderr.
+        let terminal = std::io::stderr().is_terminal();
+
+        // the first of the locale variables that is set decides, the same way setlocale does.
+        // windows consoles don't go by them and show unicode fine.
+        let utf8 = match ["LC_ALL", "LC_CTYPE", "LANG"].into_iter().find_map(var) {
+            Some(locale) => {
+                let locale = locale.to_lowercase();
+                locale.contains("utf-8") || locale.contains("utf8")
+            }
+            None => cfg!(windows),
+        };
+
+        let color = !dumb && decide_color(choice, no_color, terminal, enable_escape_sequences);
+
+        Self {
+            color,
+            // plain output is plain all the way, the same as a dumb terminal.
+            unicode: color && utf8,
+        }
+    }
+}
+
+/// Sets what `colored()` and the renderers may use for the rest of the run.
+pub fn set_output_caps(caps: OutputCaps) {
+    COLOR_ENABLED.store(caps.color, Ordering::Relaxed);
+    UNICODE_ENABLED.store(caps.unicode, Ordering::Relaxed);
+}
+
+/// Returns the capabilities set by `set_output_caps`.
+pub fn output_caps() -> OutputCaps {
+    OutputCaps {
+        color: COLOR_ENABLED.load(Ordering::Relaxed),
+        unicode: UNICODE_ENABLED.load(Ordering::Relaxed),
+    }
+}
+
+/// Combines the color choice with the environment. `enable_escapes` is only called when color
+/// would be used, and says whether the terminal could be made to understand escape sequences.
+fn decide_color(
+    choice: ColorChoice,
+    no_color: bool,
+    terminal: bool,
+    enable_escapes: impl FnOnce() -> bool,
+) -> bool {
+    match choice {
+        // the output might be looked at somewhere else, so it's colored even if this terminal
+        // can't show it.
+        ColorChoice::Always => {
+            enable_escapes();
+            true
+        }
+        ColorChoice::Never => false,
+        // rather no color than escape sequences shown as garbage.
+        ColorChoice::Auto => !no_color && terminal && enable_escapes(),
+    }
+}
+
+/// Turns on the handling of escape sequences in the console, which older Windows terminals
+/// don't do by default.
+#[cfg(windows)]
+fn enable_escape_sequences() -> bool {
+    use std::ffi::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    let enable = |std_handle| unsafe {
+        let handle = GetStdHandle(std_handle);
+        let mut mode = 0;
+        GetConsoleMode(handle, &mut mode) != 0
+            && SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    };
+
+    // stdout isn't necessarily a console, only stderr has to work for the diagnostics.
+    enable(STD_OUTPUT_HANDLE);
+    enable(STD_ERROR_HANDLE)
+}
+
+/// Every other terminal understands escape sequences already.
+#[cfg(not(windows))]
+fn enable_escape_sequences() -> bool {
+    true
+}
+
+/// Returns true if `colored()` currently emits escape sequences.
+pub fn color_enabled() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// The colors `colored()` can use, the named ones are the bright variants of the palette.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Color {
     Green,
     Red,
     Yellow,
     Blue,
     Grey,
+    Cyan,
+    Magenta,
+    White,
+    // the same as grey, under the name terminals use for it.
+    BrightBlack,
+    // one of the 256 colors of the extended palette.
+    Fixed(u8),
+}
+
+impl Color {
+    /// Returns the parameters of the escape sequence that switches to this color.
+    fn code(self) -> String {
+        match self {
+            Color::Green => "92".to_string(),
+            Color::Yellow => "93".to_string(),
+            Color::Blue => "94".to_string(),
+            Color::Red => "91".to_string(),
+            Color::Grey | Color::BrightBlack => "90".to_string(),
+            Color::Cyan => "96".to_string(),
+            Color::Magenta => "95".to_string(),
+            Color::White => "97".to_string(),
+            Color::Fixed(n) => format!("38;5;{n}"),
+        }
+    }
+}
+
+// the sequence that resets every style back to normal.
+const RESET: &str = "\x1b[0m";
+
+/// A foreground color along with text attributes, built up like `Style::new().fg(Color::Red).bold()`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Style {
+    fg: Option<Color>,
+    bold: bool,
+    dim: bool,
+    underline: bool,
+}
+
+impl Style {
+    pub const fn new() -> Self {
+        Self {
+            fg: None,
+            bold: false,
+            dim: false,
+            underline: false,
+        }
+    }
+
+    pub const fn fg(self, color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            ..self
+        }
+    }
+
+    pub const fn bold(self) -> Self {
+        Self { bold: true, ..self }
+    }
+
+    pub const fn dim(self) -> Self {
+        Self { dim: true, ..self }
+    }
+
+    #[allow(dead_code)]
+    pub const fn underline(self) -> Self {
+        Self {
+            underline: true,
+            ..self
+        }
+    }
+
+    /// Returns the escape sequence that switches to this style, or nothing for the plain style.
+    fn start(&self) -> String {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if self.dim {
+            codes.push("2".to_string());
+        }
+        if self.underline {
+            codes.push("4".to_string());
+        }
+        if let Some(color) = self.fg {
+            codes.push(color.code());
+        }
+
+        match codes.is_empty() {
+            true => String::new(),
+            false => format!("\x1b[{}m", codes.join(";")),
+        }
+    }
+
+    /// Styles the string, or returns it as is when color is disabled.
+    ///
+    /// The string may contain styled parts of its own, this style picks up again after each of
+    /// them so only a single reset is needed at the end.
+    pub fn apply(&self, string: &str) -> String {
+        self.apply_with(string, output_caps())
+    }
+
+    /// Styles the string like `apply`, but only if `caps` can show color instead of going by
+    /// what `set_output_caps` set.
+    pub fn apply_with(&self, string: &str, caps: OutputCaps) -> String {
+        let start = self.start();
+        if !caps.color || start.is_empty() {
+            return string.to_string();
+        }
+
+        let inner = string.replace(RESET, &format!("{RESET}{start}"));
+        format!("{start}{inner}{RESET}")
+    }
 }
 
 /// Given a string, print a colored version of it to the console.
 pub fn colored(string: &str, color: Color) -> String {
-    // the left hand side of the color swap, change to a specific color.
-    let lhs = format!(
-        "\x1b[{}m",
-        match color {
-            Color::Green => 92,
-            Color::Yellow => 93,
-            Color::Blue => 94,
-            Color::Red => 91,
-            Color::Grey => 90,
-        }
-    );
+    Style::new().fg(color).apply(string)
+}
 
-    // the right hand side of the swap, reset the color back to normal.
-    let rhs = "\x1b[0m";
+/// Formats a message the way `log_*!` prints it, for when it has to be buffered first.
+#[macro_export]
+macro_rules! format_log {
+    ($label:expr, $color:expr, $($args:tt)*) => {{
+        // format the string with var args.
+        let string = std::fmt::format(format_args!($($args)*));
+        // color the label according to the kind of message.
+        format!("{}: {string}\n", $crate::term_color::colored($label, $color))
+    }}
+}
 
-    // put the string inbetween the left and right hand side.
-    format!("{lhs}{string}{rhs}")
+#[macro_export]
+macro_rules! format_warn {
+    ($($args:tt)*) => {
+        $crate::format_log!("warning", $crate::term_color::Color::Yellow, $($args)*)
+    }
+}
+
+#[macro_export]
+macro_rules! format_error {
+    ($($args:tt)*) => {
+        $crate::format_log!("error", $crate::term_color::Color::Red, $($args)*)
+    }
+}
+
+#[macro_export]
+macro_rules! format_success {
+    ($($args:tt)*) => {
+        $crate::format_log!("success", $crate::term_color::Color::Green, $($args)*)
+    }
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($args:tt)*) => {
+        if $crate::term_color::verbosity() >= $crate::term_color::Verbosity::Debug {
+            eprint!("{}", $crate::format_log!("debug", $crate::term_color::Color::Blue, $($args)*))
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($args:tt)*) => {
+        if $crate::term_color::verbosity() >= $crate::term_color::Verbosity::Trace {
+            eprint!("{}", $crate::format_log!("trace", $crate::term_color::Color::Grey, $($args)*))
+        }
+    }
 }
 
 #[macro_export]
 macro_rules! log_warn {
     ($($args:tt)*) => {
-        // format the string with var args.
-        let string = std::fmt::format(format_args!($($args)*));
-        // color the string according to the macro.
-        println!("{}: {string}", colored("warning", Color::Yellow));
+        eprint!("{}", $crate::format_warn!($($args)*))
     }
 }
 
 #[macro_export]
 macro_rules! log_error {
     ($($args:tt)*) => {
-        // format the string with var args.
-        let string = std::fmt::format(format_args!($($args)*));
-        // color the string according to the macro.
-        println!("{}: {string}", colored("error", Color::Red));
+        eprint!("{}", $crate::format_error!($($args)*))
     }
 }
 
 #[macro_export]
 macro_rules! log_success {
     ($($args:tt)*) => {
-        // format the string with var args.
-        let string = std::fmt::format(format_args!($($args)*));
-        // color the string according to the macro.
-        println!("{}: {string}", colored("success", Color::Green));
+        eprint!("{}", $crate::format_success!($($args)*))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: OutputCaps = OutputCaps {
+        color: true,
+        unicode: true,
+    };
+
+    #[test]
+    fn every_color_has_its_escape_sequence() {
+        let cases = [
+            (Color::Green, "\x1b[92mx\x1b[0m"),
+            (Color::Yellow, "\x1b[93mx\x1b[0m"),
+            (Color::Blue, "\x1b[94mx\x1b[0m"),
+            (Color::Red, "\x1b[91mx\x1b[0m"),
+            (Color::Grey, "\x1b[90mx\x1b[0m"),
+            (Color::BrightBlack, "\x1b[90mx\x1b[0m"),
+            (Color::Cyan, "\x1b[96mx\x1b[0m"),
+            (Color::Magenta, "\x1b[95mx\x1b[0m"),
+            (Color::White, "\x1b[97mx\x1b[0m"),
+            (Color::Fixed(0), "\x1b[38;5;0mx\x1b[0m"),
+            (Color::Fixed(208), "\x1b[38;5;208mx\x1b[0m"),
+        ];
+        for (color, expected) in cases {
+            assert_eq!(
+                Style::new().fg(color).apply_with("x", COLOR),
+                expected,
+                "{color:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn styles_combine_and_nest() {
+        let style = Style::new().fg(Color::Red).bold().underline();
+        assert_eq!(style.apply_with("x", COLOR), "\x1b[1;4;91mx\x1b[0m");
+        assert_eq!(Style::new().dim().apply_with("x", COLOR), "\x1b[2mx\x1b[0m");
+
+        // the outer style starts again after the inner one resets.
+        let inner = Style::new().fg(Color::Blue).apply_with("b", COLOR);
+        assert_eq!(
+            Style::new()
+                .fg(Color::Green)
+                .apply_with(&format!("a{inner}c"), COLOR),
+            "\x1b[92ma\x1b[94mb\x1b[0m\x1b[92mc\x1b[0m"
+        );
+
+        let plain = OutputCaps {
+            color: false,
+            unicode: true,
+        };
+        assert_eq!(style.apply_with("x", plain), "x");
+        assert_eq!(Style::new().apply_with("x", COLOR), "x");
+    }
+
+    #[test]
+    fn color_choices_parse_from_their_names() {
+        assert_eq!(ColorChoice::parse("auto"), Some(ColorChoice::Auto));
+        assert_eq!(ColorChoice::parse("always"), Some(ColorChoice::Always));
+        assert_eq!(ColorChoice::parse("never"), Some(ColorChoice::Never));
+        assert_eq!(ColorChoice::parse("Always"), None);
+        assert_eq!(ColorChoice::parse(""), None);
+    }
+
+    #[test]
+    fn color_falls_back_to_none_when_escapes_cant_be_enabled() {
+        use ColorChoice::*;
+
+        // the choice, NO_COLOR, a terminal, whether escapes could be enabled, the decision.
+        let cases = [
+            (Auto, false, true, true, true),
+            (Auto, false, true, false, false),
+            (Auto, true, true, true, false),
+            (Auto, false, false, true, false),
+            (Always, true, false, false, true),
+            (Never, false, true, true, false),
+        ];
+        for (choice, no_color, terminal, enables, expected) in cases {
+            assert_eq!(
+                decide_color(choice, no_color, terminal, || enables),
+                expected,
+                "{choice:?}, NO_COLOR {no_color}, terminal {terminal}, enables {enables}"
+            );
+        }
+    }
+
+    #[test]
+    fn escapes_are_only_enabled_for_color() {
+        let mut asked = false;
+        decide_color(ColorChoice::Never, false, true, || {
+            asked = true;
+            true
+        });
+        decide_color(ColorChoice::Auto, false, false, || {
+            asked = true;
+            true
+        });
+        assert!(!asked);
+
+        decide_color(ColorChoice::Always, false, false, || {
+            asked = true;
+            false
+        });
+        assert!(asked);
     }
 }
@@ -0,0 +1,151 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use lua_compiler::diagnostic::{
+    Diagnostic, DiagnosticSink, RenderingSink, Severity, SourceContext,
+};
+use lua_compiler::lexer::Span;
+use lua_compiler::term_color::*;
+use lua_compiler::{codes, exports, format_error, require, suppress};
+
+use crate::{cli, source, walk, ExitCode, Output};
+
+/// A file of the project, along with its tokens.
+struct Module {
+    path: PathBuf,
+    code: String,
+    tokens: Vec<lua_compiler::lexer::SpannedToken>,
+    canonical: PathBuf,
+}
+
+/// Warns about the files of the directory that nothing requires and aren't entry points, and
+/// about the fields of modules that no module requiring them reads.
+///
+/// Modules are looked up from the directory, like `--emit=deps` does.
+pub fn check(options: &cli::Options, dir: &Path, files: &[PathBuf], out: &mut Output) -> ExitCode {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let name = |p: &Path| walk::relative_name(p, dir);
+
+    let mut entries = HashSet::new();
+    for entry in &options.entries {
+        // an entry is relative to the directory, or to where the compiler runs.
+        let path = match dir.join(entry) {
+            path if path.is_file() => path,
+            _ => entry.clone(),
+        };
+        let path = canonical(&path);
+        if !files.iter().any(|f| canonical(f) == path) {
+            out.stderr.push_str(&format_error!(
+                "the entry '{}' isn't one of the files compiled in '{}'.\n",
+                entry.display(),
+                dir.display()
+            ));
+            return ExitCode::UsageError;
+        }
+        entries.insert(path);
+    }
+
+    // the files that can't be read or lexed were already reported when they were compiled.
+    let compiler = lua_compiler::Compiler::from(options);
+    let modules = files
+        .iter()
+        .filter_map(|path| {
+            let code = source::read(path).ok()?;
+            let tokens = compiler.tokenize(&code).0?;
+            Some(Module {
+                path: path.clone(),
+                canonical: canonical(path),
+                code,
+                tokens,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // the fields read from every module that's required, None once one could read any.
+    let mut reads: HashMap<PathBuf, Option<BTreeSet<String>>> = HashMap::new();
+    for module in &modules {
+        for used in exports::find_module_uses(&module.tokens) {
+            let Some(target) = require::resolve(&used.require.name, dir) else {
+                continue;
+            };
+            let target = canonical(&target);
+            // a module requiring itself is a cycle, which doesn't make it used.
+            if target == module.canonical {
+                continue;
+            }
+
+            let fields = reads.entry(target).or_insert_with(|| Some(BTreeSet::new()));
+            match (fields.as_mut(), used.fields) {
+                (Some(fields), Some(used)) => fields.extend(used),
+                _ => *fields = None,
+            }
+        }
+    }
+
+    let mut result = ExitCode::Success;
+    for module in &modules {
+        let mut diagnostics = Vec::new();
+
+        match reads.get(&module.canonical) {
+            None if !entries.contains(&module.canonical) => {
+                let span = module.tokens.first().map_or(
+                    Span {
+                        line: 1,
+                        column: 1,
+                        ..Span::default()
+                    },
+                    |t| t.span,
+                );
+                diagnostics.emit(
+                    Diagnostic::warning(
+                        "W201",
+                        format!("module '{}' isn't required by any file", name(&module.path)),
+                        span,
+                    )
+                    .with_note(
+                        "pass it with --entry if it's run on its own".to_string(),
+                        None,
+                    ),
+                );
+            }
+            Some(Some(fields)) => {
+                for export in exports::find_exports(&module.tokens) {
+                    if !fields.contains(&export.name) {
+                        diagnostics.emit(Diagnostic::warning(
+                            "W202",
+                            format!(
+                                "field '{}' of module '{}' isn't read by any module that requires it",
+                                export.name,
+                                name(&module.path)
+                            ),
+                            export.span,
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        suppress::apply(&mut diagnostics, &module.code, &module.tokens);
+        codes::apply_levels(&mut diagnostics, &options.warnings);
+
+        let file = module.path.display().to_string();
+        let ctx = SourceContext::new(&file, &module.code);
+        let mut sink = RenderingSink::new(&ctx, options.diagnostics_format, output_caps());
+        sink.show_suppressed = options.show_suppressed;
+        for d in diagnostics {
+            sink.emit(d);
+        }
+        let (rendered, diagnostics) = sink.finish();
+        out.push_diagnostics(options.diagnostics_format, &rendered);
+
+        let fails = diagnostics.iter().any(|d| {
+            !d.suppressed && (d.severity == Severity::Error || options.warnings_as_errors)
+        });
+        if fails {
+            result = ExitCode::CompileError;
+        }
+    }
+
+    result
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Matches `text` against a glob pattern where `*` matches anything but `/`, `**` matches
+/// anything including `/`, and `?` matches a single character other than `/`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_from(&pattern, &text)
+}
+
+fn glob_match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also match no directories at all.
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|i| glob_match_from(rest, &text[i..]))
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match_from(rest, &text[i..])),
+        ['?', rest @ ..] => {
+            matches!(text, [c, ..] if *c != '/') && glob_match_from(rest, &text[1..])
+        }
+        [p, rest @ ..] => matches!(text, [c, ..] if c == p) && glob_match_from(rest, &text[1..]),
+    }
+}
+
+/// Returns true if the path matches any of the patterns. Patterns without a `/` are matched
+/// against the file name only, the rest against the whole relative path.
+fn matches_any(patterns: &[String], relative: &str) -> bool {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    patterns.iter().any(|p| {
+        if p.contains('/') {
+            glob_match(p, relative)
+        } else {
+            glob_match(p, name)
+        }
+    })
+}
+
+/// Returns `path` relative to `base`, or all of it if it isn't under `base`, with `/` between
+/// its components on every platform, so that the names in the output are the same everywhere.
+pub fn relative_name(path: &Path, base: &Path) -> String {
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Recursively finds the files under `root` that match `include` (`*.lua` if it's empty) but
+/// not `exclude`.
+///
+/// Hidden directories are skipped, symlinked directories are only visited once, and the
+/// returned paths are sorted so the output of a run doesn't depend on the file system.
+pub fn collect_files(
+    root: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> io::Result<Vec<PathBuf>> {
+    let default_include = ["*.lua".to_string()];
+    let include = match include {
+        [] => &default_include[..],
+        _ => include,
+    };
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    walk(root, root, include, exclude, &mut visited, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn walk(
+    root: &Path,
+    dir: &Path,
+    include: &[String],
+    exclude: &[String],
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    // a symlink pointing back up the tree would otherwise make us walk forever.
+    if !visited.insert(dir.canonicalize()?) {
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let relative = relative_name(&path, root);
+
+        if matches_any(exclude, &relative) {
+            continue;
+        }
+
+        // follow symlinks, the visited set takes care of cycles.
+        if path.is_dir() {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            walk(root, &path, include, exclude, visited, files)?;
+        } else if matches_any(include, &relative) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own for every test, the tests run at the same time.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("lua-compiler-walk-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, relative: &str) {
+        let path = dir.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+
+    fn names(files: &[PathBuf], root: &Path) -> Vec<String> {
+        files.iter().map(|f| relative_name(f, root)).collect()
+    }
+
+    #[test]
+    fn globs_match_within_and_across_directories() {
+        let cases = [
+            ("*.lua", "main.lua", true),
+            ("*.lua", "main.luac", false),
+            ("*.lua", "src/main.lua", false),
+            ("src/*.lua", "src/main.lua", true),
+            ("src/*.lua", "src/lib/main.lua", false),
+            ("src/**/*.lua", "src/lib/a/main.lua", true),
+            // `**/` can match no directories at all.
+            ("src/**/*.lua", "src/main.lua", true),
+            ("**", "a/b/c", true),
+            ("m?.lua", "m1.lua", true),
+            ("m?.lua", "m12.lua", false),
+            ("a?b", "a/b", false),
+            ("", "", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(glob_match(pattern, text), expected, "{pattern} {text}");
+        }
+    }
+
+    #[test]
+    fn patterns_without_a_slash_match_the_file_name() {
+        let patterns = ["*_test.lua".to_string()];
+        assert!(matches_any(&patterns, "a/b/x_test.lua"));
+        assert!(!matches_any(&patterns, "a/b/x.lua"));
+
+        let patterns = ["vendor/**".to_string()];
+        assert!(matches_any(&patterns, "vendor/x/y.lua"));
+        assert!(!matches_any(&patterns, "src/vendor/y.lua"));
+    }
+
+    #[test]
+    fn files_are_found_sorted_skipping_hidden_and_excluded_ones() {
+        let dir = temp_dir("collect");
+        for file in [
+            "b.lua",
+            "a.lua",
+            "notes.txt",
+            "lib/z.lua",
+            "lib/c_test.lua",
+            ".git/hook.lua",
+            "vendor/dep.lua",
+        ] {
+            touch(&dir, file);
+        }
+
+        let files = collect_files(&dir, &[], &[]).unwrap();
+        assert_eq!(
+            names(&files, &dir),
+            [
+                "a.lua",
+                "b.lua",
+                "lib/c_test.lua",
+                "lib/z.lua",
+                "vendor/dep.lua"
+            ]
+        );
+
+        let exclude = ["vendor".to_string(), "*_test.lua".to_string()];
+        let files = collect_files(&dir, &[], &exclude).unwrap();
+        assert_eq!(names(&files, &dir), ["a.lua", "b.lua", "lib/z.lua"]);
+
+        let include = ["*.txt".to_string()];
+        let files = collect_files(&dir, &include, &[]).unwrap();
+        assert_eq!(names(&files, &dir), ["notes.txt"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_symlink_cycle_is_only_walked_once() {
+        let dir = temp_dir("cycle");
+        touch(&dir, "lib/a.lua");
+        std::os::unix::fs::symlink(&dir, dir.join("lib/back")).unwrap();
+
+        let files = collect_files(&dir, &[], &[]).unwrap();
+        assert_eq!(names(&files, &dir), ["lib/a.lua"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_names_use_forward_slashes() {
+        let root = Path::new("project");
+        assert_eq!(
+            relative_name(&root.join("lib").join("a.lua"), root),
+            "lib/a.lua"
+        );
+        assert_eq!(
+            relative_name(Path::new("elsewhere/b.lua"), root),
+            "elsewhere/b.lua"
+        );
+    }
+}
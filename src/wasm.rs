@@ -0,0 +1,67 @@
+//! JavaScript bindings to the checker, for running it in a browser without a server.
+//!
+//! ```js
+//! import init, { check, tokenize, format } from "./lua_compiler.js";
+//!
+//! await init();
+//! check("local x = = 1"); // [{ code: "E100", message: "expected <exp>", ... }]
+//! ```
+//!
+//! Build it with `cargo build --release --target wasm32-unknown-unknown --features wasm`, then
+//! run `wasm-bindgen` over the result. Nothing here touches the file system, stdout or the
+//! clock, which a browser doesn't have.
+
+use js_sys::JSON;
+use wasm_bindgen::prelude::*;
+
+use crate::diagnostic::{render_json_array, SourceContext};
+use crate::{compile_source, json, Options};
+
+/// Checks the source, returning its diagnostics as an array of the objects
+/// `--diagnostics-format json` writes.
+#[wasm_bindgen]
+pub fn check(source: &str) -> JsValue {
+    let file = "<source>";
+    let result = compile_source(file, source, &Options::default());
+    let ctx = SourceContext::new(file, source);
+    parse_json(&render_json_array(&result.diagnostics, &ctx))
+}
+
+/// Lexes the source, returning its tokens as `{kind, lexeme, line, column, start, end}`
+/// objects, or null if it doesn't lex. `start` and `end` are byte offsets into the source as
+/// UTF-8, the end is exclusive.
+#[wasm_bindgen]
+pub fn tokenize(source: &str) -> JsValue {
+    let Some(tokens) = crate::tokenize(source).0 else {
+        return JsValue::NULL;
+    };
+
+    let objects = tokens
+        .iter()
+        .map(|t| {
+            format!(
+                "{{\"kind\": \"{}\", \"lexeme\": {}, \"line\": {}, \"column\": {}, \
+                 \"start\": {}, \"end\": {}}}",
+                t.token.kind(),
+                json::string(&source[t.span.start..t.span.end]),
+                t.span.line,
+                t.span.column,
+                t.span.start,
+                t.span.end,
+            )
+        })
+        .collect::<Vec<_>>();
+    parse_json(&format!("[{}]", objects.join(", ")))
+}
+
+/// Re-indents the source the way `format::format` does, or returns null if it doesn't lex.
+#[wasm_bindgen]
+pub fn format(source: &str) -> Option<String> {
+    let tokens = crate::tokenize(source).0?;
+    Some(crate::format::format(source, &tokens))
+}
+
+fn parse_json(json: &str) -> JsValue {
+    // the JSON is written here, so it always parses.
+    JSON::parse(json).unwrap_or(JsValue::NULL)
+}
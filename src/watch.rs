@@ -0,0 +1,105 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use lua_compiler::term_color::*;
+
+use crate::source;
+
+// how often the files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// editors often write a file in several steps, so wait for it to settle before compiling.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Returns when each of the paths was last modified, None for one that can't be looked at.
+fn modified(paths: &BTreeSet<PathBuf>) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Formats the current time of day (UTC) as `HH:MM:SS`.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Returns what a compilation of `path` depends on, from the files it read.
+///
+/// That's the required modules of a file too. The directories are watched for files being
+/// added or removed, their modification time changes when one is.
+fn watched_paths(path: &Path, read: BTreeSet<PathBuf>) -> BTreeSet<PathBuf> {
+    let mut watched = BTreeSet::from([path.to_path_buf()]);
+    if path.is_dir() {
+        watched.extend(
+            read.iter()
+                .filter_map(|f| f.parent())
+                .map(Path::to_path_buf),
+        );
+    }
+    watched.extend(read);
+    watched
+}
+
+/// Runs `compile` once, then again every time a file it read, or the input at `path`,
+/// changes. This never returns.
+///
+/// `compile` returns whether the compilation succeeded, which is shown in the header printed
+/// after each run.
+pub fn watch<F: FnMut() -> bool>(path: &Path, mut compile: F) -> ! {
+    loop {
+        // clear the screen and move the cursor to the top left corner. this goes to stderr like
+        // everything but the artifact, so it doesn't end up in a redirected output. a terminal
+        // that can't show color can't be cleared either, and a file shouldn't be.
+        if output_caps().color {
+            eprint!("\x1b[2J\x1b[H");
+        }
+
+        // only what this compilation reads is watched, a module it no longer requires isn't.
+        source::take_read();
+        let succeeded = compile();
+        let watched = watched_paths(path, source::take_read());
+        // taken before the header, so a change made once it's out isn't missed.
+        let last = modified(&watched);
+
+        let result = if succeeded {
+            colored("compiled successfully", Color::Green)
+        } else {
+            colored("compilation failed", Color::Red)
+        };
+
+        eprintln!(
+            "[{}] {result}, watching '{}' for changes.",
+            colored(&timestamp(), Color::Grey),
+            path.display()
+        );
+
+        // wait until a modification time moves, then until they stop moving.
+        let mut current = last.clone();
+        while current == last {
+            sleep(POLL_INTERVAL);
+            current = modified(&watched);
+        }
+
+        loop {
+            sleep(DEBOUNCE);
+            let settled = modified(&watched);
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+    }
+}
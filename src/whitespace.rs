@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Span, SpannedToken, Token};
+use crate::snippet::LineIndex;
+
+/// What a line is indented with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Indent {
+    Tabs,
+    Spaces,
+    // tabs and spaces on the same line.
+    Both,
+}
+
+impl Indent {
+    fn of(indentation: &str) -> Option<Indent> {
+        let tabs = indentation.contains('\t');
+        let spaces = indentation.contains(' ');
+        match (tabs, spaces) {
+            (true, false) => Some(Indent::Tabs),
+            (false, true) => Some(Indent::Spaces),
+            (true, true) => Some(Indent::Both),
+            (false, false) => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Indent::Tabs => "tabs",
+            Indent::Spaces => "spaces",
+            Indent::Both => "tabs and spaces",
+        }
+    }
+}
+
+/// Writes line numbers as compact ranges, e.g. `lines 12, 40-43`.
+fn lines(numbers: &[usize]) -> String {
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &n in numbers {
+        match groups.last_mut() {
+            Some((_, last)) if *last + 1 == n => *last = n,
+            _ => groups.push((n, n)),
+        }
+    }
+
+    let groups = groups
+        .iter()
+        .map(|&(first, last)| match first == last {
+            true => first.to_string(),
+            false => format!("{first}-{last}"),
+        })
+        .collect::<Vec<_>>();
+    match numbers.len() {
+        1 => format!("line {}", groups[0]),
+        _ => format!("lines {}", groups.join(", ")),
+    }
+}
+
+/// Warns about the lines that end with whitespace, and the lines of a block indented with
+/// something else than its first indented line, in one warning for each.
+///
+/// The lines within a string that spans lines are left alone, their whitespace is part of it.
+pub fn check(source: &str, tokens: &[SpannedToken]) -> Vec<Diagnostic> {
+    let index = LineIndex::new(source);
+    let mut diagnostics = Vec::new();
+
+    // the strings that span lines, whose line breaks aren't the end of a line of code.
+    let strings = tokens
+        .iter()
+        .filter(|t| matches!(t.token, Token::STRING(_)))
+        .filter(|t| source[t.span.start..t.span.end].contains('\n'))
+        .map(|t| t.span.start..t.span.end)
+        .collect::<Vec<_>>();
+    let in_string = |offset: usize| strings.iter().any(|s| s.contains(&offset));
+
+    let mut trailing = Vec::new();
+    let mut offset = 0;
+    for (i, line) in source.split_inclusive('\n').enumerate() {
+        let text = line.trim_end_matches(['\n', '\r']);
+        let trimmed = text.trim_end_matches([' ', '\t']);
+        if trimmed.len() < text.len() && !in_string(offset + text.len()) {
+            trailing.push((i + 1, offset + trimmed.len()..offset + text.len()));
+        }
+        offset += line.len();
+    }
+
+    if let Some((_, first)) = trailing.first() {
+        let numbers = trailing.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+        diagnostics.push(Diagnostic::warning(
+            "W205",
+            format!("trailing whitespace on {}", lines(&numbers)),
+            span(&index, source, first.start, first.end),
+        ));
+    }
+
+    // the block each line's first token is in, by the index of the token that opened it, and
+    // the main chunk as None. `end` and the like belong to the block around theirs.
+    let mut open: Vec<usize> = Vec::new();
+    let mut blocks: Vec<Option<usize>> = Vec::new();
+    let mut first_tokens = Vec::new();
+    let mut last_line = 0;
+    for (i, t) in tokens.iter().enumerate() {
+        let closes = matches!(
+            t.token,
+            Token::END | Token::UNTIL | Token::ELSE | Token::ELSEIF
+        );
+        let line = index.line_of(t.span.start);
+        if closes {
+            open.pop();
+        }
+        if line != last_line {
+            last_line = line;
+            first_tokens.push(t.span.start);
+            blocks.push(open.last().copied());
+        }
+        // `while` and `for` open their block at their `do`, and every branch of an `if` is a
+        // block of its own.
+        if matches!(
+            t.token,
+            Token::FUNCTION | Token::DO | Token::IF | Token::REPEAT | Token::ELSE | Token::ELSEIF
+        ) {
+            open.push(i);
+        }
+    }
+
+    // a block's first indented line, and the lines that aren't indented the same way.
+    let mut styles: HashMap<Option<usize>, (Indent, Span)> = HashMap::new();
+    let mut mixed: Vec<(Option<usize>, usize, Span)> = Vec::new();
+    for (start, block) in first_tokens.iter().zip(&blocks) {
+        let line = index.line_of(*start);
+        let (line_start, _) = index.line_range(line, source);
+        let Some(indent) = Indent::of(&source[line_start..*start]) else {
+            continue;
+        };
+        let indentation = span(&index, source, line_start, *start);
+
+        match styles.get(block) {
+            Some((style, _)) if *style == indent && indent != Indent::Both => {}
+            Some(_) => mixed.push((*block, line, indentation)),
+            None if indent == Indent::Both => mixed.push((*block, line, indentation)),
+            None => {
+                styles.insert(*block, (indent, indentation));
+            }
+        }
+    }
+
+    let mut reported = Vec::new();
+    for (block, _, indentation) in &mixed {
+        if reported.contains(block) {
+            continue;
+        }
+        reported.push(*block);
+
+        let numbers = mixed
+            .iter()
+            .filter(|(b, _, _)| b == block)
+            .map(|(_, line, _)| *line)
+            .collect::<Vec<_>>();
+        let mut d = Diagnostic::warning(
+            "W205",
+            format!("indentation mixes tabs and spaces on {}", lines(&numbers)),
+            *indentation,
+        );
+        if let Some((style, first)) = styles.get(block) {
+            d = d.with_note(
+                format!(
+                    "the block is indented with {} from line {}.",
+                    style.describe(),
+                    first.line
+                ),
+                Some(*first),
+            );
+        }
+        diagnostics.push(d);
+    }
+
+    diagnostics
+}
+
+/// Builds the span of a range of bytes of the source.
+fn span(index: &LineIndex, source: &str, start: usize, end: usize) -> Span {
+    let (line, column) = index.position(start, source);
+    Span {
+        line,
+        column,
+        start,
+        end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn warnings(source: &str) -> Vec<Diagnostic> {
+        check(source, &Lexer::new(source).tokenize().unwrap())
+    }
+
+    #[test]
+    fn line_numbers_are_grouped() {
+        assert_eq!(lines(&[7]), "line 7");
+        assert_eq!(lines(&[12, 40, 41, 42, 43]), "lines 12, 40-43");
+        assert_eq!(lines(&[1, 2, 4, 6, 7]), "lines 1-2, 4, 6-7");
+    }
+
+    #[test]
+    fn a_file_with_both_problems_gets_one_warning_for_each() {
+        let source = "\
+local x = 1 \n\
+function f()\n\
+\x20 if x then\t\n\
+\t\treturn 1\n\
+\x20 end\n\
+\treturn 2\n\
+end\n\
+local s = [[keep \n\
+this]]\n";
+        let diagnostics = warnings(source);
+        let found = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.span.line, d.span.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                // the space in the long string is part of its value.
+                ("trailing whitespace on lines 1, 3", 1, 12),
+                ("indentation mixes tabs and spaces on line 6", 6, 1),
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].notes[0].message,
+            "the block is indented with spaces from line 3."
+        );
+    }
+
+    #[test]
+    fn a_line_indented_with_both_is_mixed_by_itself() {
+        let found = warnings("do\n \tx = 1\nend\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].message,
+            "indentation mixes tabs and spaces on line 2"
+        );
+        assert!(found[0].notes.is_empty());
+    }
+
+    #[test]
+    fn a_clean_file_has_no_warnings() {
+        // each block is consistent with itself, even if two blocks differ.
+        let source = "function f()\n\tif x then\n\t\treturn 1\n\tend\nend\n\
+                      function g()\n  return [[\n\t \n]]\nend\n";
+        assert!(warnings(source).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "lua-compiler-artifacts-{name}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// runs the compiler in the directory, returning its exit code, stdout and stderr.
+fn run(dir: &Path, args: &[&str]) -> (Option<i32>, String, String) {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        status.code(),
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+#[test]
+fn the_syntax_tree_goes_to_stdout_when_asked_for() {
+    let dir = temp_dir("ast");
+    std::fs::write(dir.join("main.lua"), "local x = 1\n").unwrap();
+
+    let (code, stdout, stderr) = run(&dir, &["--emit=ast", "main.lua"]);
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(stdout.starts_with("Chunk("), "{stdout}");
+
+    // a compile that didn't ask for it doesn't print it.
+    let (code, stdout, _) = run(&dir, &["main.lua"]);
+    assert_eq!((code, stdout.as_str()), (Some(0), ""));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_artifact_goes_to_the_output_file_instead() {
+    let dir = temp_dir("output");
+    std::fs::write(dir.join("main.lua"), "local x = 1\n").unwrap();
+
+    for args in [
+        &["-o", "out/tree.txt", "main.lua"][..],
+        &["--emit=ast", "-o", "out/tree.txt", "main.lua"],
+    ] {
+        let (code, stdout, stderr) = run(&dir, args);
+        assert_eq!(code, Some(0), "{stderr}");
+        assert_eq!((stdout.as_str(), stderr.as_str()), ("", ""));
+        let written = std::fs::read_to_string(dir.join("out/tree.txt")).unwrap();
+        assert!(written.starts_with("Chunk("), "{written}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_input_isnt_overwritten_with_the_artifact() {
+    let dir = temp_dir("input");
+    std::fs::write(dir.join("main.lua"), "local x = 1\n").unwrap();
+
+    let (code, _, stderr) = run(&dir, &["--emit=ast", "-o", "main.lua", "main.lua"]);
+    assert_eq!(code, Some(2), "{stderr}");
+    assert!(
+        stderr.contains("refusing to overwrite the input file"),
+        "{stderr}"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("main.lua")).unwrap(),
+        "local x = 1\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_hash_is_printed_like_sha256sum_and_ignores_formatting() {
+    let dir = temp_dir("hash");
+    std::fs::write(dir.join("a.lua"), "local x = 16\nprint(x)\n").unwrap();
+    std::fs::write(dir.join("b.lua"), "-- the same\nlocal x=0x10 print( x )").unwrap();
+    std::fs::write(dir.join("c.lua"), "local x = 17\nprint(x)\n").unwrap();
+
+    let hash = |file: &str| {
+        let (code, stdout, stderr) = run(&dir, &["--emit=hash", file]);
+        assert_eq!(code, Some(0), "{stderr}");
+        let (hash, name) = stdout.trim_end().split_once("  ").unwrap();
+        assert_eq!(name, file);
+        assert_eq!(hash.len(), 16, "{stdout}");
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()), "{stdout}");
+        hash.to_string()
+    };
+    assert_eq!(hash("a.lua"), hash("b.lua"));
+    assert_ne!(hash("a.lua"), hash("c.lua"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
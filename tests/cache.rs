@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("lua-compiler-cache-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Checks the file with the cache in `cache` and `-v`, returning the diagnostics and whether
+/// the cache was used.
+fn check(dir: &Path) -> (String, bool) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args([
+            "--no-banner",
+            "--check",
+            "--cache-dir",
+            "cache",
+            "-v",
+            "--diagnostics-format=short",
+            "main.lua",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let hit = stderr.contains("main.lua: unchanged since it was last checked, using the cache.");
+    let diagnostics = stderr
+        .lines()
+        .filter(|l| !l.starts_with("debug:"))
+        .map(|l| format!("{l}\n"))
+        .collect();
+    (diagnostics, hit)
+}
+
+const MISSING: &str = "main.lua:1:11: warning: [W200] module 'json' not found\n";
+
+#[test]
+fn the_second_check_of_an_unchanged_file_hits_the_cache() {
+    let dir = temp_dir("hit");
+    std::fs::write(dir.join("main.lua"), "local j = require \"json\"\n").unwrap();
+
+    assert_eq!(check(&dir), (MISSING.to_string(), false));
+    assert_eq!(check(&dir), (MISSING.to_string(), true));
+
+    // a change to the file misses it again.
+    std::fs::write(dir.join("main.lua"), "local j = require \"json\"\n\n").unwrap();
+    assert_eq!(check(&dir), (MISSING.to_string(), false));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_corrupt_cache_is_compiled_over_silently() {
+    let dir = temp_dir("corrupt");
+    std::fs::write(dir.join("main.lua"), "local j = require \"json\"\n").unwrap();
+    check(&dir);
+
+    for entry in std::fs::read_dir(dir.join("cache")).unwrap() {
+        std::fs::write(entry.unwrap().path(), "{\"version\": ").unwrap();
+    }
+    assert_eq!(check(&dir), (MISSING.to_string(), false));
+    // and the entry is written again.
+    assert_eq!(check(&dir), (MISSING.to_string(), true));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
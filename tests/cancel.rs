@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lua_compiler::{CancellationToken, Cancelled, Compiler};
+
+/// A source that takes seconds to compile, with something for every pass to look at.
+fn large_source() -> Arc<String> {
+    let function = "local function f(t, n)\n  local s = ''\n  for i = 1, n do\n    s = s .. t[i]\n  end\n  return s, #t + n * 2\nend\nf({1, 2}, 3)\n";
+    Arc::new(function.repeat(50_000))
+}
+
+fn compile_on_a_thread(
+    source: &Arc<String>,
+    token: &CancellationToken,
+) -> thread::JoinHandle<Result<lua_compiler::CompileResult, Cancelled>> {
+    let (source, token) = (source.clone(), token.clone());
+    thread::spawn(move || Compiler::default().compile_cancellable("big.lua", &source, &token))
+}
+
+#[test]
+fn a_compile_cancelled_midway_returns_promptly() {
+    let source = large_source();
+    let token = CancellationToken::new();
+    let compiling = compile_on_a_thread(&source, &token);
+
+    thread::sleep(Duration::from_millis(50));
+    assert!(
+        !compiling.is_finished(),
+        "the compile is over before it's cancelled"
+    );
+    let cancelled = Instant::now();
+    token.cancel();
+
+    assert_eq!(compiling.join().unwrap().unwrap_err(), Cancelled);
+    let took = cancelled.elapsed();
+    assert!(took < Duration::from_secs(1), "took {took:?} to stop");
+}
+
+#[test]
+fn a_compile_cancelled_before_it_starts_does_nothing() {
+    let token = CancellationToken::new();
+    token.cancel();
+
+    let started = Instant::now();
+    let result = compile_on_a_thread(&large_source(), &token).join().unwrap();
+    assert_eq!(result.unwrap_err(), Cancelled);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("lua-compiler-config-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+    dir
+}
+
+// checks a file requiring a module that doesn't exist from the nested directory.
+fn check(dir: &Path, args: &[&str]) -> Output {
+    std::fs::write(
+        dir.join("src/deep/main.lua"),
+        "local j = require \"json\"\n",
+    )
+    .unwrap();
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir.join("src/deep"))
+        .args(["--no-banner", "--diagnostics-format=short"])
+        .args(args)
+        .arg("main.lua")
+        .output()
+        .unwrap()
+}
+
+const MISSING: &str = "main.lua:1:11: warning: [W200] module 'json' not found\n";
+
+#[test]
+fn the_nearest_config_is_found_from_a_nested_directory() {
+    let dir = temp_dir("nearest");
+    std::fs::write(
+        dir.join("luacompiler.toml"),
+        "[warnings]\nmodule-not-found = \"error\"\n",
+    )
+    .unwrap();
+
+    let output = check(&dir, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        MISSING.replace("warning", "error")
+    );
+
+    // one closer to the file wins over the one further up.
+    std::fs::write(
+        dir.join("src/luacompiler.toml"),
+        "[warnings]\nmodule-not-found = false\n",
+    )
+    .unwrap();
+    let output = check(&dir, &[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn flags_win_over_the_config() {
+    let dir = temp_dir("override");
+    std::fs::write(
+        dir.join("luacompiler.toml"),
+        "[warnings]\nmodule-not-found = false\n",
+    )
+    .unwrap();
+
+    let output = check(&dir, &["-W", "module-not-found"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), MISSING);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_malformed_config_is_an_error_at_its_key() {
+    let dir = temp_dir("malformed");
+    let config = dir.join("luacompiler.toml");
+    std::fs::write(&config, "jobs = 2\ncolour = true\n").unwrap();
+
+    let output = check(&dir, &[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{}:2:1: error: [E300] unknown key 'colour'\n",
+            config.display()
+        )
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
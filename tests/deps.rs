@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-deps-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    dir
+}
+
+// a project where main requires a and b, which both require c, and b a module that isn't there.
+fn diamond(name: &str) -> PathBuf {
+    let dir = temp_dir(name);
+    let files = [
+        (
+            "main.lua",
+            "local a = require \"lib.a\"\nlocal b = require \"lib.b\"\n",
+        ),
+        ("lib/a.lua", "return require \"lib.c\"\n"),
+        (
+            "lib/b.lua",
+            "local c = require \"lib.c\"\nlocal json = require \"json\"\nreturn c\n",
+        ),
+        ("lib/c.lua", "return {}\n"),
+    ];
+    for (file, source) in files {
+        std::fs::write(dir.join(file), source).unwrap();
+    }
+    dir
+}
+
+fn emit(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(["--no-banner", "--emit=deps", "--diagnostics-format=short"])
+        .args(args)
+        .arg(".")
+        .output()
+        .unwrap()
+}
+
+const MISSING: &str = "lib/b.lua:2:14: warning: [W200] module 'json' not found\n";
+
+#[test]
+fn the_graph_of_a_diamond_is_written_as_dot() {
+    let dir = diamond("dot");
+    let output = emit(&dir, &[]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), MISSING);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "digraph deps {\n  \
+         \"lib/a.lua\";\n  \"lib/b.lua\";\n  \"lib/c.lua\";\n  \"main.lua\";\n  \
+         \"lib/a.lua\" -> \"lib/c.lua\" [label=\"lib.c\"];\n  \
+         \"lib/b.lua\" -> \"lib/c.lua\" [label=\"lib.c\"];\n  \
+         \"main.lua\" -> \"lib/a.lua\" [label=\"lib.a\"];\n  \
+         \"main.lua\" -> \"lib/b.lua\" [label=\"lib.b\"];\n  \
+         \"json (not found)\" [shape=box, style=dashed, color=red];\n  \
+         \"lib/b.lua\" -> \"json (not found)\" [style=dashed];\n}\n"
+    );
+}
+
+#[test]
+fn the_graph_of_a_diamond_is_written_as_json() {
+    let dir = diamond("json");
+    let output = emit(&dir, &["--format=json"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), MISSING);
+    let json = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        json.contains(
+            "\"edges\": [{\"from\": \"lib/a.lua\", \"to\": \"lib/c.lua\", \"name\": \"lib.c\"}, \
+             {\"from\": \"lib/b.lua\", \"to\": \"lib/c.lua\", \"name\": \"lib.c\"}, \
+             {\"from\": \"main.lua\", \"to\": \"lib/a.lua\", \"name\": \"lib.a\"}, \
+             {\"from\": \"main.lua\", \"to\": \"lib/b.lua\", \"name\": \"lib.b\"}]"
+        ),
+        "{json}"
+    );
+    assert!(
+        json.contains(
+            "\"missing\": [{\"from\": \"lib/b.lua\", \"name\": \"json\"}], \"cycles\": []"
+        ),
+        "{json}"
+    );
+}
+
+#[test]
+fn a_cycle_is_an_error() {
+    let dir = diamond("cycle");
+    std::fs::write(dir.join("lib/c.lua"), "return require \"lib.a\"\n").unwrap();
+    let output = emit(&dir, &["--format=json"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("cyclic require between lib/a.lua, lib/c.lua."),
+        "{stderr}"
+    );
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("\"cycles\": [[\"lib/a.lua\", \"lib/c.lua\"]]"));
+}
@@ -0,0 +1,97 @@
+//! Runs every output of the compiler twice over the fixtures, and with different numbers of
+//! jobs, and checks that not a byte differs.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What a run printed and how it exited.
+type Run = (Option<i32>, Vec<u8>, Vec<u8>);
+
+fn run(args: &[&str], target: &Path) -> Run {
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--no-banner")
+        .args(args)
+        .arg(target)
+        .output()
+        .unwrap();
+    (output.status.code(), output.stdout, output.stderr)
+}
+
+/// The Lua files of the fixtures and the sample program, relative to the manifest.
+fn corpus() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = vec![PathBuf::from("program.lua")];
+    let mut dirs = vec![root.join("tests/fixtures")];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "lua") {
+                files.push(path.strip_prefix(root).unwrap().to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn every_output_of_a_file_is_the_same_twice() {
+    let outputs: &[&[&str]] = &[
+        &["--dump-tokens"],
+        &["--dump-tokens", "--format=json"],
+        &["--emit=ast"],
+        &["--emit=dot"],
+        &["--emit=hash"],
+        &["--emit=html"],
+        &["--emit=tags"],
+        &["--emit=tags", "--etags"],
+        &["--emit=strings"],
+        &["--emit=strings", "--format=json"],
+        &["--emit=loc", "--format=json"],
+        &["--emit=folds"],
+        &[
+            "--diagnostics-format=json",
+            "-W",
+            "number-style",
+            "-W",
+            "whitespace",
+        ],
+        &["--diagnostics-format=human", "--color=always"],
+    ];
+
+    let files = corpus();
+    assert!(files.len() > 5, "{files:?}");
+    for file in &files {
+        for args in outputs {
+            let first = run(args, file);
+            assert_eq!(run(args, file), first, "{} {args:?}", file.display());
+        }
+    }
+}
+
+#[test]
+fn a_directory_gives_the_same_output_whatever_the_jobs() {
+    let outputs: &[&[&str]] = &[
+        &["--diagnostics-format=json"],
+        &["--diagnostics-format=short"],
+        &["--emit=hash"],
+        &["--emit=loc"],
+        &["--emit=deps", "--format=json"],
+    ];
+
+    let fixtures = Path::new("tests/fixtures");
+    for args in outputs {
+        let one = run(&[&["-j", "1"], *args].concat(), fixtures);
+        assert!(!one.1.is_empty() || !one.2.is_empty(), "{args:?}");
+        for jobs in ["1", "8"] {
+            assert_eq!(
+                run(&[&["-j", jobs], *args].concat(), fixtures),
+                one,
+                "-j {jobs} {args:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use lua_compiler::json::{self, Value};
+
+const SOURCE: &str = "local x = 1\nlocal y = = 2\n";
+
+fn run(format: &str) -> Output {
+    let dir = std::env::temp_dir().join(format!(
+        "lua-compiler-format-{format}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file: PathBuf = dir.join("main.lua");
+    std::fs::write(&file, SOURCE).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .arg(format!("--diagnostics-format={format}"))
+        .arg(&file)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+fn is_string(value: Option<&Value>) -> bool {
+    matches!(value, Some(Value::String(_)))
+}
+
+fn is_position(value: Option<&Value>) -> bool {
+    let number =
+        |key| matches!(value.and_then(|v| v.get(key)), Some(Value::Number(n)) if *n >= 1.0);
+    number("line") && number("column")
+}
+
+#[test]
+fn json_diagnostics_follow_the_schema_on_stdout() {
+    let output = run("json");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("\"severity\""), "{stderr}");
+
+    let diagnostics = stdout
+        .lines()
+        .map(|line| json::parse(line).unwrap_or_else(|| panic!("not JSON: {line}")))
+        .collect::<Vec<_>>();
+    assert!(!diagnostics.is_empty());
+
+    for d in &diagnostics {
+        assert!(d
+            .get("file")
+            .and_then(Value::as_str)
+            .unwrap()
+            .ends_with("main.lua"));
+        assert!(matches!(
+            d.get("severity").and_then(Value::as_str),
+            Some("error" | "warning")
+        ));
+        assert!(is_string(d.get("code")) && is_string(d.get("message")));
+        assert!(
+            is_position(d.get("start")) && is_position(d.get("end")),
+            "{d:?}"
+        );
+        assert!(matches!(
+            d.get("suggestion"),
+            Some(Value::String(_) | Value::Null)
+        ));
+        assert!(matches!(d.get("suppressed"), Some(Value::Boolean(_))));
+
+        for related in d.get("related").and_then(Value::as_array).unwrap() {
+            assert!(is_string(related.get("message")));
+            assert!(is_position(related.get("start")) && is_position(related.get("end")));
+        }
+        for note in d.get("notes").and_then(Value::as_array).unwrap() {
+            assert!(is_string(note.get("message")));
+            let start = note.get("start");
+            assert!(
+                start == Some(&Value::Null) || is_position(start),
+                "{note:?}"
+            );
+        }
+    }
+
+    let error = &diagnostics[0];
+    assert_eq!(error.get("code").and_then(Value::as_str), Some("E100"));
+    assert_eq!(
+        error.get("start").and_then(|s| s.get("line")),
+        Some(&Value::Number(2.0))
+    );
+}
+
+#[test]
+fn github_annotations() {
+    let output = run("github");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let annotation = stderr
+        .lines()
+        .find(|l| l.starts_with("::error "))
+        .unwrap_or_else(|| panic!("no annotation in {stderr}"));
+    assert!(
+        annotation.contains("main.lua,line=2,col=11,"),
+        "{annotation}"
+    );
+    assert!(annotation.contains("title=E100::"), "{annotation}");
+}
@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-diff-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn diff(name: &str, old: &str, new: &str) -> Output {
+    let dir = temp_dir(name);
+    std::fs::write(dir.join("old.lua"), old).unwrap();
+    std::fs::write(dir.join("new.lua"), new).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .args(["--no-banner", "diff", "old.lua", "new.lua"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn a_pure_reformat_is_no_difference() {
+    let output = diff(
+        "reformat",
+        "local function f(a, b)\n  return a + b\nend\n",
+        "-- adds\nlocal function f( a,b ) return a+b end",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
+
+#[test]
+fn an_edit_is_listed_and_exits_with_1() {
+    let output = diff(
+        "edit",
+        "local x = 1\nlocal function f(a, b)\n  return a + b\nend\n",
+        "local function f(a, b)\n  return a - b\nend\nprint(f(1, 2))\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "local 'x' removed (was at line 1)\n\
+         local function 'f' modified (lines 1–3)\n\
+         statement added at line 4\n"
+    );
+}
+
+#[test]
+fn a_file_that_doesnt_parse_exits_with_2() {
+    let output = diff("broken", "local x = 1\n", "local x = = 1\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("[E100]"));
+}
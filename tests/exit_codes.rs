@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-exit-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn exit_code(args: &[&str], file: Option<&Path>) -> Option<i32> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lua-compiler"));
+    command.args(args);
+    if let Some(file) = file {
+        command.arg(file);
+    }
+    command.output().unwrap().status.code()
+}
+
+#[test]
+fn success_is_0() {
+    let dir = temp_dir("success");
+    let file = dir.join("main.lua");
+    std::fs::write(&file, "local x = 1\nprint(x)\n").unwrap();
+
+    assert_eq!(exit_code(&[], Some(&file)), Some(0));
+    assert_eq!(exit_code(&["--help"], None), Some(0));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compile_errors_are_1() {
+    let dir = temp_dir("compile");
+    let cases = [
+        ("lex.lua", "local s = \"unfinished\n"),
+        ("parse.lua", "local x = = 1\n"),
+        // requiring each other is an error once both are parsed.
+        ("a.lua", "local b = require \"b\"\n"),
+    ];
+    std::fs::write(dir.join("b.lua"), "local a = require \"a\"\n").unwrap();
+    for (name, source) in cases {
+        let file = dir.join(name);
+        std::fs::write(&file, source).unwrap();
+        assert_eq!(exit_code(&[], Some(&file)), Some(1), "{name}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn usage_errors_are_2() {
+    let dir = temp_dir("usage");
+    let file = dir.join("main.lua");
+    std::fs::write(&file, "local x = 1\n").unwrap();
+
+    assert_eq!(exit_code(&["--no-such-flag"], Some(&file)), Some(2));
+    assert_eq!(exit_code(&["--color"], None), Some(2));
+    assert_eq!(exit_code(&[], Some(&dir.join("missing.lua"))), Some(2));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn internal_errors_are_3() {
+    let dir = temp_dir("internal");
+    let file = dir.join("main.lua");
+    std::fs::write(&file, "local x = 1\n").unwrap();
+
+    assert_eq!(exit_code(&["--debug-panic=lex"], Some(&file)), Some(3));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
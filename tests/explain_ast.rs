@@ -0,0 +1,74 @@
+use std::process::{Command, Output};
+
+fn explain(expression: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .args(["--no-banner", "--color=never", "--explain-ast", expression])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn an_arithmetic_expression_gets_its_tree_grouping_and_value() {
+    let output = explain("1 + 2 * 3");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "\
+Expression
+  Expression
+    BinaryOp
+      Expression
+        Token 1
+      Token ADD
+      Expression
+        BinaryOp
+          Expression
+            Token 2
+          Token MULTIPLY
+          Expression
+            Token 3
+(1 + (2 * 3))
+= 7
+"
+    );
+}
+
+#[test]
+fn precedence_edge_cases_are_grouped_like_lua() {
+    let last_lines = |expression: &str| {
+        let output = explain(expression);
+        assert_eq!(output.status.code(), Some(0), "{expression}");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines = stdout.lines().collect::<Vec<_>>();
+        lines[lines.len() - 2..].join("\n")
+    };
+
+    // `^` binds tighter than a unary minus, and is right associative like `..`.
+    assert_eq!(last_lines("-2 ^ 2"), "(-(2 ^ 2))\n= -4");
+    assert_eq!(last_lines("2 ^ 3 ^ 2"), "(2 ^ (3 ^ 2))\n= 512");
+    assert_eq!(
+        last_lines("'a' .. 1 .. 2"),
+        "(\"a\" .. (1 .. 2))\n= \"a12\""
+    );
+    // `not` binds tighter than `==`, and names have no value.
+    let output = explain("not a == b");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with("((not a) == b)\n"), "{stdout}");
+}
+
+#[test]
+fn an_error_is_shown_against_the_expression() {
+    let output = explain("1 +");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "\
+error: [E100] expected \"<exp>\", found UNDEFINED.
+ --> <expr>:1:4
+  |
+1 | 1 +
+  |    ^
+"
+    );
+}
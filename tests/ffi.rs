@@ -0,0 +1,78 @@
+//! Calls the C interface the way C does, with `cargo test --features ffi --test ffi`.
+
+use std::ffi::{c_char, CStr};
+
+use lua_compiler::ffi::{luac_check, luac_free_string, luac_parse, luac_version};
+
+// calls one of the functions returning a string over the bytes, and releases the string.
+fn call(f: unsafe extern "C" fn(*const c_char, usize) -> *mut c_char, source: &[u8]) -> String {
+    unsafe {
+        let string = f(source.as_ptr().cast(), source.len());
+        assert!(!string.is_null());
+        let json = CStr::from_ptr(string).to_str().unwrap().to_string();
+        luac_free_string(string);
+        json
+    }
+}
+
+#[test]
+fn check_returns_the_diagnostics_as_json() {
+    assert_eq!(call(luac_check, b"local x = 1\n"), "[]");
+
+    let json = call(luac_check, b"local x = 1\nlocal y = = 2\n");
+    assert!(
+        json.starts_with("[{\"file\": \"<source>\", \"severity\": \"error\", \"code\": \"E100\""),
+        "{json}"
+    );
+    assert!(
+        json.contains("\"start\": {\"line\": 2, \"column\": 11}"),
+        "{json}"
+    );
+}
+
+#[test]
+fn the_source_doesnt_have_to_be_null_terminated() {
+    // only the first line is passed, the broken second one is past `len`.
+    let source = b"local x = 1\nlocal y = = 2\n";
+    let json = unsafe {
+        let string = luac_check(source.as_ptr().cast(), 12);
+        let json = CStr::from_ptr(string).to_str().unwrap().to_string();
+        luac_free_string(string);
+        json
+    };
+    assert_eq!(json, "[]");
+}
+
+#[test]
+fn parse_returns_the_tree_and_the_diagnostics() {
+    let json = call(luac_parse, b"return 1\n");
+    assert!(json.starts_with("{\"ast\": {"), "{json}");
+    assert!(json.ends_with(", \"diagnostics\": []}"), "{json}");
+
+    let json = call(luac_parse, b"return = 1\n");
+    assert!(json.contains("\"code\": \"E100\""), "{json}");
+}
+
+#[test]
+fn a_source_that_isnt_utf8_is_an_error() {
+    assert_eq!(
+        call(luac_check, b"local s = \"\xff\"\n"),
+        "{\"error\": \"the source isn't valid UTF-8\"}"
+    );
+}
+
+#[test]
+fn null_is_an_empty_source_and_is_released_as_nothing() {
+    unsafe {
+        let string = luac_check(std::ptr::null(), 10);
+        assert_eq!(CStr::from_ptr(string).to_str().unwrap(), "[]");
+        luac_free_string(string);
+        luac_free_string(std::ptr::null_mut());
+    }
+}
+
+#[test]
+fn the_version_is_the_crate_version() {
+    let version = unsafe { CStr::from_ptr(luac_version()) };
+    assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+}
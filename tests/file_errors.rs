@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-file-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> (Option<i32>, String) {
+    let Output { status, stderr, .. } = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    (status.code(), String::from_utf8(stderr).unwrap())
+}
+
+#[test]
+fn a_directory_where_a_file_is_expected() {
+    let dir = temp_dir("directory");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("main.lua"), "x = 1\n").unwrap();
+
+    let (code, stderr) = run(&dir, &["diff", "src", "main.lua"]);
+    assert_eq!(code, Some(2), "{stderr}");
+    assert!(
+        stderr.contains("'src' is a directory, pass it as the input to compile every file in it"),
+        "{stderr}"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_missing_file_suggests_a_similar_one() {
+    let dir = temp_dir("missing");
+    std::fs::write(dir.join("Main.lua"), "x = 1\n").unwrap();
+
+    let cases = [
+        (
+            "main.lua",
+            "'main.lua' doesn't exist, maybe you meant 'Main.lua'",
+        ),
+        ("Main", "'Main' doesn't exist, maybe you meant 'Main.lua'"),
+        ("other.lua", "'other.lua' doesn't exist"),
+    ];
+    for (file, message) in cases {
+        let (code, stderr) = run(&dir, &[file]);
+        assert_eq!(code, Some(2), "{stderr}");
+        assert!(stderr.contains(message), "{stderr}");
+    }
+    let (_, stderr) = run(&dir, &["other.lua"]);
+    assert!(!stderr.contains("maybe"), "{stderr}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn an_unreadable_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = temp_dir("unreadable");
+    let file = dir.join("locked.lua");
+    std::fs::write(&file, "x = 1\n").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+    // root reads it anyway, so there's nothing to test then.
+    if std::fs::read(&file).is_err() {
+        let (code, stderr) = run(&dir, &["locked.lua"]);
+        assert_eq!(code, Some(2), "{stderr}");
+        assert!(
+            stderr.contains("'locked.lua' can't be read, permission denied"),
+            "{stderr}"
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_byte_order_mark_is_left_out_of_the_columns() {
+    let dir = temp_dir("bom");
+    std::fs::write(dir.join("main.lua"), "\u{feff}x = = 1\n").unwrap();
+
+    let (code, stderr) = run(&dir, &["--color=never", "main.lua"]);
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("--> main.lua:1:5"), "{stderr}");
+    assert!(!stderr.contains('\u{feff}'), "{stderr}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,32 @@
+--[[
+A module with a bit of everything
+that folds.
+]]
+local M = {}
+
+function M.run(items)
+  for i, item in ipairs(items) do
+    if item.skip then
+      print("skipping", i)
+    elseif item.only then
+      do
+        local help = [[
+first
+second]]
+        print(help)
+      end
+    else
+      repeat
+        item = item.next
+      until not item
+    end
+  end
+
+  while false do print(1) end
+  local f = function()
+    return 1
+  end
+  return f
+end
+
+return M
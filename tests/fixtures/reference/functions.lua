@@ -0,0 +1,5 @@
+local function greet(name)
+  return "hello, " .. name
+end
+
+print(greet("world"))
@@ -0,0 +1,7 @@
+local s = [==[
+long ]] string
+]==]
+-- a comment
+--[[ a long
+comment ]]
+print(s, 0x1F, 1e-3, .5)
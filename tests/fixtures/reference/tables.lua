@@ -0,0 +1,5 @@
+local t = {1, 2, 3, x = 4, ["y"] = 5}
+for i, v in ipairs(t) do
+  t[i] = v * 2 ^ -1
+end
+print(#t, t.x, t["y"])
@@ -0,0 +1,24 @@
+local json = require "json"
+local log = require("app.log")
+
+local messages = {
+  greeting = "Hello",
+  ["farewell"] = 'Goodbye',
+}
+
+local function url(path)
+  return "https://example.com/" .. path
+end
+
+function messages.banner()
+  local function inner()
+    return "tab\there\10\x41\u{48}"
+  end
+  return inner() .. messages["greeting"]
+end
+
+local help = [[
+two
+lines]]
+print(log, json, url("a\z
+    b"), help, [==[]]]==])
@@ -0,0 +1,25 @@
+-- a module with every kind of definition a tag is made for.
+local M = {}
+VERSION = "1.0"
+local width, height = 80, 24
+
+local function clamp(x, lo, hi)
+  local inner = 1
+  return math.max(lo, math.min(x, hi))
+end
+
+function M.new(size)
+  return setmetatable({ size = size }, { __index = M })
+end
+
+function M:resize(size)
+  self.size = clamp(size, 0, width)
+end
+
+M.default = function()
+  return M.new(height)
+end
+
+M.path = "a/b\\c"
+
+return M
@@ -0,0 +1,5 @@
+-- passed around whole, so every field counts as read.
+return {
+  debug = false,
+  level = 2
+}
@@ -0,0 +1,5 @@
+local function helper()
+  return 42
+end
+
+return { helper = helper }
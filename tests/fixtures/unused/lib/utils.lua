@@ -0,0 +1,11 @@
+local M = {}
+
+function M.add(a, b)
+  return a + b
+end
+
+function M.unused()
+  return "nobody calls this"
+end
+
+return M
@@ -0,0 +1,4 @@
+local utils = require "lib.utils"
+local config = require("lib.config")
+
+print(utils.add(1, 2), config)
@@ -0,0 +1,26 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Emits the folds of a fixture, from its directory so the file is named the same everywhere.
+fn emit(file: &str) -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/folds");
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .args(["--no-banner", "--emit=folds", file])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn expected(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/folds")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn the_folds_of_a_nested_file_match_the_expectation() {
+    assert_eq!(emit("nested.lua"), expected("nested.json"));
+}
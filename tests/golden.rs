@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("lua-compiler-golden-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_tests(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(["--no-banner", "--check"])
+        .args(args)
+        .args(["test", "."])
+        .output()
+        .unwrap()
+}
+
+const EXPECTED: &str = "\
+error: [E100] expected \"<eof>\", found ASSIGN.
+ --> fail.lua:1:11
+  |
+1 | local y = = 2
+  |           ^
+";
+
+#[test]
+fn blessing_writes_the_expectations() {
+    let dir = temp_dir("bless");
+    std::fs::write(dir.join("pass.lua"), "local x = 1\n").unwrap();
+    std::fs::write(dir.join("fail.lua"), "local y = = 2\n").unwrap();
+
+    let output = run_tests(&dir, &["--bless"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "blessed 2 expectations.\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("pass.expected")).unwrap(),
+        ""
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("fail.expected")).unwrap(),
+        EXPECTED
+    );
+
+    // and the files pass against them.
+    let output = run_tests(&dir, &[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "ok fail.lua\nok pass.lua\n2 passed, 0 failed.\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failures_are_shown_as_a_diff_and_missing_expectations_fail() {
+    let dir = temp_dir("fail");
+    std::fs::write(dir.join("pass.lua"), "local x = 1\n").unwrap();
+    std::fs::write(dir.join("pass.expected"), "").unwrap();
+    std::fs::write(dir.join("fail.lua"), "local y = = 3\n").unwrap();
+    std::fs::write(dir.join("fail.expected"), EXPECTED).unwrap();
+    std::fs::write(dir.join("new.lua"), "return 1\n").unwrap();
+
+    let output = run_tests(&dir, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "FAIL fail.lua\n\
+         --- fail.expected\n\
+         +++ fail.lua\n\
+         @@ -1,5 +1,5 @@\n \
+         error: [E100] expected \"<eof>\", found ASSIGN.\n  \
+         --> fail.lua:1:11\n   \
+         |\n\
+         -1 | local y = = 2\n\
+         +1 | local y = = 3\n   \
+         |           ^\n\
+         error: new.lua: no expectation, run with --bless to create it.\n\n\
+         ok pass.lua\n\
+         1 passed, 2 failed.\n"
+    );
+    assert!(!dir.join("new.expected").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
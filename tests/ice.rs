@@ -0,0 +1,44 @@
+use std::process::Command;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[test]
+fn a_panic_is_reported_as_an_internal_compiler_error() {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-ice-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("main.lua");
+    std::fs::write(&file, "local x = 1\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .arg("--debug-panic=parse")
+        .arg(&file)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(3), "{stderr}");
+    assert!(stderr.contains("internal compiler error"), "{stderr}");
+    assert!(
+        stderr.contains(&format!("version: {VERSION}\n")),
+        "{stderr}"
+    );
+    assert!(stderr.contains("main.lua"), "{stderr}");
+    assert!(stderr.contains("stage: parse\n"), "{stderr}");
+    assert!(
+        stderr.contains("panic: asked to panic in the parse stage\n"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("location: src/lib.rs:"), "{stderr}");
+    assert!(stderr.contains("please file an issue"), "{stderr}");
+
+    // the details have the source that made it crash.
+    let details = stderr
+        .lines()
+        .find_map(|l| l.strip_prefix("details: "))
+        .unwrap();
+    let details = std::fs::read_to_string(details).unwrap();
+    assert!(details.contains("stage: parse\n"), "{details}");
+    assert!(details.ends_with("source:\nlocal x = 1\n"), "{details}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-jobs-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a few dozen files to the directory, in subdirectories too, with errors, warnings and
+/// requires of each other spread over them.
+fn generate(dir: &Path) {
+    for i in 0..36 {
+        let mut source = format!("local m{i} = {{}}\n");
+        if i % 3 == 0 {
+            source.push_str(&format!(
+                "local other = require \"lib.m{}\"\n",
+                (i + 1) % 36
+            ));
+        }
+        if i % 4 == 0 {
+            source.push_str("local missing = require \"nowhere\"\n");
+        }
+        if i % 5 == 0 {
+            source.push_str("local s = ''\nfor k = 1, 10 do\n  s = s .. k\nend\n");
+        }
+        if i % 7 == 0 {
+            source.push_str("local y = = 2\n");
+        }
+        for f in 0..i % 6 {
+            source.push_str(&format!(
+                "function m{i}.f{f}(a, b)\n  return a + b * {f}\nend\nm{i}.f{f}(1)\n"
+            ));
+        }
+        source.push_str(&format!("return m{i}\n"));
+
+        let path = match i % 2 {
+            0 => dir.join(format!("m{i}.lua")),
+            _ => dir.join(format!("lib/m{i}.lua")),
+        };
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+}
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(args)
+        .arg(".")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn the_output_is_the_same_whatever_the_number_of_jobs() {
+    let dir = temp_dir("same");
+    generate(&dir);
+
+    for args in [
+        &[][..],
+        &["--diagnostics-format=json"],
+        &["--diagnostics-format=short"],
+        &["--emit=hash"],
+    ] {
+        let one = run(&dir, &[args, &["--jobs", "1"]].concat());
+        let eight = run(&dir, &[args, &["--jobs", "8"]].concat());
+
+        assert_eq!(one.status.code(), eight.status.code(), "{args:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&one.stdout),
+            String::from_utf8_lossy(&eight.stdout),
+            "{args:?}"
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&one.stderr),
+            String::from_utf8_lossy(&eight.stderr),
+            "{args:?}"
+        );
+        // every file says something, so an empty output would compare equal for nothing.
+        assert!(!one.stdout.is_empty() || !one.stderr.is_empty(), "{args:?}");
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-loc-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    dir
+}
+
+fn loc(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(["--no-banner", "--diagnostics-format=short", "--emit=loc"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn a_directory_gets_a_row_per_file_and_a_total() {
+    let dir = temp_dir("table");
+    std::fs::write(
+        dir.join("main.lua"),
+        "-- entry\nlocal m = require 'lib.m'\n\nm.run()\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("lib/m.lua"),
+        "return {run = print} -- the module\n",
+    )
+    .unwrap();
+
+    let output = loc(&dir, &["."]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "\
+file         lines    blank  comment     code  keywords  identifiers  literals  operators
+lib/m.lua        1        0        0        1         1            2         0          3
+main.lua         4        1        1        2         1            4         1          4
+total            5        1        1        3         2            6         1          7
+
+the longest line is lib/m.lua:1, 34 characters.
+"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn json_has_the_same_counts() {
+    let dir = temp_dir("json");
+    std::fs::write(dir.join("a.lua"), "x = 1\n").unwrap();
+
+    let output = loc(&dir, &["--format=json", "a.lua"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"files\": [{\"file\": \"a.lua\", \"lines\": 1, \"blank\": 0, \"comment\": 0, \
+         \"code\": 1, \"keywords\": 0, \"identifiers\": 1, \"literals\": 1, \"operators\": 1, \
+         \"longest_line\": {\"line\": 1, \"length\": 5}}], \"total\": {\"lines\": 1, \
+         \"blank\": 0, \"comment\": 0, \"code\": 1, \"keywords\": 0, \"identifiers\": 1, \
+         \"literals\": 1, \"operators\": 1, \"longest_line\": {\"file\": \"a.lua\", \"line\": 1, \
+         \"length\": 5}}}\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_file_that_does_not_lex_is_reported_and_left_out() {
+    let dir = temp_dir("broken");
+    std::fs::write(dir.join("a.lua"), "x = 1\n").unwrap();
+    std::fs::write(dir.join("lib/bad.lua"), "y = \"\n").unwrap();
+
+    let output = loc(&dir, &["."]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("\na.lua ") && !stdout.contains("bad.lua"),
+        "{stdout}"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "lib/bad.lua:1:5: error: [E001] unfinished string near '\"'.\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,223 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Instant;
+
+use lua_compiler::json::{self, Value};
+
+/// A language server running in a child process, spoken to over its stdin and stdout.
+struct Client {
+    server: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let input = server.stdin.take().unwrap();
+        let output = BufReader::new(server.stdout.take().unwrap());
+        Client {
+            server,
+            input,
+            output,
+        }
+    }
+
+    fn send(&mut self, body: &str) {
+        write!(self.input, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.input.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            self.output.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.parse::<usize>().ok();
+            }
+        }
+
+        let mut body = vec![0; length.expect("the message has a Content-Length")];
+        self.output.read_exact(&mut body).unwrap();
+        json::parse(&String::from_utf8(body).unwrap()).expect("the message is JSON")
+    }
+
+    fn initialize(&mut self) {
+        self.send(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#);
+        assert!(self.receive().get("result").is_some());
+        self.send(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#);
+    }
+
+    /// Shuts the server down the way the protocol asks to, returning its exit code.
+    fn exit(mut self) -> Option<i32> {
+        self.send(r#"{"jsonrpc":"2.0","id":99,"method":"shutdown"}"#);
+        assert_eq!(self.receive().get("result"), Some(&Value::Null));
+        self.send(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+        self.server.wait().unwrap().code()
+    }
+}
+
+fn number(value: Option<&Value>) -> f64 {
+    match value {
+        Some(Value::Number(n)) => *n,
+        other => panic!("expected a number, got {other:?}"),
+    }
+}
+
+#[test]
+fn publishes_the_diagnostics_of_an_opened_document() {
+    let mut client = Client::start();
+    client.initialize();
+    client.send(
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":
+            {"uri":"file:///main.lua","languageId":"lua","version":1,
+             "text":"local x = 1\nlocal y = = 2\n"}}}"#,
+    );
+
+    let message = client.receive();
+    assert_eq!(
+        message.get("method").and_then(Value::as_str),
+        Some("textDocument/publishDiagnostics")
+    );
+    let params = message.get("params").unwrap();
+    assert_eq!(
+        params.get("uri").and_then(Value::as_str),
+        Some("file:///main.lua")
+    );
+
+    let diagnostics = params.get("diagnostics").and_then(Value::as_array).unwrap();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    let diagnostic = &diagnostics[0];
+    assert_eq!(diagnostic.get("code").and_then(Value::as_str), Some("E100"));
+    assert_eq!(number(diagnostic.get("severity")), 1.0);
+    let start = diagnostic
+        .get("range")
+        .and_then(|r| r.get("start"))
+        .unwrap();
+    // the protocol counts lines from 0, the error is on the second one.
+    assert_eq!(number(start.get("line")), 1.0);
+
+    assert_eq!(client.exit(), Some(0));
+}
+
+#[test]
+fn survives_malformed_messages() {
+    let mut client = Client::start();
+    client.initialize();
+
+    client.send("{not json");
+    let reply = client.receive();
+    assert_eq!(
+        number(reply.get("error").and_then(|e| e.get("code"))),
+        -32700.0
+    );
+
+    // a length the server won't allocate for is answered, not trusted.
+    write!(client.input, "Content-Length: 99999999999999\r\n\r\n").unwrap();
+    client.input.flush().unwrap();
+    let reply = client.receive();
+    assert_eq!(
+        number(reply.get("error").and_then(|e| e.get("code"))),
+        -32600.0
+    );
+
+    // the body that never comes is waited for until the client hangs up.
+    drop(client.input);
+    assert_eq!(client.server.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn a_change_cancels_the_compile_of_the_text_it_replaces() {
+    let mut client = Client::start();
+    client.initialize();
+    let open = |client: &mut Client, uri: &str, text: &str| {
+        client.send(&format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":
+                {{"uri":"{uri}","languageId":"lua","version":1,"text":"{text}"}}}}}}"#
+        ))
+    };
+
+    // how long the server takes with a text long enough to still be compiling when a change
+    // to it comes.
+    let large = "x = 1\\n".repeat(50_000);
+    let started = Instant::now();
+    open(&mut client, "file:///other.lua", &large);
+    client.receive();
+    let compiling = started.elapsed();
+
+    open(&mut client, "file:///main.lua", &large);
+    client.send(
+        r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":
+            {"uri":"file:///main.lua","version":2},
+            "contentChanges":[{"text":"local y = = 2\n"}]}}"#,
+    );
+
+    let message = client.receive();
+    let params = message.get("params").unwrap();
+    let diagnostics = params.get("diagnostics").and_then(Value::as_array).unwrap();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    assert_eq!(
+        diagnostics[0].get("code").and_then(Value::as_str),
+        Some("E100")
+    );
+
+    // the first text would be published by now, but the next message is the reply to the
+    // shutdown.
+    std::thread::sleep(compiling * 2);
+    assert_eq!(client.exit(), Some(0));
+}
+
+#[test]
+fn folds_an_opened_document_even_with_an_error() {
+    let mut client = Client::start();
+    client.initialize();
+    client.send(
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":
+            {"uri":"file:///main.lua","languageId":"lua","version":1,
+             "text":"--[[\nabout\n]]\nfunction f()\n  x = = 1\n  return 1\nend\n"}}}"#,
+    );
+    assert_eq!(
+        client.receive().get("method").and_then(Value::as_str),
+        Some("textDocument/publishDiagnostics")
+    );
+
+    client.send(
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/foldingRange",
+            "params":{"textDocument":{"uri":"file:///main.lua"}}}"#,
+    );
+    let reply = client.receive();
+    let ranges = reply
+        .get("result")
+        .and_then(Value::as_array)
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                number(r.get("startLine")),
+                number(r.get("endLine")),
+                r.get("kind").and_then(Value::as_str).unwrap().to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    // the protocol counts lines from 0.
+    assert_eq!(
+        ranges,
+        [
+            (0.0, 2.0, "comment".to_string()),
+            (3.0, 5.0, "region".to_string())
+        ]
+    );
+
+    assert_eq!(client.exit(), Some(0));
+}
@@ -0,0 +1,119 @@
+//! Runs the inputs that used to crash the fuzz targets, along with random ones, through the
+//! lexer and the parser, which have to turn anything into diagnostics rather than panic.
+
+use std::path::Path;
+
+use lua_compiler::diagnostic::{DiagnosticSink, RenderingSink, SourceContext};
+use lua_compiler::lexer::Lexer;
+use lua_compiler::parser::Parser;
+use lua_compiler::term_color::OutputCaps;
+use lua_compiler::Compiler;
+
+/// Reads every input of `fuzz/regressions/<target>`, with its name.
+fn regressions(target: &str) -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fuzz/regressions")
+        .join(target);
+    let mut inputs = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            (name, std::fs::read_to_string(&path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    inputs.sort();
+    inputs
+}
+
+// the stack the compiler's own threads get, the nesting limit keeps the parser well within it.
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Runs `f` on a thread with the stack the compiler gives its own, rather than the 2 MiB test
+/// threads get by default.
+fn with_stack(f: impl FnOnce() + Send + 'static) {
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+/// Does what both fuzz targets do, lexing and rendering the diagnostics, then parsing.
+fn lex_and_parse(source: &str) {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize();
+
+    let ctx = SourceContext::new("fuzz.lua", source);
+    let caps = OutputCaps {
+        color: false,
+        unicode: true,
+    };
+    let mut sink = RenderingSink::new(&ctx, Default::default(), caps);
+    for d in lexer.diagnostics() {
+        sink.emit(d.clone());
+    }
+    sink.finish();
+
+    if let Some(tokens) = tokens {
+        Parser::new(tokens).parse();
+    }
+}
+
+#[test]
+fn the_lexer_regressions_are_reported_rather_than_panicking() {
+    let inputs = regressions("lexer");
+    assert!(!inputs.is_empty());
+
+    with_stack(move || {
+        for (name, source) in inputs {
+            lex_and_parse(&source);
+            let result = Compiler::default().compile(&name, &source);
+            assert!(!result.diagnostics.is_empty(), "{name}");
+        }
+    });
+}
+
+#[test]
+fn the_parser_regressions_are_reported_rather_than_panicking() {
+    let inputs = regressions("parser");
+    assert!(!inputs.is_empty());
+
+    with_stack(move || {
+        for (name, source) in inputs {
+            lex_and_parse(&source);
+            let result = Compiler::default().compile(&name, &source);
+            assert!(!result.diagnostics.is_empty(), "{name}");
+        }
+    });
+}
+
+#[test]
+fn random_input_never_panics() {
+    // xorshift, so every run tries the same inputs.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    // bytes biased towards what lua is written with, so the parser gets to see some tokens.
+    let alphabet = "local function end if then else return ( ) { } [ ] = == ~= . .. ... , ; : \
+                    + - * / % ^ # < > \" ' \\ [[ ]] -- 0x 1e 1.5 _ a b \n \r \t é 💡";
+    let pieces = alphabet.split(' ').collect::<Vec<_>>();
+
+    for _ in 0..5000 {
+        let len = next() % 40;
+        let source = (0..len)
+            .map(|_| match next() % 8 {
+                // now and then a byte that may not even be valid UTF-8 on its own.
+                0 => String::from_utf8_lossy(&[next() as u8]).into_owned(),
+                _ => pieces[next() as usize % pieces.len()].to_string(),
+            })
+            .collect::<String>();
+        lex_and_parse(&source);
+    }
+}
@@ -0,0 +1,63 @@
+use lua_compiler::diagnostic::Severity;
+use lua_compiler::lexer::Token;
+use lua_compiler::parser::ASTNode;
+use lua_compiler::Compiler;
+
+// the number of statements of a chunk.
+fn statements(chunk: &ASTNode) -> usize {
+    match chunk {
+        ASTNode::Chunk(statements, last) => statements.len() + usize::from(last.is_some()),
+        other => panic!("not a chunk: {other:?}"),
+    }
+}
+
+#[test]
+fn a_syntax_error_still_gives_the_tokens_and_the_statements_before_it() {
+    let source = "local a = 1\nlocal b = a + 1\nx = = 1\nlocal c = 3\n";
+    let result = Compiler::default().compile("main.lua", source);
+
+    assert!(result.has_errors());
+    assert!(result.ast.is_none());
+    // every token, even the ones after the error.
+    let tokens = result.tokens.as_ref().expect("the source lexes");
+    assert_eq!(tokens.last().map(|t| &t.token), Some(&Token::NUMBER(3.0)));
+
+    let partial = result.partial_ast.as_ref().expect("part of it parses");
+    assert_eq!(statements(partial), 2);
+    assert_eq!(result.statement_spans.len(), 2);
+    assert_eq!(result.statement_spans[1].line, 2);
+
+    // the error, and the rest of the source that couldn't be parsed.
+    let errors = result
+        .diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .collect::<Vec<_>>();
+    assert!(!errors.is_empty());
+    assert!(
+        errors.iter().all(|d| d.code == "E100" && d.span.line == 3),
+        "{errors:?}"
+    );
+}
+
+#[test]
+fn a_source_that_does_not_lex_has_nothing_but_diagnostics() {
+    let result = Compiler::default().compile("main.lua", "local s = \"unfinished\n");
+    assert!(result.has_errors());
+    assert!(result.tokens.is_none());
+    assert!(result.ast.is_none() && result.partial_ast.is_none());
+    assert_eq!(result.diagnostics[0].code, "E001");
+}
+
+#[test]
+fn warnings_do_not_take_the_tree_away() {
+    let source = "local s = ''\nfor i = 1, 3 do s = s .. i end\n";
+    let result = Compiler::default().compile("main.lua", source);
+    assert!(!result.has_errors());
+    assert_eq!(result.diagnostics.len(), 1);
+    assert_eq!(result.diagnostics[0].code, "W206");
+    assert!(result.tokens.is_some());
+    // the whole tree, so there's no partial one.
+    assert_eq!(statements(result.ast.as_ref().unwrap()), 2);
+    assert!(result.partial_ast.is_none());
+}
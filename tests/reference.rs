@@ -0,0 +1,145 @@
+//! Compares what the compiler accepts with a reference `luac`, run with
+//! `LUA_REF=/usr/bin/luac5.1 cargo test --features reftest`.
+//!
+//! Every file of `tests/fixtures/reference` is parsed by both, which must accept it or reject it
+//! on the same line. The files listed in its `reference.skip` are left out, e.g. the ones using
+//! what only a later version of Lua than the reference understands.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use lua_compiler::chunk::ChunkName;
+use lua_compiler::diagnostic::Severity;
+use lua_compiler::Compiler;
+
+/// The file of the fixtures listing the files the reference isn't compared with.
+const SKIP_FILE: &str = "reference.skip";
+
+/// Whether a parser accepted a source, and the line of the first error if it didn't.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verdict {
+    Accepted,
+    Rejected(Option<usize>),
+}
+
+impl Verdict {
+    fn describe(&self) -> String {
+        match self {
+            Verdict::Accepted => "accepted".to_string(),
+            Verdict::Rejected(Some(line)) => format!("rejected on line {line}"),
+            Verdict::Rejected(None) => "rejected".to_string(),
+        }
+    }
+}
+
+/// Reads the skip file, one path relative to the fixtures per line, optionally followed by `#`
+/// and why it's skipped. Blank lines and lines starting with `#` are ignored.
+fn parse_skip_file(text: &str) -> HashMap<&str, Option<&str>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once('#') {
+            Some((path, reason)) => (path.trim(), Some(reason.trim())),
+            None => (line, None),
+        })
+        .collect()
+}
+
+/// Finds the line of the error in what `luac` printed, `luac: <chunk>:<line>: <message>`, where
+/// the chunk is the file's name cut the way the reference cuts long ones.
+fn error_line(stderr: &str, file: &str) -> Option<usize> {
+    let rest = &stderr[stderr.find(file)? + file.len()..];
+    let digits = rest.strip_prefix(':')?;
+    let end = digits.find(|c: char| !c.is_ascii_digit())?;
+    digits[..end].parse().ok()
+}
+
+fn reference_verdict(luac: &Path, file: &Path) -> Verdict {
+    let output = Command::new(luac)
+        .arg("-p")
+        .arg(file)
+        .output()
+        .unwrap_or_else(|e| panic!("could not run '{}': {e}", luac.display()));
+    match output.status.success() {
+        true => Verdict::Accepted,
+        false => Verdict::Rejected(error_line(
+            &String::from_utf8_lossy(&output.stderr),
+            &ChunkName::from(file).id(),
+        )),
+    }
+}
+
+fn our_verdict(file: &Path) -> Verdict {
+    let source = std::fs::read_to_string(file).unwrap();
+    // the errors of lexing and parsing are E0xx and E1xx, the rest are about other files,
+    // which the reference doesn't look at.
+    Compiler::default()
+        .compile(&file.display().to_string(), &source)
+        .diagnostics
+        .iter()
+        .filter(|d| d.code.starts_with("E0") || d.code.starts_with("E1"))
+        .find(|d| d.severity == Severity::Error)
+        .map_or(Verdict::Accepted, |d| Verdict::Rejected(Some(d.span.line)))
+}
+
+#[test]
+fn accepts_and_rejects_like_the_reference() {
+    let Some(luac) = std::env::var_os("LUA_REF").map(PathBuf::from) else {
+        eprintln!("LUA_REF isn't set, there's no reference to compare with.");
+        return;
+    };
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/reference");
+    let skip_file = std::fs::read_to_string(dir.join(SKIP_FILE)).unwrap_or_default();
+    let skipped = parse_skip_file(&skip_file);
+
+    let mut files = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "lua"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut failures = Vec::new();
+    for file in &files {
+        let name = file.file_name().unwrap().to_string_lossy();
+        if skipped.contains_key(&*name) {
+            continue;
+        }
+
+        let (ours, reference) = (our_verdict(file), reference_verdict(&luac, file));
+        // the reference's message may not have a line that can be read back.
+        let agrees = match (ours, reference) {
+            (Verdict::Rejected(_), Verdict::Rejected(None)) => true,
+            _ => ours == reference,
+        };
+        if !agrees {
+            failures.push(format!(
+                "{name}: {}, but {} {}",
+                ours.describe(),
+                luac.display(),
+                reference.describe()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn the_skip_file_has_a_path_and_a_reason_per_line() {
+    let skipped = parse_skip_file("# a comment\n\ngoto.lua # labels came with 5.2\n  a/b.lua\n");
+    assert_eq!(skipped.len(), 2);
+    assert_eq!(skipped["goto.lua"], Some("labels came with 5.2"));
+    assert_eq!(skipped["a/b.lua"], None);
+}
+
+#[test]
+fn the_error_line_is_read_after_the_chunk_name() {
+    let stderr = "luac: main.lua:12: unexpected symbol near '='\n";
+    assert_eq!(error_line(stderr, "main.lua"), Some(12));
+    // a chunk name with a colon of its own, and a message without a line.
+    assert_eq!(error_line("luac: a:b.lua:3: x\n", "a:b.lua"), Some(3));
+    assert_eq!(error_line("luac: cannot open main.lua\n", "main.lua"), None);
+}
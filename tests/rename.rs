@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("lua-compiler-rename-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn rename(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(["--no-banner", "rename"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+const SOURCE: &str = "local n = 1\ndo\n  local n = 2\n  print(n)\nend\nprint(n)\n";
+
+#[test]
+fn the_file_is_rewritten_in_place() {
+    let dir = temp_dir("in-place");
+    std::fs::write(dir.join("main.lua"), SOURCE).unwrap();
+
+    let output = rename(&dir, &["main.lua", "3:9", "inner"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("main.lua")).unwrap(),
+        "local n = 1\ndo\n  local inner = 2\n  print(inner)\nend\nprint(n)\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_dry_run_prints_a_patch_and_leaves_the_file() {
+    let dir = temp_dir("dry-run");
+    std::fs::write(dir.join("main.lua"), SOURCE).unwrap();
+
+    let output = rename(&dir, &["--dry-run", "main.lua", "1:7", "outer"]);
+    assert_eq!(output.status.code(), Some(0));
+    let patch = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        patch.contains("-local n = 1\n+local outer = 1\n"),
+        "{patch}"
+    );
+    assert!(patch.contains("-print(n)\n+print(outer)\n"), "{patch}");
+    assert!(!patch.contains("local inner"), "{patch}");
+    assert_eq!(
+        std::fs::read_to_string(dir.join("main.lua")).unwrap(),
+        SOURCE
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_conflicting_name_is_refused_with_the_reason() {
+    let dir = temp_dir("conflict");
+    std::fs::write(
+        dir.join("main.lua"),
+        "local a = 1\nlocal b = 2\nprint(a, b)\n",
+    )
+    .unwrap();
+
+    let output = rename(&dir, &["main.lua", "1:7", "b"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap().trim_end(),
+        "error: can't rename at main.lua:1:7, 'b' already refers to another variable where 'a' is used \
+         on line 3."
+    );
+
+    let output = rename(&dir, &["main.lua", "1:7", "while"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'while' is a keyword"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("main.lua")).unwrap(),
+        "local a = 1\nlocal b = 2\nprint(a, b)\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
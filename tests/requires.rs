@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "lua-compiler-requires-{name}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(dir: &Path, relative: &str, source: &str) {
+    let path = dir.join(relative);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, source).unwrap();
+}
+
+fn check(dir: &Path, file: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(dir)
+        .args(["--no-banner", "--diagnostics-format=short", file])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn the_required_modules_are_checked_too() {
+    let dir = temp_dir("checked");
+    write(
+        &dir,
+        "main.lua",
+        "local utils = require \"lib.utils\"\nlocal config = require(\"lib.config\")\nreturn utils.f(config)\n",
+    );
+    write(
+        &dir,
+        "lib/utils.lua",
+        "local M = {}\nfunction M.f(c) return c end\nreturn M\n",
+    );
+    // a module can also be a directory with an init.lua.
+    write(&dir, "lib/config/init.lua", "local x = = 1\nreturn {}\n");
+
+    let output = check(&dir, "main.lua");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "lib/config/init.lua:1:11: error: [E100] expected \"<eof>\", found ASSIGN.\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_cycle_is_an_error_with_the_chain_of_requires() {
+    let dir = temp_dir("cycle");
+    write(&dir, "main.lua", "return require \"a\"\n");
+    write(&dir, "a.lua", "return require \"b\"\n");
+    write(&dir, "b.lua", "return require \"a\"\n");
+
+    let output = check(&dir, "main.lua");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "b.lua:1:8: error: [E201] cyclic require: a -> b -> a\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_module_that_cant_be_found_is_a_warning() {
+    let dir = temp_dir("missing");
+    write(
+        &dir,
+        "main.lua",
+        "local json = require \"json\"\nlocal name = \"x\"\nreturn require(name)\n",
+    );
+
+    // the computed name is left to the runtime.
+    let output = check(&dir, "main.lua");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "main.lua:1:14: warning: [W200] module 'json' not found\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,63 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// runs the compiler on a source piped to its stdin.
+fn pipe(args: &[&str], source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .arg("--no-banner")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn diagnostics_of_stdin_are_named_after_the_stdin_filename() {
+    // the path doesn't exist, it's only a name.
+    let output = pipe(
+        &[
+            "--diagnostics-format=json",
+            "--stdin-filename",
+            "src/unsaved.lua",
+            "-",
+        ],
+        "local x = = 1\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    // json goes to stdout, for the editor to read.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(
+            "{\"file\": \"src/unsaved.lua\", \"severity\": \"error\", \"code\": \"E100\""
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn stdin_without_a_name_is_called_stdin() {
+    let output = pipe(&["--diagnostics-format=short", "-"], "local x = = 1\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "stdin:1:11: error: [E100] expected \"<eof>\", found ASSIGN.\n"
+    );
+}
+
+#[test]
+fn the_stdin_filename_is_rejected_for_a_file() {
+    let output = pipe(&["--stdin-filename", "b.lua", "Cargo.toml"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--stdin-filename only applies when the source is read from stdin."));
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Emits the strings of the fixture, from its directory so the file is named the same everywhere.
+fn emit(args: &[&str]) -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/strings");
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .args(["--no-banner", "--emit=strings"])
+        .args(args)
+        .arg("strings.lua")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn expected(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/strings")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn every_literal_matches_the_expectation() {
+    assert_eq!(emit(&[]), expected("strings.tsv"));
+}
+
+#[test]
+fn requires_and_keys_can_be_left_out() {
+    let all = emit(&[]);
+    let filtered = emit(&["--skip-strings", "require", "--skip-strings", "keys"]);
+    assert_eq!(filtered, expected("filtered.tsv"));
+
+    // each filter leaves out only its own.
+    let without_requires = emit(&["--skip-strings", "require"]);
+    assert_eq!(all.lines().count() - 2, without_requires.lines().count());
+    assert!(!without_requires.contains("\"app.log\""));
+    let without_keys = emit(&["--skip-strings", "keys"]);
+    assert_eq!(all.lines().count() - 2, without_keys.lines().count());
+    assert!(!without_keys.contains("'farewell'") && !without_keys.contains("\"greeting\""));
+}
+
+#[test]
+fn json_has_the_decoded_value_and_the_raw_literal() {
+    let json = emit(&["--format=json", "--skip-strings", "require"]);
+    assert!(json.starts_with(
+        "[{\"file\": \"strings.lua\", \"line\": 5, \"column\": 14, \"function\": null, \
+         \"value\": \"Hello\", \"raw\": \"\\\"Hello\\\"\"}, "
+    ));
+    assert!(json.contains(
+        "{\"file\": \"strings.lua\", \"line\": 15, \"column\": 12, \"function\": \"inner\", \
+         \"value\": \"tab\\there\\nAH\", \"raw\": \"\\\"tab\\\\there\\\\10\\\\x41\\\\u{48}\\\"\"}"
+    ));
+    assert!(json.contains("\"value\": \"two\\nlines\", \"raw\": \"[[\\ntwo\\nlines]]\""));
+}
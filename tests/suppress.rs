@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "lua-compiler-suppress-{name}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn check(name: &str, args: &[&str]) -> Output {
+    let dir = temp_dir(name);
+    std::fs::write(
+        dir.join("main.lua"),
+        "for i = 1, 3 do s = s .. i end --luacompiler: disable-line W206\n\
+         x = 1 --luacompiler: disable-line\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .arg("--no-banner")
+        .args(args)
+        .arg("main.lua")
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+#[test]
+fn suppressed_warnings_are_dropped_and_unused_directives_reported() {
+    let output = check("short", &["--diagnostics-format=short"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "main.lua:2:7: warning: [W001] unused suppression, line 2 has no warning it silences\n"
+    );
+}
+
+#[test]
+fn a_suppressed_warning_made_an_error_does_not_fail_the_check() {
+    let output = check(
+        "denied",
+        &["--diagnostics-format=short", "-W", "error=W206"],
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("W206"));
+}
+
+#[test]
+fn json_marks_the_suppressed_ones_when_they_are_shown() {
+    let output = check("json", &["--diagnostics-format=json", "--show-suppressed"]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert!(lines[0].contains("\"code\": \"W206\""));
+    assert!(lines[0].ends_with("\"suppressed\": true}"));
+    assert!(lines[1].contains("\"code\": \"W001\""));
+    assert!(lines[1].ends_with("\"suppressed\": false}"));
+
+    // without the flag they're left out.
+    let output = check("json-hidden", &["--diagnostics-format=json"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(!stdout.contains("W206"));
+}
@@ -0,0 +1,33 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Emits the tags of the fixture, from its directory so the file is named the same everywhere.
+fn emit(args: &[&str]) -> String {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tags");
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .args(["--no-banner", "--emit=tags"])
+        .args(args)
+        .arg("module.lua")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn expected(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tags")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn ctags_match_the_expectation() {
+    assert_eq!(emit(&[]), expected("module.tags"));
+}
+
+#[test]
+fn etags_match_the_expectation() {
+    assert_eq!(emit(&["--etags"]), expected("module.TAGS"));
+}
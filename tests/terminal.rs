@@ -0,0 +1,90 @@
+use std::process::Command;
+
+// the error is past the end of a line long enough to be cut down to a window with a `…`.
+fn source() -> String {
+    format!("local s = \"{}\" .. = 1\n", "a".repeat(100))
+}
+
+/// Compiles the source with `--color=always` in a terminal described by `TERM` and `LANG`,
+/// returning what's written to stderr.
+fn render(term: &str, lang: &str) -> String {
+    render_with("always", term, lang, None)
+}
+
+/// Compiles the source with the `--color` choice, in a terminal described by `TERM` and `LANG`
+/// and with `NO_COLOR` set to `no_color` if given. Stderr is a pipe, never a terminal.
+fn render_with(choice: &str, term: &str, lang: &str, no_color: Option<&str>) -> String {
+    let dir = std::env::temp_dir().join(format!(
+        "lua-compiler-terminal-{choice}-{term}-{lang}-{}-{}",
+        no_color.is_some(),
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("main.lua");
+    std::fs::write(&file, source()).unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_lua-compiler"));
+    command
+        .arg(format!("--color={choice}"))
+        .arg("--no-banner")
+        .arg(&file)
+        .env("TERM", term)
+        .env("LANG", lang)
+        .env_remove("LC_ALL")
+        .env_remove("LC_CTYPE")
+        .env_remove("NO_COLOR");
+    if let Some(value) = no_color {
+        command.env("NO_COLOR", value);
+    }
+    let output = command.output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn a_capable_terminal_gets_colors_and_unicode() {
+    let stderr = render("xterm-256color", "en_US.UTF-8");
+    assert!(stderr.contains("\x1b["), "{stderr:?}");
+    assert!(stderr.contains('…'), "{stderr:?}");
+}
+
+#[test]
+fn a_dumb_terminal_gets_plain_ascii_even_when_color_is_asked_for() {
+    let stderr = render("dumb", "en_US.UTF-8");
+    assert!(!stderr.contains('\x1b'), "{stderr:?}");
+    assert!(stderr.is_ascii(), "{stderr:?}");
+    assert!(stderr.contains("| ...aaaa"), "{stderr:?}");
+}
+
+#[test]
+fn a_locale_that_isnt_utf8_gets_colors_but_ascii() {
+    let stderr = render("xterm-256color", "C");
+    assert!(stderr.contains("\x1b["), "{stderr:?}");
+    assert!(stderr.is_ascii(), "{stderr:?}");
+    assert!(stderr.contains("...aaaa"), "{stderr:?}");
+}
+
+#[test]
+fn never_has_no_escapes_even_in_a_capable_terminal() {
+    let stderr = render_with("never", "xterm-256color", "en_US.UTF-8", None);
+    assert!(!stderr.contains('\x1b'), "{stderr:?}");
+    assert!(stderr.contains("error"), "{stderr:?}");
+}
+
+#[test]
+fn auto_has_no_escapes_when_the_output_is_piped() {
+    let stderr = render_with("auto", "xterm-256color", "en_US.UTF-8", None);
+    assert!(!stderr.contains('\x1b'), "{stderr:?}");
+}
+
+#[test]
+fn no_color_only_turns_off_auto() {
+    let stderr = render_with("auto", "xterm-256color", "en_US.UTF-8", Some("1"));
+    assert!(!stderr.contains('\x1b'), "{stderr:?}");
+
+    // an explicit choice wins over the environment.
+    let stderr = render_with("always", "xterm-256color", "en_US.UTF-8", Some("1"));
+    assert!(stderr.contains("\x1b["), "{stderr:?}");
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Checks the fixture project, from its directory so the files are named the same everywhere.
+fn check(args: &[&str]) -> Output {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/unused");
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .args(["--no-banner", "--diagnostics-format=short"])
+        .args(args)
+        .arg(".")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn the_orphan_module_and_the_unused_export_are_the_only_warnings() {
+    let output = check(&["--entry", "main.lua"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "./lib/orphan.lua:1:1: warning: [W201] module 'lib/orphan.lua' isn't required by any \
+         file\n\
+         ./lib/utils.lua:7:12: warning: [W202] field 'unused' of module 'lib/utils.lua' isn't \
+         read by any module that requires it\n"
+    );
+}
+
+#[test]
+fn without_an_entry_the_project_is_not_looked_at_as_a_whole() {
+    let output = check(&[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+}
+
+#[test]
+fn an_entry_has_to_be_one_of_the_files() {
+    let output = check(&["--entry", "missing.lua"]);
+    assert_eq!(output.status.code(), Some(2));
+}
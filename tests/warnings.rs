@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// a directory of its own for every test, the tests run at the same time.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "lua-compiler-warnings-{name}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .arg("--no-banner")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// a source with two warnings, a missing module and a string built up in a loop.
+fn check(name: &str, args: &[&str]) -> (Option<i32>, String) {
+    let dir = temp_dir(name);
+    std::fs::write(
+        dir.join("main.lua"),
+        "local j = require \"json\"\nlocal s = \"\"\nfor i = 1, 3 do\n  s = s .. i\nend\nreturn s\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .current_dir(&dir)
+        .args(["--no-banner", "--diagnostics-format=short"])
+        .args(args)
+        .arg("main.lua")
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn warnings_are_shown_with_their_code() {
+    assert_eq!(
+        check("default", &[]),
+        (
+            Some(0),
+            "main.lua:1:11: warning: [W200] module 'json' not found\n\
+             main.lua:4:3: warning: [W206] 's' is built up with '..' in a loop\n"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn a_warning_can_be_disabled_and_another_denied() {
+    assert_eq!(
+        check("levels", &["-W", "no-concat-in-loop", "-W", "error=W200"]),
+        (
+            Some(1),
+            "main.lua:1:11: error: [W200] module 'json' not found\n".to_string()
+        )
+    );
+
+    // the last flag for a warning wins.
+    let (code, stderr) = check(
+        "last",
+        &[
+            "-W",
+            "error=W200",
+            "-W",
+            "module-not-found",
+            "-W",
+            "no-W206",
+        ],
+    );
+    assert_eq!(code, Some(0));
+    assert_eq!(
+        stderr,
+        "main.lua:1:11: warning: [W200] module 'json' not found\n"
+    );
+}
+
+#[test]
+fn errors_and_unknown_warnings_cant_be_toggled() {
+    let output = run(&["-W", "no-syntax-error", "main.lua"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'syntax-error' is an error and can't be changed"));
+
+    let output = run(&["-W", "nope", "main.lua"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("unknown warning 'nope'"));
+}
+
+#[test]
+fn a_code_is_explained_by_its_code_or_name() {
+    let by_code = run(&["--explain", "W200"]);
+    assert_eq!(by_code.status.code(), Some(0));
+    let text = String::from_utf8(by_code.stdout).unwrap();
+    assert!(
+        text.starts_with("W200 (module-not-found), warning\n\n"),
+        "{text}"
+    );
+    assert!(text.contains("-W no-module-not-found"), "{text}");
+
+    let by_name = run(&["--explain", "module-not-found"]);
+    assert_eq!(String::from_utf8(by_name.stdout).unwrap(), text);
+
+    let unknown = run(&["--explain", "nope"]);
+    assert_eq!(unknown.status.code(), Some(2));
+    assert!(String::from_utf8(unknown.stderr)
+        .unwrap()
+        .contains("there is no diagnostic 'nope'"));
+}
+
+#[test]
+fn too_few_arguments_is_only_reported_when_asked_for() {
+    let dir = temp_dir("arity");
+    std::fs::write(
+        dir.join("main.lua"),
+        "local function clamp(x, lo, hi)\n  return x\nend\nclamp(1, 2, 3, 4, 5)\nclamp(1)\n",
+    )
+    .unwrap();
+    let check = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+            .current_dir(&dir)
+            .args(["--no-banner", "--diagnostics-format=short"])
+            .args(args)
+            .arg("main.lua")
+            .output()
+            .unwrap();
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let too_many = "main.lua:4:1: warning: [W207] function 'clamp' defined at line 1 takes 3 \
+                    arguments but 5 were given\n";
+    assert_eq!(check(&[]), too_many);
+    assert_eq!(
+        check(&["-W", "too-few-arguments"]),
+        format!(
+            "{too_many}main.lua:5:1: warning: [W208] function 'clamp' defined at line 1 takes 3 \
+             arguments but 1 was given\n"
+        )
+    );
+    assert_eq!(check(&["-W", "no-too-many-arguments"]), "");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
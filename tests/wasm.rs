@@ -0,0 +1,55 @@
+//! Runs in a JavaScript engine, with
+//! `cargo test --target wasm32-unknown-unknown --features wasm --test wasm` and
+//! `wasm-bindgen-test-runner` as the target's runner.
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Reflect};
+use lua_compiler::wasm::{check, format, tokenize};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn field(object: &JsValue, name: &str) -> JsValue {
+    Reflect::get(object, &JsValue::from_str(name)).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn check_reports_the_one_error() {
+    let diagnostics = Array::from(&check("local x = 1\nlocal y = = 2\n"));
+    assert_eq!(diagnostics.length(), 1);
+
+    let diagnostic = diagnostics.get(0);
+    assert_eq!(
+        field(&diagnostic, "code").as_string().as_deref(),
+        Some("E100")
+    );
+    assert_eq!(
+        field(&diagnostic, "severity").as_string().as_deref(),
+        Some("error")
+    );
+    assert_eq!(
+        field(&field(&diagnostic, "start"), "line").as_f64(),
+        Some(2.0)
+    );
+
+    assert_eq!(Array::from(&check("local x = 1")).length(), 0);
+}
+
+#[wasm_bindgen_test]
+fn tokenize_and_format() {
+    let tokens = Array::from(&tokenize("x = 'a'"));
+    assert_eq!(tokens.length(), 3);
+    assert_eq!(
+        field(&tokens.get(2), "kind").as_string().as_deref(),
+        Some("STRING")
+    );
+    assert_eq!(
+        field(&tokens.get(2), "lexeme").as_string().as_deref(),
+        Some("'a'")
+    );
+    assert!(tokenize("s = 'unfinished").is_null());
+
+    assert_eq!(
+        format("do\nx = 1\nend").as_deref(),
+        Some("do\n    x = 1\nend")
+    );
+}
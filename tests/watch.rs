@@ -0,0 +1,125 @@
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, SystemTime};
+
+// long enough for a poll, the debounce and a compile on a slow machine.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+// the watcher never exits by itself, so it's killed even when the test fails.
+struct Watcher(Child);
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn watch(args: &[&str], file: &Path) -> Watcher {
+    let child = Command::new(env!("CARGO_BIN_EXE_lua-compiler"))
+        .arg("--watch")
+        .args(args)
+        .arg(file)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    Watcher(child)
+}
+
+// the lines the watcher prints to stderr, as they come.
+fn lines(watcher: &mut Watcher) -> Receiver<String> {
+    let (sender, lines) = mpsc::channel();
+    let stderr = watcher.0.stderr.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    lines
+}
+
+// writes the file with a modification time that's surely later than the last one.
+fn touch(path: &Path, contents: &str, seconds_later: u64) {
+    std::fs::write(path, contents).unwrap();
+    let file = std::fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(seconds_later))
+        .unwrap();
+}
+
+// waits for the header printed after a compile, returning whether it succeeded.
+fn next_compile(lines: &Receiver<String>) -> bool {
+    loop {
+        let line = lines
+            .recv_timeout(TIMEOUT)
+            .expect("no compile in time, or the watcher exited");
+        if line.contains("watching") {
+            return line.contains("compiled successfully");
+        }
+    }
+}
+
+#[test]
+fn edits_to_the_file_and_its_modules_recompile() {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (main, module) = (dir.join("main.lua"), dir.join("module.lua"));
+    std::fs::write(&main, "local m = require \"module\"\n").unwrap();
+    std::fs::write(&module, "return 1\n").unwrap();
+
+    let mut watcher = watch(&["--check"], &main);
+    let lines = lines(&mut watcher);
+
+    assert!(next_compile(&lines));
+    touch(&module, "return = 1\n", 10);
+    assert!(!next_compile(&lines), "the module wasn't watched");
+    touch(&main, "local m = 1\n", 20);
+    assert!(next_compile(&lines), "the file wasn't watched");
+
+    drop(watcher);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn edits_inside_a_directory_recompile() {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-watch-dir-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib").join("a.lua"), "local a = 1\n").unwrap();
+
+    let mut watcher = watch(&["--check"], &dir);
+    let lines = lines(&mut watcher);
+
+    assert!(next_compile(&lines));
+    touch(&dir.join("lib").join("a.lua"), "local a = = 1\n", 10);
+    assert!(!next_compile(&lines), "the file in it wasn't watched");
+    touch(&dir.join("lib").join("a.lua"), "local a = 1\n", 20);
+    assert!(next_compile(&lines));
+    // a new file changes the modification time of its directory.
+    std::fs::write(dir.join("lib").join("b.lua"), "local b = = 1\n").unwrap();
+    assert!(!next_compile(&lines), "the new file wasn't noticed");
+
+    drop(watcher);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_screen_without_color_isnt_cleared() {
+    let dir = std::env::temp_dir().join(format!("lua-compiler-watch-clear-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.lua");
+    std::fs::write(&main, "local x = 1\n").unwrap();
+
+    let mut watcher = watch(&["--check", "--color=never"], &main);
+
+    let mut stderr = BufReader::new(watcher.0.stderr.take().unwrap());
+    let mut first = String::new();
+    stderr.read_line(&mut first).unwrap();
+    assert!(first.contains("watching"), "{first:?}");
+    assert!(!first.contains('\x1b'), "{first:?}");
+
+    drop(watcher);
+    std::fs::remove_dir_all(&dir).unwrap();
+}